@@ -191,11 +191,16 @@ class Config:
             'server_ip': self.get('server', 'server_ip'),
             'server_port': self.get('server', 'server_port'),
             'use_https': self.get('server', 'use_https', False),
+            'ca_cert': self.get('server', 'ca_cert', None),
+            'client_cert': self.get('server', 'client_cert', None),
+            'client_key': self.get('server', 'client_key', None),
             'api_path': self.get('server', 'api_path', '/api/events'),
             'api_key': self.get('server', 'api_key', None),
             'timeout': self.get('server', 'timeout', 30),
             'max_retries': self.get('server', 'max_retries', 3),
             'retry_delay': self.get('server', 'retry_delay', 5),
+            'signing_key': self.get('server', 'signing_key', None),
+            'compression': self.get('server', 'compression', 'none'),
         }
 
     def get_collection_config(self) -> Dict[str, Any]:
@@ -207,6 +212,7 @@ class Config:
             'enabled_categories': self.get('collection', 'enabled_categories',
                                          ['auth', 'system', 'service', 'software']),
             'max_events_per_batch': self.get('collection', 'max_events_per_batch', 500),
+            'ttl': self.get('collection', 'ttl', {}) or {},
         }
 
     def __str__(self) -> str:
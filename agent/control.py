@@ -0,0 +1,201 @@
+"""
+Control Channel for Loglumen Agent
+
+Keeps a WebSocket open to the server so it can send commands such as
+"flush now", "change log level" or "send inventory" without waiting for
+the next collection cycle. Results are sent back over the same socket and
+the server records them as events.
+
+Requires the optional websocket-client package; without it the agent runs
+as before and simply has no control channel.
+"""
+
+import json
+import socket
+import platform
+import threading
+import time
+from typing import Any, Callable, Dict, List
+
+# Try to import websocket-client library
+try:
+    import websocket
+    WEBSOCKET_AVAILABLE = True
+except ImportError:
+    WEBSOCKET_AVAILABLE = False
+
+
+LOG_LEVELS = ('debug', 'info', 'warning', 'error')
+
+
+class ControlChannel:
+    """
+    Background connection that receives server commands.
+
+    Reconnects with a fixed delay whenever the socket drops.
+    """
+
+    def __init__(self, server_config: Dict[str, Any], agent):
+        """
+        Initialize the control channel.
+
+        Args:
+            server_config: Server configuration dictionary
+            agent: The LoglumenAgent the commands act on
+        """
+        self.agent = agent
+        self.hostname = socket.gethostname()
+        self.retry_delay = server_config.get('retry_delay', 5)
+        self.api_key = server_config.get('api_key', None)
+        self.sslopt = {}
+        if server_config.get('ca_cert'):
+            self.sslopt['ca_certs'] = server_config['ca_cert']
+        if server_config.get('client_cert'):
+            self.sslopt['certfile'] = server_config['client_cert']
+            self.sslopt['keyfile'] = server_config.get('client_key')
+
+        protocol = "wss" if server_config.get('use_https', False) else "ws"
+        self.url = (f"{protocol}://{server_config['server_ip']}:{server_config['server_port']}"
+                    f"/api/agents/{self.hostname}/control")
+
+        self.handlers: Dict[str, Callable[[Dict[str, Any]], Any]] = {
+            'flush_now': self._flush_now,
+            'set_log_level': self._set_log_level,
+            'send_inventory': self._send_inventory,
+        }
+
+        self._ws = None
+        self._thread = None
+
+    def start(self) -> bool:
+        """Start the background thread. Returns False if unavailable."""
+        if not WEBSOCKET_AVAILABLE:
+            print("[WARN] websocket-client not installed - control channel disabled")
+            return False
+
+        self._thread = threading.Thread(target=self._run, name="control", daemon=True)
+        self._thread.start()
+        return True
+
+    def stop(self):
+        """Close the socket so the background thread exits."""
+        ws = self._ws
+        if ws is not None:
+            try:
+                ws.close()
+            except Exception:
+                pass
+
+    def _run(self):
+        while self.agent.running:
+            try:
+                self._ws = websocket.create_connection(self.url, timeout=30,
+                                                       header=self._headers(),
+                                                       sslopt=self.sslopt or None)
+                print(f"[OK] Control channel connected to {self.url}")
+                self._send({
+                    'type': 'hello',
+                    'host_ipv4': self._local_ip(),
+                    'os': self.agent.os_type,
+                })
+                self._ws.settimeout(None)
+
+                while self.agent.running:
+                    message = self._ws.recv()
+                    if not message:
+                        break
+                    self._handle(message)
+
+            except Exception as e:
+                if self.agent.running:
+                    print(f"[WARN] Control channel error: {e}")
+            finally:
+                self.stop()
+                self._ws = None
+
+            # Sleep in small increments to allow for quick shutdown
+            for _ in range(self.retry_delay):
+                if not self.agent.running:
+                    break
+                time.sleep(1)
+
+    def _headers(self) -> List[str]:
+        """
+        The API key, and the agent token once registered; the server won't
+        hand a registered host's channel to anyone without its token.
+        """
+        headers = []
+        if self.api_key:
+            headers.append(f'Authorization: Bearer {self.api_key}')
+        try:
+            with open(self.agent._registration_file()) as f:
+                headers.append(f"X-Loglumen-Agent-Token: {json.load(f)['token']}")
+        except (OSError, ValueError, KeyError):
+            pass
+        return headers
+
+    def _handle(self, message: str):
+        try:
+            command = json.loads(message)
+        except ValueError:
+            print("[WARN] Ignoring malformed control message")
+            return
+
+        if command.get('type') != 'command':
+            return
+
+        name = command.get('command')
+        handler = self.handlers.get(name)
+        print(f"[INFO] Control command received: {name}")
+
+        if handler is None:
+            status, detail = 'error', f"unsupported command '{name}'"
+        else:
+            try:
+                status, detail = 'ok', handler(command.get('args') or {})
+            except Exception as e:
+                status, detail = 'error', str(e)
+
+        self._send({
+            'type': 'result',
+            'id': command.get('id'),
+            'status': status,
+            'detail': detail,
+        })
+
+    def _send(self, message: Dict[str, Any]):
+        self._ws.send(json.dumps(message))
+
+    def _flush_now(self, args: Dict[str, Any]) -> str:
+        self.agent.flush_requested.set()
+        return "collection cycle triggered"
+
+    def _set_log_level(self, args: Dict[str, Any]) -> str:
+        level = str(args.get('level', '')).lower()
+        if level not in LOG_LEVELS:
+            raise ValueError(f"level must be one of {', '.join(LOG_LEVELS)}")
+        self.agent.log_level = level
+        return f"log level set to {level}"
+
+    def _send_inventory(self, args: Dict[str, Any]) -> Dict[str, Any]:
+        return {
+            'hostname': self.hostname,
+            'host_ipv4': self._local_ip(),
+            'os': self.agent.os_type,
+            'platform': platform.platform(),
+            'release': platform.release(),
+            'machine': platform.machine(),
+            'python': platform.python_version(),
+            'enabled_categories': self.agent.collection_config['enabled_categories'],
+            'collection_interval': self.agent.collection_config['collection_interval'],
+        }
+
+    def _local_ip(self) -> str:
+        """Address of the interface used to reach the server."""
+        try:
+            sock = self._ws.sock if self._ws is not None else None
+            if sock is not None:
+                return sock.getsockname()[0]
+        except Exception:
+            pass
+        return ""
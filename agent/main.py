@@ -23,10 +23,12 @@ Usage:
 
 import os
 import sys
+import json
 import time
 import signal
 import argparse
 import platform
+import threading
 from datetime import datetime
 from typing import List, Dict, Any
 
@@ -36,6 +38,12 @@ sys.path.insert(0, os.path.join(os.path.dirname(__file__), 'collectors'))
 # Import configuration and sender
 from config_loader import load_config, ConfigurationError
 from sender import EventSender, SenderError
+from control import ControlChannel
+from signing import SigningError, generate_key
+from utils import create_event, get_hostname
+
+# Reported when registering with the server
+AGENT_VERSION = '1.0.0'
 
 
 class LoglumenAgent:
@@ -75,6 +83,10 @@ class LoglumenAgent:
         # Running flag
         self.running = True
 
+        # Set by the control channel to cut the current wait short
+        self.flush_requested = threading.Event()
+        self.log_level = str(self.config.get('logging', 'log_level', 'info')).lower()
+
         # Setup signal handlers for graceful shutdown
         signal.signal(signal.SIGINT, self._signal_handler)
         signal.signal(signal.SIGTERM, self._signal_handler)
@@ -107,6 +119,64 @@ class LoglumenAgent:
 
         raise ConfigurationError(f"Unsupported operating system: {platform.system()}")
 
+    def _registration_file(self) -> str:
+        """Where the ID and token from registering are kept."""
+        configured = self.config.get('agent', 'registration_file', None)
+        if configured:
+            return configured
+        config_dir = os.path.dirname(os.path.abspath(self.config.config_path))
+        return os.path.join(config_dir, 'agent-registration.json')
+
+    def register(self) -> bool:
+        """
+        Register with the server's agent inventory, or refresh an earlier
+        registration, so the dashboard lists this host even while it is quiet.
+
+        Returns:
+            bool: False if it should be tried again next cycle
+        """
+        details = {
+            'hostname': get_hostname(),
+            'os': self.os_type,
+            'agent_version': AGENT_VERSION,
+            'tags': list(self.config.get('agent', 'tags', []) or []),
+        }
+        client_ipv4 = self.config.get('agent', 'client_ipv4', None)
+        if client_ipv4:
+            details['host_ipv4'] = client_ipv4
+        path = self._registration_file()
+
+        try:
+            with open(path) as f:
+                saved = json.load(f)
+        except FileNotFoundError:
+            saved = None
+        except (OSError, ValueError) as e:
+            print(f"[WARN] Ignoring unreadable registration file {path}: {e}")
+            saved = None
+
+        try:
+            if saved and self.sender.update_agent(saved['id'], saved['token'], details):
+                print(f"[OK] Registration {saved['id']} updated")
+                return True
+            registered = self.sender.register_agent(details)
+        except SenderError as e:
+            print(f"[WARN] Could not register with the server: {e}")
+            return False
+        if registered is None:
+            print("[INFO] Server has no agent inventory; skipping registration")
+            return True
+
+        try:
+            fd = os.open(path, os.O_WRONLY | os.O_CREAT | os.O_TRUNC, 0o600)
+            with os.fdopen(fd, 'w') as f:
+                json.dump({'id': registered['id'], 'token': registered['token']}, f)
+        except OSError as e:
+            print(f"[WARN] Registered as {registered['id']} but could not save {path}: {e}")
+            return True
+        print(f"[OK] Registered as {registered['id']}")
+        return True
+
     def _category_enabled(self, enabled: List[str], *names: str) -> bool:
         """Return True if any provided category alias is enabled."""
         normalized = [cat.lower() for cat in enabled]
@@ -124,8 +194,17 @@ class LoglumenAgent:
         hours = self.collection_config['hours_lookback']
 
         if self.os_type == 'windows':
-            return self._collect_windows_events(enabled, hours, max_lines)
-        return self._collect_linux_events(enabled, hours, max_lines)
+            events = self._collect_windows_events(enabled, hours, max_lines)
+        else:
+            events = self._collect_linux_events(enabled, hours, max_lines)
+
+        # Ask the server to drop some categories sooner than its default
+        ttl = self.collection_config.get('ttl', {})
+        if ttl:
+            for event in events:
+                if event.get('category') in ttl:
+                    event.setdefault('ttl', ttl[event['category']])
+        return events
 
     def _collect_linux_events(self, enabled, hours, max_lines):
         """Collect events using Linux collectors."""
@@ -250,6 +329,18 @@ class LoglumenAgent:
 
         return all_events
 
+    def _heartbeat_event(self) -> Dict[str, Any]:
+        """Event sent instead of a heartbeat to servers without /api/heartbeat."""
+        return create_event(
+            category="agent",
+            event_type="heartbeat",
+            severity="debug",
+            message="Agent heartbeat",
+            source="agent",
+            os=self.os_type,
+            data={"collection_interval": self.collection_config['collection_interval']}
+        )
+
     def run_once(self, send_events: bool = True) -> bool:
         """
         Run one collection and send cycle.
@@ -272,6 +363,10 @@ class LoglumenAgent:
 
         if not events:
             print("[INFO] No events collected this cycle")
+            if send_events:
+                # Still report in so the server doesn't count this as downtime
+                if self.sender.send_heartbeat(get_hostname()) is None:
+                    self.sender.send_events([self._heartbeat_event()])
             return True
 
         print(f"\n[INFO] Total collected: {len(events)} events")
@@ -322,20 +417,33 @@ class LoglumenAgent:
         else:
             print("[WARN] Server not reachable (will retry each cycle)")
 
+        registered = not self.config.get('agent', 'register', False)
+
+        # Optional channel for server-initiated commands
+        control = None
+        if self.config.get('server', 'control_channel', False):
+            control = ControlChannel(self.server_config, self)
+            if not control.start():
+                control = None
+
         # Main loop
         while self.running:
             try:
+                if not registered:
+                    registered = self.register()
                 self.run_once(send_events=True)
 
                 if self.running:
-                    print(f"\n[INFO] Waiting {interval} seconds until next collection...")
-                    print(f"[INFO] Press Ctrl+C to stop gracefully")
+                    if self.log_level in ('debug', 'info'):
+                        print(f"\n[INFO] Waiting {interval} seconds until next collection...")
+                        print(f"[INFO] Press Ctrl+C to stop gracefully")
 
                     # Sleep in small increments to allow for quick shutdown
+                    # or an early flush requested by the server
                     for _ in range(interval):
-                        if not self.running:
+                        if not self.running or self.flush_requested.wait(1):
                             break
-                        time.sleep(1)
+                    self.flush_requested.clear()
 
             except KeyboardInterrupt:
                 print("\n[INFO] Keyboard interrupt received")
@@ -350,6 +458,9 @@ class LoglumenAgent:
                     print(f"[INFO] Waiting {interval} seconds before retry...")
                     time.sleep(interval)
 
+        if control is not None:
+            control.stop()
+
         # Shutdown
         print("\n" + "=" * 70)
         print("Agent Shutdown")
@@ -419,8 +530,24 @@ def main():
         action='store_true',
         help='Dry run: show what would be collected'
     )
+    parser.add_argument(
+        '--generate-signing-key',
+        metavar='PATH',
+        help='Write a new event signing key to PATH and print its public key'
+    )
     args = parser.parse_args()
 
+    if args.generate_signing_key:
+        try:
+            public = generate_key(args.generate_signing_key)
+        except SigningError as e:
+            print(f"[ERROR] {e}")
+            return 1
+        print(f"[INFO] Wrote signing key to {args.generate_signing_key}")
+        print("[INFO] Register this public key on the server under [signing.keys]:")
+        print(public)
+        return 0
+
     # Load and initialize agent
     try:
         agent = LoglumenAgent(config_path=args.config)
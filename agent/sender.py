@@ -5,12 +5,15 @@ Sends collected events to the Loglumen server via HTTP/HTTPS.
 Includes retry logic, batching, and error handling.
 """
 
+import gzip
 import json
 import time
 import sys
 from typing import List, Dict, Any, Optional
 from datetime import datetime
 
+from signing import EventSigner, SigningError
+
 # Try to import requests library
 try:
     import requests
@@ -31,6 +34,19 @@ class SenderError(Exception):
     pass
 
 
+class BatchTooLarge(SenderError):
+    """The server refused the batch with 413; smaller batches may fit."""
+    pass
+
+
+class ServerBusy(SenderError):
+    """The server's ingest queue is full (429); retry after a delay."""
+
+    def __init__(self, retry_after: float):
+        super().__init__(f"server busy, retry after {retry_after:g}s")
+        self.retry_after = retry_after
+
+
 class EventSender:
     """
     Sends events to the Loglumen server.
@@ -45,21 +61,43 @@ class EventSender:
         Args:
             server_config: Dictionary with server configuration
                 Required keys: server_ip, server_port
-                Optional keys: use_https, api_path, api_key, timeout,
-                              max_retries, retry_delay
+                Optional keys: use_https, ca_cert, client_cert, client_key,
+                              api_path, api_key, timeout, max_retries,
+                              retry_delay, signing_key, compression
         """
         self.server_ip = server_config['server_ip']
         self.server_port = server_config['server_port']
         self.use_https = server_config.get('use_https', False)
+        # CA bundle for servers with a private or self-signed certificate
+        self.ca_cert = server_config.get('ca_cert', None)
+        # Certificate and key presented to servers that require mutual TLS
+        self.client_cert = server_config.get('client_cert', None)
+        self.client_key = server_config.get('client_key', None)
         self.api_path = server_config.get('api_path', '/api/events')
         self.api_key = server_config.get('api_key', None)
         self.timeout = server_config.get('timeout', 30)
         self.max_retries = server_config.get('max_retries', 3)
         self.retry_delay = server_config.get('retry_delay', 5)
+        # "gzip" compresses batches for metered links; the server undoes it
+        self.compression = (server_config.get('compression') or 'none').lower()
+        if self.compression not in ('none', 'gzip'):
+            raise SenderError(
+                f"Unsupported compression '{self.compression}' (use 'none' or 'gzip')"
+            )
+
+        # Sign events when a key is configured
+        self.signer = None
+        if server_config.get('signing_key'):
+            try:
+                self.signer = EventSigner(server_config['signing_key'])
+            except SigningError as e:
+                raise SenderError(str(e))
 
         # Build server URL
         protocol = "https" if self.use_https else "http"
         self.server_url = f"{protocol}://{self.server_ip}:{self.server_port}{self.api_path}"
+        self.heartbeat_url = f"{protocol}://{self.server_ip}:{self.server_port}/api/heartbeat"
+        self.agents_url = f"{protocol}://{self.server_ip}:{self.server_port}/api/agents"
 
         # Statistics
         self.total_sent = 0
@@ -121,6 +159,23 @@ class EventSender:
         for attempt in range(self.max_retries):
             try:
                 return self._send_batch(batch)
+            except BatchTooLarge:
+                if len(batch) < 2:
+                    print("\n[ERROR] Server refused a single event as too large")
+                    return False
+                half = len(batch) // 2
+                print(f"\n[WARN] Server refused {len(batch)} events as too large; "
+                      f"sending {half} and {len(batch) - half}")
+                first = self._send_batch_with_retry(batch[:half])
+                second = self._send_batch_with_retry(batch[half:])
+                return first and second
+            except ServerBusy as e:
+                if attempt < self.max_retries - 1:
+                    print(f"\n[WARN] Server busy; retrying in {e.retry_after:g} seconds...")
+                    time.sleep(e.retry_after)
+                else:
+                    print(f"\n[ERROR] Server still busy after {self.max_retries} attempts")
+                    return False
             except Exception as e:
                 if attempt < self.max_retries - 1:
                     print(f"\n[WARN] Attempt {attempt + 1} failed: {e}")
@@ -135,7 +190,9 @@ class EventSender:
     def _send_batch(self, batch: List[Dict[str, Any]]) -> bool:
         """Send a single batch to the server."""
         # Prepare JSON payload
-        payload = json.dumps(batch)
+        if self.signer:
+            batch = [self.signer.sign(event) for event in batch]
+        payload = json.dumps(batch).encode('utf-8')
 
         # Prepare headers
         headers = {
@@ -143,6 +200,10 @@ class EventSender:
             'User-Agent': 'Loglumen-Agent/1.0'
         }
 
+        if self.compression == 'gzip':
+            payload = gzip.compress(payload)
+            headers['Content-Encoding'] = 'gzip'
+
         if self.api_key:
             headers['Authorization'] = f'Bearer {self.api_key}'
             # Also support X-API-Key header
@@ -156,16 +217,31 @@ class EventSender:
         else:
             raise SenderError("No HTTP library available")
 
-    def _send_with_requests(self, payload: str, headers: Dict[str, str]) -> bool:
+    def _check_backpressure(self, status: int, retry_after: Optional[str]):
+        """Raise for the statuses the server uses to push back."""
+        if status == 413:
+            raise BatchTooLarge("batch too large")
+        if status == 429:
+            try:
+                delay = float(retry_after)
+            except (TypeError, ValueError):
+                delay = self.retry_delay
+            raise ServerBusy(min(max(delay, 1), 300))
+
+    def _send_with_requests(self, payload: bytes, headers: Dict[str, str]) -> bool:
         """Send using the requests library."""
         try:
             response = requests.post(
                 self.server_url,
                 data=payload,
                 headers=headers,
-                timeout=self.timeout
+                timeout=self.timeout,
+                verify=self.ca_cert or True,
+                cert=(self.client_cert, self.client_key) if self.client_cert else None
             )
 
+            self._check_backpressure(response.status_code,
+                                     response.headers.get('Retry-After'))
             if response.status_code == 200:
                 return True
             else:
@@ -180,32 +256,37 @@ class EventSender:
         except requests.exceptions.ConnectionError as e:
             print(f"\n[ERROR] Connection failed: {e}")
             return False
+        except SenderError:
+            raise
         except Exception as e:
             print(f"\n[ERROR] Unexpected error: {e}")
             return False
 
-    def _send_with_urllib(self, payload: str, headers: Dict[str, str]) -> bool:
+    def _send_with_urllib(self, payload: bytes, headers: Dict[str, str]) -> bool:
         """Send using urllib (fallback if requests not available)."""
         try:
-            # Convert payload to bytes
-            payload_bytes = payload.encode('utf-8')
-
             # Create request
             req = urllib.request.Request(
                 self.server_url,
-                data=payload_bytes,
+                data=payload,
                 headers=headers,
                 method='POST'
             )
 
+            context = self._urllib_context()
+
             # Send request
-            with urllib.request.urlopen(req, timeout=self.timeout) as response:
+            with urllib.request.urlopen(req, timeout=self.timeout, context=context) as response:
                 if response.status == 200:
                     return True
                 else:
                     print(f"\n[ERROR] Server returned status {response.status}")
                     return False
 
+        except urllib.error.HTTPError as e:
+            self._check_backpressure(e.code, e.headers.get('Retry-After'))
+            print(f"\n[ERROR] Server returned status {e.code}")
+            return False
         except urllib.error.URLError as e:
             print(f"\n[ERROR] Connection failed: {e}")
             return False
@@ -213,6 +294,150 @@ class EventSender:
             print(f"\n[ERROR] Unexpected error: {e}")
             return False
 
+    def _urllib_context(self):
+        """TLS settings for urllib, or None for its defaults."""
+        if not (self.ca_cert or self.client_cert):
+            return None
+        import ssl
+        context = ssl.create_default_context(cafile=self.ca_cert)
+        if self.client_cert:
+            context.load_cert_chain(self.client_cert, self.client_key)
+        return context
+
+    def send_heartbeat(self, host: str) -> Optional[bool]:
+        """
+        Report in without events through POST /api/heartbeat.
+
+        Args:
+            host: Hostname the server knows this agent by
+
+        Returns:
+            True if the server recorded it, False if that failed, or None if
+            the server is too old to have the endpoint
+        """
+        payload = json.dumps({'host': host}).encode('utf-8')
+        headers = {
+            'Content-Type': 'application/json',
+            'User-Agent': 'Loglumen-Agent/1.0'
+        }
+        if self.api_key:
+            headers['Authorization'] = f'Bearer {self.api_key}'
+
+        try:
+            if REQUESTS_AVAILABLE:
+                response = requests.post(
+                    self.heartbeat_url,
+                    data=payload,
+                    headers=headers,
+                    timeout=self.timeout,
+                    verify=self.ca_cert or True,
+                    cert=(self.client_cert, self.client_key) if self.client_cert else None
+                )
+                status = response.status_code
+            else:
+                req = urllib.request.Request(self.heartbeat_url, data=payload,
+                                             headers=headers, method='POST')
+                try:
+                    with urllib.request.urlopen(req, timeout=self.timeout,
+                                                context=self._urllib_context()) as response:
+                        status = response.status
+                except urllib.error.HTTPError as e:
+                    status = e.code
+        except Exception as e:
+            print(f"[WARN] Heartbeat failed: {e}")
+            return False
+
+        if status == 404:
+            return None
+        if status != 200:
+            print(f"[WARN] Heartbeat returned status {status}")
+            return False
+        return True
+
+    def _request_json(self, method: str, url: str, body: Dict[str, Any],
+                      headers: Optional[Dict[str, str]] = None):
+        """
+        Send a JSON request and return (status, decoded body).
+
+        Raises:
+            SenderError: If the server could not be reached
+        """
+        payload = json.dumps(body).encode('utf-8')
+        headers = dict(headers or {})
+        headers['Content-Type'] = 'application/json'
+        headers['User-Agent'] = 'Loglumen-Agent/1.0'
+        if self.api_key:
+            headers['Authorization'] = f'Bearer {self.api_key}'
+
+        try:
+            if REQUESTS_AVAILABLE:
+                response = requests.request(
+                    method,
+                    url,
+                    data=payload,
+                    headers=headers,
+                    timeout=self.timeout,
+                    verify=self.ca_cert or True,
+                    cert=(self.client_cert, self.client_key) if self.client_cert else None
+                )
+                status, text = response.status_code, response.text
+            else:
+                req = urllib.request.Request(url, data=payload, headers=headers, method=method)
+                try:
+                    with urllib.request.urlopen(req, timeout=self.timeout,
+                                                context=self._urllib_context()) as response:
+                        status, text = response.status, response.read().decode('utf-8')
+                except urllib.error.HTTPError as e:
+                    status, text = e.code, e.read().decode('utf-8', 'replace')
+        except Exception as e:
+            raise SenderError(f"{method} {url} failed: {e}")
+
+        try:
+            return status, json.loads(text) if text else {}
+        except ValueError:
+            return status, {}
+
+    def register_agent(self, details: Dict[str, Any]) -> Optional[Dict[str, Any]]:
+        """
+        Register with POST /api/agents.
+
+        Args:
+            details: hostname, os, agent_version and tags
+
+        Returns:
+            The server's response with the assigned id and token, or None if
+            the server is too old to have the endpoint
+
+        Raises:
+            SenderError: If registration failed
+        """
+        status, body = self._request_json('POST', self.agents_url, details)
+        if status == 404:
+            return None
+        if status != 201:
+            raise SenderError(body.get('message') or f"registration returned status {status}")
+        return body
+
+    def update_agent(self, agent_id: str, token: str, details: Dict[str, Any]) -> bool:
+        """
+        Refresh this agent's registration with PUT /api/agents/{id}.
+
+        Returns:
+            True if updated, False if the server no longer knows the ID
+
+        Raises:
+            SenderError: If the update failed for another reason
+        """
+        status, body = self._request_json(
+            'PUT', f"{self.agents_url}/{agent_id}", details,
+            headers={'X-Loglumen-Agent-Token': token}
+        )
+        if status == 404:
+            return False
+        if status != 200:
+            raise SenderError(body.get('message') or f"update returned status {status}")
+        return True
+
     def get_stats(self) -> Dict[str, int]:
         """Get sender statistics."""
         return {
@@ -0,0 +1,113 @@
+"""
+Event Signing for Loglumen Agent
+
+Signs every event with the agent's Ed25519 key so the server can tell
+whether an event was altered after it left this machine. The server keeps
+the matching public key under [signing.keys] in its own config.
+
+Requires the optional cryptography package; without it signing cannot be
+enabled.
+"""
+
+import base64
+import json
+import os
+from typing import Any, Dict
+
+# Try to import cryptography library
+try:
+    from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+    from cryptography.hazmat.primitives import serialization
+    CRYPTOGRAPHY_AVAILABLE = True
+except ImportError:
+    CRYPTOGRAPHY_AVAILABLE = False
+
+
+# Event fields covered by the signature, in the order the server expects
+SIGNED_FIELDS = (
+    'schema_version', 'category', 'event_type', 'time', 'host', 'host_ipv4',
+    'os', 'source', 'severity', 'message', 'data',
+)
+
+
+class SigningError(Exception):
+    """Raised when the signing key cannot be used."""
+    pass
+
+
+def signed_bytes(event: Dict[str, Any]) -> bytes:
+    """
+    Build the message that gets signed.
+
+    Must match signed_bytes() in the server's signing.rs: a compact JSON
+    array of the signed fields with object keys sorted.
+    """
+    fields = [event.get(name) for name in SIGNED_FIELDS]
+    return json.dumps(fields, sort_keys=True, separators=(',', ':'),
+                      ensure_ascii=False).encode('utf-8')
+
+
+class EventSigner:
+    """Signs events with a private key loaded from disk."""
+
+    def __init__(self, key_path: str):
+        """
+        Load the signing key.
+
+        Args:
+            key_path: File holding the base64 32-byte private key written
+                by generate_key()
+        """
+        if not CRYPTOGRAPHY_AVAILABLE:
+            raise SigningError(
+                "Event signing needs cryptography: pip install cryptography"
+            )
+        try:
+            with open(key_path, 'r') as f:
+                seed = base64.b64decode(f.read().strip())
+            self.key = Ed25519PrivateKey.from_private_bytes(seed)
+        except (OSError, ValueError) as e:
+            raise SigningError(f"Cannot load signing key {key_path}: {e}")
+
+    def sign(self, event: Dict[str, Any]) -> Dict[str, Any]:
+        """Return a copy of the event with its signature attached."""
+        signed = dict(event)
+        signed.pop('verification', None)
+        signature = self.key.sign(signed_bytes(signed))
+        signed['signature'] = base64.b64encode(signature).decode('ascii')
+        return signed
+
+
+def public_key(private_key) -> str:
+    """Base64 public key, the value the server config expects."""
+    raw = private_key.public_key().public_bytes(
+        encoding=serialization.Encoding.Raw,
+        format=serialization.PublicFormat.Raw,
+    )
+    return base64.b64encode(raw).decode('ascii')
+
+
+def generate_key(key_path: str) -> str:
+    """
+    Create a new private key file readable only by the current user.
+
+    Returns:
+        str: The base64 public key to register on the server
+    """
+    if not CRYPTOGRAPHY_AVAILABLE:
+        raise SigningError(
+            "Event signing needs cryptography: pip install cryptography"
+        )
+    if os.path.exists(key_path):
+        raise SigningError(f"{key_path} already exists")
+
+    key = Ed25519PrivateKey.generate()
+    seed = key.private_bytes(
+        encoding=serialization.Encoding.Raw,
+        format=serialization.PrivateFormat.Raw,
+        encryption_algorithm=serialization.NoEncryption(),
+    )
+    fd = os.open(key_path, os.O_WRONLY | os.O_CREAT | os.O_EXCL, 0o600)
+    with os.fdopen(fd, 'w') as f:
+        f.write(base64.b64encode(seed).decode('ascii') + '\n')
+    return public_key(key)
@@ -0,0 +1,51 @@
+// Build facts for GET /api/version and `loglumen-server version`: the git
+// commit and when the binary was built. Builds without a git checkout (the
+// Docker image copies only this directory) can pass LOGLUMEN_GIT_COMMIT;
+// SOURCE_DATE_EPOCH fixes the build time for reproducible builds.
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=LOGLUMEN_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = std::env::var("LOGLUMEN_GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]));
+    // Build again when HEAD moves, whether to another branch or another
+    // commit on this one
+    if commit.is_some() {
+        let mut watched = vec!["HEAD".to_string(), "packed-refs".to_string()];
+        watched.extend(git(&["symbolic-ref", "-q", "HEAD"]));
+        for name in watched {
+            if let Some(path) = git(&["rev-parse", "--git-path", &name]) {
+                println!("cargo:rerun-if-changed={}", path);
+            }
+        }
+    }
+    println!(
+        "cargo:rustc-env=LOGLUMEN_GIT_COMMIT={}",
+        commit.unwrap_or_default()
+    );
+
+    let built = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64)
+        });
+    println!("cargo:rustc-env=LOGLUMEN_BUILD_TIME={}", built);
+}
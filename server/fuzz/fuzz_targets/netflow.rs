@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Several datagrams per run, so state carried between them gets exercised
+fuzz_target!(|packets: Vec<Vec<u8>>| {
+    loglumen_server::fuzzing::netflow(&packets);
+});
@@ -0,0 +1,360 @@
+use crate::{live, AppState};
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// `[[access.tokens]]` entry
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenConfig {
+    name: String,
+    token: String,
+    role: String,
+}
+
+// `[access]` section
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+    // Role of requests without a known bearer token. Once tokens are handed
+    // out, set this to the most restricted role.
+    default_role: String,
+    tokens: Vec<TokenConfig>,
+    // Role -> event fields it may not see, e.g. "data.command_line" or
+    // "host_ipv4". Roles without an entry see everything.
+    hidden_fields: HashMap<String, Vec<String>>,
+}
+
+// Who a request is made by; stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub role: String,
+}
+
+pub struct Access {
+    default_role: String,
+    // Bearer token -> principal
+    tokens: HashMap<String, Principal>,
+    hidden_fields: HashMap<String, Vec<Vec<String>>>,
+}
+
+impl Access {
+    pub fn new(config: AccessConfig) -> Self {
+        for (role, fields) in &config.hidden_fields {
+            log_line!(
+                "CONFIG",
+                "Role '{}' cannot see: {}",
+                role,
+                fields.join(", ")
+            );
+        }
+        Access {
+            default_role: config.default_role,
+            tokens: config
+                .tokens
+                .into_iter()
+                .map(|entry| {
+                    let principal = Principal {
+                        name: entry.name,
+                        role: entry.role,
+                    };
+                    log_line!(
+                        "CONFIG",
+                        "Access token '{}' has role '{}'",
+                        principal.name,
+                        principal.role
+                    );
+                    (entry.token, principal)
+                })
+                .collect(),
+            hidden_fields: config
+                .hidden_fields
+                .into_iter()
+                .map(|(role, fields)| {
+                    let paths = fields
+                        .iter()
+                        .map(|field| field.split('.').map(str::to_string).collect())
+                        .collect();
+                    (role, paths)
+                })
+                .collect(),
+        }
+    }
+
+    // The `[access]` token presented, if any. Unknown tokens aren't an
+    // error, as agents send their ingest key in the same header.
+    fn token_principal(&self, request: &ServiceRequest) -> Option<Principal> {
+        request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| self.tokens.get(token.trim()))
+            .cloned()
+    }
+
+    fn anonymous(&self) -> Principal {
+        Principal {
+            name: "anonymous".to_string(),
+            role: self.default_role.clone(),
+        }
+    }
+
+    fn hidden(&self, role: &str) -> &[Vec<String>] {
+        self.hidden_fields.get(role).map_or(&[], Vec::as_slice)
+    }
+
+    // Whether `field` is one of the role's hidden fields, sits inside one,
+    // or is an object holding one
+    pub fn hides(&self, role: &str, field: &str) -> bool {
+        let field: Vec<&str> = field.split('.').collect();
+        self.hidden(role)
+            .iter()
+            .any(|path| path.iter().zip(&field).all(|(hidden, part)| hidden == part))
+    }
+
+    // For endpoints that take a field name: redaction only reaches fields
+    // inside events, so a hidden field used as a group key or filter would
+    // come back as a plain value. Refuse it before anything is read.
+    pub fn check_field(&self, request: &HttpRequest, field: &str) -> Result<(), HttpResponse> {
+        self.check_fields(request, [field])
+    }
+
+    // Same for every field a set of filters reads
+    pub fn check_fields<'a>(
+        &self,
+        request: &HttpRequest,
+        fields: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), HttpResponse> {
+        let role = live::caller_role(request);
+        match self.refusal(&role, fields) {
+            None => Ok(()),
+            Some(message) => Err(HttpResponse::Forbidden().json(serde_json::json!({
+                "status": "error",
+                "message": message
+            }))),
+        }
+    }
+
+    // Why filtering on `fields` is refused to the role, if it is
+    pub fn refusal<'a>(
+        &self,
+        role: &str,
+        fields: impl IntoIterator<Item = &'a str>,
+    ) -> Option<String> {
+        let field = fields.into_iter().find(|field| self.hides(role, field))?;
+        Some(format!("field '{}' is hidden from role '{}'", field, role))
+    }
+
+    // Remove the role's hidden fields from every event and host in `value`,
+    // however deeply it is nested in the response
+    pub fn redact(&self, role: &str, value: &mut serde_json::Value) {
+        let hidden = self.hidden(role);
+        if !hidden.is_empty() {
+            redact_events(hidden, value);
+        }
+    }
+}
+
+// Anything with the core event fields is treated as an event
+pub fn is_event(object: &serde_json::Map<String, serde_json::Value>) -> bool {
+    object.contains_key("category")
+        && object.contains_key("event_type")
+        && object.contains_key("data")
+}
+
+// Per-host summaries (stats nodes, node status, registered agents) repeat
+// event fields such as `host_ipv4`, so they are redacted like events.
+// Agents call the host field `hostname`.
+fn is_host(object: &serde_json::Map<String, serde_json::Value>) -> bool {
+    object.contains_key("host") || object.contains_key("hostname")
+}
+
+fn redact_events(hidden: &[Vec<String>], value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            if is_event(object) || is_host(object) {
+                for path in hidden {
+                    remove_path(object, path);
+                    if path == &["host"] {
+                        object.remove("hostname");
+                    }
+                }
+            }
+            for child in object.values_mut() {
+                redact_events(hidden, child);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                redact_events(hidden, item);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove_path(object: &mut serde_json::Map<String, serde_json::Value>, path: &[String]) {
+    match path {
+        [] => {}
+        [last] => {
+            object.remove(last);
+        }
+        [first, rest @ ..] => {
+            if let Some(serde_json::Value::Object(child)) = object.get_mut(first) {
+                remove_path(child, rest);
+            }
+        }
+    }
+}
+
+// Middleware: resolves the caller, then redacts hidden fields from JSON
+// responses. Doing it on the serialized body covers every endpoint,
+// including plugin routes, without each handler having to remember.
+pub async fn enforce(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(data) = request.app_data::<web::Data<AppState>>().cloned() else {
+        return Ok(next.call(request).await?.map_into_boxed_body());
+    };
+    // A bearer token, then a dashboard session; without either, requests
+    // that need a login stop here
+    let principal = match data
+        .access
+        .token_principal(&request)
+        .or_else(|| data.sessions.principal(request.request()))
+    {
+        Some(principal) => principal,
+        None => {
+            if let Some(response) = data.sessions.refuse(&request) {
+                return Ok(request.into_response(response).map_into_boxed_body());
+            }
+            data.access.anonymous()
+        }
+    };
+    let role = principal.role.clone();
+    request.extensions_mut().insert(principal);
+
+    let response = next.call(request).await?.map_into_boxed_body();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json || data.access.hidden(&role).is_empty() {
+        return Ok(response);
+    }
+
+    let (request, response) = response.into_parts();
+    let (response, payload) = response.into_parts();
+    let bytes = body::to_bytes(payload)
+        .await
+        .map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()))?;
+    let bytes = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(mut value) => {
+            data.access.redact(&role, &mut value);
+            serde_json::to_vec(&value).unwrap_or_default().into()
+        }
+        Err(_) => bytes,
+    };
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(bytes)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn access() -> Access {
+        Access::new(
+            serde_json::from_value(json!({
+                "default_role": "viewer",
+                "tokens": [{"name": "ops", "token": "adm", "role": "admin"}],
+                "hidden_fields": {"viewer": ["host_ipv4", "data.command_line"]},
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn event() -> serde_json::Value {
+        json!({
+            "category": "process",
+            "event_type": "exec",
+            "host": "web01",
+            "host_ipv4": "10.1.2.3",
+            "data": {"command_line": "secret", "user": "bob"},
+        })
+    }
+
+    #[test]
+    fn hides_fields_and_what_holds_them() {
+        let access = access();
+        assert!(access.hides("viewer", "host_ipv4"));
+        assert!(access.hides("viewer", "data.command_line"));
+        // Inside a hidden field, and the object around one
+        assert!(access.hides("viewer", "host_ipv4.octet"));
+        assert!(access.hides("viewer", "data"));
+        assert!(!access.hides("viewer", "data.user"));
+        assert!(!access.hides("viewer", "host"));
+        assert!(!access.hides("admin", "host_ipv4"));
+        assert!(!access.hides("", "host_ipv4"));
+    }
+
+    #[test]
+    fn refusal_names_the_first_hidden_field() {
+        let access = access();
+        assert_eq!(
+            access.refusal("viewer", ["category", "host_ipv4", "data"]),
+            Some("field 'host_ipv4' is hidden from role 'viewer'".to_string())
+        );
+        assert_eq!(access.refusal("viewer", ["category", "data.user"]), None);
+        assert_eq!(access.refusal("admin", ["host_ipv4"]), None);
+    }
+
+    #[test]
+    fn redacts_events_however_nested() {
+        let access = access();
+        let mut value = json!({
+            "events": [event()],
+            "groups": [{"key": "web01", "events": [event()]}],
+        });
+        access.redact("viewer", &mut value);
+        for event in [&value["events"][0], &value["groups"][0]["events"][0]] {
+            assert_eq!(event.get("host_ipv4"), None);
+            assert_eq!(event["data"], json!({"user": "bob"}));
+            assert_eq!(event["host"], "web01");
+        }
+
+        let mut unredacted = json!([event()]);
+        access.redact("admin", &mut unredacted);
+        assert_eq!(unredacted, json!([event()]));
+    }
+
+    #[test]
+    fn redacts_host_entries_but_not_other_objects() {
+        let access = Access::new(
+            serde_json::from_value(json!({
+                "hidden_fields": {"viewer": ["host", "host_ipv4"]},
+            }))
+            .unwrap(),
+        );
+        let mut value = json!({
+            "nodes": [{"host": "web01", "host_ipv4": "10.1.2.3", "total_events": 3}],
+            "agents": [{"hostname": "web01", "host_ipv4": "10.1.2.3", "agent_id": "a1"}],
+            "limits": {"host_ipv4": "kept", "max": 10},
+        });
+        access.redact("viewer", &mut value);
+        assert_eq!(value["nodes"][0], json!({"total_events": 3}));
+        assert_eq!(value["agents"][0], json!({"agent_id": "a1"}));
+        assert_eq!(value["limits"], json!({"host_ipv4": "kept", "max": 10}));
+    }
+}
@@ -0,0 +1,485 @@
+// Agents that registered with the server, whether or not they have sent
+// anything since. Registration hands out an ID and a token; the token lets
+// the agent update or remove its own entry, and the dashboard lists every
+// registered host so a silent one shows up as offline rather than missing.
+use crate::api_keys::ApiKeys;
+use crate::AppState;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use base64::Engine;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+pub const TOKEN_HEADER: &str = "X-Loglumen-Agent-Token";
+const MAX_HOSTNAME: usize = 255;
+const MAX_TAGS: usize = 32;
+const MAX_TAG: usize = 64;
+
+// `[agents]` section
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AgentsConfig {
+    // Registrations are kept here (token hashes only); without it they last
+    // until the server restarts
+    file: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Agent {
+    pub id: String,
+    pub hostname: String,
+    pub host_ipv4: String,
+    pub os: String,
+    pub agent_version: String,
+    pub tags: Vec<String>,
+    registered_at: String,
+    updated_at: String,
+    // Hex SHA-256 of the token, which is only shown at registration
+    #[serde(skip)]
+    token_hash: String,
+}
+
+// What the file holds; `Agent` leaves the hash out of API responses
+#[derive(Serialize, Deserialize)]
+struct StoredAgent {
+    #[serde(flatten)]
+    agent: Agent,
+    token_hash: String,
+}
+
+pub struct AgentRegistry {
+    file: Option<String>,
+    agents: RwLock<Vec<Agent>>,
+}
+
+fn hash(token: &str) -> String {
+    Sha256::digest(token.trim().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn error(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+fn random(bytes: usize) -> std::result::Result<Vec<u8>, HttpResponse> {
+    let mut buffer = vec![0u8; bytes];
+    getrandom::getrandom(&mut buffer).map_err(|err| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not generate a token: {}", err),
+        )
+    })?;
+    Ok(buffer)
+}
+
+// Trimmed, without empties or repeats, within the limits
+fn clean_tags(tags: Vec<String>) -> std::result::Result<Vec<String>, String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_string();
+        if tag.len() > MAX_TAG {
+            return Err(format!("tags are at most {} bytes", MAX_TAG));
+        }
+        if !tag.is_empty() && !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    if cleaned.len() > MAX_TAGS {
+        return Err(format!("an agent has at most {} tags", MAX_TAGS));
+    }
+    Ok(cleaned)
+}
+
+fn clean_hostname(hostname: &str) -> std::result::Result<String, String> {
+    let hostname = hostname.trim();
+    if hostname.is_empty() {
+        return Err("an agent needs a `hostname`".to_string());
+    }
+    if hostname.len() > MAX_HOSTNAME {
+        return Err(format!("hostnames are at most {} bytes", MAX_HOSTNAME));
+    }
+    Ok(hostname.to_string())
+}
+
+impl AgentRegistry {
+    pub fn new(config: AgentsConfig) -> Self {
+        let mut agents = Vec::new();
+        if let Some(file) = &config.file {
+            match std::fs::read_to_string(file) {
+                Ok(contents) => match serde_json::from_str::<Vec<StoredAgent>>(&contents) {
+                    Ok(stored) => {
+                        agents.extend(stored.into_iter().map(|stored| Agent {
+                            token_hash: stored.token_hash,
+                            ..stored.agent
+                        }));
+                        log_line!(
+                            "CONFIG",
+                            "Loaded {} registered agents from {}",
+                            agents.len(),
+                            file
+                        );
+                    }
+                    Err(err) => log_line!(
+                        "ERROR",
+                        "Ignoring unreadable agent registry {}: {}",
+                        file,
+                        err
+                    ),
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => log_line!("ERROR", "Could not read agent registry {}: {}", file, err),
+            }
+        }
+        AgentRegistry {
+            file: config.file,
+            agents: RwLock::new(agents),
+        }
+    }
+
+    // Registered agents by hostname, for joining against the event tally
+    pub fn by_host(&self) -> HashMap<String, Agent> {
+        self.agents
+            .read()
+            .iter()
+            .map(|agent| (agent.hostname.clone(), agent.clone()))
+            .collect()
+    }
+
+    // Whether the request carries the token of the agent registered as
+    // `hostname`; None when no agent has registered that hostname
+    pub fn token_matches(&self, request: &HttpRequest, hostname: &str) -> Option<bool> {
+        let token = request
+            .headers()
+            .get(TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok());
+        self.agents
+            .read()
+            .iter()
+            .find(|agent| agent.hostname == hostname)
+            .map(|agent| token.is_some_and(|token| hash(token) == agent.token_hash))
+    }
+
+    // The agent's own token, or an admin by `[api_keys] admin_roles`, may
+    // change an entry. Returns who is making the change.
+    fn authorize(
+        &self,
+        request: &HttpRequest,
+        agent: &Agent,
+        api_keys: &ApiKeys,
+    ) -> std::result::Result<String, HttpResponse> {
+        let token = request
+            .headers()
+            .get(TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok());
+        if token.is_some_and(|token| hash(token) == agent.token_hash) {
+            return Ok(agent.hostname.clone());
+        }
+        api_keys.authorize_admin(request).map_err(|_| {
+            error(
+                StatusCode::FORBIDDEN,
+                format!(
+                    "changing an agent needs its token in {} or a login or [access] token with role {}",
+                    TOKEN_HEADER,
+                    api_keys.admin_roles().join(" or ")
+                ),
+            )
+        })
+    }
+
+    // Write the registry to the file, through a temp file like backups
+    fn save(&self, agents: &[Agent]) -> std::result::Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let stored: Vec<StoredAgent> = agents
+            .iter()
+            .map(|agent| StoredAgent {
+                agent: agent.clone(),
+                token_hash: agent.token_hash.clone(),
+            })
+            .collect();
+        let temp_path = format!("{}.tmp", file);
+        let json = serde_json::to_vec_pretty(&stored).map_err(|err| err.to_string())?;
+        std::fs::write(&temp_path, json).map_err(|err| err.to_string())?;
+        std::fs::rename(&temp_path, file).map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Registration {
+    hostname: String,
+    // Defaults to the address the registration came from
+    #[serde(default)]
+    host_ipv4: Option<String>,
+    #[serde(default)]
+    os: String,
+    #[serde(default)]
+    agent_version: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// POST /api/agents - Register an agent. Takes the same API key as
+// /api/events; the token in the response is the only time it is shown.
+pub async fn register(
+    request: HttpRequest,
+    body: web::Json<Registration>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    let registration = body.into_inner();
+    let hostname = match clean_hostname(&registration.hostname) {
+        Ok(hostname) => hostname,
+        Err(message) => return Ok(error(StatusCode::BAD_REQUEST, message)),
+    };
+    let tags = match clean_tags(registration.tags) {
+        Ok(tags) => tags,
+        Err(message) => return Ok(error(StatusCode::BAD_REQUEST, message)),
+    };
+    let host_ipv4 = registration.host_ipv4.unwrap_or_else(|| {
+        request
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default()
+    });
+    let id = match random(8) {
+        Ok(bytes) => format!(
+            "agt_{}",
+            bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        ),
+        Err(response) => return Ok(response),
+    };
+    let token = match random(24) {
+        Ok(bytes) => format!(
+            "lla_{}",
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+        ),
+        Err(response) => return Ok(response),
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    let agent = Agent {
+        id: id.clone(),
+        hostname: hostname.clone(),
+        host_ipv4,
+        os: registration.os.trim().to_string(),
+        agent_version: registration.agent_version.trim().to_string(),
+        tags,
+        registered_at: now.clone(),
+        updated_at: now,
+        token_hash: hash(&token),
+    };
+
+    let mut agents = data.agents.agents.write();
+    if let Some(existing) = agents.iter().find(|agent| agent.hostname == hostname) {
+        return Ok(error(
+            StatusCode::CONFLICT,
+            format!(
+                "{} is already registered as {}; update it with its token or delete it first",
+                hostname, existing.id
+            ),
+        ));
+    }
+    agents.push(agent.clone());
+    if let Err(err) = data.agents.save(&agents) {
+        agents.pop();
+        return Ok(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the agent registry: {}", err),
+        ));
+    }
+    drop(agents);
+    data.availability.seen(&hostname);
+    log_line!("INFO", "Agent {} registered as {}", hostname, id);
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "status": "success",
+        "id": id,
+        "token": token,
+        "agent": agent,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgentFilter {
+    tag: Option<String>,
+}
+
+// GET /api/agents - Registered agents, optionally only those with ?tag=
+pub async fn list_agents(
+    query: web::Query<AgentFilter>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let agents: Vec<Agent> = data
+        .agents
+        .agents
+        .read()
+        .iter()
+        .filter(|agent| {
+            query
+                .tag
+                .as_ref()
+                .is_none_or(|tag| agent.tags.contains(tag))
+        })
+        .cloned()
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": agents.len(),
+        "agents": agents,
+    })))
+}
+
+fn not_found(id: &str) -> HttpResponse {
+    error(StatusCode::NOT_FOUND, format!("no agent with ID '{}'", id))
+}
+
+// GET /api/agents/{id} - One registered agent
+pub async fn get_agent(id: web::Path<String>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let agents = data.agents.agents.read();
+    Ok(match agents.iter().find(|agent| agent.id == *id) {
+        Some(agent) => HttpResponse::Ok().json(agent),
+        None => not_found(&id),
+    })
+}
+
+// Fields left out keep their value
+#[derive(Debug, Deserialize)]
+pub struct AgentUpdate {
+    hostname: Option<String>,
+    host_ipv4: Option<String>,
+    os: Option<String>,
+    agent_version: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+// PUT /api/agents/{id} - Update an agent, e.g. after an upgrade or a retag
+pub async fn update_agent(
+    request: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<AgentUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let update = body.into_inner();
+    let hostname = match update.hostname.as_deref().map(clean_hostname).transpose() {
+        Ok(hostname) => hostname,
+        Err(message) => return Ok(error(StatusCode::BAD_REQUEST, message)),
+    };
+    let tags = match update.tags.map(clean_tags).transpose() {
+        Ok(tags) => tags,
+        Err(message) => return Ok(error(StatusCode::BAD_REQUEST, message)),
+    };
+
+    let mut agents = data.agents.agents.write();
+    let Some(index) = agents.iter().position(|agent| agent.id == *id) else {
+        return Ok(not_found(&id));
+    };
+    let by = match data
+        .agents
+        .authorize(&request, &agents[index], &data.api_keys)
+    {
+        Ok(by) => by,
+        Err(response) => return Ok(response),
+    };
+    if let Some(hostname) = &hostname {
+        if let Some(other) = agents
+            .iter()
+            .find(|agent| agent.id != *id && agent.hostname == *hostname)
+        {
+            return Ok(error(
+                StatusCode::CONFLICT,
+                format!("{} is already registered as {}", hostname, other.id),
+            ));
+        }
+    }
+    let before = agents[index].clone();
+    let agent = &mut agents[index];
+    if let Some(hostname) = hostname {
+        agent.hostname = hostname;
+    }
+    if let Some(host_ipv4) = update.host_ipv4 {
+        agent.host_ipv4 = host_ipv4.trim().to_string();
+    }
+    if let Some(os) = update.os {
+        agent.os = os.trim().to_string();
+    }
+    if let Some(agent_version) = update.agent_version {
+        agent.agent_version = agent_version.trim().to_string();
+    }
+    if let Some(tags) = tags {
+        agent.tags = tags;
+    }
+    agent.updated_at = chrono::Utc::now().to_rfc3339();
+    let updated = agent.clone();
+    if let Err(err) = data.agents.save(&agents) {
+        agents[index] = before;
+        return Ok(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the agent registry: {}", err),
+        ));
+    }
+    drop(agents);
+    log_line!(
+        "INFO",
+        "Agent {} ({}) updated by {}",
+        updated.hostname,
+        updated.id,
+        by
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "agent": updated,
+    })))
+}
+
+// DELETE /api/agents/{id} - Forget an agent. Its events stay; the host just
+// stops being listed once it has none.
+pub async fn delete_agent(
+    request: HttpRequest,
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut agents = data.agents.agents.write();
+    let Some(index) = agents.iter().position(|agent| agent.id == *id) else {
+        return Ok(not_found(&id));
+    };
+    let by = match data
+        .agents
+        .authorize(&request, &agents[index], &data.api_keys)
+    {
+        Ok(by) => by,
+        Err(response) => return Ok(response),
+    };
+    let removed = agents.remove(index);
+    if let Err(err) = data.agents.save(&agents) {
+        agents.insert(index, removed);
+        return Ok(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the agent registry: {}", err),
+        ));
+    }
+    drop(agents);
+    log_line!(
+        "INFO",
+        "Agent {} ({}) removed by {}",
+        removed.hostname,
+        removed.id,
+        by
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "id": removed.id,
+    })))
+}
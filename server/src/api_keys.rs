@@ -0,0 +1,510 @@
+use crate::access::Principal;
+use crate::usage::{Counts, KeyUsage, UsageKey};
+use crate::AppState;
+use actix_web::http::header;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Result};
+use base64::Engine;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// `[[api_keys.keys]]` entry
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyConfig {
+    name: String,
+    key: String,
+}
+
+// `[api_keys]` section. Unknown fields are refused, so `require = true`
+// can't quietly leave ingestion open.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiKeysConfig {
+    // Reject POST /api/events without a valid key
+    required: bool,
+    keys: Vec<KeyConfig>,
+    // Keys minted through the admin API are kept here (hashes only); without
+    // it they last until the server restarts
+    file: Option<String>,
+    // `[access]` roles allowed to mint and revoke keys
+    admin_roles: Vec<String>,
+}
+
+impl Default for ApiKeysConfig {
+    fn default() -> Self {
+        ApiKeysConfig {
+            required: false,
+            keys: Vec::new(),
+            file: None,
+            admin_roles: vec!["admin".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    name: String,
+    // Hex SHA-256 of the key; the key itself is only shown when minted
+    hash: String,
+    created: String,
+    // Defined in server.toml rather than minted
+    #[serde(skip)]
+    configured: bool,
+}
+
+pub struct ApiKeys {
+    required: bool,
+    file: Option<String>,
+    admin_roles: Vec<String>,
+    keys: RwLock<Vec<StoredKey>>,
+    usage: KeyUsage,
+}
+
+fn hash(key: &str) -> String {
+    Sha256::digest(key.trim().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn error(status: actix_web::http::StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+impl ApiKeys {
+    pub fn new(config: ApiKeysConfig, usage: KeyUsage) -> Self {
+        let mut keys: Vec<StoredKey> = config
+            .keys
+            .into_iter()
+            .map(|entry| StoredKey {
+                name: entry.name,
+                hash: hash(&entry.key),
+                created: String::new(),
+                configured: true,
+            })
+            .collect();
+        if let Some(file) = &config.file {
+            match std::fs::read_to_string(file) {
+                Ok(contents) => match serde_json::from_str::<Vec<StoredKey>>(&contents) {
+                    Ok(minted) => keys.extend(minted),
+                    Err(err) => log_line!(
+                        "ERROR",
+                        "Ignoring unreadable API key file {}: {}",
+                        file,
+                        err
+                    ),
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => log_line!("ERROR", "Could not read API key file {}: {}", file, err),
+            }
+        }
+        if config.required {
+            log_line!(
+                "CONFIG",
+                "Event ingestion requires an API key ({} configured)",
+                keys.len()
+            );
+            if keys.is_empty() {
+                log_line!(
+                    "WARN",
+                    "No API keys yet; every POST /api/events is rejected until one is minted"
+                );
+            }
+        }
+        ApiKeys {
+            required: config.required,
+            file: config.file,
+            admin_roles: config.admin_roles,
+            keys: RwLock::new(keys),
+            usage,
+        }
+    }
+
+    pub fn usage(&self) -> &KeyUsage {
+        &self.usage
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.keys.read().iter().any(|key| key.name == name)
+    }
+
+    // Counts an accepted request against its key and remembers the key for
+    // `count_events`
+    fn record(&self, request: &HttpRequest, key: Option<String>) {
+        let bytes = request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        self.usage.add(
+            key.as_deref(),
+            Counts {
+                requests: 1,
+                bytes,
+                events: 0,
+            },
+        );
+        request.extensions_mut().insert(UsageKey(key));
+    }
+
+    // Events a request accepted by `check` or `accepts` turned into
+    pub fn count_events(&self, request: &HttpRequest, events: usize) {
+        self.count(request, 0, events);
+    }
+
+    // Body bytes of a request streamed without a Content-Length
+    pub fn count_bytes(&self, request: &HttpRequest, bytes: usize) {
+        if !request.headers().contains_key(header::CONTENT_LENGTH) {
+            self.count(request, bytes, 0);
+        }
+    }
+
+    fn count(&self, request: &HttpRequest, bytes: usize, events: usize) {
+        let Some(UsageKey(key)) = request.extensions().get::<UsageKey>().cloned() else {
+            return;
+        };
+        self.usage.add(
+            key.as_deref(),
+            Counts {
+                requests: 0,
+                bytes: bytes as u64,
+                events: events as u64,
+            },
+        );
+    }
+
+    // POST /api/events needs a key
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    // `[access]` roles that authorize_admin lets through
+    pub fn admin_roles(&self) -> &[String] {
+        &self.admin_roles
+    }
+
+    // Name of the key presented as `Authorization: Bearer <key>`, if valid
+    fn identify(&self, request: &HttpRequest) -> Option<String> {
+        let presented = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))?;
+        self.name_of(presented)
+    }
+
+    fn name_of(&self, presented: &str) -> Option<String> {
+        let presented = hash(presented);
+        self.keys
+            .read()
+            .iter()
+            .find(|key| key.hash == presented)
+            .map(|key| key.name.clone())
+    }
+
+    // For inputs that carry the key some other way than `Bearer` (Splunk
+    // HEC's `Authorization: Splunk <token>`)
+    pub fn accepts(&self, request: &HttpRequest, presented: Option<&str>) -> bool {
+        let key = presented.and_then(|key| self.name_of(key));
+        let accepted = !self.required || key.is_some();
+        if accepted {
+            self.record(request, key);
+        }
+        accepted
+    }
+
+    // Called by the ingest handler before it reads anything
+    pub fn check(&self, request: &HttpRequest) -> std::result::Result<(), HttpResponse> {
+        let key = self.identify(request);
+        if !self.required || key.is_some() {
+            self.record(request, key);
+            return Ok(());
+        }
+        let peer = request
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        log_line!(
+            "WARN",
+            "Rejected events from {} without a valid API key",
+            peer
+        );
+        Err(error(
+            actix_web::http::StatusCode::UNAUTHORIZED,
+            "missing or invalid API key".to_string(),
+        ))
+    }
+
+    pub fn authorize_admin(
+        &self,
+        request: &HttpRequest,
+    ) -> std::result::Result<String, HttpResponse> {
+        let principal = request.extensions().get::<Principal>().cloned();
+        match principal.filter(|principal| self.admin_roles.contains(&principal.role)) {
+            Some(principal) => Ok(principal.name),
+            None => Err(error(
+                actix_web::http::StatusCode::FORBIDDEN,
+                format!(
+                    "this needs a login or [access] token with role {}",
+                    self.admin_roles.join(" or ")
+                ),
+            )),
+        }
+    }
+
+    // Write minted keys to the file, through a temp file like backups
+    fn save(&self, keys: &[StoredKey]) -> std::result::Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let minted: Vec<&StoredKey> = keys.iter().filter(|key| !key.configured).collect();
+        let temp_path = format!("{}.tmp", file);
+        let json = serde_json::to_vec_pretty(&minted).map_err(|err| err.to_string())?;
+        std::fs::write(&temp_path, json).map_err(|err| err.to_string())?;
+        std::fs::rename(&temp_path, file).map_err(|err| err.to_string())
+    }
+}
+
+// GET /api/admin/api-keys - Key names and when they were minted; never the
+// keys themselves
+pub async fn list_keys(request: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let keys: Vec<serde_json::Value> = data
+        .api_keys
+        .keys
+        .read()
+        .iter()
+        .map(|key| {
+            serde_json::json!({
+                "name": key.name,
+                "id": &key.hash[..12],
+                "created": (!key.created.is_empty()).then_some(&key.created),
+                "source": if key.configured { "config" } else { "minted" },
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "required": data.api_keys.required,
+        "keys": keys,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MintRequest {
+    name: String,
+}
+
+// POST /api/admin/api-keys - Mint a key for an agent. The response is the
+// only time the key is shown.
+pub async fn mint_key(
+    request: HttpRequest,
+    body: web::Json<MintRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let by = match data.api_keys.authorize_admin(&request) {
+        Ok(by) => by,
+        Err(response) => return Ok(response),
+    };
+    let name = body.into_inner().name.trim().to_string();
+    if name.is_empty() {
+        return Ok(error(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            "a key needs a `name`".to_string(),
+        ));
+    }
+    let mut secret = [0u8; 24];
+    if let Err(err) = getrandom::getrandom(&mut secret) {
+        return Ok(error(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not generate a key: {}", err),
+        ));
+    }
+    let key = format!(
+        "ll_{}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret)
+    );
+    let stored = StoredKey {
+        name: name.clone(),
+        hash: hash(&key),
+        created: chrono::Utc::now().to_rfc3339(),
+        configured: false,
+    };
+    let id = stored.hash[..12].to_string();
+
+    let mut keys = data.api_keys.keys.write();
+    keys.push(stored);
+    if let Err(err) = data.api_keys.save(&keys) {
+        keys.pop();
+        return Ok(error(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the key: {}", err),
+        ));
+    }
+    drop(keys);
+    log_line!("INFO", "API key '{}' ({}) minted by {}", name, id, by);
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "status": "success",
+        "name": name,
+        "id": id,
+        "key": key,
+    })))
+}
+
+// DELETE /api/admin/api-keys/{name} - Revoke every minted key with this name.
+// Keys from server.toml are removed there.
+pub async fn revoke_key(
+    request: HttpRequest,
+    name: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let by = match data.api_keys.authorize_admin(&request) {
+        Ok(by) => by,
+        Err(response) => return Ok(response),
+    };
+    let name = name.into_inner();
+    let mut keys = data.api_keys.keys.write();
+    let before = keys.clone();
+    let configured = keys.iter().any(|key| key.configured && key.name == name);
+    keys.retain(|key| key.configured || key.name != name);
+    let revoked = before.len() - keys.len();
+    if revoked == 0 {
+        return Ok(if configured {
+            error(
+                actix_web::http::StatusCode::BAD_REQUEST,
+                format!("key '{}' is defined in server.toml; remove it there", name),
+            )
+        } else {
+            error(
+                actix_web::http::StatusCode::NOT_FOUND,
+                format!("no API key named '{}'", name),
+            )
+        });
+    }
+    if let Err(err) = data.api_keys.save(&keys) {
+        *keys = before;
+        return Ok(error(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the key file: {}", err),
+        ));
+    }
+    drop(keys);
+    log_line!("INFO", "API key '{}' revoked by {}", name, by);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "name": name,
+        "revoked": revoked,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn api_keys(config: serde_json::Value) -> ApiKeys {
+        ApiKeys::new(
+            serde_json::from_value(config).unwrap(),
+            KeyUsage::new(Default::default(), crate::clock::system()),
+        )
+    }
+
+    fn bearer(key: &str) -> HttpRequest {
+        TestRequest::default()
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", key)))
+            .to_http_request()
+    }
+
+    fn as_role(role: &str) -> HttpRequest {
+        let request = TestRequest::default().to_http_request();
+        request.extensions_mut().insert(Principal {
+            name: "alice".to_string(),
+            role: role.to_string(),
+        });
+        request
+    }
+
+    #[test]
+    fn keys_are_kept_as_sha256_hex() {
+        // The FIPS 180-2 "abc" test vector
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash(" abc\n"), hash("abc"));
+    }
+
+    #[test]
+    fn only_known_keys_pass_when_required() {
+        let keys = api_keys(serde_json::json!({
+            "required": true,
+            "keys": [{"name": "web", "key": "ll_web"}],
+        }));
+        assert!(keys.check(&bearer("ll_web")).is_ok());
+        assert_eq!(keys.name_of("ll_web").as_deref(), Some("web"));
+        let refused = keys.check(&bearer("ll_other")).unwrap_err();
+        assert_eq!(refused.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert!(keys
+            .check(&TestRequest::default().to_http_request())
+            .is_err());
+        assert!(!keys.accepts(&TestRequest::default().to_http_request(), None));
+        assert!(keys.accepts(&TestRequest::default().to_http_request(), Some("ll_web")));
+
+        // Without `required` anything goes, known key or not
+        let open = api_keys(serde_json::json!({}));
+        assert!(open.check(&bearer("ll_other")).is_ok());
+        assert!(open
+            .check(&TestRequest::default().to_http_request())
+            .is_ok());
+    }
+
+    #[test]
+    fn admin_needs_one_of_the_admin_roles() {
+        let keys = api_keys(serde_json::json!({}));
+        assert_eq!(
+            keys.authorize_admin(&as_role("admin")).ok().as_deref(),
+            Some("alice")
+        );
+        let refused = keys.authorize_admin(&as_role("viewer")).unwrap_err();
+        assert_eq!(refused.status(), actix_web::http::StatusCode::FORBIDDEN);
+        // Requests nobody resolved a caller for are never let through
+        assert!(keys
+            .authorize_admin(&TestRequest::default().to_http_request())
+            .is_err());
+
+        let keys = api_keys(serde_json::json!({"admin_roles": ["ops", "security"]}));
+        assert!(keys.authorize_admin(&as_role("security")).is_ok());
+        assert!(keys.authorize_admin(&as_role("admin")).is_err());
+    }
+
+    #[test]
+    fn only_minted_keys_are_saved() {
+        let file = std::env::temp_dir().join(format!("loglumen-keys-{}.json", std::process::id()));
+        let file_name = file.to_string_lossy().to_string();
+        let keys = api_keys(serde_json::json!({
+            "keys": [{"name": "web", "key": "ll_web"}],
+            "file": file_name,
+        }));
+        let mut stored = keys.keys.read().clone();
+        stored.push(StoredKey {
+            name: "db".to_string(),
+            hash: hash("ll_db"),
+            created: "2026-10-15T10:00:00Z".to_string(),
+            configured: false,
+        });
+        keys.save(&stored).unwrap();
+
+        let reloaded = api_keys(serde_json::json!({"file": file_name}));
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(reloaded.name_of("ll_db").as_deref(), Some("db"));
+        assert_eq!(reloaded.name_of("ll_web"), None);
+    }
+}
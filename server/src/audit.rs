@@ -0,0 +1,281 @@
+use crate::access::{is_event, Principal};
+use crate::{AppState, TimeRange};
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+// `[audit]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    // Record every read query
+    enabled: bool,
+    // Entries kept in memory for /api/admin/audit
+    max_entries: usize,
+    // Also append each entry to this NDJSON file, for retention beyond
+    // restarts
+    path: Option<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            enabled: false,
+            max_entries: 10_000,
+            path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AuditEntry {
+    time: chrono::DateTime<chrono::Utc>,
+    who: String,
+    role: String,
+    peer: Option<String>,
+    method: String,
+    path: String,
+    // The raw query string, i.e. the filter that was applied
+    query: String,
+    // The request body, for queries that send their filter that way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    status: u16,
+    rows: usize,
+}
+
+pub struct Audit {
+    enabled: bool,
+    max_entries: usize,
+    entries: Mutex<VecDeque<AuditEntry>>,
+    file: Option<Mutex<File>>,
+}
+
+impl Audit {
+    pub fn new(config: AuditConfig) -> Self {
+        let path = config.path.as_deref().filter(|_| config.enabled);
+        let file =
+            path.and_then(
+                |path| match OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => Some(Mutex::new(file)),
+                    Err(err) => {
+                        log_line!("WARN", "Cannot open audit log {}: {}", path, err);
+                        None
+                    }
+                },
+            );
+        if config.enabled {
+            log_line!(
+                "CONFIG",
+                "Query audit enabled{}",
+                path.map(|path| format!(", appending to {}", path))
+                    .unwrap_or_default()
+            );
+        }
+
+        Audit {
+            enabled: config.enabled,
+            max_entries: config.max_entries.max(1),
+            entries: Mutex::new(VecDeque::new()),
+            file,
+        }
+    }
+
+    fn push(&self, entry: AuditEntry) {
+        if let Some(file) = &self.file {
+            if let Ok(line) = serde_json::to_string(&entry) {
+                if let Err(err) = writeln!(file.lock(), "{}", line) {
+                    log_line!("WARN", "Audit log write failed: {}", err);
+                }
+            }
+        }
+        let mut entries = self.entries.lock();
+        if entries.len() == self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+// Events in the response, or for endpoints that return something else the
+// length of their main list
+fn count_rows(value: &serde_json::Value) -> usize {
+    fn events(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::Object(object) if is_event(object) => 1,
+            serde_json::Value::Object(object) => object.values().map(events).sum(),
+            serde_json::Value::Array(items) => items.iter().map(events).sum(),
+            _ => 0,
+        }
+    }
+
+    match events(value) {
+        0 => match value {
+            serde_json::Value::Array(items) => items.len(),
+            serde_json::Value::Object(object) => object
+                .values()
+                .find_map(|child| child.as_array().map(Vec::len))
+                .unwrap_or_default(),
+            _ => 0,
+        },
+        rows => rows,
+    }
+}
+
+// Route middleware: records who ran which read query and how many rows it
+// returned. The route table puts it on the routes added with `add_query`;
+// `access::enforce` has identified the caller by then.
+pub async fn record(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let data = request.app_data::<web::Data<AppState>>().cloned();
+    let Some(data) = data.filter(|data| data.audit.enabled) else {
+        return Ok(next.call(request).await?.map_into_boxed_body());
+    };
+
+    // A POST query's filter is its body: read it, then hand it back for
+    // the handler. Compressed bodies are left alone, as the handler
+    // decompresses them itself.
+    let body = if request.method() != Method::GET
+        && !request.headers().contains_key(header::CONTENT_ENCODING)
+    {
+        let bytes = request.extract::<web::Bytes>().await?;
+        let (_, mut payload) = actix_http::h1::Payload::create(true);
+        payload.unread_data(bytes.clone());
+        request.set_payload(payload.into());
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    } else {
+        None
+    };
+
+    let (who, role) = request
+        .extensions()
+        .get::<Principal>()
+        .map(|principal| (principal.name.clone(), principal.role.clone()))
+        .unwrap_or_default();
+    let peer = request.peer_addr().map(|addr| addr.ip().to_string());
+    let method = request.method().to_string();
+    let path = request.path().to_string();
+    let query = request.query_string().to_string();
+
+    let response = next.call(request).await?.map_into_boxed_body();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let mut entry = AuditEntry {
+        time: chrono::Utc::now(),
+        who,
+        role,
+        peer,
+        method,
+        path,
+        query,
+        body,
+        status: response.status().as_u16(),
+        rows: 0,
+    };
+    if !is_json {
+        data.audit.push(entry);
+        return Ok(response);
+    }
+
+    let (request, response) = response.into_parts();
+    let (response, payload) = response.into_parts();
+    let bytes = body::to_bytes(payload)
+        .await
+        .map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()))?;
+    if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+        entry.rows = count_rows(&value);
+    }
+    data.audit.push(entry);
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(bytes)),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    who: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    500
+}
+
+#[derive(Debug, Default, Serialize)]
+struct AccessSummary {
+    queries: usize,
+    rows: usize,
+    last_query: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// GET /api/admin/audit - Recorded read queries, newest first, with totals
+// per caller over the same filter. Needs an admin role.
+pub async fn get_audit(
+    request: HttpRequest,
+    query: web::Query<AuditQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    if !data.audit.enabled {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "query audit is disabled; set [audit] enabled = true"
+        })));
+    }
+    let range = match TimeRange::parse(query.since.as_deref(), query.until.as_deref()) {
+        Ok(range) => range,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": err
+            })))
+        }
+    };
+
+    let entries = data.audit.entries.lock();
+    let mut by_caller: BTreeMap<String, AccessSummary> = BTreeMap::new();
+    let mut total = 0;
+    let mut listed = Vec::new();
+    for entry in entries.iter().rev() {
+        if query.who.as_deref().is_some_and(|who| entry.who != who) {
+            continue;
+        }
+        if range.since.is_some_and(|since| entry.time < since)
+            || range.until.is_some_and(|until| entry.time > until)
+        {
+            continue;
+        }
+        total += 1;
+        let summary = by_caller.entry(entry.who.clone()).or_default();
+        summary.queries += 1;
+        summary.rows += entry.rows;
+        summary.last_query.get_or_insert(entry.time);
+        if listed.len() < query.limit {
+            listed.push(entry.clone());
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total": total,
+        "callers": by_caller,
+        "entries": listed,
+    })))
+}
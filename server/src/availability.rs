@@ -0,0 +1,305 @@
+use crate::clock::SharedClock;
+use crate::config;
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+const DEFAULT_WINDOWS: &str = "1h,24h,7d,30d";
+
+// `[availability]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AvailabilityConfig {
+    // A host counts as up for this long after each report. Set it to a few
+    // collection intervals so one slow cycle is not an outage.
+    #[serde(deserialize_with = "config::de_duration")]
+    grace: Duration,
+    // Up periods older than this are forgotten
+    #[serde(deserialize_with = "config::de_duration")]
+    retention: Duration,
+    // A node not heard from for this long is stale, and past offline_after
+    // offline
+    #[serde(deserialize_with = "config::de_duration")]
+    stale_after: Duration,
+    #[serde(deserialize_with = "config::de_duration")]
+    offline_after: Duration,
+}
+
+impl Default for AvailabilityConfig {
+    fn default() -> Self {
+        AvailabilityConfig {
+            grace: Duration::from_secs(3 * 60),
+            retention: Duration::from_secs(30 * 24 * 60 * 60),
+            stale_after: Duration::from_secs(3 * 60),
+            offline_after: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+// Merged up periods of one host, oldest first
+struct Uptime {
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    periods: VecDeque<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+// How long since a node was last heard from, for the dashboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liveness {
+    Online,
+    Stale,
+    Offline,
+}
+
+pub struct Availability {
+    hosts: RwLock<HashMap<String, Uptime>>,
+    grace: chrono::Duration,
+    retention: chrono::Duration,
+    stale_after: chrono::Duration,
+    offline_after: chrono::Duration,
+    clock: SharedClock,
+}
+
+impl Availability {
+    pub fn new(config: AvailabilityConfig, clock: SharedClock) -> Self {
+        let stale_after =
+            chrono::Duration::from_std(config.stale_after).unwrap_or(chrono::Duration::minutes(3));
+        let mut offline_after = chrono::Duration::from_std(config.offline_after)
+            .unwrap_or(chrono::Duration::minutes(15));
+        if offline_after < stale_after {
+            log_line!(
+                "WARN",
+                "[availability] offline_after is shorter than stale_after; nodes go straight to offline"
+            );
+            offline_after = stale_after;
+        }
+        Availability {
+            hosts: RwLock::new(HashMap::new()),
+            grace: chrono::Duration::from_std(config.grace).unwrap_or(chrono::Duration::minutes(3)),
+            retention: chrono::Duration::from_std(config.retention)
+                .unwrap_or(chrono::Duration::days(30)),
+            stale_after,
+            offline_after,
+            clock,
+        }
+    }
+
+    // When a host last reported and what that makes it now. Hosts that
+    // haven't reported since the server started fall back to the time of
+    // their latest stored event.
+    pub fn liveness(
+        &self,
+        host: &str,
+        last_event_time: Option<&str>,
+    ) -> (Liveness, Option<DateTime<Utc>>) {
+        let last_seen = self
+            .hosts
+            .read()
+            .get(host)
+            .map(|uptime| uptime.last_seen)
+            .or_else(|| {
+                last_event_time
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| time.with_timezone(&Utc))
+            });
+        let Some(last_seen) = last_seen else {
+            return (Liveness::Offline, None);
+        };
+        let silent = self.clock.now() - last_seen;
+        let liveness = if silent <= self.stale_after {
+            Liveness::Online
+        } else if silent <= self.offline_after {
+            Liveness::Stale
+        } else {
+            Liveness::Offline
+        };
+        (liveness, Some(last_seen))
+    }
+
+    // Called for batches agents post themselves, so events the server makes
+    // up on a host's behalf (alerts, feeds) never count as the host being up
+    pub fn observe(&self, events: &[Event]) {
+        let mut hosts: Vec<&str> = events.iter().map(|event| event.host.as_str()).collect();
+        hosts.sort_unstable();
+        hosts.dedup();
+        for host in hosts {
+            self.seen(host);
+        }
+    }
+
+    pub fn seen(&self, host: &str) {
+        let now = self.clock.now();
+        let mut hosts = self.hosts.write();
+        let uptime = hosts.entry(host.to_string()).or_insert_with(|| Uptime {
+            first_seen: now,
+            last_seen: now,
+            periods: VecDeque::new(),
+        });
+        uptime.last_seen = now;
+        match uptime.periods.back_mut() {
+            Some((_, end)) if now <= *end => *end = now + self.grace,
+            _ => uptime.periods.push_back((now, now + self.grace)),
+        }
+        let horizon = now - self.retention;
+        while uptime
+            .periods
+            .front()
+            .is_some_and(|(_, end)| *end < horizon)
+        {
+            uptime.periods.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Outage {
+    start: String,
+    end: Option<String>,
+    duration_seconds: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct WindowReport {
+    window: String,
+    // Start of the measured span; later than `now - window` for new hosts
+    from: String,
+    availability_percent: f64,
+    downtime_seconds: i64,
+    outages: Vec<Outage>,
+}
+
+fn report(
+    uptime: &Uptime,
+    label: &str,
+    window: chrono::Duration,
+    now: DateTime<Utc>,
+) -> WindowReport {
+    let from = (now - window).max(uptime.first_seen);
+    let span = (now - from).num_milliseconds().max(1);
+
+    let mut up = 0;
+    let mut outages = Vec::new();
+    let mut cursor = from;
+    for (start, end) in &uptime.periods {
+        let (start, end) = ((*start).max(from), (*end).min(now));
+        if end <= start {
+            continue;
+        }
+        if start > cursor {
+            outages.push(Outage {
+                start: cursor.to_rfc3339(),
+                end: Some(start.to_rfc3339()),
+                duration_seconds: (start - cursor).num_seconds(),
+            });
+        }
+        up += (end - start).num_milliseconds();
+        cursor = end;
+    }
+    // Still down: the outage is open-ended
+    if cursor < now {
+        outages.push(Outage {
+            start: cursor.to_rfc3339(),
+            end: None,
+            duration_seconds: (now - cursor).num_seconds(),
+        });
+    }
+
+    WindowReport {
+        window: label.to_string(),
+        from: from.to_rfc3339(),
+        availability_percent: (up as f64 * 10_000.0 / span as f64).round() / 100.0,
+        downtime_seconds: (span - up) / 1000,
+        outages,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Heartbeat {
+    host: String,
+}
+
+// POST /api/heartbeat - An agent reporting in without events, so idle hosts
+// stay online without filling the store with heartbeat events
+pub async fn heartbeat(
+    request: HttpRequest,
+    heartbeat: web::Json<Heartbeat>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    let host = heartbeat.host.trim();
+    if host.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "heartbeat needs a host"
+        })));
+    }
+    data.availability.seen(host);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "server_time": data.availability.clock.now().to_rfc3339(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AvailabilityQuery {
+    // Comma-separated durations, e.g. "24h,7d"
+    windows: Option<String>,
+}
+
+// GET /api/nodes/{host}/availability - Uptime percentage and outages over one
+// or more windows ending now
+pub async fn get_availability(
+    host: web::Path<String>,
+    query: web::Query<AvailabilityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let mut windows = Vec::new();
+    for label in query
+        .windows
+        .as_deref()
+        .unwrap_or(DEFAULT_WINDOWS)
+        .split(',')
+    {
+        let label = label.trim();
+        let Some(window) = config::parse_duration(label)
+            .filter(|window| !window.is_zero())
+            .and_then(|window| chrono::Duration::from_std(window).ok())
+        else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": format!("invalid window '{}'", label)
+            })));
+        };
+        windows.push((label, window));
+    }
+
+    let hosts = data.availability.hosts.read();
+    let Some(uptime) = hosts.get(host.as_str()) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("host '{}' has not reported", host)
+        })));
+    };
+
+    let now = data.availability.clock.now();
+    let up = uptime.periods.back().is_some_and(|(_, end)| *end >= now);
+    let reports: Vec<WindowReport> = windows
+        .into_iter()
+        .map(|(label, window)| report(uptime, label, window, now))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "host": host.as_str(),
+        "status": if up { "up" } else { "down" },
+        "first_seen": uptime.first_seen.to_rfc3339(),
+        "last_seen": uptime.last_seen.to_rfc3339(),
+        "windows": reports,
+    })))
+}
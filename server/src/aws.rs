@@ -0,0 +1,850 @@
+// Built with `--features aws`. Polls CloudWatch Logs and S3 with SigV4
+// requests over the reqwest client the server already has, instead of
+// pulling in the AWS SDK.
+use crate::{config, severity, AppState, Event};
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Characters SigV4 leaves unencoded
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+const OBJECT_KEY: &AsciiSet = &UNRESERVED.remove(b'/');
+// Upper bounds per poll so one busy source can't stall the others
+const MAX_PAGES: usize = 10;
+const MAX_OBJECTS: usize = 100;
+
+// `[[aws.cloudwatch]]` entry
+#[derive(Debug, Deserialize)]
+struct CloudwatchSource {
+    log_group: String,
+    // CloudWatch filter syntax, e.g. "ERROR" or "{ $.level = \"error\" }"
+    #[serde(default)]
+    filter_pattern: Option<String>,
+    #[serde(default = "default_category")]
+    category: String,
+    #[serde(default)]
+    region: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum S3Format {
+    // One event per line; JSON lines have their keys merged into `data`
+    #[default]
+    Lines,
+    // Application Load Balancer access logs
+    Alb,
+    // CloudTrail `{"Records": [...]}` objects
+    Cloudtrail,
+}
+
+// `[[aws.s3]]` entry
+#[derive(Debug, Deserialize)]
+struct S3Source {
+    bucket: String,
+    // Object keys under the prefix must sort by time, as AWS log
+    // deliveries do
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    format: S3Format,
+    #[serde(default = "default_category")]
+    category: String,
+    // Host stored on the events; ALB logs default to the load balancer
+    #[serde(default)]
+    host: Option<String>,
+    // Read objects already in the bucket on the first poll
+    #[serde(default)]
+    backfill: bool,
+    #[serde(default)]
+    region: Option<String>,
+}
+
+fn default_category() -> String {
+    "cloud".to_string()
+}
+
+// `[aws]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AwsConfig {
+    region: String,
+    // Falls back to AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and
+    // AWS_SESSION_TOKEN
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+    session_token: Option<String>,
+    // Base URL replacing the AWS endpoints, e.g. LocalStack or MinIO
+    endpoint: Option<String>,
+    #[serde(deserialize_with = "config::de_duration")]
+    interval: Duration,
+    // Where each source's position is kept across restarts
+    state_file: Option<String>,
+    cloudwatch: Vec<CloudwatchSource>,
+    s3: Vec<S3Source>,
+}
+
+impl Default for AwsConfig {
+    fn default() -> Self {
+        AwsConfig {
+            region: "us-east-1".to_string(),
+            access_key_id: None,
+            secret_access_key: None,
+            session_token: None,
+            endpoint: None,
+            interval: Duration::from_secs(60),
+            state_file: None,
+            cloudwatch: Vec::new(),
+            s3: Vec::new(),
+        }
+    }
+}
+
+impl AwsConfig {
+    pub fn enabled(&self) -> bool {
+        !self.cloudwatch.is_empty() || !self.s3.is_empty()
+    }
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+impl Credentials {
+    // Headers to add to a request for AWS Signature Version 4
+    fn sign(
+        &self,
+        method: &str,
+        url: &reqwest::Url,
+        region: &str,
+        service: &str,
+        extra: &[(&str, &str)],
+        body: &[u8],
+    ) -> Vec<(String, String)> {
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let payload_hash = sha256_hex(body);
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut headers: Vec<(String, String)> = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        for (name, value) in extra {
+            headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+        }
+        headers.sort();
+
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| {
+                (
+                    utf8_percent_encode(&key, UNRESERVED).to_string(),
+                    utf8_percent_encode(&value, UNRESERVED).to_string(),
+                )
+            })
+            .collect();
+        query.sort();
+        let query: Vec<String> = query
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+
+        let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        let signed_headers = signed_headers.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            url.path(),
+            query.join("&"),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+        let secret = format!("AWS4{}", self.secret_access_key);
+        let key = hmac(secret.as_bytes(), date.as_bytes());
+        let key = hmac(&key, region.as_bytes());
+        let key = hmac(&key, service.as_bytes());
+        let key = hmac(&key, b"aws4_request");
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        headers.retain(|(name, _)| name != "host");
+        headers.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        headers
+    }
+}
+
+// Text of every `<tag>` element, with XML entities undone
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        values.push(
+            rest[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &rest[end + close.len()..];
+    }
+    values
+}
+
+// Severity from common level fields of a JSON log line
+fn json_severity(fields: &Map<String, Value>) -> Option<&'static str> {
+    ["level", "severity", "levelname", "log_level"]
+        .iter()
+        .find_map(|key| fields.get(*key).and_then(Value::as_str))
+        .map(severity::normalize)
+}
+
+fn log_event(
+    category: &str,
+    event_type: &str,
+    host: &str,
+    source: &str,
+    time: DateTime<Utc>,
+    message: &str,
+    mut data: Map<String, Value>,
+) -> Event {
+    let mut severity = "info";
+    let mut text = message.trim_end().to_string();
+    if let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(message) {
+        severity = json_severity(&fields).unwrap_or("info");
+        if let Some(msg) = ["message", "msg"]
+            .iter()
+            .find_map(|key| fields.get(*key).and_then(Value::as_str))
+        {
+            text = msg.to_string();
+        }
+        for (key, value) in fields {
+            data.entry(key).or_insert(value);
+        }
+    }
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type: event_type.to_string(),
+        time: time.to_rfc3339(),
+        host: host.to_string(),
+        host_ipv4: String::new(),
+        os: String::new(),
+        source: source.to_string(),
+        severity: severity.to_string(),
+        message: text,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// Split an ALB log line on spaces, keeping "quoted fields" together
+fn alb_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+fn alb_event(source: &S3Source, key: &str, line: &str) -> Option<Event> {
+    const NAMES: [&str; 16] = [
+        "type",
+        "time",
+        "elb",
+        "client",
+        "target",
+        "request_processing_time",
+        "target_processing_time",
+        "response_processing_time",
+        "elb_status_code",
+        "target_status_code",
+        "received_bytes",
+        "sent_bytes",
+        "request",
+        "user_agent",
+        "ssl_cipher",
+        "ssl_protocol",
+    ];
+    let fields = alb_fields(line);
+    if fields.len() < NAMES.len() {
+        return None;
+    }
+    let time = DateTime::parse_from_rfc3339(&fields[1])
+        .ok()?
+        .with_timezone(&Utc);
+    let mut data: Map<String, Value> = NAMES
+        .iter()
+        .zip(&fields)
+        .map(|(name, value)| (name.to_string(), Value::String(value.clone())))
+        .collect();
+    data.insert("object".to_string(), Value::String(key.to_string()));
+    let status: u16 = fields[8].parse().unwrap_or(0);
+    let host = source.host.clone().unwrap_or_else(|| fields[2].clone());
+
+    let mut event = log_event(
+        &source.category,
+        "http_request",
+        &host,
+        "alb",
+        time,
+        &format!("{} {}", fields[12], fields[8]),
+        data,
+    );
+    event.host_ipv4 = fields[3]
+        .rsplit_once(':')
+        .map(|(ip, _)| ip.to_string())
+        .unwrap_or_default();
+    event.severity = match status {
+        500.. => "error",
+        400..=499 => "warning",
+        _ => "info",
+    }
+    .to_string();
+    Some(event)
+}
+
+fn cloudtrail_event(source: &S3Source, key: &str, record: Map<String, Value>) -> Option<Event> {
+    let text = |name: &str| record.get(name).and_then(Value::as_str).map(str::to_string);
+    let time = DateTime::parse_from_rfc3339(&text("eventTime")?)
+        .ok()?
+        .with_timezone(&Utc);
+    let name = text("eventName").unwrap_or_default();
+    let actor = record
+        .get("userIdentity")
+        .and_then(|identity| identity.get("arn"))
+        .and_then(Value::as_str)
+        .unwrap_or("unknown")
+        .to_string();
+    let host = source
+        .host
+        .clone()
+        .or_else(|| text("recipientAccountId"))
+        .unwrap_or_else(|| source.bucket.clone());
+    let failed = record.contains_key("errorCode");
+    let ip = text("sourceIPAddress").unwrap_or_default();
+
+    let mut data = record;
+    data.insert("object".to_string(), Value::String(key.to_string()));
+    let mut event = log_event(
+        &source.category,
+        &name,
+        &host,
+        "cloudtrail",
+        time,
+        &format!("{} by {}", name, actor),
+        data,
+    );
+    event.host_ipv4 = ip;
+    if failed {
+        event.severity = "warning".to_string();
+    }
+    Some(event)
+}
+
+fn object_events(source: &S3Source, key: &str, body: &str, modified: DateTime<Utc>) -> Vec<Event> {
+    match source.format {
+        S3Format::Alb => body
+            .lines()
+            .filter_map(|line| alb_event(source, key, line))
+            .collect(),
+        S3Format::Cloudtrail => serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|value| match value.get("Records") {
+                Some(Value::Array(records)) => Some(records.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|record| match record {
+                Value::Object(record) => cloudtrail_event(source, key, record),
+                _ => None,
+            })
+            .collect(),
+        S3Format::Lines => {
+            let host = source.host.as_deref().unwrap_or(&source.bucket);
+            body.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    let mut data = Map::new();
+                    data.insert("bucket".to_string(), Value::String(source.bucket.clone()));
+                    data.insert("object".to_string(), Value::String(key.to_string()));
+                    log_event(&source.category, "s3_log", host, "s3", modified, line, data)
+                })
+                .collect()
+        }
+    }
+}
+
+struct Puller {
+    state: web::Data<AppState>,
+    client: reqwest::Client,
+    credentials: Credentials,
+    region: String,
+    endpoint: Option<String>,
+    state_file: Option<String>,
+    // Source name -> position (newest timestamp or object key)
+    positions: Mutex<HashMap<String, String>>,
+}
+
+impl Puller {
+    fn position(&self, name: &str) -> Option<String> {
+        self.positions.lock().get(name).cloned()
+    }
+
+    fn set_position(&self, name: &str, position: String) {
+        let mut positions = self.positions.lock();
+        positions.insert(name.to_string(), position);
+        let Some(file) = &self.state_file else {
+            return;
+        };
+        let temp_path = format!("{}.tmp", file);
+        let written = serde_json::to_vec_pretty(&*positions)
+            .map_err(|err| err.to_string())
+            .and_then(|json| std::fs::write(&temp_path, json).map_err(|err| err.to_string()))
+            .and_then(|_| std::fs::rename(&temp_path, file).map_err(|err| err.to_string()));
+        if let Err(err) = written {
+            log_line!("WARN", "Could not save AWS positions to {}: {}", file, err);
+        }
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: reqwest::Url,
+        region: &str,
+        service: &str,
+        extra: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, String> {
+        let headers = self
+            .credentials
+            .sign(method.as_str(), &url, region, service, extra, &body);
+        let mut request = self.client.request(method, url).body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.map_err(|err| err.to_string())?;
+        let status = response.status();
+        let bytes = response.bytes().await.map_err(|err| err.to_string())?;
+        if !status.is_success() {
+            let detail = String::from_utf8_lossy(&bytes);
+            let detail = xml_values(&detail, "Message")
+                .pop()
+                .or_else(|| {
+                    serde_json::from_slice::<Value>(&bytes)
+                        .ok()
+                        .and_then(|value| {
+                            value
+                                .get("message")
+                                .or_else(|| value.get("Message"))
+                                .and_then(Value::as_str)
+                                .map(str::to_string)
+                        })
+                })
+                .unwrap_or_else(|| detail.chars().take(200).collect());
+            return Err(format!("{}: {}", status, detail));
+        }
+        Ok(bytes.to_vec())
+    }
+
+    fn url(
+        &self,
+        service: &str,
+        region: &str,
+        bucket: Option<&str>,
+    ) -> Result<reqwest::Url, String> {
+        let url = match (&self.endpoint, bucket) {
+            (Some(endpoint), Some(bucket)) => {
+                format!("{}/{}/", endpoint.trim_end_matches('/'), bucket)
+            }
+            (Some(endpoint), None) => format!("{}/", endpoint.trim_end_matches('/')),
+            (None, Some(bucket)) => format!("https://{}.s3.{}.amazonaws.com/", bucket, region),
+            (None, None) => format!("https://{}.{}.amazonaws.com/", service, region),
+        };
+        reqwest::Url::parse(&url).map_err(|err| err.to_string())
+    }
+
+    // One FilterLogEvents sweep from the saved timestamp. Events at exactly
+    // that timestamp were already stored unless their ID is new.
+    async fn poll_cloudwatch(
+        &self,
+        source: &CloudwatchSource,
+        seen: &mut HashSet<String>,
+    ) -> Result<usize, String> {
+        let name = format!("cloudwatch:{}", source.log_group);
+        let region = source.region.as_deref().unwrap_or(&self.region);
+        let start: i64 = match self
+            .position(&name)
+            .and_then(|position| position.parse().ok())
+        {
+            Some(start) => start,
+            None => {
+                let now = Utc::now().timestamp_millis();
+                self.set_position(&name, now.to_string());
+                now
+            }
+        };
+
+        let mut newest = start;
+        let mut batch = Vec::new();
+        let mut token: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            let mut body = serde_json::json!({
+                "logGroupName": source.log_group,
+                "startTime": start,
+            });
+            if let Some(pattern) = &source.filter_pattern {
+                body["filterPattern"] = Value::String(pattern.clone());
+            }
+            if let Some(token) = &token {
+                body["nextToken"] = Value::String(token.clone());
+            }
+            let url = self.url("logs", region, None)?;
+            let response = self
+                .send(
+                    reqwest::Method::POST,
+                    url,
+                    region,
+                    "logs",
+                    &[
+                        ("content-type", "application/x-amz-json-1.1"),
+                        ("x-amz-target", "Logs_20140328.FilterLogEvents"),
+                    ],
+                    body.to_string().into_bytes(),
+                )
+                .await?;
+            let response: Value =
+                serde_json::from_slice(&response).map_err(|err| err.to_string())?;
+
+            for entry in response["events"].as_array().into_iter().flatten() {
+                let id = entry["eventId"].as_str().unwrap_or_default().to_string();
+                let timestamp = entry["timestamp"].as_i64().unwrap_or(start);
+                if timestamp < start || (timestamp == start && seen.contains(&id)) {
+                    continue;
+                }
+                if timestamp > newest {
+                    newest = timestamp;
+                    seen.clear();
+                }
+                if timestamp == newest {
+                    seen.insert(id.clone());
+                }
+                let stream = entry["logStreamName"].as_str().unwrap_or_default();
+                let mut data = Map::new();
+                data.insert(
+                    "log_group".to_string(),
+                    Value::String(source.log_group.clone()),
+                );
+                data.insert("log_stream".to_string(), Value::String(stream.to_string()));
+                data.insert("event_id".to_string(), Value::String(id));
+                batch.push(log_event(
+                    &source.category,
+                    "cloudwatch_log",
+                    stream,
+                    &source.log_group,
+                    DateTime::from_timestamp_millis(timestamp).unwrap_or_else(Utc::now),
+                    entry["message"].as_str().unwrap_or_default(),
+                    data,
+                ));
+            }
+            token = response["nextToken"].as_str().map(str::to_string);
+            if token.is_none() {
+                break;
+            }
+        }
+
+        let count = batch.len();
+        if !batch.is_empty() {
+            crate::ingest(&self.state, batch).await;
+        }
+        if newest != start {
+            self.set_position(&name, newest.to_string());
+        }
+        Ok(count)
+    }
+
+    // Keys after `start_after`, oldest first
+    async fn list_objects(
+        &self,
+        source: &S3Source,
+        region: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut url = self.url("s3", region, Some(&source.bucket))?;
+            {
+                let mut query = url.query_pairs_mut();
+                query.append_pair("list-type", "2");
+                if !source.prefix.is_empty() {
+                    query.append_pair("prefix", &source.prefix);
+                }
+                if let Some(start_after) = start_after {
+                    query.append_pair("start-after", start_after);
+                }
+                if let Some(token) = &token {
+                    query.append_pair("continuation-token", token);
+                }
+            }
+            let body = self
+                .send(reqwest::Method::GET, url, region, "s3", &[], Vec::new())
+                .await?;
+            let body = String::from_utf8_lossy(&body);
+            let keys = xml_values(&body, "Key");
+            let modified = xml_values(&body, "LastModified");
+            for (index, key) in keys.into_iter().enumerate() {
+                let time = modified
+                    .get(index)
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| time.with_timezone(&Utc))
+                    .unwrap_or_else(Utc::now);
+                objects.push((key, time));
+            }
+            token = xml_values(&body, "NextContinuationToken").pop();
+            let truncated = xml_values(&body, "IsTruncated").pop().as_deref() == Some("true");
+            if !truncated || token.is_none() || objects.len() >= limit {
+                break;
+            }
+        }
+        objects.truncate(limit);
+        Ok(objects)
+    }
+
+    async fn poll_s3(&self, source: &S3Source) -> Result<usize, String> {
+        let name = format!("s3:{}/{}", source.bucket, source.prefix);
+        let region = source.region.as_deref().unwrap_or(&self.region);
+        let start_after = self.position(&name);
+        // Without a saved position only objects written from now on are read
+        if start_after.is_none() && !source.backfill {
+            let existing = self.list_objects(source, region, None, usize::MAX).await?;
+            if let Some((last, _)) = existing.last() {
+                log_line!(
+                    "INFO",
+                    "S3 source {} starts after {} existing objects",
+                    name,
+                    existing.len()
+                );
+                self.set_position(&name, last.clone());
+            } else {
+                self.set_position(&name, String::new());
+            }
+            return Ok(0);
+        }
+
+        let start_after = start_after.filter(|key| !key.is_empty());
+        let objects = self
+            .list_objects(source, region, start_after.as_deref(), MAX_OBJECTS)
+            .await?;
+        let mut count = 0;
+        for (key, modified) in objects {
+            let mut url = self.url("s3", region, Some(&source.bucket))?;
+            url.set_path(&format!(
+                "{}{}",
+                url.path(),
+                utf8_percent_encode(&key, OBJECT_KEY)
+            ));
+            let bytes = self
+                .send(reqwest::Method::GET, url, region, "s3", &[], Vec::new())
+                .await?;
+            // ALB and CloudTrail deliver gzip objects
+            let body = if bytes.starts_with(&[0x1f, 0x8b]) {
+                let mut text = String::new();
+                flate2::read::MultiGzDecoder::new(&bytes[..])
+                    .read_to_string(&mut text)
+                    .map_err(|err| format!("{}: {}", key, err))?;
+                text
+            } else {
+                String::from_utf8_lossy(&bytes).into_owned()
+            };
+            let batch = object_events(source, &key, &body, modified);
+            count += batch.len();
+            if !batch.is_empty() {
+                crate::ingest(&self.state, batch).await;
+            }
+            self.set_position(&name, key);
+        }
+        Ok(count)
+    }
+}
+
+fn credentials(config: &AwsConfig) -> Option<Credentials> {
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    Some(Credentials {
+        access_key_id: config
+            .access_key_id
+            .clone()
+            .or_else(|| env("AWS_ACCESS_KEY_ID"))?,
+        secret_access_key: config
+            .secret_access_key
+            .clone()
+            .or_else(|| env("AWS_SECRET_ACCESS_KEY"))?,
+        session_token: config
+            .session_token
+            .clone()
+            .or_else(|| env("AWS_SESSION_TOKEN")),
+    })
+}
+
+pub fn spawn(state: web::Data<AppState>, config: AwsConfig) {
+    let Some(credentials) = credentials(&config) else {
+        log_line!(
+            "ERROR",
+            "AWS sources need access_key_id and secret_access_key (or AWS_* variables); not polling"
+        );
+        return;
+    };
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log_line!("ERROR", "AWS sources disabled: {}", err);
+            return;
+        }
+    };
+    let positions = config
+        .state_file
+        .as_ref()
+        .and_then(|file| std::fs::read_to_string(file).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+
+    let puller = Arc::new(Puller {
+        state,
+        client,
+        credentials,
+        region: config.region,
+        endpoint: config.endpoint,
+        state_file: config.state_file,
+        positions: Mutex::new(positions),
+    });
+    let interval = config.interval.max(Duration::from_secs(1));
+
+    for source in config.cloudwatch {
+        log_line!(
+            "CONFIG",
+            "Polling CloudWatch log group {} every {}s",
+            source.log_group,
+            interval.as_secs()
+        );
+        let puller = puller.clone();
+        actix_web::rt::spawn(async move {
+            let mut seen = HashSet::new();
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(err) = puller.poll_cloudwatch(&source, &mut seen).await {
+                    log_line!(
+                        "WARN",
+                        "CloudWatch {} poll failed: {}",
+                        source.log_group,
+                        err
+                    );
+                }
+            }
+        });
+    }
+    for source in config.s3 {
+        log_line!(
+            "CONFIG",
+            "Polling s3://{}/{} ({:?}) every {}s",
+            source.bucket,
+            source.prefix,
+            source.format,
+            interval.as_secs()
+        );
+        let puller = puller.clone();
+        actix_web::rt::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(err) = puller.poll_s3(&source).await {
+                    log_line!("WARN", "S3 {} poll failed: {}", source.bucket, err);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,248 @@
+// Keeps a misbehaving agent from growing server memory: a batch with more
+// events than [limits] max_batch_events gets a 413, and once
+// max_queued_events are waiting for the ingest writers new batches get a 429
+// with Retry-After until the backlog drains.
+use crate::config::LimitsConfig;
+use actix_web::HttpResponse;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often a streaming request checks for room again
+const WAIT_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct Backpressure {
+    max_batch: usize,
+    max_queued: usize,
+    retry_after: Duration,
+    queued: Arc<AtomicUsize>,
+}
+
+// Events counted against the queue until this is dropped, which a writer
+// does once they are stored
+pub struct Reservation {
+    queued: Arc<AtomicUsize>,
+    events: usize,
+}
+
+impl Reservation {
+    // Moves `events` of these into a reservation of their own, for part of
+    // a batch that is queued separately
+    pub fn split(&mut self, events: usize) -> Reservation {
+        let events = events.min(self.events);
+        self.events -= events;
+        Reservation {
+            queued: self.queued.clone(),
+            events,
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(self.events, Ordering::AcqRel);
+    }
+}
+
+impl Backpressure {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Backpressure {
+            max_batch: config.max_batch_events,
+            max_queued: config.max_queued_events,
+            retry_after: config.retry_after,
+            queued: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    pub fn check_batch(&self, events: usize) -> Result<(), HttpResponse> {
+        if self.max_batch == 0 || events <= self.max_batch {
+            return Ok(());
+        }
+        Err(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "status": "error",
+            "message": format!(
+                "batch of {} events is over the limit of {}; send smaller batches",
+                events, self.max_batch
+            ),
+            "max_batch_events": self.max_batch,
+        })))
+    }
+
+    // Counts `events` as queued, or a 429 when there is no room. An empty
+    // queue always takes the batch so one larger than the limit isn't
+    // turned away forever.
+    pub fn reserve(&self, events: usize) -> Result<Reservation, HttpResponse> {
+        let mut current = self.queued.load(Ordering::Acquire);
+        loop {
+            if self.max_queued > 0 && current > 0 && current + events > self.max_queued {
+                return Err(self.busy(current));
+            }
+            match self.queued.compare_exchange_weak(
+                current,
+                current + events,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    return Ok(Reservation {
+                        queued: self.queued.clone(),
+                        events,
+                    })
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    // For streams: instead of failing, stop reading until there is room,
+    // which slows the sender down through TCP flow control
+    pub async fn wait(&self, events: usize) -> Reservation {
+        loop {
+            if let Ok(reservation) = self.reserve(events) {
+                return reservation;
+            }
+            tokio::time::sleep(WAIT_INTERVAL).await;
+        }
+    }
+
+    // For listeners with nobody to answer: counted, never refused
+    pub fn claim(&self, events: usize) -> Reservation {
+        self.queued.fetch_add(events, Ordering::AcqRel);
+        Reservation {
+            queued: self.queued.clone(),
+            events,
+        }
+    }
+
+    // Wait for everything queued to be stored, up to `timeout`. Returns how
+    // many events were still waiting.
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let queued = self.queued();
+            if queued == 0 || tokio::time::Instant::now() >= deadline {
+                return queued;
+            }
+            tokio::time::sleep(WAIT_INTERVAL).await;
+        }
+    }
+
+    // The 429 for a batch the writers have no room for
+    pub fn full(&self) -> HttpResponse {
+        self.busy(self.queued())
+    }
+
+    fn busy(&self, queued: usize) -> HttpResponse {
+        let seconds = self.retry_after.as_secs().max(1);
+        HttpResponse::TooManyRequests()
+            .insert_header((actix_web::http::header::RETRY_AFTER, seconds.to_string()))
+            .json(serde_json::json!({
+                "status": "error",
+                "message": format!(
+                    "ingest queue is full ({} events waiting); retry in {}s",
+                    queued, seconds
+                ),
+                "retry_after": seconds,
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+
+    fn backpressure(max_batch: usize, max_queued: usize) -> Backpressure {
+        Backpressure::new(&LimitsConfig {
+            max_batch_events: max_batch,
+            max_queued_events: max_queued,
+            retry_after: Duration::from_secs(7),
+            ..LimitsConfig::default()
+        })
+    }
+
+    #[test]
+    fn oversized_batches_get_a_413() {
+        let limits = backpressure(100, 0);
+        assert!(limits.check_batch(100).is_ok());
+        let refused = limits.check_batch(101).unwrap_err();
+        assert_eq!(refused.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(backpressure(0, 0).check_batch(usize::MAX).is_ok());
+    }
+
+    #[test]
+    fn a_full_queue_gets_a_429_until_it_drains() {
+        let limits = backpressure(0, 10);
+        let first = limits.reserve(6).unwrap();
+        let second = limits.reserve(4).unwrap();
+        assert_eq!(limits.queued(), 10);
+
+        let Err(refused) = limits.reserve(1) else {
+            panic!("a full queue took another batch");
+        };
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(refused.headers().get(header::RETRY_AFTER).unwrap(), "7");
+        // A refused batch isn't counted
+        assert_eq!(limits.queued(), 10);
+
+        drop(first);
+        assert_eq!(limits.queued(), 4);
+        assert!(limits.reserve(6).is_ok());
+        drop(second);
+        assert_eq!(limits.queued(), 0);
+    }
+
+    #[test]
+    fn an_empty_queue_takes_any_batch() {
+        let limits = backpressure(0, 10);
+        let large = limits.reserve(25).unwrap();
+        assert_eq!(limits.queued(), 25);
+        assert!(limits.reserve(1).is_err());
+        // Listeners are counted but never refused
+        let claimed = limits.claim(5);
+        assert_eq!(limits.queued(), 30);
+        drop(large);
+        drop(claimed);
+        assert_eq!(limits.queued(), 0);
+
+        let unlimited = backpressure(0, 0);
+        let _held = unlimited.reserve(1_000_000).unwrap();
+        assert!(unlimited.reserve(1_000_000).is_ok());
+    }
+
+    #[test]
+    fn split_reservations_release_their_own_share() {
+        let limits = backpressure(0, 0);
+        let mut batch = limits.reserve(10).unwrap();
+        let part = batch.split(4);
+        // Never more than is left
+        let rest = batch.split(100);
+        assert_eq!(rest.events, 6);
+        drop(batch);
+        assert_eq!(limits.queued(), 10);
+        drop(part);
+        assert_eq!(limits.queued(), 6);
+        drop(rest);
+        assert_eq!(limits.queued(), 0);
+    }
+
+    #[actix_web::test]
+    async fn streams_wait_for_room() {
+        let limits = Arc::new(backpressure(0, 10));
+        let held = limits.reserve(10).unwrap();
+        let waiting = {
+            let limits = limits.clone();
+            actix_web::rt::spawn(async move { limits.wait(5).await.events })
+        };
+        tokio::time::sleep(WAIT_INTERVAL * 3).await;
+        assert!(!waiting.is_finished());
+        drop(held);
+        assert_eq!(waiting.await.unwrap(), 5);
+        assert_eq!(limits.queued(), 0);
+        assert_eq!(limits.drain(Duration::from_millis(1)).await, 0);
+    }
+}
@@ -0,0 +1,86 @@
+// What agents may rely on when talking to this server: body encodings,
+// size limits, how they can authenticate and which optional endpoints
+// exist. Agents of different versions read this at startup instead of
+// assuming the newest server.
+use crate::config::LimitsConfig;
+use crate::tls::TlsConfig;
+use crate::{version, AppState};
+use actix_web::{web, HttpResponse, Result};
+
+pub struct Capabilities {
+    max_body_size: usize,
+    max_batch_events: usize,
+    max_queued_events: usize,
+    retry_after: u64,
+    client_certificates: &'static str,
+}
+
+impl Capabilities {
+    pub fn new(limits: &LimitsConfig, tls: &TlsConfig) -> Self {
+        Capabilities {
+            max_body_size: limits.max_body_size,
+            max_batch_events: limits.max_batch_events,
+            max_queued_events: limits.max_queued_events,
+            retry_after: limits.retry_after.as_secs(),
+            client_certificates: tls.client_certificates(),
+        }
+    }
+}
+
+// 0 means no limit in [limits]; agents get null for that
+fn limit(value: usize) -> Option<usize> {
+    (value > 0).then_some(value)
+}
+
+// GET /api/capabilities - Encodings, limits, auth modes and extensions
+pub async fn get_capabilities(data: web::Data<AppState>) -> Result<HttpResponse> {
+    let capabilities = &data.capabilities;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "version": version::VERSION,
+        "schema_versions": version::SCHEMA_VERSIONS,
+        "ingest": {
+            "path": "/api/events",
+            "content_encodings": crate::CONTENT_ENCODINGS,
+            "max_body_bytes": capabilities.max_body_size,
+            "max_batch_events": limit(capabilities.max_batch_events),
+            "max_queued_events": limit(capabilities.max_queued_events),
+            "retry_after_seconds": capabilities.retry_after,
+        },
+        "auth": {
+            "api_key": {
+                "scheme": "Bearer",
+                "required": data.api_keys.required(),
+            },
+            "signatures": {
+                "algorithm": "ed25519",
+                "required": data.signing.required(),
+            },
+            "client_certificates": capabilities.client_certificates,
+        },
+        "extensions": {
+            "ndjson": {
+                "path": "/api/events/ndjson",
+                "max_line_bytes": crate::NDJSON_MAX_LINE,
+            },
+            "raw": {
+                "path": "/api/events/raw",
+                "formats": ["cef", "leef"],
+            },
+            "inventory": {
+                "path": "/api/inventory",
+            },
+            "agents": {
+                "path": "/api/agents",
+                "token_header": crate::agents::TOKEN_HEADER,
+            },
+            "control": {
+                "path": "/api/agents/{host}/control",
+                "commands": crate::control::COMMANDS,
+            },
+            // Per-event retention, bounded by [retention]
+            "ttl": {
+                "field": "ttl",
+            },
+        },
+    })))
+}
@@ -0,0 +1,204 @@
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// Distinct values tracked per field before we stop counting; a field past
+// this is already the problem the report is meant to surface
+const DISTINCT_CAP: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct CardinalityQuery {
+    #[serde(default = "default_top")]
+    top: usize,
+}
+
+fn default_top() -> usize {
+    10
+}
+
+#[derive(Debug, Serialize)]
+struct ValueCount {
+    value: String,
+    count: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct DimensionReport {
+    name: &'static str,
+    distinct: usize,
+    distinct_capped: bool,
+    // Bytes held by this field's values across the whole store
+    bytes: usize,
+    top_values: Vec<ValueCount>,
+}
+
+#[derive(Debug, Serialize)]
+struct DataKeyReport {
+    key: String,
+    events: usize,
+    distinct_values: usize,
+    distinct_capped: bool,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct Dimension {
+    counts: HashMap<String, usize>,
+    capped: bool,
+    bytes: usize,
+}
+
+impl Dimension {
+    fn add(&mut self, value: &str) {
+        self.bytes += value.len();
+        if let Some(count) = self.counts.get_mut(value) {
+            *count += 1;
+        } else if self.counts.len() < DISTINCT_CAP {
+            self.counts.insert(value.to_string(), 1);
+        } else {
+            self.capped = true;
+        }
+    }
+
+    fn report(self, name: &'static str, top: usize) -> DimensionReport {
+        let distinct = self.counts.len();
+        let mut values: Vec<ValueCount> = self
+            .counts
+            .into_iter()
+            .map(|(value, count)| ValueCount { value, count })
+            .collect();
+        values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        values.truncate(top);
+
+        DimensionReport {
+            name,
+            distinct,
+            distinct_capped: self.capped,
+            bytes: self.bytes,
+            top_values: values,
+        }
+    }
+}
+
+#[derive(Default)]
+struct DataKey {
+    events: usize,
+    values: HashSet<String>,
+    capped: bool,
+    bytes: usize,
+}
+
+// Record every leaf under `data` as "data.a.b"; arrays count as one value
+fn walk_data(prefix: &mut String, value: &serde_json::Value, keys: &mut HashMap<String, DataKey>) {
+    if let serde_json::Value::Object(map) = value {
+        for (name, child) in map {
+            let length = prefix.len();
+            prefix.push('.');
+            prefix.push_str(name);
+            walk_data(prefix, child, keys);
+            prefix.truncate(length);
+        }
+        return;
+    }
+
+    let rendered = match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    let entry = keys.entry(prefix.clone()).or_default();
+    entry.events += 1;
+    entry.bytes += prefix.len() + rendered.len();
+    if entry.values.len() < DISTINCT_CAP {
+        entry.values.insert(rendered);
+    } else if !entry.values.contains(&rendered) {
+        entry.capped = true;
+    }
+}
+
+// Rough heap + inline size of one stored event
+fn event_bytes(event: &Event) -> usize {
+    std::mem::size_of::<Event>()
+        + event.category.len()
+        + event.event_type.len()
+        + event.time.len()
+        + event.host.len()
+        + event.host_ipv4.len()
+        + event.os.len()
+        + event.source.len()
+        + event.severity.len()
+        + event.message.len()
+        + serde_json::to_vec(&event.data).map_or(0, |bytes| bytes.len())
+}
+
+// GET /api/admin/cardinality?top=10 - Distinct values per dimension and the
+// fields using the most memory
+pub async fn get_cardinality(
+    request: HttpRequest,
+    query: web::Query<CardinalityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let top = query.top.clamp(1, 100);
+
+    let mut hosts = Dimension::default();
+    let mut categories = Dimension::default();
+    let mut event_types = Dimension::default();
+    let mut sources = Dimension::default();
+    let mut severities = Dimension::default();
+    let mut messages = Dimension::default();
+    let mut data_keys: HashMap<String, DataKey> = HashMap::new();
+    let mut total_bytes = 0;
+
+    let total_events = {
+        let store = data.store.read();
+        let mut prefix = String::from("data");
+        for event in store.iter() {
+            total_bytes += event_bytes(event);
+            hosts.add(&event.host);
+            categories.add(&event.category);
+            event_types.add(&event.event_type);
+            sources.add(&event.source);
+            severities.add(&event.severity);
+            messages.add(&event.message);
+            walk_data(&mut prefix, &event.data, &mut data_keys);
+        }
+        store.len()
+    };
+
+    let mut dimensions = vec![
+        hosts.report("host", top),
+        categories.report("category", top),
+        event_types.report("event_type", top),
+        sources.report("source", top),
+        severities.report("severity", top),
+        messages.report("message", top),
+    ];
+    dimensions.sort_by_key(|dimension| std::cmp::Reverse(dimension.bytes));
+
+    let distinct_data_keys = data_keys.len();
+    let mut keys: Vec<DataKeyReport> = data_keys
+        .into_iter()
+        .map(|(key, stats)| DataKeyReport {
+            key,
+            events: stats.events,
+            distinct_values: stats.values.len(),
+            distinct_capped: stats.capped,
+            bytes: stats.bytes,
+        })
+        .collect();
+    keys.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+    keys.truncate(top);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total_events": total_events,
+        "estimated_bytes": total_bytes,
+        "dimensions": dimensions,
+        "data_keys": {
+            "distinct": distinct_data_keys,
+            "top": keys,
+        },
+    })))
+}
@@ -0,0 +1,625 @@
+// Subcommands of the server binary. Without one it serves, as it always has.
+use crate::service::ServiceCommand;
+use crate::{config, hooks, notifiers, outputs, tls};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::net::ToSocketAddrs;
+use std::num::NonZeroUsize;
+
+#[derive(Parser)]
+#[command(
+    name = "loglumen-server",
+    about = "Loglumen SIEM server",
+    version = crate::version::describe()
+)]
+struct Arguments {
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Config file, in place of LOGLUMEN_SERVER_CONFIG"
+    )]
+    config: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "COMMAND",
+        help = "Manage the Windows or macOS service"
+    )]
+    service: Option<ServiceCommand>,
+    // Read by the service itself, which it is installed to run with
+    #[arg(long, hide = true, requires = "service")]
+    workdir: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Run the server (the default)")]
+    Serve,
+    #[command(about = "Validate the config file and exit")]
+    CheckConfig,
+    #[command(about = "Write stored events to a file as JSON lines")]
+    Export(Export),
+    #[command(about = "Send events from a JSON lines file (or stdin)")]
+    Import(Import),
+    #[command(about = "Read a password from stdin and print an [auth] password_hash")]
+    HashPassword,
+    #[command(about = "Show the version")]
+    Version,
+    // From --service rather than a subcommand
+    #[command(skip)]
+    Service(ServiceCommand),
+}
+
+pub struct Cli {
+    // LOGLUMEN_SERVER_CONFIG for this run
+    pub config: Option<String>,
+    pub command: Command,
+}
+
+// Options shared by export and import
+#[derive(Args)]
+pub struct Transfer {
+    #[arg(long, help = "Server to talk to (default: from [server] bind_address)")]
+    url: Option<String>,
+    #[arg(
+        long,
+        env = "LOGLUMEN_TOKEN",
+        hide_env_values = true,
+        help = "Sent as `Authorization: Bearer` ([access] token or agent API key)"
+    )]
+    token: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "CA certificate to trust for a self-signed server"
+    )]
+    ca_cert: Option<String>,
+}
+
+// The filters are the GET /api/events query parameters
+#[derive(Args)]
+pub struct Export {
+    #[command(flatten)]
+    transfer: Transfer,
+    #[arg(long, value_name = "FILE", help = "Where to write (default: stdout)")]
+    output: Option<String>,
+    #[arg(
+        long,
+        value_name = "TIME",
+        help_heading = "Filters, as for GET /api/events"
+    )]
+    from: Option<String>,
+    #[arg(
+        long,
+        value_name = "TIME",
+        help_heading = "Filters, as for GET /api/events"
+    )]
+    to: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help_heading = "Filters, as for GET /api/events"
+    )]
+    category: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help_heading = "Filters, as for GET /api/events"
+    )]
+    event_type: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help_heading = "Filters, as for GET /api/events"
+    )]
+    source: Option<String>,
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        help_heading = "Filters, as for GET /api/events"
+    )]
+    severity: Option<String>,
+}
+
+impl Export {
+    fn filters(&self) -> Vec<(&'static str, String)> {
+        [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("category", &self.category),
+            ("event_type", &self.event_type),
+            ("source", &self.source),
+            ("severity", &self.severity),
+        ]
+        .into_iter()
+        .filter_map(|(parameter, value)| Some((parameter, value.clone()?)))
+        .collect()
+    }
+}
+
+#[derive(Args)]
+pub struct Import {
+    #[command(flatten)]
+    transfer: Transfer,
+    #[arg(long, default_value = "500", help = "Events per request")]
+    batch_size: NonZeroUsize,
+    #[arg(
+        value_name = "FILE",
+        help = "JSON lines or a JSON array; stdin when left out or `-`"
+    )]
+    file: Option<String>,
+}
+
+// `args` includes the program name. Errors, --help and --version come back
+// as a clap error, whose `exit` prints them.
+pub fn parse<I, T>(args: I) -> Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let arguments = Arguments::try_parse_from(args)?;
+    let command = match (arguments.service, arguments.command) {
+        (Some(_), Some(_)) => {
+            return Err(Arguments::command().error(
+                ErrorKind::ArgumentConflict,
+                "--service cannot be combined with a subcommand",
+            ))
+        }
+        (Some(service), None) => Command::Service(service),
+        (None, command) => command.unwrap_or(Command::Serve),
+    };
+    Ok(Cli {
+        config: arguments.config,
+        command,
+    })
+}
+
+// Run everything but serve and the service commands; the result is the
+// process exit code
+pub fn run(command: Command) -> io::Result<i32> {
+    match command {
+        Command::Version => {
+            println!("loglumen-server {}", crate::version::describe());
+            Ok(0)
+        }
+        Command::CheckConfig => Ok(if check_config() { 0 } else { 1 }),
+        Command::HashPassword => hash_password(),
+        Command::Export(options) => {
+            crate::logging::init_stderr();
+            actix_web::rt::System::new().block_on(export(options))
+        }
+        Command::Import(options) => {
+            crate::logging::init_stderr();
+            actix_web::rt::System::new().block_on(import(options))
+        }
+        Command::Serve | Command::Service(_) => Ok(0),
+    }
+}
+
+// Deserialize a section into the type the server reads it as. Ok(Some(..))
+// is a warning that doesn't fail the check.
+fn check_section(name: &str, value: &Value) -> Result<Option<String>, String> {
+    fn typed<T: serde::de::DeserializeOwned>(value: &Value) -> Result<Option<String>, String> {
+        serde_json::from_value::<T>(value.clone())
+            .map(|_| None)
+            .map_err(|err| err.to_string())
+    }
+    fn entries(
+        value: &Value,
+        check: fn(&Value) -> Result<(), String>,
+    ) -> Result<Option<String>, String> {
+        let Value::Array(entries) = value else {
+            return Err("expected a list of tables".to_string());
+        };
+        for (index, entry) in entries.iter().enumerate() {
+            let name = entry.get("name").and_then(Value::as_str).unwrap_or("?");
+            check(entry).map_err(|err| format!("entry {} ('{}'): {}", index + 1, name, err))?;
+        }
+        Ok(None)
+    }
+    #[allow(unused)]
+    fn feature(name: &str) -> Result<Option<String>, String> {
+        Ok(Some(format!(
+            "ignored: the server was built without the `{}` feature",
+            name
+        )))
+    }
+
+    match name {
+        "server" => typed::<config::ServerConfig>(value),
+        "limits" => typed::<config::LimitsConfig>(value),
+        "tls" => typed::<tls::TlsConfig>(value),
+        "storage" => typed::<crate::storage::StorageConfig>(value),
+        "retention" => typed::<crate::retention::RetentionConfig>(value),
+        "logging" => typed::<crate::logging::LoggingConfig>(value),
+        "scheduler" => typed::<crate::scheduler::SchedulerConfig>(value),
+        "vulnerabilities" => typed::<crate::vulnerabilities::VulnerabilityConfig>(value),
+        "detection" => typed::<crate::rules::DetectionConfig>(value),
+        "incidents" => typed::<crate::incidents::IncidentsConfig>(value),
+        "notifiers" => entries(value, notifiers::check),
+        "notifications" => typed::<notifiers::NotificationsConfig>(value),
+        "outputs" => entries(value, outputs::check),
+        "plugins" => typed::<crate::plugins::PluginsConfig>(value),
+        "stats" => typed::<crate::stats::StatsConfig>(value),
+        "display" => typed::<crate::display::DisplayConfig>(value),
+        "severity" => typed::<crate::severity::SeverityConfig>(value),
+        "inventory" => typed::<crate::inventory::InventoryConfig>(value),
+        "fim" => typed::<crate::fim::FimConfig>(value),
+        "flows" => typed::<crate::flows::FlowConfig>(value),
+        "availability" => typed::<crate::availability::AvailabilityConfig>(value),
+        "signing" => typed::<crate::signing::SigningConfig>(value),
+        "integrity" => typed::<crate::integrity::IntegrityConfig>(value),
+        "access" => typed::<crate::access::AccessConfig>(value),
+        "api_keys" => typed::<crate::api_keys::ApiKeysConfig>(value),
+        "agents" => typed::<crate::agents::AgentsConfig>(value),
+        "auth" => typed::<crate::sessions::AuthConfig>(value),
+        "usage" => typed::<crate::usage::UsageConfig>(value),
+        "raw" => typed::<crate::raw::RawConfig>(value),
+        "hooks" => typed::<HashMap<String, hooks::HookConfig>>(value),
+        "loki" => typed::<crate::loki::LokiConfig>(value),
+        "elastic" => typed::<crate::elastic::ElasticConfig>(value),
+        "splunk" => typed::<crate::splunk::SplunkConfig>(value),
+        "datadog" => typed::<crate::datadog::DatadogConfig>(value),
+        "otlp" => typed::<crate::otlp::OtlpConfig>(value),
+        "audit" => typed::<crate::audit::AuditConfig>(value),
+        "demo" => typed::<crate::demo::DemoConfig>(value),
+        "dev" => typed::<crate::generator::DevConfig>(value),
+        "hardening" => typed::<crate::hardening::HardeningConfig>(value),
+        "crash" => typed::<crate::crash::CrashConfig>(value),
+        "search" => typed::<crate::search::SearchConfig>(value),
+        #[cfg(all(feature = "docker", unix))]
+        "docker" => typed::<crate::docker::DockerConfig>(value),
+        #[cfg(not(all(feature = "docker", unix)))]
+        "docker" => feature("docker"),
+        #[cfg(feature = "aws")]
+        "aws" => typed::<crate::aws::AwsConfig>(value),
+        #[cfg(not(feature = "aws"))]
+        "aws" => feature("aws"),
+        #[cfg(feature = "netflow")]
+        "netflow" => typed::<crate::netflow::NetflowConfig>(value),
+        #[cfg(not(feature = "netflow"))]
+        "netflow" => feature("netflow"),
+        #[cfg(feature = "syslog")]
+        "syslog" => typed::<crate::syslog::SyslogConfig>(value),
+        #[cfg(not(feature = "syslog"))]
+        "syslog" => feature("syslog"),
+        #[cfg(feature = "gelf")]
+        "gelf" => typed::<crate::gelf::GelfConfig>(value),
+        #[cfg(not(feature = "gelf"))]
+        "gelf" => feature("gelf"),
+        #[cfg(feature = "statsd")]
+        "statsd" => typed::<crate::statsd::StatsdConfig>(value),
+        #[cfg(not(feature = "statsd"))]
+        "statsd" => feature("statsd"),
+        #[cfg(feature = "smtp")]
+        "smtp" => typed::<crate::smtp::SmtpConfig>(value),
+        #[cfg(not(feature = "smtp"))]
+        "smtp" => feature("smtp"),
+        _ => Ok(Some("unknown section, ignored".to_string())),
+    }
+}
+
+// check-config: every section of the file the server would read, plus the
+// bind address and TLS files. Env overrides are not applied.
+fn check_config() -> bool {
+    let Some((path, contents)) = config::candidate_paths()
+        .into_iter()
+        .find_map(|path| Some((path.clone(), std::fs::read_to_string(&path).ok()?)))
+    else {
+        println!("No config file found; the server would start with defaults");
+        return true;
+    };
+    let document = match config::parse_document(&path, &contents) {
+        Ok(document) => document,
+        Err(err) => {
+            println!("{}: {}", path.display(), err);
+            return false;
+        }
+    };
+    let Value::Object(sections) = &document else {
+        println!("{}: expected a table of sections", path.display());
+        return false;
+    };
+
+    let mut valid = true;
+    for (name, value) in sections {
+        match check_section(name, value) {
+            Ok(None) => println!("[{}] ok", name),
+            Ok(Some(warning)) => println!("[{}] {}", name, warning),
+            Err(err) => {
+                println!("[{}] invalid: {}", name, err);
+                valid = false;
+            }
+        }
+    }
+
+    let section = |name: &str| document.get(name).cloned().unwrap_or(Value::Null);
+    let server: config::ServerConfig =
+        serde_json::from_value(section("server")).unwrap_or_default();
+    if let Err(err) = server.bind_address.to_socket_addrs() {
+        println!(
+            "[server] bind_address '{}' is not an address: {}",
+            server.bind_address, err
+        );
+        valid = false;
+    }
+    let tls: tls::TlsConfig = serde_json::from_value(section("tls")).unwrap_or_default();
+    if tls.enabled() {
+        if let Err(err) = tls::acceptor(&tls) {
+            println!("[tls] {}", err);
+            valid = false;
+        }
+    }
+
+    println!(
+        "{}: {}",
+        path.display(),
+        if valid { "ok" } else { "has errors" }
+    );
+    valid
+}
+
+// One line from stdin; the typed password is echoed, so pipe it in from
+// `read -s` where that matters
+fn hash_password() -> io::Result<i32> {
+    use std::io::IsTerminal;
+    if io::stdin().is_terminal() {
+        eprint!("Password: ");
+        io::stderr().flush()?;
+    }
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        eprintln!("No password given");
+        return Ok(1);
+    }
+    match crate::sessions::hash_password(password) {
+        Ok(hash) => {
+            println!("{}", hash);
+            Ok(0)
+        }
+        Err(err) => {
+            eprintln!("Could not hash the password: {}", err);
+            Ok(1)
+        }
+    }
+}
+
+fn client(transfer: &Transfer) -> io::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(path) = &transfer.ca_cert {
+        let pem = std::fs::read(path)?;
+        let cert = reqwest::Certificate::from_pem(&pem)
+            .map_err(|err| io::Error::other(format!("{}: {}", path, err)))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+// --url, or the server from this host's config
+fn base_url(transfer: &Transfer) -> String {
+    if let Some(url) = &transfer.url {
+        return url.trim_end_matches('/').to_string();
+    }
+    let config = config::ServeConfig::load();
+    let scheme = if config.tls.enabled() {
+        "https"
+    } else {
+        "http"
+    };
+    let address = config
+        .server
+        .bind_address
+        .replace("0.0.0.0", "127.0.0.1")
+        .replace("[::]", "[::1]");
+    format!("{}://{}", scheme, address)
+}
+
+fn request(builder: reqwest::RequestBuilder, transfer: &Transfer) -> reqwest::RequestBuilder {
+    match &transfer.token {
+        Some(token) => builder.bearer_auth(token),
+        None => builder,
+    }
+}
+
+async fn failure(response: reqwest::Response) -> io::Error {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|body| {
+            body.get("message")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or(body);
+    io::Error::other(format!("server answered {}: {}", status, message))
+}
+
+// export: page through GET /api/events, every severity, oldest first
+async fn export(options: Export) -> io::Result<i32> {
+    let transfer = &options.transfer;
+    let client = client(transfer)?;
+    let url = format!("{}/api/events", base_url(transfer));
+    let mut output: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(io::BufWriter::new(io::stdout().lock())),
+    };
+
+    let mut cursor: Option<String> = None;
+    let mut exported = 0;
+    loop {
+        let mut query: Vec<(&str, String)> = vec![
+            ("limit", "1000".to_string()),
+            ("min_severity", "debug".to_string()),
+        ];
+        query.extend(options.filters());
+        if let Some(cursor) = &cursor {
+            query.push(("cursor", cursor.clone()));
+        }
+        let response = request(client.get(&url).query(&query), transfer)
+            .send()
+            .await
+            .map_err(|err| io::Error::other(format!("{}: {}", url, err)))?;
+        if !response.status().is_success() {
+            return Err(failure(response).await);
+        }
+        let page: Value = response.json().await.map_err(io::Error::other)?;
+        for event in page
+            .get("events")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            writeln!(output, "{}", event)?;
+            exported += 1;
+        }
+        cursor = page
+            .get("next_cursor")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    output.flush()?;
+    log_line!("INFO", "Exported {} events from {}", exported, url);
+    Ok(0)
+}
+
+// import: POST /api/events in batches. Takes JSON lines as written by
+// export, or a single JSON array.
+async fn import(options: Import) -> io::Result<i32> {
+    let transfer = &options.transfer;
+    let input: Box<dyn BufRead> = match &options.file {
+        Some(path) if path != "-" => Box::new(io::BufReader::new(std::fs::File::open(path)?)),
+        _ => Box::new(io::BufReader::new(io::stdin().lock())),
+    };
+    let mut events = Vec::new();
+    let mut text = String::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if !text.is_empty() || (events.is_empty() && line.trim_start().starts_with('[')) {
+            text.push_str(&line);
+            text.push('\n');
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: Value = serde_json::from_str(&line)
+            .map_err(|err| io::Error::other(format!("line {}: {}", number + 1, err)))?;
+        events.push(event);
+    }
+    if !text.is_empty() {
+        events = serde_json::from_str(&text).map_err(io::Error::other)?;
+    }
+
+    let client = client(transfer)?;
+    let url = format!("{}/api/events", base_url(transfer));
+    let (mut sent, mut rejected) = (0, 0);
+    for batch in events.chunks(options.batch_size.get()) {
+        let response = request(client.post(&url).json(batch), transfer)
+            .send()
+            .await
+            .map_err(|err| io::Error::other(format!("{}: {}", url, err)))?;
+        if !response.status().is_success() {
+            log_line!("ERROR", "Stopped after {} of {} events", sent, events.len());
+            return Err(failure(response).await);
+        }
+        let reply: Value = response.json().await.unwrap_or_default();
+        rejected += reply.get("rejected").and_then(Value::as_u64).unwrap_or(0);
+        sent += batch.len();
+    }
+    log_line!(
+        "INFO",
+        "Imported {} events into {} ({} rejected)",
+        sent,
+        url,
+        rejected
+    );
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(args: &[&str]) -> Result<Cli, clap::Error> {
+        parse(std::iter::once("loglumen-server").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn arguments_are_consistent() {
+        Arguments::command().debug_assert();
+    }
+
+    #[test]
+    fn serves_without_a_subcommand() {
+        let cli = parsed(&["--config", "server.toml"]).unwrap();
+        assert_eq!(cli.config.as_deref(), Some("server.toml"));
+        assert!(matches!(cli.command, Command::Serve));
+    }
+
+    #[test]
+    fn config_goes_before_or_after_the_subcommand() {
+        for args in [
+            ["--config", "server.toml", "check-config"],
+            ["check-config", "--config", "server.toml"],
+        ] {
+            let cli = parsed(&args).unwrap();
+            assert_eq!(cli.config.as_deref(), Some("server.toml"));
+            assert!(matches!(cli.command, Command::CheckConfig));
+        }
+    }
+
+    #[test]
+    fn export_collects_filters() {
+        let cli = parsed(&[
+            "export",
+            "--event-type",
+            "login",
+            "--from",
+            "2024-01-01T00:00:00Z",
+        ])
+        .unwrap();
+        let Command::Export(export) = cli.command else {
+            panic!("expected export");
+        };
+        assert_eq!(
+            export.filters(),
+            vec![
+                ("from", "2024-01-01T00:00:00Z".to_string()),
+                ("event_type", "login".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_takes_a_file_and_batch_size() {
+        let cli = parsed(&["import", "--batch-size", "50", "events.ndjson"]).unwrap();
+        let Command::Import(import) = cli.command else {
+            panic!("expected import");
+        };
+        assert_eq!(import.batch_size.get(), 50);
+        assert_eq!(import.file.as_deref(), Some("events.ndjson"));
+        assert!(parsed(&["import", "--batch-size", "0"]).is_err());
+    }
+
+    #[test]
+    fn service_excludes_subcommands() {
+        let cli = parsed(&["--service", "install"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Service(ServiceCommand::Install)
+        ));
+        assert!(parsed(&["--service", "stop"]).is_err());
+        assert!(parsed(&["--service", "run", "export"]).is_err());
+    }
+}
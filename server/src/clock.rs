@@ -0,0 +1,123 @@
+// Where the scheduler, retention, availability, the stats and flow rollups
+// and the alert rules read "now". Normally the system clock; with `[dev] clock = "manual"`
+// it stands still until moved through /api/dev/clock, so time-dependent
+// behaviour can be stepped through deterministically.
+use crate::AppState;
+use actix_web::{web, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    // Only the manual clock can be moved
+    fn manual(&self) -> Option<&ManualClock> {
+        None
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        ManualClock {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.now.lock() = time;
+    }
+
+    pub fn advance(&self, by: chrono::Duration) -> DateTime<Utc> {
+        let mut now = self.now.lock();
+        *now = now.checked_add_signed(by).unwrap_or(*now);
+        *now
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock()
+    }
+
+    fn manual(&self) -> Option<&ManualClock> {
+        Some(self)
+    }
+}
+
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+pub fn manual(start: DateTime<Utc>) -> SharedClock {
+    Arc::new(ManualClock::new(start))
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClockUpdate {
+    // RFC 3339 time to jump to
+    set: Option<String>,
+    // Duration to move forward by, e.g. "90s" or "1h"
+    advance: Option<String>,
+}
+
+// GET /api/dev/clock - The server's current time
+pub async fn current(data: web::Data<AppState>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "now": data.clock.now().to_rfc3339(),
+        "manual": data.clock.manual().is_some()
+    })))
+}
+
+// POST /api/dev/clock {"set": "..."} or {"advance": "1h"} - Move the manual
+// clock. Background checks still run on their real-time intervals and see
+// the new time on their next tick.
+pub async fn update(
+    body: web::Json<ClockUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let Some(clock) = data.clock.manual() else {
+        return Ok(bad_request(
+            "the clock is not manual; set [dev] clock = \"manual\"".to_string(),
+        ));
+    };
+    let body = body.into_inner();
+    if let Some(raw) = &body.set {
+        match DateTime::parse_from_rfc3339(raw) {
+            Ok(time) => clock.set(time.with_timezone(&Utc)),
+            Err(_) => return Ok(bad_request(format!("invalid time '{}'", raw))),
+        }
+    }
+    if let Some(raw) = &body.advance {
+        match crate::config::parse_duration(raw).and_then(|by| chrono::Duration::from_std(by).ok())
+        {
+            Some(by) => {
+                clock.advance(by);
+            }
+            None => return Ok(bad_request(format!("invalid duration '{}'", raw))),
+        }
+    }
+    current(data).await
+}
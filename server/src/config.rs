@@ -0,0 +1,378 @@
+use crate::{retention, storage, tls};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_CONFIG_DIR: &str = "config";
+const EXAMPLE_CONFIG_PATH: &str = "config/server.example.toml";
+// File names tried, in order, when the config path is a directory
+const CONFIG_FILE_NAMES: [&str; 3] = ["server.toml", "server.yaml", "server.yml"];
+
+// Config files to try, in order: LOGLUMEN_SERVER_CONFIG (or config/server.toml,
+// falling back to config/server.yaml), then the bundled example.
+pub fn candidate_paths() -> Vec<PathBuf> {
+    let mut paths = match std::env::var("LOGLUMEN_SERVER_CONFIG") {
+        Ok(configured_path) => vec![resolve_path(configured_path)],
+        Err(_) => vec![resolve_path(DEFAULT_CONFIG_DIR)],
+    };
+    let example = resolve_path(EXAMPLE_CONFIG_PATH);
+    if !paths.contains(&example) {
+        paths.push(example);
+    }
+    paths
+}
+
+// A directory is treated as the folder holding server.toml (or server.yaml)
+pub fn resolve_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path_ref = path.as_ref();
+    if !path_ref.is_dir() {
+        return path_ref.to_path_buf();
+    }
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| path_ref.join(name))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| path_ref.join(CONFIG_FILE_NAMES[0]))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    // Picked from the extension; anything that is not .yaml/.yml is TOML
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                ConfigFormat::Yaml
+            }
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+// Parse a whole config file into a format-neutral document so sections can be
+// deserialized the same way from TOML and YAML.
+pub fn parse_document(path: &Path, contents: &str) -> Result<serde_json::Value, String> {
+    let document: serde_json::Value = match ConfigFormat::of(path) {
+        ConfigFormat::Toml => toml::from_str(contents).map_err(|err| err.to_string())?,
+        ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|err| err.to_string())?,
+    };
+    // An empty YAML file parses as null
+    Ok(match document {
+        serde_json::Value::Null => serde_json::Value::Object(Default::default()),
+        document => document,
+    })
+}
+
+// Deserialize one top-level `[section]` from the first config file that exists.
+// A missing file or section yields the default; an invalid section is logged
+// and also falls back to the default so a typo never stops the server.
+pub fn load_section<T: DeserializeOwned + Default>(section: &str) -> T {
+    load_section_inner(section, false)
+}
+
+// Same as load_section, without the "[CONFIG] Loaded" line. Used for the
+// logging section, which is read before the log format is known.
+pub fn load_section_quiet<T: DeserializeOwned + Default>(section: &str) -> T {
+    load_section_inner(section, true)
+}
+
+fn load_section_inner<T: DeserializeOwned + Default>(section: &str, quiet: bool) -> T {
+    match find_section(section) {
+        Some((value, path)) => parse_section(section, value, &path.display().to_string(), quiet),
+        None => T::default(),
+    }
+}
+
+// Like load_section, but a config file that doesn't parse or an invalid
+// section is an error. For the sections whose defaults are the open ones
+// (`[api_keys]`, `[auth]`, `[access]`, `[signing]`): falling back there would
+// quietly turn authentication off, so the server refuses to start instead.
+pub fn load_section_strict<T: DeserializeOwned + Default>(section: &str) -> Result<T, String> {
+    let Some((value, path)) = read_section(section)? else {
+        return Ok(T::default());
+    };
+    let parsed = serde_json::from_value(value).map_err(|err| {
+        format!(
+            "Invalid [{}] section in {}: {}",
+            section,
+            path.display(),
+            err
+        )
+    })?;
+    log_line!("CONFIG", "Loaded [{}] from {}", section, path.display());
+    Ok(parsed)
+}
+
+// The raw `[section]` from the first config file that exists, and that file
+fn find_section(section: &str) -> Option<(serde_json::Value, PathBuf)> {
+    read_section(section).unwrap_or_else(|err| {
+        log_line!("WARN", "{}", err);
+        None
+    })
+}
+
+fn read_section(section: &str) -> Result<Option<(serde_json::Value, PathBuf)>, String> {
+    for path in candidate_paths() {
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+
+        return match parse_document(&path, &contents) {
+            Ok(document) => Ok(document.get(section).cloned().map(|value| (value, path))),
+            Err(err) => Err(format!("Could not parse {}: {}", path.display(), err)),
+        };
+    }
+
+    Ok(None)
+}
+
+fn parse_section<T: DeserializeOwned + Default>(
+    section: &str,
+    value: serde_json::Value,
+    origin: &str,
+    quiet: bool,
+) -> T {
+    match serde_json::from_value(value) {
+        Ok(parsed) => {
+            if !quiet {
+                log_line!("CONFIG", "Loaded [{}] from {}", section, origin);
+            }
+            parsed
+        }
+        Err(err) => {
+            log_line!(
+                "WARN",
+                "Ignoring invalid [{}] section in {}: {}",
+                section,
+                origin,
+                err
+            );
+            T::default()
+        }
+    }
+}
+
+// Same as load_section, but any field can also be set with
+// LOGLUMEN_<SECTION>_<FIELD> (LOGLUMEN_TLS_CERT_PATH), which wins over the
+// file. The fields are the ones T's default serializes.
+pub fn load_section_with_env<T: DeserializeOwned + Serialize + Default>(section: &str) -> T {
+    let found = find_section(section);
+    let Ok(serde_json::Value::Object(defaults)) = serde_json::to_value(T::default()) else {
+        return load_section(section);
+    };
+    let mut overrides = serde_json::Map::new();
+    for (field, default) in defaults {
+        let variable = format!("LOGLUMEN_{}_{}", section, field).to_uppercase();
+        let Ok(raw) = std::env::var(&variable) else {
+            continue;
+        };
+        log_line!("CONFIG", "Using [{}] {} from {}", section, field, variable);
+        // Strings stay as given, lists are comma-separated and anything
+        // else is read as JSON (numbers, true/false) or else a string
+        let value = match default {
+            serde_json::Value::String(_) => serde_json::Value::String(raw),
+            serde_json::Value::Array(_) => raw
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| serde_json::Value::String(item.to_string()))
+                .collect(),
+            _ => serde_json::from_str(&raw).unwrap_or(serde_json::Value::String(raw)),
+        };
+        overrides.insert(field, value);
+    }
+    if overrides.is_empty() {
+        return match found {
+            Some((value, path)) => {
+                parse_section(section, value, &path.display().to_string(), false)
+            }
+            None => T::default(),
+        };
+    }
+
+    let (mut value, origin) = match found {
+        Some((value @ serde_json::Value::Object(_), path)) => {
+            (value, format!("{} and the environment", path.display()))
+        }
+        _ => (
+            serde_json::Value::Object(Default::default()),
+            "the environment".to_string(),
+        ),
+    };
+    if let Some(fields) = value.as_object_mut() {
+        fields.extend(overrides);
+    }
+    parse_section(section, value, &origin, false)
+}
+
+// `[server]` section
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    // HTTP worker threads; one per CPU core when unset
+    pub workers: Option<usize>,
+    // Origins browsers may call the API from; any origin when empty
+    pub cors_origins: Vec<String>,
+    // How long SIGTERM/SIGINT waits for in-flight requests, and then for
+    // queued output batches, before exiting anyway
+    #[serde(deserialize_with = "de_duration")]
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "0.0.0.0:8080".to_string(),
+            workers: None,
+            cors_origins: Vec::new(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+// `[limits]` section
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    // Largest request body any endpoint reads, e.g. "2MiB" or bytes
+    #[serde(deserialize_with = "de_size")]
+    pub max_body_size: usize,
+    // Events one POST /api/events may carry; 0 for no limit
+    pub max_batch_events: usize,
+    // Events accepted but not yet stored, across every request, before
+    // senders get a 429; 0 for no limit
+    pub max_queued_events: usize,
+    // Retry-After sent with that 429
+    #[serde(deserialize_with = "de_duration")]
+    pub retry_after: Duration,
+    // Fraction of each limit past which senders are warned (see
+    // soft_limits.rs); 0 turns the warnings off
+    pub soft_limit: f64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_body_size: 2 * 1024 * 1024,
+            max_batch_events: 10_000,
+            max_queued_events: 50_000,
+            retry_after: Duration::from_secs(5),
+            soft_limit: 0.8,
+        }
+    }
+}
+
+// The sections the HTTP server itself is built from, loaded once at startup
+#[derive(Debug, Default)]
+pub struct ServeConfig {
+    pub server: ServerConfig,
+    pub limits: LimitsConfig,
+    pub tls: tls::TlsConfig,
+    pub storage: storage::StorageConfig,
+    pub retention: retention::RetentionConfig,
+}
+
+impl ServeConfig {
+    pub fn load() -> Self {
+        let mut server: ServerConfig = load_section_with_env("server");
+        // Kept from before every field had its own variable
+        if std::env::var_os("LOGLUMEN_SERVER_BIND_ADDRESS").is_none() {
+            if let Ok(address) = std::env::var("LOGLUMEN_BIND_ADDRESS") {
+                log_line!("CONFIG", "Using bind address from LOGLUMEN_BIND_ADDRESS");
+                server.bind_address = address;
+            }
+        }
+        ServeConfig {
+            server,
+            limits: load_section_with_env("limits"),
+            tls: load_section_with_env("tls"),
+            storage: load_section_with_env("storage"),
+            retention: load_section_with_env("retention"),
+        }
+    }
+}
+
+// Parse durations like "30s", "10m", "1h" or "7d". A bare number is seconds.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+
+    let multiplier = match unit.trim() {
+        "" | "s" | "sec" | "secs" => 1,
+        "m" | "min" | "mins" => 60,
+        "h" | "hr" | "hrs" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        _ => return None,
+    };
+
+    number.checked_mul(multiplier).map(Duration::from_secs)
+}
+
+// Parse sizes like "512KiB", "2MB" or "1GiB". A bare number is bytes.
+pub fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: usize = number.parse().ok()?;
+
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "kib" => 1024,
+        "m" | "mb" => 1000 * 1000,
+        "mib" => 1024 * 1024,
+        "g" | "gb" => 1000 * 1000 * 1000,
+        "gib" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    number.checked_mul(multiplier)
+}
+
+// serde helper for sizes, given as a string or a number of bytes
+pub fn de_size<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(usize),
+        Text(String),
+    }
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(bytes),
+        Size::Text(raw) => parse_size(&raw)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid size '{}'", raw))),
+    }
+}
+
+// serde helper for duration strings in config sections
+pub fn de_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    parse_duration(&raw)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid duration '{}'", raw)))
+}
+
+// The other way, as seconds (`"300s"`), for rules shown or saved as JSON
+pub fn ser_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format!("{}s", duration.as_secs()))
+}
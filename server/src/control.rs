@@ -0,0 +1,297 @@
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use actix_ws::Message;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Commands an agent understands; anything else is rejected before sending
+pub const COMMANDS: [&str; 3] = ["flush_now", "set_log_level", "send_inventory"];
+
+struct Connection {
+    serial: u64,
+    session: actix_ws::Session,
+    host_ipv4: String,
+    os: String,
+    connected_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PendingCommand {
+    id: u64,
+    host: String,
+    command: String,
+    issued_at: String,
+}
+
+// Live control connections, keyed by the agent's hostname
+#[derive(Default)]
+pub struct AgentChannels {
+    connections: Mutex<HashMap<String, Connection>>,
+    pending: Mutex<HashMap<u64, PendingCommand>>,
+    next_id: AtomicU64,
+    next_serial: AtomicU64,
+}
+
+// Messages the agent sends back over the socket
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AgentMessage {
+    Hello {
+        #[serde(default)]
+        host_ipv4: String,
+        #[serde(default)]
+        os: String,
+    },
+    Result {
+        id: u64,
+        status: String,
+        #[serde(default)]
+        detail: serde_json::Value,
+    },
+}
+
+impl AgentChannels {
+    // Turn a command result into a regular event so it shows up in the
+    // dashboard, rules and outputs like anything else the agent reports
+    fn result_event(&self, host: &str, id: u64, status: &str, detail: serde_json::Value) -> Event {
+        let pending = self.pending.lock().remove(&id);
+        let (host_ipv4, os) = self
+            .connections
+            .lock()
+            .get(host)
+            .map(|connection| (connection.host_ipv4.clone(), connection.os.clone()))
+            .unwrap_or_default();
+        let command = pending
+            .as_ref()
+            .map(|pending| pending.command.clone())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Event {
+            schema_version: 1,
+            category: "agent".to_string(),
+            event_type: "command_result".to_string(),
+            time: chrono::Utc::now().to_rfc3339(),
+            host: host.to_string(),
+            host_ipv4,
+            os,
+            source: "control".to_string(),
+            severity: if status == "ok" { "info" } else { "warning" }.to_string(),
+            message: format!("Command {} on {}: {}", command, host, status),
+            data: serde_json::json!({
+                "command_id": id,
+                "command": command,
+                "status": status,
+                "issued_at": pending.map(|pending| pending.issued_at),
+                "detail": detail,
+            }),
+            signature: None,
+            verification: None,
+            identity: None,
+            ttl: None,
+        }
+    }
+}
+
+// GET /api/agents/{host}/control - WebSocket the agent keeps open to receive
+// commands. A reconnect replaces the previous connection for that host.
+// Takes the same API key as /api/events, plus the agent's token once the
+// host is registered, so nobody else can take over its channel.
+pub async fn connect(
+    req: HttpRequest,
+    host: web::Path<String>,
+    body: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let host = host.into_inner();
+    if let Err(response) = data.api_keys.check(&req) {
+        return Ok(response);
+    }
+    if data.agents.token_matches(&req, &host) == Some(false) {
+        log_line!(
+            "WARN",
+            "Rejected control channel for {} without its agent token",
+            host
+        );
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "status": "error",
+            "message": format!(
+                "'{}' is a registered agent; its control channel needs its token in {}",
+                host,
+                crate::agents::TOKEN_HEADER
+            )
+        })));
+    }
+    let (response, session, mut stream) = actix_ws::handle(&req, body)?;
+    let peer_ip = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+
+    let serial = data.control.next_serial.fetch_add(1, Ordering::SeqCst);
+    data.control.connections.lock().insert(
+        host.clone(),
+        Connection {
+            serial,
+            session: session.clone(),
+            host_ipv4: peer_ip,
+            os: String::new(),
+            connected_at: chrono::Utc::now().to_rfc3339(),
+        },
+    );
+    log_line!("INFO", "Agent {} opened control channel", host);
+
+    actix_web::rt::spawn(async move {
+        let mut session = session;
+        while let Some(Ok(message)) = stream.recv().await {
+            match message {
+                Message::Text(text) => match serde_json::from_str::<AgentMessage>(&text) {
+                    Ok(AgentMessage::Hello { host_ipv4, os }) => {
+                        data.availability.seen(&host);
+                        if let Some(connection) = data.control.connections.lock().get_mut(&host) {
+                            if !host_ipv4.is_empty() {
+                                connection.host_ipv4 = host_ipv4;
+                            }
+                            connection.os = os;
+                        }
+                    }
+                    Ok(AgentMessage::Result { id, status, detail }) => {
+                        let event = data.control.result_event(&host, id, &status, detail);
+                        crate::ingest(&data, vec![event]).await;
+                    }
+                    Err(err) => {
+                        log_line!("WARN", "Bad control message from {}: {}", host, err);
+                    }
+                },
+                Message::Ping(bytes) if session.pong(&bytes).await.is_err() => break,
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+
+        // Only drop the entry if it is still ours and not a newer reconnect
+        let mut connections = data.control.connections.lock();
+        if connections
+            .get(&host)
+            .is_some_and(|connection| connection.serial == serial)
+        {
+            connections.remove(&host);
+        }
+        drop(connections);
+        log_line!("INFO", "Agent {} closed control channel", host);
+    });
+
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommandRequest {
+    command: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+// POST /api/admin/agents/{host}/commands - Send a command to a connected agent.
+// The result arrives later as an `agent`/`command_result` event.
+pub async fn send_command(
+    http_request: HttpRequest,
+    host: web::Path<String>,
+    request: web::Json<CommandRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let sender = match data.api_keys.authorize_admin(&http_request) {
+        Ok(sender) => sender,
+        Err(response) => return Ok(response),
+    };
+    let host = host.into_inner();
+    let request = request.into_inner();
+    if !COMMANDS.contains(&request.command.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": format!(
+                "unknown command '{}' (expected one of {})",
+                request.command,
+                COMMANDS.join(", ")
+            )
+        })));
+    }
+
+    let Some(mut session) = data
+        .control
+        .connections
+        .lock()
+        .get(&host)
+        .map(|connection| connection.session.clone())
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("agent '{}' has no open control channel", host)
+        })));
+    };
+
+    let id = data.control.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+    let pending = PendingCommand {
+        id,
+        host: host.clone(),
+        command: request.command.clone(),
+        issued_at: chrono::Utc::now().to_rfc3339(),
+    };
+    data.control.pending.lock().insert(id, pending.clone());
+
+    let message = serde_json::json!({
+        "type": "command",
+        "id": id,
+        "command": request.command,
+        "args": request.args,
+    });
+    if session.text(message.to_string()).await.is_err() {
+        data.control.pending.lock().remove(&id);
+        data.control.connections.lock().remove(&host);
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "status": "error",
+            "message": format!("control channel to '{}' is closed", host)
+        })));
+    }
+
+    log_line!(
+        "INFO",
+        "Sent {} to agent {} for {} (id {})",
+        request.command,
+        host,
+        sender,
+        id
+    );
+    Ok(HttpResponse::Accepted().json(pending))
+}
+
+// GET /api/admin/agents - Agents with an open control channel and commands
+// still waiting for a result
+pub async fn list_agents(request: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let mut agents: Vec<serde_json::Value> = data
+        .control
+        .connections
+        .lock()
+        .iter()
+        .map(|(host, connection)| {
+            serde_json::json!({
+                "host": host,
+                "host_ipv4": connection.host_ipv4,
+                "os": connection.os,
+                "connected_at": connection.connected_at,
+            })
+        })
+        .collect();
+    agents.sort_by(|a, b| a["host"].as_str().cmp(&b["host"].as_str()));
+
+    let mut pending: Vec<PendingCommand> = data.control.pending.lock().values().cloned().collect();
+    pending.sort_by_key(|command| command.id);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "agents": agents,
+        "pending": pending,
+    })))
+}
@@ -0,0 +1,268 @@
+// Crash reports for triage in the field. With `[crash] enabled`, every
+// panic, including one that aborts the process, is written to `dir` as a
+// JSON file with the full backtrace, the server version, a hash of the
+// config file and the store's counters. With `endpoint` set, reports are
+// also POSTed there: right away when the server survives the panic, and on
+// the next start for any that weren't delivered.
+use crate::panics::Report;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Suffix of a report the endpoint accepted
+const SENT: &str = ".sent.json";
+
+// `[crash]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CrashConfig {
+    enabled: bool,
+    // Relative to the working directory, or to data_dir under chroot
+    dir: String,
+    // Reports kept on disk, oldest removed first
+    keep: usize,
+    endpoint: Option<String>,
+    headers: HashMap<String, String>,
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        CrashConfig {
+            enabled: false,
+            dir: "crashes".to_string(),
+            keep: 20,
+            endpoint: None,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+struct Reporter {
+    config: CrashConfig,
+    // Path and SHA-256 of the config file the server started with
+    config_file: Option<(String, String)>,
+    started: chrono::DateTime<chrono::Utc>,
+    uploads: mpsc::UnboundedSender<PathBuf>,
+    pending: parking_lot::Mutex<Option<mpsc::UnboundedReceiver<PathBuf>>>,
+}
+
+static REPORTER: OnceLock<Reporter> = OnceLock::new();
+static STORE: OnceLock<Arc<dyn Storage>> = OnceLock::new();
+
+#[derive(Serialize)]
+struct CrashReport<'a> {
+    #[serde(flatten)]
+    panic: &'a Report,
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    pid: u32,
+    os: &'static str,
+    arch: &'static str,
+    started: String,
+    config_file: Option<&'a str>,
+    config_sha256: Option<&'a str>,
+    store: Option<crate::storage::StoreStats>,
+    backtrace: String,
+}
+
+pub fn init(config: CrashConfig) {
+    if !config.enabled {
+        return;
+    }
+    let config_file = crate::config::candidate_paths()
+        .into_iter()
+        .find_map(|path| {
+            let contents = std::fs::read(&path).ok()?;
+            let digest = Sha256::digest(&contents);
+            let hash = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+            Some((path.display().to_string(), hash))
+        });
+    log_line!(
+        "CONFIG",
+        "Crash reports written to {}{}",
+        config.dir,
+        config
+            .endpoint
+            .as_deref()
+            .map(|endpoint| format!(" and sent to {}", endpoint))
+            .unwrap_or_default()
+    );
+    let (uploads, pending) = mpsc::unbounded_channel();
+    let _ = REPORTER.set(Reporter {
+        config,
+        config_file,
+        started: chrono::Utc::now(),
+        uploads,
+        pending: parking_lot::Mutex::new(Some(pending)),
+    });
+}
+
+// Adds the store's counters to later reports. They are atomics, so reading
+// them from the panic hook never waits on the store's lock.
+pub fn watch(store: Arc<dyn Storage>) {
+    let _ = STORE.set(store);
+}
+
+// Called from the panic hook; must not panic itself
+pub fn write(panic: &Report, backtrace: String) {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+    let (config_file, config_sha256) = match &reporter.config_file {
+        Some((path, hash)) => (Some(path.as_str()), Some(hash.as_str())),
+        None => (None, None),
+    };
+    let report = CrashReport {
+        panic,
+        version: crate::version::VERSION,
+        git_commit: crate::version::commit(),
+        pid: std::process::id(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        started: reporter.started.to_rfc3339(),
+        config_file,
+        config_sha256,
+        store: STORE.get().map(|store| store.stats()),
+        backtrace,
+    };
+    let dir = Path::new(&reporter.config.dir);
+    let name = format!(
+        "crash-{}-{}-{}.json",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+        report.pid,
+        panic.id
+    );
+    let path = dir.join(name);
+    let written = serde_json::to_vec_pretty(&report)
+        .map_err(|err| err.to_string())
+        .and_then(|json| {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            let partial = path.with_extension("tmp");
+            std::fs::write(&partial, json).map_err(|err| err.to_string())?;
+            std::fs::rename(&partial, &path).map_err(|err| err.to_string())
+        });
+    match written {
+        Ok(()) => {
+            log_line!("ERROR", "Crash report written to {}", path.display());
+            prune(dir, reporter.config.keep);
+            if reporter.config.endpoint.is_some() {
+                let _ = reporter.uploads.send(path);
+            }
+        }
+        Err(err) => log_line!(
+            "ERROR",
+            "Cannot write crash report to {}: {}",
+            dir.display(),
+            err
+        ),
+    }
+}
+
+// Report files, oldest first (names start with their time)
+fn reports(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("crash-") && name.ends_with(".json"))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn prune(dir: &Path, keep: usize) {
+    let paths = reports(dir);
+    for path in &paths[..paths.len().saturating_sub(keep.max(1))] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+async fn upload(
+    client: &reqwest::Client,
+    endpoint: &str,
+    headers: &reqwest::header::HeaderMap,
+    path: &Path,
+) -> Result<(), String> {
+    let body = tokio::fs::read(path).await.map_err(|err| err.to_string())?;
+    let response = client
+        .post(endpoint)
+        .headers(headers.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("{} returned {}", endpoint, response.status()));
+    }
+    let name = path.to_string_lossy();
+    let sent = format!("{}{}", name.trim_end_matches(".json"), SENT);
+    tokio::fs::rename(path, sent)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+// Sends reports left over from earlier runs, then each new one as it is
+// written
+pub fn spawn_uploader() {
+    let Some(reporter) = REPORTER.get() else {
+        return;
+    };
+    let Some(endpoint) = reporter.config.endpoint.clone() else {
+        return;
+    };
+    let Some(mut pending) = reporter.pending.lock().take() else {
+        return;
+    };
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in &reporter.config.headers {
+        match (
+            reqwest::header::HeaderName::from_bytes(name.as_bytes()),
+            reqwest::header::HeaderValue::from_str(value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                headers.insert(name, value);
+            }
+            _ => log_line!("WARN", "Ignoring invalid [crash] header '{}'", name),
+        }
+    }
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log_line!("ERROR", "Crash report uploads disabled: {}", err);
+            return;
+        }
+    };
+    let earlier: Vec<PathBuf> = reports(Path::new(&reporter.config.dir))
+        .into_iter()
+        .filter(|path| !path.to_string_lossy().ends_with(SENT))
+        .collect();
+    actix_web::rt::spawn(async move {
+        for path in earlier {
+            if let Err(err) = upload(&client, &endpoint, &headers, &path).await {
+                log_line!("WARN", "Crash report {} not sent: {}", path.display(), err);
+            }
+        }
+        while let Some(path) = pending.recv().await {
+            match upload(&client, &endpoint, &headers, &path).await {
+                Ok(()) => log_line!("INFO", "Crash report sent to {}", endpoint),
+                Err(err) => {
+                    log_line!("WARN", "Crash report {} not sent: {}", path.display(), err)
+                }
+            }
+        }
+    });
+}
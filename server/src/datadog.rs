@@ -0,0 +1,270 @@
+use crate::{severity, AppState, Event};
+use actix_web::http::{header, StatusCode};
+use actix_web::{dev, web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+// Largest intake body accepted, after decompression
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+// `[datadog]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DatadogConfig {
+    category: String,
+    // Values accepted in `DD-API-KEY`; without any, the key is checked
+    // against `[api_keys]` like the bearer key on /api/events
+    api_keys: Vec<String>,
+}
+
+impl Default for DatadogConfig {
+    fn default() -> Self {
+        DatadogConfig {
+            category: "logs".to_string(),
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+pub struct Datadog {
+    config: DatadogConfig,
+}
+
+impl Datadog {
+    pub fn new(config: DatadogConfig) -> Self {
+        Datadog { config }
+    }
+}
+
+// Reserved attributes the intake also takes from the query string, as
+// defaults for every entry
+#[derive(Debug, Default, Deserialize)]
+pub struct IntakeQuery {
+    ddtags: Option<String>,
+    ddsource: Option<String>,
+    service: Option<String>,
+    hostname: Option<String>,
+    #[serde(rename = "dd-api-key")]
+    api_key: Option<String>,
+}
+
+fn reply(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({"errors": [message]}))
+}
+
+fn authorize(
+    request: &HttpRequest,
+    query: &IntakeQuery,
+    data: &AppState,
+) -> std::result::Result<(), HttpResponse> {
+    let presented = request
+        .headers()
+        .get("DD-API-KEY")
+        .and_then(|value| value.to_str().ok())
+        .or(query.api_key.as_deref())
+        .map(str::trim);
+    let keys = &data.datadog.config.api_keys;
+    let accepted = if keys.is_empty() {
+        data.api_keys.accepts(request, presented)
+    } else {
+        presented.is_some_and(|key| keys.iter().any(|known| known == key))
+    };
+    if accepted {
+        Ok(())
+    } else {
+        Err(reply(StatusCode::FORBIDDEN, "Forbidden"))
+    }
+}
+
+// `env:prod,team:payments,canary` as {"env": "prod", "team": "payments",
+// "canary": ""}; later tags win over earlier ones with the same key
+fn parse_tags(tags: &str, into: &mut Map<String, Value>) {
+    for tag in tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+        let (key, value) = tag.split_once(':').unwrap_or((tag, ""));
+        into.insert(key.to_string(), Value::String(value.to_string()));
+    }
+}
+
+// Datadog statuses include syslog's; severity::normalize knows the rest
+fn severity(status: &str) -> &'static str {
+    match status.to_ascii_lowercase().as_str() {
+        "emerg" | "emergency" | "alert" => "critical",
+        "notice" | "ok" | "success" => "info",
+        other => severity::normalize(other),
+    }
+}
+
+// Epoch milliseconds (what the Datadog Agent sends), seconds or RFC 3339
+fn parse_time(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(number) => {
+            let number = number.as_i64()?;
+            if number > 100_000_000_000 {
+                DateTime::from_timestamp_millis(number)
+            } else {
+                DateTime::from_timestamp(number, 0)
+            }
+        }
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+fn to_event(
+    category: &str,
+    mut entry: Map<String, Value>,
+    query: &IntakeQuery,
+    peer: &str,
+) -> Event {
+    let mut take = |key: &str, fallback: &Option<String>| match entry.remove(key) {
+        Some(Value::String(text)) if !text.is_empty() => Some(text),
+        Some(Value::Null) | None => fallback.clone(),
+        Some(other) => Some(other.to_string()),
+    };
+    let message = take("message", &None).unwrap_or_default();
+    let hostname = take("hostname", &query.hostname);
+    let service = take("service", &query.service);
+    let ddsource = take("ddsource", &query.ddsource);
+    let status = take("status", &None).or_else(|| take("level", &None));
+    let entry_tags = take("ddtags", &None);
+    let time = entry
+        .remove("timestamp")
+        .as_ref()
+        .and_then(parse_time)
+        .unwrap_or_else(Utc::now);
+
+    let mut tags = Map::new();
+    for source in [&query.ddtags, &entry_tags].into_iter().flatten() {
+        parse_tags(source, &mut tags);
+    }
+    let host = hostname
+        .or_else(|| tags.get("host").and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_else(|| peer.to_string());
+    let event_type = service
+        .clone()
+        .or_else(|| ddsource.clone())
+        .unwrap_or_else(|| "datadog".to_string());
+
+    let mut data = entry;
+    for (key, value) in [("service", service), ("ddsource", ddsource)] {
+        if let Some(value) = value {
+            data.insert(key.to_string(), Value::String(value));
+        }
+    }
+    if !tags.is_empty() {
+        data.insert("tags".to_string(), Value::Object(tags));
+    }
+
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type,
+        time: time.to_rfc3339(),
+        host,
+        host_ipv4: peer.to_string(),
+        os: String::new(),
+        source: "datadog".to_string(),
+        severity: status
+            .map(|status| severity(&status).to_string())
+            .unwrap_or_else(|| "info".to_string()),
+        message,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// POST /api/v2/logs (and the older /v1/input) - Datadog's log intake, so
+// the Datadog Agent and its libraries can ship here while migrating off.
+// Takes a JSON array of log entries, a single entry or, as text/plain, one
+// message per line. `ddtags` become `data.tags`; other attributes stay in
+// `data`. Answers 202 like Datadog does.
+pub async fn intake(
+    request: HttpRequest,
+    query: web::Query<IntakeQuery>,
+    payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = authorize(&request, &query, &data) {
+        return Ok(response);
+    }
+    // The Agent gzips its payloads
+    let mut payload = dev::Decompress::from_headers(payload.into_inner(), request.headers());
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY {
+            return Ok(reply(StatusCode::PAYLOAD_TOO_LARGE, "Payload too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let plain_text = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/plain"));
+    let entries: Vec<Map<String, Value>> = if plain_text {
+        String::from_utf8_lossy(&body)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Map::from_iter([("message".to_string(), Value::String(line.to_string()))]))
+            .collect()
+    } else {
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Array(items)) => {
+                let mut entries = Vec::with_capacity(items.len());
+                for (number, item) in items.into_iter().enumerate() {
+                    match item {
+                        Value::Object(entry) => entries.push(entry),
+                        _ => {
+                            return Ok(reply(
+                                StatusCode::BAD_REQUEST,
+                                &format!("log entry {} is not a JSON object", number),
+                            ))
+                        }
+                    }
+                }
+                entries
+            }
+            Ok(Value::Object(entry)) => vec![entry],
+            Ok(_) => {
+                return Ok(reply(
+                    StatusCode::BAD_REQUEST,
+                    "expected a JSON array of log entries",
+                ))
+            }
+            Err(err) => {
+                return Ok(reply(
+                    StatusCode::BAD_REQUEST,
+                    &format!("invalid JSON: {}", err),
+                ))
+            }
+        }
+    };
+
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let identity = crate::tls::client_identity(&request);
+    let batch: Vec<Event> = entries
+        .into_iter()
+        .map(|entry| {
+            let mut event = to_event(&data.datadog.config.category, entry, &query, &peer);
+            event.identity = identity.clone();
+            event
+        })
+        .collect();
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+    Ok(HttpResponse::Accepted().json(json!({})))
+}
@@ -0,0 +1,331 @@
+use crate::AppState;
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Uri};
+use actix_web::middleware::Next;
+use actix_web::web;
+use parking_lot::RwLock;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+// Pseudonyms handed out are remembered so links built from them resolve;
+// past this many the map starts over
+const MAX_PSEUDONYMS: usize = 100_000;
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+// `[demo]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DemoConfig {
+    // Serve every API response with hosts, IPs and users pseudonymized
+    anonymize: bool,
+    // Keeps pseudonyms stable across restarts; a random one is used if unset
+    salt: Option<String>,
+    // JSON keys, anywhere in a response, whose values are hostnames
+    host_fields: Vec<String>,
+    // Keys holding user names
+    user_fields: Vec<String>,
+    // Keys holding IP addresses. IPv4 addresses are also replaced inside any
+    // other text, such as event messages.
+    ip_fields: Vec<String>,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        DemoConfig {
+            anonymize: false,
+            salt: None,
+            host_fields: strings(&["host", "hostname", "node", "nodes", "computer"]),
+            user_fields: strings(&["user", "username", "user_name", "target_user", "account"]),
+            ip_fields: strings(&[
+                "host_ipv4",
+                "ip",
+                "src_ip",
+                "dst_ip",
+                "remote_ip",
+                "source_ip",
+                "client_ip",
+                "peer",
+            ]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Host,
+    User,
+    Ip,
+}
+
+pub struct Demo {
+    enabled: bool,
+    salt: String,
+    fields: HashMap<String, Kind>,
+    // Pseudonym -> real value, for requests that use a pseudonym
+    reverse: RwLock<HashMap<String, String>>,
+}
+
+impl Demo {
+    pub fn new(config: DemoConfig) -> Self {
+        if config.anonymize {
+            log_line!(
+                "CONFIG",
+                "Demo mode: hosts, IPs and users are pseudonymized"
+            );
+        }
+        let salt = config.salt.unwrap_or_else(|| {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_nanos())
+                .unwrap_or_default();
+            format!("{}-{}", nanos, std::process::id())
+        });
+
+        let mut fields = HashMap::new();
+        for (keys, kind) in [
+            (config.host_fields, Kind::Host),
+            (config.user_fields, Kind::User),
+            (config.ip_fields, Kind::Ip),
+        ] {
+            for key in keys {
+                fields.insert(key, kind);
+            }
+        }
+
+        Demo {
+            enabled: config.anonymize,
+            salt,
+            fields,
+            reverse: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn pseudonym(&self, kind: Kind, real: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update([kind as u8]);
+        hasher.update(real.as_bytes());
+        let digest = hasher.finalize();
+        let fake = match kind {
+            Kind::Host => format!("host-{:02x}{:02x}{:02x}", digest[0], digest[1], digest[2]),
+            Kind::User => format!("user-{:02x}{:02x}{:02x}", digest[0], digest[1], digest[2]),
+            Kind::Ip => format!("10.{}.{}.{}", digest[0], digest[1], digest[2]),
+        };
+
+        if !self.reverse.read().contains_key(&fake) {
+            let mut reverse = self.reverse.write();
+            if reverse.len() >= MAX_PSEUDONYMS {
+                reverse.clear();
+            }
+            reverse.insert(fake.clone(), real.to_string());
+        }
+        fake
+    }
+
+    // Real values of the pseudonymized fields, so the same names can be
+    // replaced where they show up in free text
+    fn collect(&self, value: &serde_json::Value, names: &mut HashSet<(Kind, String)>) {
+        match value {
+            serde_json::Value::Object(object) => {
+                for (key, child) in object {
+                    match (self.fields.get(key), child) {
+                        (Some(kind), serde_json::Value::String(text)) if !text.is_empty() => {
+                            names.insert((*kind, text.clone()));
+                        }
+                        (Some(kind), serde_json::Value::Array(items)) => {
+                            for text in items.iter().filter_map(|item| item.as_str()) {
+                                names.insert((*kind, text.to_string()));
+                            }
+                        }
+                        _ => self.collect(child, names),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.collect(item, names);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rewrite_text(&self, text: &str, names: &[(String, String)]) -> String {
+        let mut text = replace_ipv4(text, |ip| self.pseudonym(Kind::Ip, ip));
+        for (real, fake) in names {
+            if text.contains(real.as_str()) {
+                text = text.replace(real.as_str(), fake);
+            }
+        }
+        text
+    }
+
+    fn rewrite(&self, value: &mut serde_json::Value, names: &[(String, String)]) {
+        match value {
+            serde_json::Value::Object(object) => {
+                let keys: Vec<String> = object.keys().cloned().collect();
+                for key in keys {
+                    // Maps keyed by hostname or address, like per-node counts
+                    let renamed = self.rewrite_text(&key, names);
+                    if renamed != key {
+                        if let Some(child) = object.remove(&key) {
+                            object.insert(renamed.clone(), child);
+                        }
+                    }
+                    if let Some(child) = object.get_mut(&renamed) {
+                        self.rewrite(child, names);
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.rewrite(item, names);
+                }
+            }
+            serde_json::Value::String(text) => *text = self.rewrite_text(text, names),
+            _ => {}
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn anonymize(&self, value: &mut serde_json::Value) {
+        let mut found = HashSet::new();
+        self.collect(value, &mut found);
+        // Longest first, so "web01-db" is not rewritten as "<web01>-db"
+        let mut names: Vec<(String, String)> = found
+            .into_iter()
+            // IPv4 addresses are replaced wherever they appear anyway; doing
+            // them again here could match inside an address already replaced
+            .filter(|(kind, real)| *kind != Kind::Ip || real.parse::<std::net::Ipv4Addr>().is_err())
+            .map(|(kind, real)| {
+                let fake = self.pseudonym(kind, &real);
+                (real, fake)
+            })
+            .collect();
+        names.sort_by_key(|(real, _)| std::cmp::Reverse(real.len()));
+        self.rewrite(value, &names);
+    }
+
+    // Swap pseudonyms in the path and query back to the real values
+    fn resolve(&self, text: &str) -> String {
+        let reverse = self.reverse.read();
+        text.split('/')
+            .map(|segment| {
+                let decoded = percent_decode_str(segment).decode_utf8_lossy();
+                match reverse.get(decoded.as_ref()) {
+                    Some(real) => utf8_percent_encode(real, NON_ALPHANUMERIC).to_string(),
+                    None => segment.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn resolve_uri(&self, uri: &Uri) -> Option<Uri> {
+        let path = self.resolve(uri.path());
+        let query = uri.query().map(|query| {
+            query
+                .split('&')
+                .map(|pair| match pair.split_once('=') {
+                    Some((name, value)) => {
+                        format!("{}={}", name, self.resolve(value))
+                    }
+                    None => pair.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("&")
+        });
+        let rebuilt = match query {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        (rebuilt != uri.path_and_query().map_or("", |pq| pq.as_str()))
+            .then(|| rebuilt.parse().ok())
+            .flatten()
+    }
+}
+
+// Replace every dotted-quad IPv4 address in `text`
+fn replace_ipv4(text: &str, mut replace: impl FnMut(&str) -> String) -> String {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        let boundary =
+            index == 0 || !(bytes[index - 1].is_ascii_digit() || bytes[index - 1] == b'.');
+        if !boundary || !bytes[index].is_ascii_digit() {
+            index += 1;
+            continue;
+        }
+        let end = bytes[index..]
+            .iter()
+            .position(|byte| !(byte.is_ascii_digit() || *byte == b'.'))
+            .map_or(bytes.len(), |offset| index + offset);
+        let candidate = text[index..end].trim_end_matches('.');
+        if candidate.parse::<std::net::Ipv4Addr>().is_ok() {
+            out.push_str(&text[copied..index]);
+            out.push_str(&replace(candidate));
+            copied = index + candidate.len();
+        }
+        index = end;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+// Middleware: pseudonymizes JSON responses and maps pseudonyms in request
+// URLs back, so the dashboard's links keep working in demo mode
+pub async fn anonymize(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(data) = request
+        .app_data::<web::Data<AppState>>()
+        .cloned()
+        .filter(|data| data.demo.enabled)
+    else {
+        return Ok(next.call(request).await?.map_into_boxed_body());
+    };
+
+    if let Some(uri) = data.demo.resolve_uri(request.uri()) {
+        request.match_info_mut().get_mut().update(&uri);
+        request.head_mut().uri = uri;
+    }
+
+    let response = next.call(request).await?.map_into_boxed_body();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return Ok(response);
+    }
+
+    let (request, response) = response.into_parts();
+    let (response, payload) = response.into_parts();
+    let bytes = body::to_bytes(payload)
+        .await
+        .map_err(|err| actix_web::error::ErrorInternalServerError(err.to_string()))?;
+    let bytes = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(mut value) => {
+            data.demo.anonymize(&mut value);
+            serde_json::to_vec(&value).unwrap_or_default().into()
+        }
+        Err(_) => bytes,
+    };
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(bytes)),
+    ))
+}
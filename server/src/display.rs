@@ -0,0 +1,211 @@
+// Timestamps formatted once on the server, in one locale and time zone, so
+// thin clients can show them as-is and every user sees the same times.
+// Responses keep the RFC 3339 value next to each `*_display` one.
+use crate::Event;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+
+// Locale -> date and time layout, close to what browsers print for it
+const LOCALES: &[(&str, &str)] = &[
+    ("en-US", "%m/%d/%Y, %I:%M:%S %p"),
+    ("en-GB", "%d/%m/%Y, %H:%M:%S"),
+    ("en-AU", "%d/%m/%Y, %I:%M:%S %p"),
+    ("en-CA", "%Y-%m-%d, %I:%M:%S %p"),
+    ("de-DE", "%d.%m.%Y, %H:%M:%S"),
+    ("fr-FR", "%d/%m/%Y %H:%M:%S"),
+    ("es-ES", "%d/%m/%Y, %H:%M:%S"),
+    ("it-IT", "%d/%m/%Y, %H:%M:%S"),
+    ("nl-NL", "%d-%m-%Y, %H:%M:%S"),
+    ("pt-BR", "%d/%m/%Y, %H:%M:%S"),
+    ("pl-PL", "%d.%m.%Y, %H:%M:%S"),
+    ("ru-RU", "%d.%m.%Y, %H:%M:%S"),
+    ("sv-SE", "%Y-%m-%d %H:%M:%S"),
+    ("ja-JP", "%Y/%m/%d %H:%M:%S"),
+    ("zh-CN", "%Y/%m/%d %H:%M:%S"),
+    ("ko-KR", "%Y. %m. %d. %H:%M:%S"),
+];
+
+// `[display]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    // One of LOCALES, e.g. "de-DE"
+    locale: String,
+    // "UTC", "local" for the server's zone (named zones via the TZ
+    // environment variable) or a fixed offset such as "+05:30"
+    timezone: String,
+    // strftime pattern replacing the locale's layout
+    format: Option<String>,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            locale: "en-US".to_string(),
+            timezone: "UTC".to_string(),
+            format: None,
+        }
+    }
+}
+
+enum Zone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+fn parse_zone(name: &str) -> Option<Zone> {
+    if name.eq_ignore_ascii_case("utc") || name == "Z" {
+        return Some(Zone::Utc);
+    }
+    if name.eq_ignore_ascii_case("local") {
+        return Some(Zone::Local);
+    }
+    // "+05:30", "-08:00", "+0530" or "UTC+02:00"
+    let offset = name
+        .strip_prefix("UTC")
+        .or_else(|| name.strip_prefix("utc"))
+        .unwrap_or(name);
+    let (sign, digits) = match offset.as_bytes().first()? {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Zone::Fixed)
+}
+
+pub struct TimeDisplay {
+    locale: String,
+    timezone: String,
+    zone: Zone,
+    pattern: String,
+    // Layouts from LOCALES say which zone they are in; custom ones choose
+    suffix_zone: bool,
+}
+
+impl TimeDisplay {
+    pub fn new(config: DisplayConfig) -> Self {
+        let locale = match LOCALES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&config.locale.replace('_', "-")))
+        {
+            Some((name, _)) => name.to_string(),
+            None => {
+                log_line!(
+                    "WARN",
+                    "Unknown [display] locale '{}'; using en-US",
+                    config.locale
+                );
+                "en-US".to_string()
+            }
+        };
+        let (zone, timezone) = match parse_zone(config.timezone.trim()) {
+            Some(zone) => (zone, config.timezone.trim().to_string()),
+            None => {
+                log_line!(
+                    "WARN",
+                    "Unknown [display] timezone '{}'; using UTC",
+                    config.timezone
+                );
+                (Zone::Utc, "UTC".to_string())
+            }
+        };
+        let custom = config.format.filter(|format| {
+            let valid = !format.is_empty()
+                && !StrftimeItems::new(format).any(|item| matches!(item, Item::Error));
+            if !valid {
+                log_line!(
+                    "WARN",
+                    "Invalid [display] format '{}'; using the locale's",
+                    format
+                );
+            }
+            valid
+        });
+        let suffix_zone = custom.is_none();
+        let pattern = custom.unwrap_or_else(|| {
+            LOCALES
+                .iter()
+                .find(|(name, _)| *name == locale)
+                .map_or("%Y-%m-%d %H:%M:%S", |(_, pattern)| pattern)
+                .to_string()
+        });
+        TimeDisplay {
+            locale,
+            timezone,
+            zone,
+            pattern,
+            suffix_zone,
+        }
+    }
+
+    pub fn format_time(&self, time: DateTime<Utc>) -> String {
+        let (text, offset) = match &self.zone {
+            Zone::Utc => (time.format(&self.pattern).to_string(), None),
+            Zone::Local => {
+                let local = time.with_timezone(&Local);
+                (
+                    local.format(&self.pattern).to_string(),
+                    Some(local.format("%:z").to_string()),
+                )
+            }
+            Zone::Fixed(offset) => {
+                let shifted = time.with_timezone(offset);
+                (
+                    shifted.format(&self.pattern).to_string(),
+                    Some(shifted.format("%:z").to_string()),
+                )
+            }
+        };
+        if !self.suffix_zone {
+            return text;
+        }
+        match offset.filter(|offset| offset != "+00:00") {
+            Some(offset) => format!("{} UTC{}", text, offset),
+            None => format!("{} UTC", text),
+        }
+    }
+
+    // None for a timestamp that isn't RFC 3339, which clients show raw
+    pub fn format(&self, time: &str) -> Option<String> {
+        DateTime::parse_from_rfc3339(time)
+            .ok()
+            .map(|time| self.format_time(time.with_timezone(&Utc)))
+    }
+
+    // `event` may be owned or a reference
+    pub fn event<E: Borrow<Event>>(&self, event: E) -> DisplayedEvent<E> {
+        let time_display = self.format(&event.borrow().time);
+        DisplayedEvent {
+            event,
+            time_display,
+        }
+    }
+
+    // What clients are told the `*_display` values are in
+    pub fn settings(&self) -> serde_json::Value {
+        serde_json::json!({
+            "locale": self.locale,
+            "timezone": self.timezone,
+        })
+    }
+}
+
+// An event with its time as shown to users
+#[derive(Debug, Serialize)]
+pub struct DisplayedEvent<E> {
+    #[serde(flatten)]
+    event: E,
+    time_display: Option<String>,
+}
@@ -0,0 +1,488 @@
+// Built with `--features docker`. Talks to the Docker Engine API over its
+// Unix socket with plain HTTP/1.1, so it needs no extra dependencies.
+use crate::{AppState, Event};
+use actix_web::web;
+use parking_lot::Mutex;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+
+// Docker Engine API version; 1.41 is supported by Docker 20.10 and later
+const API_VERSION: &str = "v1.41";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Events buffered between the socket readers and the store
+const QUEUE_SIZE: usize = 10_000;
+const MAX_BATCH: usize = 1000;
+
+// `[docker]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DockerConfig {
+    enabled: bool,
+    socket: String,
+    // Also follow stdout/stderr of every running container
+    logs: bool,
+    // Host name stored on the events; defaults to this machine's
+    host: Option<String>,
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        DockerConfig {
+            enabled: false,
+            socket: "/var/run/docker.sock".to_string(),
+            logs: false,
+            host: None,
+        }
+    }
+}
+
+impl DockerConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+fn local_hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its full length and gethostname
+    // NUL-terminates on success
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return "localhost".to_string();
+    }
+    let end = buffer
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).into_owned()
+}
+
+// Response body of one streaming request, de-chunked if needed
+struct Body {
+    reader: BufReader<UnixStream>,
+    chunked: bool,
+    // Bytes left in the current chunk
+    left: usize,
+    started: bool,
+    done: bool,
+    pending: Vec<u8>,
+}
+
+impl Body {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.chunked {
+            return self.reader.read(buf).await;
+        }
+        if self.left == 0 {
+            if self.done {
+                return Ok(0);
+            }
+            let mut line = String::new();
+            // Each chunk after the first starts after the previous one's CRLF
+            if self.started {
+                self.reader.read_line(&mut line).await?;
+                line.clear();
+            }
+            self.started = true;
+            self.reader.read_line(&mut line).await?;
+            let size = line.trim().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad chunk size"))?;
+            if size == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+            self.left = size;
+        }
+        let limit = buf.len().min(self.left);
+        let read = self.reader.read(&mut buf[..limit]).await?;
+        self.left -= read;
+        Ok(read)
+    }
+
+    // Reads until `pending` holds at least `count` bytes; false at end of body
+    async fn fill(&mut self, count: usize) -> io::Result<bool> {
+        let mut buf = [0u8; 8192];
+        while self.pending.len() < count {
+            let read = self.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(false);
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+        }
+        Ok(true)
+    }
+
+    async fn next_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = [0u8; 8192];
+        loop {
+            if let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+                let mut line: Vec<u8> = self.pending.drain(..=end).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                return Ok(Some(line));
+            }
+            let read = self.read(&mut buf).await?;
+            if read == 0 {
+                if self.pending.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(std::mem::take(&mut self.pending)));
+            }
+            self.pending.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    async fn next_bytes(&mut self, count: usize) -> io::Result<Option<Vec<u8>>> {
+        if !self.fill(count).await? {
+            return Ok(None);
+        }
+        Ok(Some(self.pending.drain(..count).collect()))
+    }
+
+    async fn read_to_end(&mut self) -> io::Result<Vec<u8>> {
+        self.fill(usize::MAX).await?;
+        Ok(std::mem::take(&mut self.pending))
+    }
+}
+
+async fn get(socket: &str, path: &str) -> io::Result<Body> {
+    let stream = UnixStream::connect(socket).await?;
+    let mut reader = BufReader::new(stream);
+    let request = format!(
+        "GET /{}{} HTTP/1.1\r\nHost: docker\r\nConnection: close\r\n\r\n",
+        API_VERSION, path
+    );
+    reader.get_mut().write_all(request.as_bytes()).await?;
+
+    let mut status = String::new();
+    reader.read_line(&mut status).await?;
+    let code = status
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut chunked = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+            {
+                chunked = true;
+            }
+        }
+    }
+
+    let mut body = Body {
+        reader,
+        chunked,
+        left: 0,
+        started: false,
+        done: false,
+        pending: Vec::new(),
+    };
+    if code != "200" {
+        let detail = body.read_to_end().await.unwrap_or_default();
+        return Err(io::Error::other(format!(
+            "{} returned {}: {}",
+            path,
+            code,
+            String::from_utf8_lossy(&detail).trim()
+        )));
+    }
+    Ok(body)
+}
+
+#[derive(Debug, Deserialize)]
+struct EngineEvent {
+    #[serde(rename = "Action", default)]
+    action: String,
+    #[serde(rename = "Actor", default)]
+    actor: Actor,
+    #[serde(rename = "timeNano", default)]
+    time_nano: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Actor {
+    #[serde(rename = "ID", default)]
+    id: String,
+    #[serde(rename = "Attributes", default)]
+    attributes: HashMap<String, String>,
+}
+
+struct Collector {
+    socket: String,
+    host: String,
+    logs: bool,
+    queue: mpsc::Sender<Event>,
+    // Containers whose logs are being followed
+    following: Mutex<HashSet<String>>,
+}
+
+impl Collector {
+    fn event(&self, category: &str, event_type: String, severity: &str) -> Event {
+        Event {
+            schema_version: 1,
+            category: category.to_string(),
+            event_type,
+            time: chrono::Utc::now().to_rfc3339(),
+            host: self.host.clone(),
+            host_ipv4: String::new(),
+            os: "linux".to_string(),
+            source: "docker".to_string(),
+            severity: severity.to_string(),
+            message: String::new(),
+            data: serde_json::Value::Null,
+            signature: None,
+            verification: None,
+            identity: None,
+            ttl: None,
+        }
+    }
+
+    fn lifecycle_event(&self, engine: EngineEvent) -> Event {
+        // "exec_start: sh -c ..." -> "exec_start"
+        let action = engine
+            .action
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let attributes = &engine.actor.attributes;
+        let name = attributes.get("name").cloned().unwrap_or_default();
+        let image = attributes.get("image").cloned().unwrap_or_default();
+        let exit_code = attributes.get("exitCode").cloned();
+
+        let severity = match action.as_str() {
+            "oom" => "critical",
+            "die" if exit_code.as_deref().is_some_and(|code| code != "0") => "error",
+            "kill" => "warning",
+            "health_status" if engine.action.contains("unhealthy") => "warning",
+            _ => "info",
+        };
+
+        let mut event = self.event("container", format!("container_{}", action), severity);
+        if engine.time_nano > 0 {
+            event.time = chrono::DateTime::from_timestamp_nanos(engine.time_nano).to_rfc3339();
+        }
+        event.message = match &exit_code {
+            Some(code) if action == "die" => {
+                format!("Container {} ({}) exited with code {}", name, image, code)
+            }
+            _ => format!("Container {} ({}) {}", name, image, engine.action),
+        };
+        event.data = serde_json::json!({
+            "container_id": engine.actor.id,
+            "container_name": name,
+            "image": image,
+            "action": engine.action,
+            "exit_code": exit_code,
+            "attributes": attributes,
+        });
+        event
+    }
+
+    async fn watch_events(self: Arc<Self>) -> io::Result<()> {
+        let filters = utf8_percent_encode(r#"{"type":["container"]}"#, NON_ALPHANUMERIC);
+        let mut body = get(&self.socket, &format!("/events?filters={}", filters)).await?;
+        log_line!("OK", "Docker events connected ({})", self.socket);
+
+        if self.logs {
+            self.follow_running().await;
+        }
+
+        while let Some(line) = body.next_line().await? {
+            if line.is_empty() {
+                continue;
+            }
+            let engine: EngineEvent = match serde_json::from_slice(&line) {
+                Ok(engine) => engine,
+                Err(err) => {
+                    log_line!("WARN", "Unreadable Docker event: {}", err);
+                    continue;
+                }
+            };
+            if self.logs && engine.action == "start" {
+                self.clone().follow(engine.actor.id.clone());
+            }
+            if self.queue.try_send(self.lifecycle_event(engine)).is_err() {
+                log_line!("WARN", "Docker event queue full, dropping event");
+            }
+        }
+        Err(io::Error::other("event stream closed"))
+    }
+
+    async fn follow_running(self: &Arc<Self>) {
+        #[derive(Deserialize)]
+        struct Summary {
+            #[serde(rename = "Id")]
+            id: String,
+        }
+        let listing = match get(&self.socket, "/containers/json").await {
+            Ok(mut body) => body.read_to_end().await,
+            Err(err) => Err(err),
+        };
+        match listing.map(|bytes| serde_json::from_slice::<Vec<Summary>>(&bytes)) {
+            Ok(Ok(containers)) => {
+                for container in containers {
+                    self.clone().follow(container.id);
+                }
+            }
+            Ok(Err(err)) => log_line!("WARN", "Unreadable Docker container list: {}", err),
+            Err(err) => log_line!("WARN", "Could not list Docker containers: {}", err),
+        }
+    }
+
+    fn follow(self: Arc<Self>, id: String) {
+        if !self.following.lock().insert(id.clone()) {
+            return;
+        }
+        actix_web::rt::spawn(async move {
+            if let Err(err) = self.stream_logs(&id).await {
+                log_line!(
+                    "WARN",
+                    "Stopped following logs of {}: {}",
+                    short_id(&id),
+                    err
+                );
+            }
+            self.following.lock().remove(&id);
+        });
+    }
+
+    async fn stream_logs(&self, id: &str) -> io::Result<()> {
+        #[derive(Deserialize)]
+        struct Inspect {
+            #[serde(rename = "Name", default)]
+            name: String,
+            #[serde(rename = "Config")]
+            config: InspectConfig,
+        }
+        #[derive(Deserialize)]
+        struct InspectConfig {
+            #[serde(rename = "Tty", default)]
+            tty: bool,
+            #[serde(rename = "Image", default)]
+            image: String,
+        }
+
+        let bytes = get(&self.socket, &format!("/containers/{}/json", id))
+            .await?
+            .read_to_end()
+            .await?;
+        let inspect: Inspect = serde_json::from_slice(&bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let name = inspect.name.trim_start_matches('/').to_string();
+
+        let since = chrono::Utc::now().timestamp();
+        let path = format!(
+            "/containers/{}/logs?follow=1&stdout=1&stderr=1&since={}",
+            id, since
+        );
+        let mut body = get(&self.socket, &path).await?;
+
+        let emit = |stream: &str, line: &[u8]| {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end();
+            if line.is_empty() {
+                return;
+            }
+            let mut event = self.event("container_log", stream.to_string(), "info");
+            event.message = line.to_string();
+            event.data = serde_json::json!({
+                "container_id": id,
+                "container_name": name,
+                "image": inspect.config.image,
+                "stream": stream,
+            });
+            if self.queue.try_send(event).is_err() {
+                log_line!("WARN", "Docker event queue full, dropping log line");
+            }
+        };
+
+        // Without a TTY, Docker multiplexes stdout and stderr as frames with an
+        // 8-byte header: stream type, three zero bytes, big-endian length
+        if inspect.config.tty {
+            while let Some(line) = body.next_line().await? {
+                emit("stdout", &line);
+            }
+        } else {
+            while let Some(header) = body.next_bytes(8).await? {
+                let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+                let Some(frame) = body.next_bytes(length as usize).await? else {
+                    break;
+                };
+                let stream = if header[0] == 2 { "stderr" } else { "stdout" };
+                for line in frame.split(|byte| *byte == b'\n') {
+                    emit(stream, line);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(12)]
+}
+
+pub fn spawn(state: web::Data<AppState>, config: DockerConfig) {
+    let (sender, mut receiver) = mpsc::channel(QUEUE_SIZE);
+    let collector = Arc::new(Collector {
+        host: config.host.unwrap_or_else(local_hostname),
+        socket: config.socket,
+        logs: config.logs,
+        queue: sender,
+        following: Mutex::new(HashSet::new()),
+    });
+    log_line!(
+        "CONFIG",
+        "Docker collector on {} (logs {})",
+        collector.socket,
+        if collector.logs { "on" } else { "off" }
+    );
+
+    // Store whatever has queued up together instead of one event at a time
+    actix_web::rt::spawn(async move {
+        while let Some(first) = receiver.recv().await {
+            let mut batch = vec![first];
+            while batch.len() < MAX_BATCH {
+                match receiver.try_recv() {
+                    Ok(event) => batch.push(event),
+                    Err(_) => break,
+                }
+            }
+            crate::ingest(&state, batch).await;
+        }
+    });
+
+    actix_web::rt::spawn(async move {
+        loop {
+            if let Err(err) = collector.clone().watch_events().await {
+                log_line!(
+                    "WARN",
+                    "Docker events unavailable ({}), retrying in {}s",
+                    err,
+                    RECONNECT_DELAY.as_secs()
+                );
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
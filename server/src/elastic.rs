@@ -0,0 +1,342 @@
+use crate::{severity, AppState, Event};
+use actix_web::http::StatusCode;
+use actix_web::{dev, web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+// Largest bulk body accepted, after decompression; Beats send 1600
+// documents per request by default
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+// `[elastic]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ElasticConfig {
+    // Category for documents whose `event.category` isn't in `categories`
+    category: String,
+    // ECS `event.category` values to Loglumen categories
+    categories: HashMap<String, String>,
+    // Elasticsearch version reported by GET /es; Beats refuse to ship to a
+    // cluster older than themselves
+    version: String,
+}
+
+impl Default for ElasticConfig {
+    fn default() -> Self {
+        let categories = [
+            ("authentication", "auth"),
+            ("iam", "privilege"),
+            ("package", "software"),
+            ("process", "system"),
+            ("session", "remote"),
+        ];
+        ElasticConfig {
+            category: "logs".to_string(),
+            categories: categories
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+            version: "8.19.0".to_string(),
+        }
+    }
+}
+
+pub struct Elastic {
+    config: ElasticConfig,
+}
+
+impl Elastic {
+    pub fn new(config: ElasticConfig) -> Self {
+        Elastic { config }
+    }
+}
+
+// Field at an ECS path, nested (`{"host":{"name":..}}`) or dotted
+// (`{"host.name":..}`) at any level
+fn field<'a>(document: &'a Value, path: &str) -> Option<&'a Value> {
+    let object = document.as_object()?;
+    if let Some(value) = object.get(path) {
+        return Some(value);
+    }
+    let mut split = path.match_indices('.');
+    split.find_map(|(at, _)| field(object.get(&path[..at])?, &path[at + 1..]))
+}
+
+// First non-empty string at any of the paths; ECS allows arrays for most
+// keyword fields (`event.category`, `host.ip`)
+fn text(document: &Value, paths: &[&str]) -> Option<String> {
+    paths.iter().find_map(|path| {
+        let value = match field(document, path)? {
+            Value::Array(values) => values.first()?,
+            value => value,
+        };
+        match value {
+            Value::String(text) if !text.is_empty() => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            _ => None,
+        }
+    })
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(millis) = value.trim().parse::<i64>() {
+        return DateTime::from_timestamp_millis(millis);
+    }
+    DateTime::parse_from_rfc3339(value.trim())
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn to_event(config: &ElasticConfig, index: &str, document: Value, peer: &str) -> Event {
+    let time = text(&document, &["@timestamp", "event.created"])
+        .and_then(|time| parse_time(&time))
+        .unwrap_or_else(Utc::now);
+    let host = text(&document, &["host.name", "host.hostname", "agent.hostname"])
+        .unwrap_or_else(|| peer.to_string());
+    let host_ipv4 = field(&document, "host.ip")
+        .and_then(|ips| match ips {
+            Value::Array(ips) => ips
+                .iter()
+                .filter_map(Value::as_str)
+                .find(|ip| ip.parse::<std::net::Ipv4Addr>().is_ok())
+                .map(str::to_string),
+            Value::String(ip) => Some(ip.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| peer.to_string());
+    let os = text(
+        &document,
+        &["host.os.type", "host.os.family", "host.os.platform"],
+    )
+    .unwrap_or_default();
+    let category = text(&document, &["event.category"])
+        .and_then(|category| config.categories.get(&category).cloned())
+        .unwrap_or_else(|| config.category.clone());
+    let event_type = text(&document, &["event.action", "event.code", "event.dataset"])
+        .unwrap_or_else(|| index.to_string());
+    let severity = text(&document, &["log.level", "event.severity"])
+        .map(|level| severity::normalize(&level).to_string())
+        .unwrap_or_else(|| "info".to_string());
+    let message =
+        text(&document, &["message", "event.original"]).unwrap_or_else(|| event_type.clone());
+    let source = text(&document, &["agent.type"]).unwrap_or_else(|| "elastic".to_string());
+
+    let mut data = match document {
+        Value::Object(data) => data,
+        _ => Map::new(),
+    };
+    data.insert("_index".to_string(), Value::String(index.to_string()));
+
+    Event {
+        schema_version: 1,
+        category,
+        event_type,
+        time: time.to_rfc3339(),
+        host,
+        host_ipv4,
+        os,
+        source,
+        severity,
+        message,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// Errors in the shape Elasticsearch clients parse
+fn es_error(status: StatusCode, kind: &str, reason: String) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header(("X-Elastic-Product", "Elasticsearch"))
+        .json(json!({
+            "error": {"type": kind, "reason": reason},
+            "status": status.as_u16()
+        }))
+}
+
+fn item_error(action: &str, index: &str, id: Option<&str>, reason: String) -> Value {
+    json!({action: {
+        "_index": index,
+        "_id": id,
+        "status": 400,
+        "error": {"type": "illegal_argument_exception", "reason": reason}
+    }})
+}
+
+// GET /es - the cluster info Beats, Vector and Fluent Bit check before
+// their first bulk request
+pub async fn info(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("X-Elastic-Product", "Elasticsearch"))
+        .json(json!({
+            "name": "loglumen",
+            "cluster_name": "loglumen",
+            "version": {
+                "number": data.elastic.config.version,
+                "build_flavor": "default",
+                "minimum_wire_compatibility_version": "7.17.0",
+                "minimum_index_compatibility_version": "7.0.0"
+            },
+            "tagline": "You Know, for Search"
+        }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BulkPath {
+    index: Option<String>,
+}
+
+// POST /es/_bulk and /es/{index}/_bulk - Elasticsearch's bulk API, so
+// Filebeat and Winlogbeat can ship here without Logstash. `index` and
+// `create` documents become events with ECS fields mapped onto the event
+// and the document kept as `data`; other actions are refused per item.
+pub async fn bulk(
+    request: HttpRequest,
+    path: web::Path<BulkPath>,
+    payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    // Beats 8 gzip their requests by default
+    let mut payload = dev::Decompress::from_headers(payload.into_inner(), request.headers());
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY {
+            return Ok(es_error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "content_too_long_exception",
+                format!("bulk bodies are limited to {} bytes", MAX_BODY),
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let started = std::time::Instant::now();
+    let default_index = path.into_inner().index.unwrap_or_default();
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let identity = crate::tls::client_identity(&request);
+
+    let mut id_prefix = [0u8; 6];
+    let _ = getrandom::getrandom(&mut id_prefix);
+    let id_prefix: String = id_prefix
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let mut lines = body[..]
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty());
+    let mut batch = Vec::new();
+    let mut items = Vec::new();
+    let mut errors = false;
+    while let Some(line) = lines.next() {
+        let action: Map<String, Value> = match serde_json::from_slice(line) {
+            Ok(Value::Object(action)) if action.len() == 1 => action,
+            _ => {
+                return Ok(es_error(
+                    StatusCode::BAD_REQUEST,
+                    "illegal_argument_exception",
+                    format!(
+                        "malformed action/metadata line [{}]",
+                        String::from_utf8_lossy(line)
+                    ),
+                ))
+            }
+        };
+        let (name, metadata) = action.into_iter().next().unwrap_or_default();
+        let index = metadata
+            .get("_index")
+            .and_then(Value::as_str)
+            .unwrap_or(&default_index)
+            .to_string();
+        let id = metadata
+            .get("_id")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+
+        match name.as_str() {
+            "index" | "create" => {}
+            "update" | "delete" => {
+                // An update is followed by its partial document; a delete isn't
+                if name == "update" {
+                    lines.next();
+                }
+                errors = true;
+                items.push(item_error(
+                    &name,
+                    &index,
+                    id.as_deref(),
+                    format!("loglumen only accepts index and create, not {}", name),
+                ));
+                continue;
+            }
+            other => {
+                return Ok(es_error(
+                    StatusCode::BAD_REQUEST,
+                    "illegal_argument_exception",
+                    format!("unknown bulk action [{}]", other),
+                ))
+            }
+        }
+
+        let document = lines
+            .next()
+            .ok_or_else(|| "missing document".to_string())
+            .and_then(|line| serde_json::from_slice::<Value>(line).map_err(|err| err.to_string()));
+        match document {
+            Ok(document @ Value::Object(_)) => {
+                let mut event = to_event(&data.elastic.config, &index, document, &peer);
+                event.identity = identity.clone();
+                batch.push(event);
+                let id = id.unwrap_or_else(|| format!("{}{:x}", id_prefix, items.len()));
+                items.push(json!({name: {
+                    "_index": index,
+                    "_id": id,
+                    "_version": 1,
+                    "result": "created",
+                    "status": 201
+                }}));
+            }
+            Ok(_) => {
+                errors = true;
+                items.push(item_error(
+                    &name,
+                    &index,
+                    id.as_deref(),
+                    "document is not a JSON object".to_string(),
+                ));
+            }
+            Err(err) => {
+                errors = true;
+                items.push(item_error(
+                    &name,
+                    &index,
+                    id.as_deref(),
+                    format!("failed to parse document: {}", err),
+                ));
+            }
+        }
+    }
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Elastic-Product", "Elasticsearch"))
+        .json(json!({
+            "took": started.elapsed().as_millis() as u64,
+            "errors": errors,
+            "items": items
+        })))
+}
@@ -0,0 +1,296 @@
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Events in this category carry `data.path`, `data.hash` and `data.change_type`
+pub const FIM_CATEGORY: &str = "file_integrity";
+
+// `[fim]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FimConfig {
+    // Adopt the first hash seen for a path as its baseline instead of
+    // alerting on every file the first time an agent reports it. Files
+    // created on a host that already has a baseline still alert.
+    learn: bool,
+    // Severity of baseline violation alerts
+    severity: String,
+}
+
+impl Default for FimConfig {
+    fn default() -> Self {
+        FimConfig {
+            learn: true,
+            severity: "warning".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ChangeType {
+    // Initial scan or re-scan reported by the agent; always becomes the baseline
+    Baseline,
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl ChangeType {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "baseline" | "scan" => Some(ChangeType::Baseline),
+            "created" | "added" | "new" => Some(ChangeType::Created),
+            "modified" | "changed" | "updated" => Some(ChangeType::Modified),
+            "deleted" | "removed" => Some(ChangeType::Deleted),
+            _ => None,
+        }
+    }
+}
+
+// The fields of a FIM event, or None if it is missing a path
+struct FileChange {
+    path: String,
+    hash: Option<String>,
+    change: ChangeType,
+}
+
+impl FileChange {
+    fn from_event(event: &Event) -> Option<Self> {
+        let path = event.data.get("path")?.as_str()?.to_string();
+        let hash = ["hash", "sha256"]
+            .iter()
+            .find_map(|field| event.data.get(*field).and_then(|value| value.as_str()))
+            .map(|hash| hash.to_ascii_lowercase());
+        let change = event
+            .data
+            .get("change_type")
+            .and_then(|value| value.as_str())
+            .and_then(ChangeType::parse)
+            .unwrap_or(ChangeType::Modified);
+        Some(FileChange { path, hash, change })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FileState {
+    // None once the file has been deleted
+    hash: Option<String>,
+    since: String,
+}
+
+#[derive(Default)]
+struct HostFiles {
+    baseline: BTreeMap<String, FileState>,
+    // Last reported state of every path that differs from the baseline
+    drift: BTreeMap<String, FileState>,
+}
+
+impl HostFiles {
+    fn differs(&self, path: &str, hash: Option<&str>) -> bool {
+        match self.baseline.get(path) {
+            Some(state) => state.hash.as_deref() != hash,
+            None => hash.is_some(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DriftEntry<'a> {
+    path: &'a str,
+    change: ChangeType,
+    baseline_hash: Option<&'a str>,
+    current_hash: Option<&'a str>,
+    changed_at: &'a str,
+}
+
+pub struct Baselines {
+    hosts: RwLock<HashMap<String, HostFiles>>,
+    learn: bool,
+    severity: String,
+}
+
+impl Baselines {
+    pub fn new(config: FimConfig) -> Self {
+        Baselines {
+            hosts: RwLock::new(HashMap::new()),
+            learn: config.learn,
+            severity: config.severity,
+        }
+    }
+
+    // Compare FIM events with their host's baseline and raise an alert for
+    // each one that moves a file away from it
+    pub fn observe(&self, events: &[Event], rules: &crate::rules::RuleEngine) {
+        let mut hosts = None;
+        for event in events {
+            if !event.category.eq_ignore_ascii_case(FIM_CATEGORY) {
+                continue;
+            }
+            let Some(change) = FileChange::from_event(event) else {
+                continue;
+            };
+
+            let hosts = hosts.get_or_insert_with(|| self.hosts.write());
+            let files = hosts.entry(event.host.clone()).or_default();
+            let hash = match change.change {
+                ChangeType::Deleted => None,
+                _ => change.hash,
+            };
+            let state = FileState {
+                hash,
+                since: event.time.clone(),
+            };
+
+            let learn = self.learn
+                && !files.baseline.contains_key(&change.path)
+                && (change.change != ChangeType::Created || files.baseline.is_empty());
+            if change.change == ChangeType::Baseline || (learn && state.hash.is_some()) {
+                files.drift.remove(&change.path);
+                files.baseline.insert(change.path, state);
+                continue;
+            }
+
+            if !files.differs(&change.path, state.hash.as_deref()) {
+                // Back to the baseline, e.g. a reverted edit
+                files.drift.remove(&change.path);
+                continue;
+            }
+
+            let message = match (&state.hash, files.baseline.get(&change.path)) {
+                (None, _) => format!("{} was deleted", change.path),
+                (Some(_), None) => format!("{} is not in the baseline", change.path),
+                (Some(_), Some(_)) => {
+                    format!("{} no longer matches its baseline hash", change.path)
+                }
+            };
+            rules.raise_for_event(
+                "fim_baseline",
+                "fim",
+                &self.severity,
+                &change.path,
+                message,
+                event,
+            );
+            files.drift.insert(change.path, state);
+        }
+    }
+}
+
+fn no_baseline(host: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "status": "error",
+        "message": format!("no file integrity data for '{}'", host)
+    }))
+}
+
+// GET /api/fim/{host}/diff - Files whose last reported state differs from
+// the baseline
+pub async fn get_diff(host: web::Path<String>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let hosts = data.fim.hosts.read();
+    let Some(files) = hosts.get(host.as_str()) else {
+        return Ok(no_baseline(&host));
+    };
+
+    let drift: Vec<DriftEntry> = files
+        .drift
+        .iter()
+        .map(|(path, current)| {
+            let baseline = files.baseline.get(path);
+            let change = match (baseline, &current.hash) {
+                (_, None) => ChangeType::Deleted,
+                (None, Some(_)) => ChangeType::Created,
+                (Some(_), Some(_)) => ChangeType::Modified,
+            };
+            DriftEntry {
+                path,
+                change,
+                baseline_hash: baseline.and_then(|state| state.hash.as_deref()),
+                current_hash: current.hash.as_deref(),
+                changed_at: &current.since,
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "host": host.as_str(),
+        "baseline_files": files.baseline.len(),
+        "changed": drift.len(),
+        "changes": drift,
+    })))
+}
+
+// GET /api/fim/{host}/baseline - Expected hash of every tracked path
+pub async fn get_baseline(
+    host: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let hosts = data.fim.hosts.read();
+    let Some(files) = hosts.get(host.as_str()) else {
+        return Ok(no_baseline(&host));
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "host": host.as_str(),
+        "files": files.baseline,
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AcceptRequest {
+    // Paths to accept; empty accepts every current change
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+// POST /api/fim/{host}/baseline - Accept current changes as the new baseline
+// after they have been reviewed. Needs an admin role, as it clears the drift
+// this feature exists to keep.
+pub async fn accept_changes(
+    request: HttpRequest,
+    host: web::Path<String>,
+    body: Option<web::Json<AcceptRequest>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let admin = match data.api_keys.authorize_admin(&request) {
+        Ok(admin) => admin,
+        Err(response) => return Ok(response),
+    };
+    let body = body.map(|body| body.into_inner()).unwrap_or_default();
+    let mut hosts = data.fim.hosts.write();
+    let Some(files) = hosts.get_mut(host.as_str()) else {
+        return Ok(no_baseline(&host));
+    };
+
+    let accepted: Vec<String> = files
+        .drift
+        .keys()
+        .filter(|path| body.paths.is_empty() || body.paths.contains(path))
+        .cloned()
+        .collect();
+    for path in &accepted {
+        let Some(state) = files.drift.remove(path) else {
+            continue;
+        };
+        if state.hash.is_some() {
+            files.baseline.insert(path.clone(), state);
+        } else {
+            files.baseline.remove(path);
+        }
+    }
+
+    log_line!(
+        "INFO",
+        "{} accepted {} file integrity change(s) on {}",
+        admin,
+        accepted.len(),
+        host
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "host": host.as_str(),
+        "accepted": accepted,
+    })))
+}
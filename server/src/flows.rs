@@ -0,0 +1,343 @@
+use crate::clock::SharedClock;
+use crate::config;
+use crate::{AppState, Event, TimeRange};
+use actix_web::{web, HttpResponse, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+// `[flows]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FlowConfig {
+    // Events in this category are aggregated instead of stored
+    category: String,
+    // How long per-minute conversations are kept
+    #[serde(deserialize_with = "config::de_duration")]
+    retention: Duration,
+    // Upper bound on stored conversation rows; the oldest minutes go first
+    max_conversations: usize,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        FlowConfig {
+            category: "network_flow".to_string(),
+            retention: Duration::from_secs(24 * 60 * 60),
+            max_conversations: 200_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Conversation {
+    host: String,
+    src_ip: String,
+    dst_ip: String,
+    dst_port: u64,
+    protocol: String,
+}
+
+impl Conversation {
+    fn from_event(event: &Event) -> Self {
+        let text = |field: &str| {
+            event
+                .data
+                .get(field)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        Conversation {
+            host: event.host.clone(),
+            src_ip: text("src_ip"),
+            dst_ip: text("dst_ip"),
+            dst_port: number(event, "dst_port"),
+            protocol: text("protocol").to_ascii_lowercase(),
+        }
+    }
+}
+
+// Agents send counters as numbers or numeric strings
+fn number(event: &Event, field: &str) -> u64 {
+    match event.data.get(field) {
+        Some(serde_json::Value::Number(value)) => value.as_u64().unwrap_or_default(),
+        Some(serde_json::Value::String(value)) => value.parse().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct Totals {
+    bytes: u64,
+    packets: u64,
+    flows: u64,
+}
+
+impl Totals {
+    fn add(&mut self, other: Totals) {
+        self.bytes += other.bytes;
+        self.packets += other.packets;
+        self.flows += other.flows;
+    }
+}
+
+#[derive(Default)]
+struct FlowStore {
+    // Minute (unix seconds / 60) -> conversations seen in that minute
+    minutes: BTreeMap<i64, HashMap<Conversation, Totals>>,
+    rows: usize,
+}
+
+pub struct Flows {
+    category: String,
+    retention: Duration,
+    max_conversations: usize,
+    store: Mutex<FlowStore>,
+    clock: SharedClock,
+}
+
+impl Flows {
+    pub fn new(config: FlowConfig, clock: SharedClock) -> Self {
+        Flows {
+            category: config.category,
+            retention: config.retention,
+            max_conversations: config.max_conversations,
+            store: Mutex::new(FlowStore::default()),
+            clock,
+        }
+    }
+
+    // Category flow events are stored under, for built-in collectors
+    #[cfg(feature = "netflow")]
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    // Fold flow events into their minute's conversations and hand back
+    // everything else for the normal pipeline
+    pub fn absorb(&self, batch: Vec<Event>) -> Vec<Event> {
+        if !batch
+            .iter()
+            .any(|event| event.category.eq_ignore_ascii_case(&self.category))
+        {
+            return batch;
+        }
+
+        let now = self.clock.now();
+        let mut store = self.store.lock();
+        let mut rest = Vec::with_capacity(batch.len());
+        for event in batch {
+            if !event.category.eq_ignore_ascii_case(&self.category) {
+                rest.push(event);
+                continue;
+            }
+            let minute = event.timestamp().unwrap_or(now).timestamp().div_euclid(60);
+            let totals = Totals {
+                bytes: number(&event, "bytes"),
+                packets: number(&event, "packets"),
+                flows: 1,
+            };
+            let conversations = store.minutes.entry(minute).or_default();
+            let before = conversations.len();
+            conversations
+                .entry(Conversation::from_event(&event))
+                .or_default()
+                .add(totals);
+            let added = conversations.len() - before;
+            store.rows += added;
+        }
+
+        let oldest = (now.timestamp() - self.retention.as_secs() as i64).div_euclid(60);
+        while let Some((&minute, conversations)) = store.minutes.first_key_value() {
+            if minute >= oldest && store.rows <= self.max_conversations {
+                break;
+            }
+            store.rows -= conversations.len();
+            store.minutes.remove(&minute);
+        }
+        rest
+    }
+
+    // Minutes overlapping the range, newest first
+    fn visit(&self, range: &TimeRange, mut visit: impl FnMut(i64, &Conversation, &Totals)) {
+        let from = range
+            .since
+            .map_or(i64::MIN, |since| since.timestamp().div_euclid(60));
+        let to = range
+            .until
+            .map_or(i64::MAX, |until| until.timestamp().div_euclid(60));
+        let store = self.store.lock();
+        for (minute, conversations) in store.minutes.range(from..=to).rev() {
+            for (conversation, totals) in conversations {
+                visit(*minute, conversation, totals);
+            }
+        }
+    }
+}
+
+fn minute_time(minute: i64) -> String {
+    chrono::DateTime::from_timestamp(minute * 60, 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConversationQuery {
+    since: Option<String>,
+    until: Option<String>,
+    host: Option<String>,
+    // Matches either end of the conversation
+    ip: Option<String>,
+    #[serde(default = "default_conversation_limit")]
+    limit: usize,
+}
+
+fn default_conversation_limit() -> usize {
+    500
+}
+
+// GET /api/flows/conversations - Per-minute conversation rows, newest minute
+// first and largest first within a minute
+pub async fn get_conversations(
+    query: web::Query<ConversationQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let range = match TimeRange::parse(query.since.as_deref(), query.until.as_deref()) {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+
+    let mut rows: Vec<(i64, Conversation, Totals)> = Vec::new();
+    let mut total = 0;
+    data.flows.visit(&range, |minute, conversation, totals| {
+        if query
+            .host
+            .as_deref()
+            .is_some_and(|host| conversation.host != host)
+        {
+            return;
+        }
+        if query
+            .ip
+            .as_deref()
+            .is_some_and(|ip| conversation.src_ip != ip && conversation.dst_ip != ip)
+        {
+            return;
+        }
+        total += 1;
+        rows.push((minute, conversation.clone(), *totals));
+    });
+    rows.sort_by_key(|(minute, _, totals)| std::cmp::Reverse((*minute, totals.bytes)));
+    rows.truncate(query.limit);
+
+    let conversations: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|(minute, conversation, totals)| {
+            serde_json::json!({
+                "minute": minute_time(minute),
+                "host": conversation.host,
+                "src_ip": conversation.src_ip,
+                "dst_ip": conversation.dst_ip,
+                "dst_port": conversation.dst_port,
+                "protocol": conversation.protocol,
+                "bytes": totals.bytes,
+                "packets": totals.packets,
+                "flows": totals.flows,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total": total,
+        "conversations": conversations,
+    })))
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TalkerKey {
+    #[default]
+    Src,
+    Dst,
+    Pair,
+    Port,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Metric {
+    #[default]
+    Bytes,
+    Packets,
+    Flows,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopQuery {
+    since: Option<String>,
+    until: Option<String>,
+    #[serde(default)]
+    by: TalkerKey,
+    #[serde(default)]
+    metric: Metric,
+    #[serde(default = "default_top")]
+    limit: usize,
+}
+
+fn default_top() -> usize {
+    10
+}
+
+// GET /api/flows/top - Top talkers over a time range
+pub async fn get_top_talkers(
+    query: web::Query<TopQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let range = match TimeRange::parse(query.since.as_deref(), query.until.as_deref()) {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+
+    let mut talkers: HashMap<String, Totals> = HashMap::new();
+    data.flows.visit(&range, |_, conversation, totals| {
+        let key = match query.by {
+            TalkerKey::Src => conversation.src_ip.clone(),
+            TalkerKey::Dst => conversation.dst_ip.clone(),
+            TalkerKey::Pair => format!("{} -> {}", conversation.src_ip, conversation.dst_ip),
+            TalkerKey::Port => format!("{}/{}", conversation.dst_port, conversation.protocol),
+        };
+        talkers.entry(key).or_default().add(*totals);
+    });
+
+    let metric = |totals: &Totals| match query.metric {
+        Metric::Bytes => totals.bytes,
+        Metric::Packets => totals.packets,
+        Metric::Flows => totals.flows,
+    };
+    let mut talkers: Vec<(String, Totals)> = talkers.into_iter().collect();
+    talkers.sort_by_key(|(key, totals)| (std::cmp::Reverse(metric(totals)), key.clone()));
+    talkers.truncate(query.limit);
+
+    let talkers: Vec<serde_json::Value> = talkers
+        .into_iter()
+        .map(|(key, totals)| {
+            serde_json::json!({
+                "key": key,
+                "bytes": totals.bytes,
+                "packets": totals.packets,
+                "flows": totals.flows,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "talkers": talkers })))
+}
@@ -0,0 +1,382 @@
+// Built with `--features gelf`. Receives GELF (Graylog Extended Log Format)
+// over UDP, so applications already logging to Graylog can send here by
+// changing the address. Chunked messages are reassembled and zlib or gzip
+// payloads decompressed.
+use crate::{severity, AppState, Event};
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+// Messages ingested together at most
+const BATCH: usize = 500;
+// GELF allows 128 chunks per message, and senders give up after 5 seconds
+const MAX_CHUNKS: usize = 128;
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+// Messages being reassembled at once; the oldest is dropped past this
+const MAX_PENDING: usize = 1024;
+// Largest message after decompression
+const MAX_MESSAGE: u64 = 8 * 1024 * 1024;
+
+// `[gelf]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GelfConfig {
+    enabled: bool,
+    // UDP address senders log to
+    bind: String,
+    category: String,
+}
+
+impl Default for GelfConfig {
+    fn default() -> Self {
+        GelfConfig {
+            enabled: false,
+            bind: "0.0.0.0:12201".to_string(),
+            category: "logs".to_string(),
+        }
+    }
+}
+
+impl GelfConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+// Chunks of one message, by sequence number
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct Chunks {
+    pending: HashMap<[u8; 8], Partial>,
+}
+
+impl Chunks {
+    // The whole message once its last chunk is in
+    fn add(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        if packet.len() < 12 {
+            return Err("truncated chunk header".to_string());
+        }
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&packet[2..10]);
+        let (sequence, count) = (usize::from(packet[10]), usize::from(packet[11]));
+        if count == 0 || count > MAX_CHUNKS || sequence >= count {
+            return Err(format!("bad chunk {} of {}", sequence, count));
+        }
+
+        let now = Instant::now();
+        self.pending
+            .retain(|_, partial| now.duration_since(partial.started) < CHUNK_TIMEOUT);
+        if self.pending.len() >= MAX_PENDING && !self.pending.contains_key(&id) {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, partial)| partial.started)
+                .map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+
+        let partial = self.pending.entry(id).or_insert_with(|| Partial {
+            chunks: vec![None; count],
+            received: 0,
+            started: now,
+        });
+        if partial.chunks.len() != count {
+            return Err("chunk count changed mid-message".to_string());
+        }
+        if partial.chunks[sequence].is_none() {
+            partial.chunks[sequence] = Some(packet[12..].to_vec());
+            partial.received += 1;
+        }
+        if partial.received < count {
+            return Ok(None);
+        }
+        let Some(partial) = self.pending.remove(&id) else {
+            return Ok(None);
+        };
+        Ok(Some(
+            partial.chunks.into_iter().flatten().flatten().collect(),
+        ))
+    }
+}
+
+fn decompress(payload: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    let result = match payload {
+        [0x1f, 0x8b, ..] => flate2::read::GzDecoder::new(payload)
+            .take(MAX_MESSAGE + 1)
+            .read_to_end(&mut output),
+        [0x78, ..] => flate2::read::ZlibDecoder::new(payload)
+            .take(MAX_MESSAGE + 1)
+            .read_to_end(&mut output),
+        _ => return Ok(payload.to_vec()),
+    };
+    result.map_err(|err| format!("cannot decompress: {}", err))?;
+    if output.len() as u64 > MAX_MESSAGE {
+        return Err(format!("larger than {} bytes decompressed", MAX_MESSAGE));
+    }
+    Ok(output)
+}
+
+fn to_event(
+    message: Map<String, Value>,
+    category: &str,
+    peer: &SocketAddr,
+) -> Result<Event, String> {
+    let peer = peer.ip().to_string();
+    let text = |key: &str| {
+        message
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let short_message = text("short_message").ok_or("missing short_message")?;
+    let host = text("host").unwrap_or_else(|| peer.clone());
+    // Seconds since the epoch, with optional decimal places
+    let time = message
+        .get("timestamp")
+        .and_then(Value::as_f64)
+        .and_then(|seconds| DateTime::from_timestamp_millis((seconds * 1000.0) as i64))
+        .unwrap_or_else(Utc::now);
+    // Syslog levels. The spec defaults to 1 (alert), but a sender that
+    // leaves it out rarely means that, so it is info here.
+    let level = message
+        .get("level")
+        .and_then(Value::as_u64)
+        .map_or(6, |level| level.min(7) as u8);
+    let event_type = text("_event_type")
+        .or_else(|| text("facility"))
+        .unwrap_or_else(|| "gelf".to_string());
+
+    // Additional fields lose their underscore; `_id` is reserved by GELF
+    let mut data = Map::new();
+    for (key, value) in message {
+        match key.as_str() {
+            "version" | "host" | "short_message" | "timestamp" | "level" | "_id" => {}
+            _ => {
+                let key = key.strip_prefix('_').map(str::to_string).unwrap_or(key);
+                data.insert(key, value);
+            }
+        }
+    }
+
+    Ok(Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type,
+        time: time.to_rfc3339(),
+        host,
+        host_ipv4: peer,
+        os: String::new(),
+        source: "gelf".to_string(),
+        severity: severity::from_syslog(level).to_string(),
+        message: short_message,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    })
+}
+
+fn decode(
+    chunks: &mut Chunks,
+    packet: &[u8],
+    category: &str,
+    peer: &SocketAddr,
+) -> Result<Option<Event>, String> {
+    let payload = match packet {
+        [0x1e, 0x0f, ..] => match chunks.add(packet)? {
+            Some(payload) => payload,
+            None => return Ok(None),
+        },
+        _ => packet.to_vec(),
+    };
+    let message = match serde_json::from_slice(&decompress(&payload)?) {
+        Ok(Value::Object(message)) => message,
+        Ok(_) => return Err("not a JSON object".to_string()),
+        Err(err) => return Err(format!("invalid JSON: {}", err)),
+    };
+    to_event(message, category, peer).map(Some)
+}
+
+async fn receive(state: web::Data<AppState>, socket: UdpSocket, category: String) {
+    let mut chunks = Chunks::default();
+    let mut buffer = vec![0u8; 65535];
+    // Only the first bad packet in a burst is logged
+    let mut warned = false;
+    loop {
+        let mut received = match socket.recv_from(&mut buffer).await {
+            Ok(received) => Some(received),
+            Err(err) => {
+                log_line!("WARN", "GELF receive failed: {}", err);
+                continue;
+            }
+        };
+        let mut batch = Vec::new();
+        while let Some((length, peer)) = received {
+            match decode(&mut chunks, &buffer[..length], &category, &peer) {
+                Ok(Some(event)) => batch.push(event),
+                Ok(None) => {}
+                Err(err) if !warned => {
+                    log_line!("WARN", "Ignoring GELF message from {}: {}", peer.ip(), err);
+                    warned = true;
+                }
+                Err(_) => {}
+            }
+            received = if batch.len() < BATCH {
+                socket.try_recv_from(&mut buffer).ok()
+            } else {
+                None
+            };
+        }
+        if !batch.is_empty() {
+            warned = false;
+            state.availability.observe(&batch);
+            crate::ingest(&state, batch).await;
+        }
+    }
+}
+
+// Datagrams from a fuzzer or a property test, through one chunk buffer so
+// chunks of a message can meet across them. Malformed input must not panic.
+pub fn fuzz(packets: &[Vec<u8>]) {
+    let peer: SocketAddr = "192.0.2.1:12201".parse().unwrap();
+    let mut chunks = Chunks::default();
+    for packet in packets {
+        let _ = decode(&mut chunks, packet, "logs", &peer);
+    }
+}
+
+// Binds right away, before hardening drops root
+pub fn spawn(state: web::Data<AppState>, config: GelfConfig) {
+    let socket = std::net::UdpSocket::bind(&config.bind)
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .and_then(UdpSocket::from_std);
+    match socket {
+        Ok(socket) => {
+            log_line!("CONFIG", "GELF listening on udp://{}", config.bind);
+            actix_web::rt::spawn(receive(state, socket, config.category));
+        }
+        Err(err) => log_line!(
+            "ERROR",
+            "GELF listener could not bind udp://{}: {}",
+            config.bind,
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+    use std::io::Write;
+
+    const MESSAGE: &[u8] = br#"{"version":"1.1","host":"web01","short_message":"disk full","timestamp":1760436000.25,"level":3,"_user":"alice","_id":"x"}"#;
+
+    fn compressed(gzip: bool) -> Vec<u8> {
+        if gzip {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(MESSAGE).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(MESSAGE).unwrap();
+            encoder.finish().unwrap()
+        }
+    }
+
+    fn chunk(id: u8, sequence: u8, count: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x1e, 0x0f, id, 0, 0, 0, 0, 0, 0, 0, sequence, count];
+        packet.extend(payload);
+        packet
+    }
+
+    fn samples() -> Vec<Vec<u8>> {
+        vec![
+            MESSAGE.to_vec(),
+            compressed(true),
+            compressed(false),
+            chunk(1, 0, 2, &MESSAGE[..40]),
+            chunk(1, 1, 2, &MESSAGE[40..]),
+        ]
+    }
+
+    fn packet() -> impl Strategy<Value = Vec<u8>> {
+        let samples = samples();
+        let samples: Vec<&[u8]> = samples.iter().map(Vec::as_slice).collect();
+        mutations::input(
+            &samples,
+            &[&[0x1e, 0x0f], &[0x1f, 0x8b], &[0x78], b"\"_", b"\""],
+        )
+    }
+
+    // Whole messages, plain or compressed, with cut points and a send order
+    fn chunked() -> impl Strategy<Value = (Vec<u8>, Vec<usize>, Vec<usize>)> {
+        prop_oneof![
+            Just(MESSAGE.to_vec()),
+            Just(compressed(true)),
+            Just(compressed(false))
+        ]
+        .prop_flat_map(|payload| {
+            let cuts = prop::collection::vec(1..payload.len(), 0..8).prop_map(|mut cuts| {
+                cuts.sort_unstable();
+                cuts.dedup();
+                cuts
+            });
+            (Just(payload), cuts)
+        })
+        .prop_flat_map(|(payload, cuts)| {
+            let order = Just((0..=cuts.len()).collect::<Vec<usize>>()).prop_shuffle();
+            (Just(payload), Just(cuts), order)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_decode(packets in prop::collection::vec(packet(), 1..4)) {
+            fuzz(&packets);
+        }
+
+        #[test]
+        fn chunks_reassemble_in_any_order((payload, cuts, order) in chunked()) {
+            let peer: SocketAddr = "192.0.2.1:12201".parse().unwrap();
+            let bounds: Vec<usize> = std::iter::once(0)
+                .chain(cuts.iter().copied())
+                .chain(std::iter::once(payload.len()))
+                .collect();
+            let mut chunks = Chunks::default();
+            let mut events = Vec::new();
+            for (sent, index) in order.iter().enumerate() {
+                let packet = chunk(
+                    7,
+                    *index as u8,
+                    order.len() as u8,
+                    &payload[bounds[*index]..bounds[index + 1]],
+                );
+                let event = decode(&mut chunks, &packet, "logs", &peer).unwrap();
+                prop_assert_eq!(event.is_some(), sent + 1 == order.len());
+                events.extend(event);
+            }
+            prop_assert_eq!(&events[0].message, "disk full");
+            prop_assert_eq!(&events[0].data["user"], "alice");
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{clock, AppState, Event};
+use actix_web::{web, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Upper bounds so a typo in the query cannot flood the store
+const MAX_RATE: u32 = 5_000;
+const MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+
+// `[dev]` section. The generator and clock routes are only registered when
+// enabled.
+#[derive(Debug, Default, Deserialize)]
+pub struct DevConfig {
+    #[serde(default)]
+    generator: bool,
+    // "system" (default) or "manual"
+    #[serde(default)]
+    clock: Option<String>,
+    // RFC 3339 time a manual clock starts at; defaults to startup
+    #[serde(default)]
+    clock_start: Option<String>,
+}
+
+impl DevConfig {
+    pub fn generator_enabled(&self) -> bool {
+        self.generator
+    }
+
+    pub fn clock(&self) -> clock::SharedClock {
+        match self.clock.as_deref() {
+            None | Some("system") => return clock::system(),
+            Some("manual") => {}
+            Some(other) => {
+                log_line!(
+                    "WARN",
+                    "Unknown [dev] clock '{}'; using the system clock",
+                    other
+                );
+                return clock::system();
+            }
+        }
+        let start = match self.clock_start.as_deref() {
+            None => Utc::now(),
+            Some(raw) => match DateTime::parse_from_rfc3339(raw) {
+                Ok(start) => start.with_timezone(&Utc),
+                Err(_) => {
+                    log_line!(
+                        "WARN",
+                        "Invalid [dev] clock_start '{}'; starting at now",
+                        raw
+                    );
+                    Utc::now()
+                }
+            },
+        };
+        clock::manual(start)
+    }
+}
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// (category, event_type, severity, source) templates, roughly weighted by how
+// often the agents report them
+const TEMPLATES: &[(&str, &str, &str, &str)] = &[
+    ("auth", "ssh_login_success", "info", "auth.log"),
+    ("auth", "ssh_login_success", "info", "auth.log"),
+    ("auth", "ssh_login_failed", "warning", "auth.log"),
+    ("auth", "ssh_login_failed", "warning", "auth.log"),
+    ("auth", "login_failed", "warning", "Security"),
+    ("auth", "account_locked", "error", "Security"),
+    ("privilege", "sudo_used", "info", "auth.log"),
+    ("privilege", "su_success", "warning", "auth.log"),
+    (
+        "privilege",
+        "special_privileges_assigned",
+        "warning",
+        "Security",
+    ),
+    ("remote", "rdp_login", "info", "Security"),
+    ("service", "service_failed", "error", "systemd"),
+    ("service", "service_crashed", "error", "System"),
+    ("software", "software_installed", "info", "dpkg.log"),
+    ("software", "software_updated", "info", "dpkg.log"),
+    ("system", "system_boot", "info", "journald"),
+    ("system", "oom_kill", "error", "kernel"),
+    ("system", "kernel_panic", "critical", "kernel"),
+];
+
+const USERS: &[&str] = &["alice", "bob", "carol", "deploy", "root", "svc_backup"];
+const SERVICES: &[&str] = &["nginx", "postgresql", "sshd", "docker", "cron"];
+const PACKAGES: &[&str] = &["openssl", "curl", "python3", "nginx", "linux-image"];
+
+// xorshift64*: plenty for fake data and avoids pulling in a rand crate
+struct Rng(u64);
+
+impl Rng {
+    fn seeded() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0x9E37_79B9_7F4A_7C15);
+        Rng(nanos | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+fn fake_event(rng: &mut Rng, hosts: usize, now: DateTime<Utc>) -> Event {
+    let (category, event_type, severity, source) = TEMPLATES[rng.below(TEMPLATES.len())];
+    let host_index = rng.below(hosts);
+    let windows = host_index % 3 == 2;
+    let user = rng.pick(USERS);
+    let remote_ip = format!("203.0.113.{}", rng.below(254) + 1);
+
+    let (message, data) = match category {
+        "auth" | "remote" => (
+            format!("{} for {} from {}", event_type, user, remote_ip),
+            serde_json::json!({ "username": user, "remote_ip": remote_ip }),
+        ),
+        "privilege" => {
+            let command = rng.pick(&[
+                "/usr/bin/systemctl restart nginx",
+                "/bin/bash",
+                "/usr/bin/apt upgrade",
+            ]);
+            (
+                format!("{} by {}: {}", event_type, user, command),
+                serde_json::json!({ "username": user, "command": command, "target_user": "root" }),
+            )
+        }
+        "service" => {
+            let service = rng.pick(SERVICES);
+            (
+                format!("Service {} reported {}", service, event_type),
+                serde_json::json!({ "service_name": service }),
+            )
+        }
+        "software" => {
+            let package = rng.pick(PACKAGES);
+            (
+                format!(
+                    "Package {} {}",
+                    package,
+                    event_type.trim_start_matches("software_")
+                ),
+                serde_json::json!({ "package": package }),
+            )
+        }
+        _ => (
+            format!("System event {}", event_type),
+            serde_json::json!({}),
+        ),
+    };
+
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type: event_type.to_string(),
+        time: now.to_rfc3339(),
+        host: format!(
+            "demo-{}{:02}",
+            if windows { "win" } else { "web" },
+            host_index + 1
+        ),
+        host_ipv4: format!("10.20.0.{}", host_index + 10),
+        os: if windows { "windows" } else { "linux" }.to_string(),
+        source: source.to_string(),
+        severity: severity.to_string(),
+        message,
+        data,
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateQuery {
+    // Events per second
+    #[serde(default = "default_rate")]
+    rate: u32,
+    #[serde(default = "default_duration")]
+    duration: String,
+    #[serde(default = "default_hosts")]
+    hosts: usize,
+}
+
+fn default_rate() -> u32 {
+    100
+}
+
+fn default_duration() -> String {
+    "60s".to_string()
+}
+
+fn default_hosts() -> usize {
+    8
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// POST /api/dev/generate?rate=100&duration=60s&hosts=8 - Feed fake events
+// through the normal ingest path (rules, outputs, store)
+pub async fn generate(
+    query: web::Query<GenerateQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let Some(duration) = crate::config::parse_duration(&query.duration) else {
+        return Ok(bad_request(format!(
+            "invalid duration '{}'",
+            query.duration
+        )));
+    };
+    if query.rate == 0 || query.rate > MAX_RATE {
+        return Ok(bad_request(format!(
+            "rate must be between 1 and {}",
+            MAX_RATE
+        )));
+    }
+    if duration.is_zero() || duration > MAX_DURATION {
+        return Ok(bad_request(
+            "duration must be between 1s and 1h".to_string(),
+        ));
+    }
+    let hosts = query.hosts.clamp(1, 100);
+
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "status": "error",
+            "message": "a generator run is already in progress"
+        })));
+    }
+
+    let seconds = duration.as_secs();
+    let rate = query.rate as usize;
+    log_line!(
+        "INFO",
+        "Generating {} events/s across {} hosts for {}s",
+        rate,
+        hosts,
+        seconds
+    );
+
+    let state = data.clone();
+    actix_web::rt::spawn(async move {
+        let mut rng = Rng::seeded();
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        for _ in 0..seconds {
+            ticker.tick().await;
+            let now = state.clock.now();
+            let batch: Vec<Event> = (0..rate)
+                .map(|_| fake_event(&mut rng, hosts, now))
+                .collect();
+            crate::ingest(&state, batch).await;
+        }
+        RUNNING.store(false, Ordering::SeqCst);
+        log_line!(
+            "OK",
+            "Generator finished ({} events)",
+            rate as u64 * seconds
+        );
+    });
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "status": "accepted",
+        "rate": rate,
+        "duration_seconds": seconds,
+        "hosts": hosts,
+        "events": rate as u64 * seconds,
+    })))
+}
@@ -0,0 +1,208 @@
+use serde::Deserialize;
+use std::io;
+
+// Options for operators running the binary directly on a host. Everything is
+// applied after the listening socket is bound, so the server can start as root
+// to grab a privileged port and then give that up.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HardeningConfig {
+    run_as_user: Option<String>,
+    run_as_group: Option<String>,
+    // Directory the server is allowed to write to (backups, exports)
+    data_dir: Option<String>,
+    // chroot into data_dir; paths in the config are then relative to it
+    chroot: bool,
+    // Landlock: read/write only inside data_dir, read-only in landlock_read_paths
+    landlock: bool,
+    landlock_read_paths: Option<Vec<String>>,
+    no_new_privileges: bool,
+}
+
+impl HardeningConfig {
+    fn is_empty(&self) -> bool {
+        self.run_as_user.is_none()
+            && self.run_as_group.is_none()
+            && !self.chroot
+            && !self.landlock
+            && !self.no_new_privileges
+    }
+}
+
+#[cfg(unix)]
+pub fn apply(config: &HardeningConfig) -> io::Result<()> {
+    use std::ffi::CString;
+
+    if config.is_empty() {
+        return Ok(());
+    }
+
+    // Resolve names first: /etc/passwd is gone after a chroot
+    let user = match &config.run_as_user {
+        Some(name) => Some(lookup_user(name)?),
+        None => None,
+    };
+    let group = match &config.run_as_group {
+        Some(name) => Some(lookup_group(name)?),
+        None => user.map(|(_, gid)| gid),
+    };
+
+    if config.chroot {
+        let dir = config.data_dir.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "chroot requires data_dir")
+        })?;
+        let path = CString::new(dir)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid data_dir"))?;
+        // SAFETY: path is a valid NUL-terminated string
+        if unsafe { libc::chroot(path.as_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        std::env::set_current_dir("/")?;
+        log_line!("SECURITY", "Chrooted into {}", dir);
+    }
+
+    if let Some(gid) = group {
+        // SAFETY: plain syscalls with values resolved above
+        unsafe {
+            if libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        log_line!("SECURITY", "Running with gid {}", gid);
+    }
+
+    if let Some((uid, _)) = user {
+        // SAFETY: plain syscall
+        if unsafe { libc::setuid(uid) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Make sure root cannot be regained
+        // SAFETY: plain syscall
+        if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            return Err(io::Error::other("privilege drop could be reverted"));
+        }
+        log_line!("SECURITY", "Running with uid {}", uid);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if config.no_new_privileges {
+            // SAFETY: prctl with constant arguments
+            if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            log_line!("SECURITY", "no_new_privs set");
+        }
+
+        if config.landlock {
+            apply_landlock(config)?;
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if config.no_new_privileges || config.landlock {
+        log_line!(
+            "WARN",
+            "no_new_privileges and landlock are only supported on Linux"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(config: &HardeningConfig) -> io::Result<()> {
+    if !config.is_empty() {
+        log_line!(
+            "WARN",
+            "[hardening] options are only supported on Unix; ignoring"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn lookup_user(name: &str) -> io::Result<(libc::uid_t, libc::gid_t)> {
+    let c_name = std::ffi::CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid user name"))?;
+    // SAFETY: getpwnam returns a pointer into static storage or NULL; fields
+    // are copied out before any other libc call can overwrite it
+    let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown user '{}'", name),
+        ));
+    }
+    // SAFETY: checked non-null above
+    Ok(unsafe { ((*entry).pw_uid, (*entry).pw_gid) })
+}
+
+#[cfg(unix)]
+fn lookup_group(name: &str) -> io::Result<libc::gid_t> {
+    let c_name = std::ffi::CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid group name"))?;
+    // SAFETY: as in lookup_user
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown group '{}'", name),
+        ));
+    }
+    // SAFETY: checked non-null above
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(target_os = "linux")]
+fn apply_landlock(config: &HardeningConfig) -> io::Result<()> {
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetStatus, ABI,
+    };
+
+    let writable = if config.chroot {
+        "/".to_string()
+    } else {
+        config.data_dir.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "landlock requires data_dir")
+        })?
+    };
+    // Outbound TLS and DNS still need certificates and resolver config
+    let readable = config
+        .landlock_read_paths
+        .clone()
+        .unwrap_or_else(|| vec!["/etc".to_string()])
+        .into_iter()
+        .filter(|path| std::path::Path::new(path).exists())
+        .collect::<Vec<_>>();
+
+    let abi = ABI::V2;
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))
+        .and_then(|ruleset| ruleset.create())
+        .and_then(|ruleset| {
+            ruleset.add_rules(path_beneath_rules(&[&writable], AccessFs::from_all(abi)))
+        })
+        .and_then(|ruleset| {
+            ruleset.add_rules(path_beneath_rules(&readable, AccessFs::from_read(abi)))
+        })
+        .and_then(|ruleset| ruleset.restrict_self())
+        .map_err(io::Error::other)?;
+
+    match status.ruleset {
+        RulesetStatus::NotEnforced => {
+            log_line!(
+                "WARN",
+                "Landlock is not supported by this kernel; filesystem is unrestricted"
+            )
+        }
+        _ => log_line!(
+            "SECURITY",
+            "Landlock: read/write limited to {}, read-only {:?}",
+            writable,
+            readable
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use crate::{severity, AppState, Event};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// `[hooks.<source>]` section. Every field but `events`, `token` and
+// `severity_map` is a template: `{path}` is replaced with the value at a
+// dotted path in the payload (`{labels.alertname}`, `{commits.0.id}`),
+// `{path|fallback}` gives a default, `{header:X-GitHub-Event}` reads a
+// request header and `{root:path}` the whole payload when `events` splits it.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+    // Path to an array in the payload; each element becomes one event
+    events: Option<String>,
+    // Required as `?token=` or `Authorization: Bearer`; without one the
+    // source checks `[api_keys]` like /api/events
+    token: Option<String>,
+    category: String,
+    event_type: Option<String>,
+    host: Option<String>,
+    severity: Option<String>,
+    // Source values (`firing`, `P1`) to Loglumen severities, before
+    // the usual spellings are normalized
+    severity_map: HashMap<String, String>,
+    message: Option<String>,
+    // RFC 3339 or epoch seconds; the time it arrived otherwise
+    time: Option<String>,
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        HookConfig {
+            events: None,
+            token: None,
+            category: "webhook".to_string(),
+            event_type: None,
+            host: None,
+            severity: None,
+            severity_map: HashMap::new(),
+            message: None,
+            time: None,
+        }
+    }
+}
+
+pub struct Hooks {
+    sources: HashMap<String, HookConfig>,
+}
+
+impl Hooks {
+    pub fn new(sources: HashMap<String, HookConfig>) -> Self {
+        if !sources.is_empty() {
+            let mut names: Vec<&str> = sources.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            log_line!("CONFIG", "Webhook sources: {}", names.join(", "));
+        }
+        Hooks { sources }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HookQuery {
+    token: Option<String>,
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|part| !part.is_empty())
+        .try_fold(value, |current, part| match current {
+            Value::Array(items) => items.get(part.parse::<usize>().ok()?),
+            _ => current.get(part),
+        })
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+// Where a template takes its values from
+struct Scope<'a> {
+    item: &'a Value,
+    root: &'a Value,
+    request: &'a HttpRequest,
+}
+
+impl Scope<'_> {
+    fn resolve(&self, reference: &str) -> Option<String> {
+        if let Some(name) = reference.strip_prefix("header:") {
+            return self
+                .request
+                .headers()
+                .get(name.trim())
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+        }
+        match reference.strip_prefix("root:") {
+            Some(path) => lookup(self.root, path.trim()).and_then(text),
+            None => lookup(self.item, reference.trim()).and_then(text),
+        }
+    }
+
+    // None when it renders empty, so the field's own default applies
+    fn render(&self, template: &str) -> Option<String> {
+        let mut output = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            output.push_str(&rest[..start]);
+            let reference = &rest[start + 1..start + end];
+            let (reference, fallback) = match reference.split_once('|') {
+                Some((reference, fallback)) => (reference, Some(fallback)),
+                None => (reference, None),
+            };
+            match self.resolve(reference) {
+                Some(value) => output.push_str(&value),
+                None => output.push_str(fallback.unwrap_or_default()),
+            }
+            rest = &rest[start + end + 1..];
+        }
+        output.push_str(rest);
+        (!output.is_empty()).then_some(output)
+    }
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn to_event(source: &str, config: &HookConfig, scope: &Scope, peer: &str) -> Event {
+    let field = |template: &Option<String>| {
+        template
+            .as_deref()
+            .and_then(|template| scope.render(template))
+    };
+    let severity = field(&config.severity)
+        .map(|value| {
+            let mapped = config
+                .severity_map
+                .iter()
+                .find(|(from, _)| from.eq_ignore_ascii_case(&value))
+                .map(|(_, to)| to.as_str())
+                .unwrap_or(&value);
+            severity::normalize(mapped).to_string()
+        })
+        .unwrap_or_else(|| "info".to_string());
+    let time = field(&config.time)
+        .and_then(|time| parse_time(&time))
+        .unwrap_or_else(Utc::now);
+    let event_type = field(&config.event_type).unwrap_or_else(|| source.to_string());
+    let message = field(&config.message).unwrap_or_else(|| format!("{} webhook", source));
+
+    Event {
+        schema_version: 1,
+        category: scope
+            .render(&config.category)
+            .unwrap_or_else(|| "webhook".to_string()),
+        event_type,
+        time: time.to_rfc3339(),
+        host: field(&config.host).unwrap_or_else(|| peer.to_string()),
+        host_ipv4: peer.to_string(),
+        os: String::new(),
+        source: source.to_string(),
+        severity,
+        message,
+        data: scope.item.clone(),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+fn error(status: actix_web::http::StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// POST /api/hook/{source} - JSON from a third-party service (GitHub,
+// Stripe, Alertmanager), mapped to events by `[hooks.<source>]`. The
+// payload itself is kept as `data`.
+pub async fn receive_hook(
+    request: HttpRequest,
+    source: web::Path<String>,
+    query: web::Query<HookQuery>,
+    body: web::Bytes,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let source = source.into_inner();
+    let Some(config) = data.hooks.sources.get(&source) else {
+        return Ok(error(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!("no webhook source '{}' in [hooks]", source),
+        ));
+    };
+    match &config.token {
+        Some(token) => {
+            let presented = query.token.as_deref().or_else(|| {
+                request
+                    .headers()
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+            });
+            // Digests are compared so how long the comparison takes says
+            // nothing about how much of the token matched
+            let matches = presented.is_some_and(|presented| {
+                Sha256::digest(presented.trim().as_bytes()) == Sha256::digest(token.as_bytes())
+            });
+            if !matches {
+                return Ok(error(
+                    actix_web::http::StatusCode::UNAUTHORIZED,
+                    format!("missing or invalid token for webhook source '{}'", source),
+                ));
+            }
+        }
+        None => {
+            if let Err(response) = data.api_keys.check(&request) {
+                return Ok(response);
+            }
+        }
+    }
+
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(err) => {
+            return Ok(error(
+                actix_web::http::StatusCode::BAD_REQUEST,
+                format!("webhook body is not JSON: {}", err),
+            ))
+        }
+    };
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+
+    let items: Vec<&Value> = match &config.events {
+        Some(path) => match lookup(&payload, path) {
+            Some(Value::Array(items)) => items.iter().collect(),
+            Some(item) => vec![item],
+            None => Vec::new(),
+        },
+        None => vec![&payload],
+    };
+    let identity = crate::tls::client_identity(&request);
+    let batch: Vec<Event> = items
+        .into_iter()
+        .map(|item| {
+            let scope = Scope {
+                item,
+                root: &payload,
+                request: &request,
+            };
+            let mut event = to_event(&source, config, &scope, &peer);
+            event.identity = identity.clone();
+            event
+        })
+        .collect();
+    let received = batch.len();
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "received": received
+    })))
+}
@@ -0,0 +1,293 @@
+use crate::rules::Alert;
+use crate::{config, severity, AppState};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+// Incidents kept in memory; closed ones are dropped first
+const MAX_INCIDENTS: usize = 1000;
+
+// `[incidents]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct IncidentsConfig {
+    // Alerts for the same host and rule family this close together join one
+    // incident
+    #[serde(deserialize_with = "config::de_duration")]
+    window: Duration,
+    // Rule name -> family, for rules that should be grouped together, e.g.
+    // several brute-force rules. Unlisted rules use their alert kind.
+    families: HashMap<String, String>,
+}
+
+impl Default for IncidentsConfig {
+    fn default() -> Self {
+        IncidentsConfig {
+            window: Duration::from_secs(30 * 60),
+            families: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Open,
+    // Someone is looking at it; new alerts still join
+    Ack,
+    Closed,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Open => "open",
+            Status::Ack => "ack",
+            Status::Closed => "closed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TimelineEntry {
+    time: DateTime<Utc>,
+    // "alert" or "status"
+    kind: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Incident {
+    id: u64,
+    status: Status,
+    host: String,
+    family: String,
+    // Highest severity among its alerts
+    severity: String,
+    title: String,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    alert_ids: Vec<u64>,
+    timeline: Vec<TimelineEntry>,
+}
+
+pub struct Incidents {
+    window: chrono::Duration,
+    families: HashMap<String, String>,
+    incidents: RwLock<VecDeque<Incident>>,
+}
+
+impl Incidents {
+    pub fn new(config: IncidentsConfig) -> Self {
+        for (rule, family) in &config.families {
+            log_line!(
+                "CONFIG",
+                "Rule '{}' grouped into incident family '{}'",
+                rule,
+                family
+            );
+        }
+        Incidents {
+            window: chrono::Duration::from_std(config.window)
+                .unwrap_or(chrono::Duration::minutes(30)),
+            families: config.families,
+            incidents: RwLock::new(VecDeque::new()),
+        }
+    }
+
+    fn family(&self, alert: &Alert) -> String {
+        self.families
+            .get(&alert.rule)
+            .cloned()
+            .unwrap_or_else(|| alert.kind.to_string())
+    }
+
+    // Called for every fired alert: joins the latest unclosed incident for
+    // the same host and family if it saw an alert within the window,
+    // otherwise opens a new one
+    pub fn observe(&self, alert: &Alert) {
+        let time = chrono::DateTime::parse_from_rfc3339(&alert.time)
+            .map(|time| time.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        let family = self.family(alert);
+        let entry = TimelineEntry {
+            time,
+            kind: "alert",
+            message: format!("{}: {}", alert.rule, alert.message),
+            alert_id: Some(alert.id),
+            by: None,
+        };
+
+        let mut incidents = self.incidents.write();
+        let existing = incidents.iter_mut().rev().find(|incident| {
+            incident.status != Status::Closed
+                && incident.host == alert.host
+                && incident.family == family
+                && (time - incident.last_seen).abs() <= self.window
+        });
+        if let Some(incident) = existing {
+            incident.alert_ids.push(alert.id);
+            incident.first_seen = incident.first_seen.min(time);
+            incident.last_seen = incident.last_seen.max(time);
+            if severity::rank(&alert.severity) > severity::rank(&incident.severity) {
+                incident.severity = alert.severity.clone();
+            }
+            incident.timeline.push(entry);
+            return;
+        }
+
+        let id = incidents.back().map_or(1, |incident| incident.id + 1);
+        log_line!(
+            "INCIDENT",
+            "Opened incident {} ({} on {})",
+            id,
+            family,
+            alert.host
+        );
+        if incidents.len() >= MAX_INCIDENTS {
+            let oldest = incidents
+                .iter()
+                .position(|incident| incident.status == Status::Closed)
+                .unwrap_or(0);
+            incidents.remove(oldest);
+        }
+        incidents.push_back(Incident {
+            id,
+            status: Status::Open,
+            host: alert.host.clone(),
+            family,
+            severity: alert.severity.clone(),
+            title: alert.message.clone(),
+            first_seen: time,
+            last_seen: time,
+            alert_ids: vec![alert.id],
+            timeline: vec![entry],
+        });
+    }
+}
+
+fn not_found(id: u64) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "status": "error",
+        "message": format!("no incident {}", id)
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncidentsQuery {
+    status: Option<Status>,
+    host: Option<String>,
+}
+
+// GET /api/incidents?status=open&host=web01 - Incidents, most recently
+// active first, without their timelines
+pub async fn list_incidents(
+    query: web::Query<IncidentsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let incidents = data.rules.incidents.incidents.read();
+    let mut matching: Vec<&Incident> = incidents
+        .iter()
+        .filter(|incident| query.status.is_none_or(|status| incident.status == status))
+        .filter(|incident| {
+            query
+                .host
+                .as_deref()
+                .is_none_or(|host| incident.host == host)
+        })
+        .collect();
+    matching.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then(b.id.cmp(&a.id)));
+    let listed: Vec<serde_json::Value> = matching
+        .into_iter()
+        .map(|incident| {
+            serde_json::json!({
+                "id": incident.id,
+                "status": incident.status,
+                "host": incident.host,
+                "family": incident.family,
+                "severity": incident.severity,
+                "title": incident.title,
+                "first_seen": incident.first_seen,
+                "last_seen": incident.last_seen,
+                "alerts": incident.alert_ids.len(),
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+// GET /api/incidents/{id} - One incident with its timeline, oldest first,
+// and the alerts still held in memory
+pub async fn get_incident(id: web::Path<u64>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let id = id.into_inner();
+    let Some(mut incident) = data
+        .rules
+        .incidents
+        .incidents
+        .read()
+        .iter()
+        .find(|incident| incident.id == id)
+        .cloned()
+    else {
+        return Ok(not_found(id));
+    };
+    incident.timeline.sort_by_key(|entry| entry.time);
+    let alerts = data.rules.alerts_by_id(&incident.alert_ids);
+
+    let mut response = serde_json::to_value(&incident).unwrap_or_default();
+    response["alerts"] = serde_json::to_value(alerts).unwrap_or_default();
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusChange {
+    status: Status,
+    note: Option<String>,
+}
+
+// POST /api/incidents/{id}/status - Move an incident to open, ack or closed;
+// the change and who made it go on the timeline. Needs an admin role.
+pub async fn set_status(
+    request: HttpRequest,
+    id: web::Path<u64>,
+    change: web::Json<StatusChange>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let by = match data.api_keys.authorize_admin(&request) {
+        Ok(name) => name,
+        Err(response) => return Ok(response),
+    };
+    let id = id.into_inner();
+    let mut incidents = data.rules.incidents.incidents.write();
+    let Some(incident) = incidents.iter_mut().find(|incident| incident.id == id) else {
+        return Ok(not_found(id));
+    };
+
+    let previous = incident.status;
+    incident.status = change.status;
+    let mut message = format!("{} -> {}", previous.as_str(), change.status.as_str());
+    if let Some(note) = change.note.as_deref().filter(|note| !note.is_empty()) {
+        message = format!("{}: {}", message, note);
+    }
+    incident.timeline.push(TimelineEntry {
+        time: Utc::now(),
+        kind: "status",
+        message: message.clone(),
+        alert_id: None,
+        by: Some(by.clone()),
+    });
+    log_line!("INCIDENT", "Incident {} {} by {}", id, message, by);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "id": id,
+        "incident_status": change.status,
+    })))
+}
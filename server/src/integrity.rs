@@ -0,0 +1,270 @@
+use crate::signing::canonical;
+use crate::storage::StoreView;
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+type Hash = [u8; 32];
+
+// `[integrity]` section
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IntegrityConfig {
+    // Hash every stored batch into a chain
+    enabled: bool,
+}
+
+// One stored batch: the Merkle root of its events and the chain link that
+// commits to it and to every batch before it
+struct BatchRecord {
+    // First sequence number and how many sequence numbers the batch spans
+    start: u64,
+    count: u64,
+    // Events hashed into the root
+    events: usize,
+    time: String,
+    root: Hash,
+    link: Hash,
+}
+
+pub struct Integrity {
+    enabled: bool,
+    batches: RwLock<Vec<BatchRecord>>,
+}
+
+impl Integrity {
+    pub fn new(config: IntegrityConfig) -> Self {
+        Integrity {
+            enabled: config.enabled,
+            batches: RwLock::new(Vec::new()),
+        }
+    }
+
+    // Runs before the store lock is taken
+    pub fn hash<'a>(&self, batch: impl IntoIterator<Item = &'a Event>) -> Option<Hash> {
+        self.enabled
+            .then(|| merkle_root(batch.into_iter().map(leaf).collect()))
+    }
+
+    // Called from the store's index callback, one batch at a time, so links
+    // are in store order
+    pub fn record(&self, start: u64, count: u64, events: usize, root: Option<Hash>) {
+        let Some(root) = root else {
+            return;
+        };
+        let mut batches = self.batches.write();
+        let previous = batches.last().map_or([0; 32], |batch| batch.link);
+        batches.push(BatchRecord {
+            start,
+            count,
+            events,
+            time: chrono::Utc::now().to_rfc3339(),
+            root,
+            link: link(&previous, &root, start, count),
+        });
+    }
+}
+
+// SHA-256(0x00 || canonical JSON of the stored event)
+fn leaf(event: &Event) -> Hash {
+    let mut json = String::new();
+    canonical(&serde_json::to_value(event).unwrap_or_default(), &mut json);
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(json.as_bytes());
+    hasher.finalize().into()
+}
+
+// SHA-256(0x01 || left || right)
+fn node(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// SHA-256(previous link || root || start || count), integers as 8-byte
+// big-endian. The first batch uses an all-zero previous link.
+fn link(previous: &Hash, root: &Hash, start: u64, count: u64) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    hasher.update(root);
+    hasher.update(start.to_be_bytes());
+    hasher.update(count.to_be_bytes());
+    hasher.finalize().into()
+}
+
+// Tree levels from the leaves up. Pairs are hashed left to right; an
+// unpaired last node moves up a level unchanged.
+fn levels(leaves: Vec<Hash>) -> Vec<Vec<Hash>> {
+    let mut levels = vec![leaves];
+    while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+        let next = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node(left, right),
+                _ => pair[0],
+            })
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+fn merkle_root(leaves: Vec<Hash>) -> Hash {
+    levels(leaves)
+        .last()
+        .and_then(|level| level.first().copied())
+        .unwrap_or([0; 32])
+}
+
+fn hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Sibling hashes from the leaf at `index` up to the root
+fn proof_path(leaves: Vec<Hash>, mut index: usize) -> Vec<serde_json::Value> {
+    let mut path = Vec::new();
+    for level in levels(leaves) {
+        let sibling = index ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            path.push(serde_json::json!({
+                "side": if sibling < index { "left" } else { "right" },
+                "hash": hex(hash),
+            }));
+        }
+        index /= 2;
+    }
+    path
+}
+
+// The batch's events with their sequence numbers, or None once retention
+// removed any
+fn batch_events<'a>(store: &'a StoreView, batch: &BatchRecord) -> Option<Vec<(u64, &'a Event)>> {
+    let events: Vec<(u64, &Event)> = store
+        .range(batch.start..batch.start + batch.count)
+        .collect();
+    (events.len() == batch.events).then_some(events)
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IntegrityQuery {
+    // Sequence number of an event to prove inclusion for
+    position: Option<u64>,
+    // Recompute every batch from the store and check the whole chain
+    #[serde(default)]
+    verify: bool,
+}
+
+// GET /api/admin/integrity - Chain head, an optional inclusion proof for one
+// event and an optional full re-verification of the store
+pub async fn get_integrity(
+    request: HttpRequest,
+    query: web::Query<IntegrityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    if !data.integrity.enabled {
+        return Ok(bad_request(
+            "the integrity chain is disabled; set [integrity] enabled = true".to_string(),
+        ));
+    }
+
+    // Same lock order as ingest: store first, then the chain
+    let store = data.store.read();
+    let batches = data.integrity.batches.read();
+    let head = batches.last();
+    let mut response = serde_json::json!({
+        "batches": batches.len(),
+        "events": head.map_or(0, |batch| batch.start + batch.count),
+        "head": head.map(|batch| hex(&batch.link)),
+        "head_time": head.map(|batch| batch.time.clone()),
+    });
+
+    if let Some(position) = query.position {
+        let index = batches.partition_point(|batch| batch.start + batch.count <= position);
+        let Some(batch) = batches.get(index).filter(|batch| batch.start <= position) else {
+            return Ok(bad_request(format!(
+                "position {} is not covered by the chain",
+                position
+            )));
+        };
+        let Some(events) = batch_events(&store, batch) else {
+            return Ok(bad_request(format!(
+                "events of the batch holding {} were removed by retention",
+                position
+            )));
+        };
+        let Some(offset) = events
+            .iter()
+            .position(|(sequence, _)| *sequence == position)
+        else {
+            return Ok(bad_request(format!("no stored event {}", position)));
+        };
+        let leaves: Vec<Hash> = events.iter().map(|(_, event)| leaf(event)).collect();
+        let previous = index
+            .checked_sub(1)
+            .map_or([0; 32], |previous| batches[previous].link);
+        response["proof"] = serde_json::json!({
+            "position": position,
+            "event": events[offset].1,
+            "leaf": hex(&leaves[offset]),
+            "path": proof_path(leaves, offset),
+            "batch": index,
+            "start": batch.start,
+            "count": batch.count,
+            "root": hex(&batch.root),
+            "previous": hex(&previous),
+            "link": hex(&batch.link),
+        });
+    }
+
+    if query.verify {
+        let mut previous = [0; 32];
+        let mut failure = None;
+        // Batches that lost events to retention can only have their link
+        // checked against the recorded root
+        let mut pruned = 0;
+        for (index, batch) in batches.iter().enumerate() {
+            match batch_events(&store, batch) {
+                Some(events) => {
+                    if merkle_root(events.iter().map(|(_, event)| leaf(event)).collect())
+                        != batch.root
+                    {
+                        failure = Some((index, "events no longer match the batch root"));
+                        break;
+                    }
+                }
+                None => pruned += 1,
+            }
+            if link(&previous, &batch.root, batch.start, batch.count) != batch.link {
+                failure = Some((index, "chain link does not match"));
+                break;
+            }
+            previous = batch.link;
+        }
+        response["verification"] = serde_json::json!({
+            "valid": failure.is_none(),
+            "pruned_batches": pruned,
+            "first_invalid": failure.map(|(index, reason)| serde_json::json!({
+                "batch": index,
+                "start": batches[index].start,
+                "reason": reason,
+            })),
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,328 @@
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+// `[inventory]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct InventoryConfig {
+    // Diffs kept per host, oldest dropped first
+    history: usize,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        InventoryConfig { history: 50 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OpenPort {
+    pub port: u16,
+    #[serde(default = "default_protocol")]
+    pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+}
+
+fn default_protocol() -> String {
+    "tcp".to_string()
+}
+
+// What an agent reports about its machine. Anything beyond the known fields
+// is kept as-is in `extra` and shown but not diffed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventoryDocument {
+    pub host: String,
+    #[serde(default)]
+    pub host_ipv4: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default)]
+    pub kernel: Option<String>,
+    #[serde(default)]
+    pub packages: Vec<Package>,
+    #[serde(default)]
+    pub open_ports: Vec<OpenPort>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct VersionChange {
+    name: String,
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct KernelChange {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+// Changes between two consecutive documents from one host
+#[derive(Debug, Clone, Default, Serialize)]
+struct InventoryDiff {
+    received_at: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    packages_added: Vec<Package>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    packages_removed: Vec<Package>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    packages_changed: Vec<VersionChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel: Option<KernelChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ports_opened: Vec<OpenPort>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ports_closed: Vec<OpenPort>,
+}
+
+impl InventoryDiff {
+    fn between(old: &InventoryDocument, new: &InventoryDocument, received_at: &str) -> Self {
+        let mut diff = InventoryDiff {
+            received_at: received_at.to_string(),
+            ..Default::default()
+        };
+
+        let old_packages: BTreeMap<&str, &str> = old
+            .packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.version.as_str()))
+            .collect();
+        let new_packages: BTreeMap<&str, &str> = new
+            .packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.version.as_str()))
+            .collect();
+        for (name, version) in &new_packages {
+            match old_packages.get(name) {
+                None => diff.packages_added.push(Package {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                }),
+                Some(previous) if previous != version => {
+                    diff.packages_changed.push(VersionChange {
+                        name: name.to_string(),
+                        from: previous.to_string(),
+                        to: version.to_string(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, version) in &old_packages {
+            if !new_packages.contains_key(name) {
+                diff.packages_removed.push(Package {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                });
+            }
+        }
+
+        if old.kernel != new.kernel {
+            diff.kernel = Some(KernelChange {
+                from: old.kernel.clone(),
+                to: new.kernel.clone(),
+            });
+        }
+
+        // Ports are compared by number and protocol; the owning process may
+        // change PID or name between scans without the port being "new"
+        let key = |port: &OpenPort| (port.port, port.protocol.to_ascii_lowercase());
+        let old_ports: BTreeSet<_> = old.open_ports.iter().map(key).collect();
+        let new_ports: BTreeSet<_> = new.open_ports.iter().map(key).collect();
+        diff.ports_opened = new
+            .open_ports
+            .iter()
+            .filter(|port| !old_ports.contains(&key(port)))
+            .cloned()
+            .collect();
+        diff.ports_closed = old
+            .open_ports
+            .iter()
+            .filter(|port| !new_ports.contains(&key(port)))
+            .cloned()
+            .collect();
+
+        diff
+    }
+
+    fn is_empty(&self) -> bool {
+        self.packages_added.is_empty()
+            && self.packages_removed.is_empty()
+            && self.packages_changed.is_empty()
+            && self.kernel.is_none()
+            && self.ports_opened.is_empty()
+            && self.ports_closed.is_empty()
+    }
+
+    fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        let packages =
+            self.packages_added.len() + self.packages_removed.len() + self.packages_changed.len();
+        if packages > 0 {
+            parts.push(format!("{} package change(s)", packages));
+        }
+        if let Some(kernel) = &self.kernel {
+            parts.push(format!(
+                "kernel {} -> {}",
+                kernel.from.as_deref().unwrap_or("none"),
+                kernel.to.as_deref().unwrap_or("none")
+            ));
+        }
+        if !self.ports_opened.is_empty() {
+            parts.push(format!("{} port(s) opened", self.ports_opened.len()));
+        }
+        if !self.ports_closed.is_empty() {
+            parts.push(format!("{} port(s) closed", self.ports_closed.len()));
+        }
+        parts.join(", ")
+    }
+}
+
+struct HostInventory {
+    latest: InventoryDocument,
+    received_at: String,
+    history: VecDeque<InventoryDiff>,
+}
+
+pub struct Inventory {
+    hosts: RwLock<HashMap<String, HostInventory>>,
+    history: usize,
+}
+
+impl Inventory {
+    pub fn new(config: InventoryConfig) -> Self {
+        Inventory {
+            hosts: RwLock::new(HashMap::new()),
+            history: config.history,
+        }
+    }
+
+    // Replace the host's document, returning the diff against the previous
+    // one. The first document from a host has nothing to diff against.
+    fn record(&self, document: InventoryDocument) -> Option<InventoryDiff> {
+        let received_at = chrono::Utc::now().to_rfc3339();
+        let mut hosts = self.hosts.write();
+        let Some(entry) = hosts.get_mut(&document.host) else {
+            hosts.insert(
+                document.host.clone(),
+                HostInventory {
+                    latest: document,
+                    received_at,
+                    history: VecDeque::new(),
+                },
+            );
+            return None;
+        };
+
+        let diff = InventoryDiff::between(&entry.latest, &document, &received_at);
+        entry.latest = document;
+        entry.received_at = received_at;
+        if diff.is_empty() {
+            return Some(diff);
+        }
+        entry.history.push_front(diff.clone());
+        entry.history.truncate(self.history);
+        Some(diff)
+    }
+
+    // Latest package list of every host, for vulnerability matching
+    pub fn packages(&self) -> Vec<(String, Vec<Package>)> {
+        self.hosts
+            .read()
+            .iter()
+            .map(|(host, entry)| (host.clone(), entry.latest.packages.clone()))
+            .collect()
+    }
+}
+
+// POST /api/inventory - Latest inventory document from an agent. Takes the
+// same API key as /api/events.
+pub async fn receive_inventory(
+    request: HttpRequest,
+    document: web::Json<InventoryDocument>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    let document = document.into_inner();
+    if document.host.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "inventory document needs a host"
+        })));
+    }
+
+    let host = document.host.clone();
+    data.availability.seen(&host);
+    let host_ipv4 = document.host_ipv4.clone();
+    let os = document.os.clone();
+    let packages = document.packages.len();
+    let diff = data.inventory.record(document);
+
+    // Changes become events so rules, outputs and the node page see them
+    if let Some(diff) = diff.as_ref().filter(|diff| !diff.is_empty()) {
+        let event = Event {
+            schema_version: 1,
+            category: "inventory".to_string(),
+            event_type: "inventory_changed".to_string(),
+            time: diff.received_at.clone(),
+            host: host.clone(),
+            host_ipv4,
+            os,
+            source: "inventory".to_string(),
+            severity: "info".to_string(),
+            message: format!("Inventory changed on {}: {}", host, diff.summary()),
+            data: serde_json::to_value(diff).unwrap_or_default(),
+            signature: None,
+            verification: None,
+            identity: None,
+            ttl: None,
+        };
+        data.api_keys.count_events(&request, 1);
+        crate::ingest(&data, vec![event]).await;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "host": host,
+        "packages": packages,
+        "first_report": diff.is_none(),
+        "changed": diff.is_some_and(|diff| !diff.is_empty()),
+    })))
+}
+
+// GET /api/nodes/{host}/inventory - Latest document plus change history,
+// newest first
+pub async fn get_inventory(
+    host: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let hosts = data.inventory.hosts.read();
+    let Some(entry) = hosts.get(host.as_str()) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("no inventory reported for '{}'", host)
+        })));
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "host": host.as_str(),
+        "received_at": entry.received_at,
+        "inventory": entry.latest,
+        "history": entry.history,
+    })))
+}
@@ -0,0 +1,266 @@
+use crate::storage::StoreView;
+use crate::{severity, AppState, Event};
+use actix_web::{web, HttpResponse, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// Where the common shippers (Fluent Bit, Vector, Filebeat, our own
+// DaemonSet) put container metadata, most specific first
+const NAMESPACE_FIELDS: [&str; 4] = [
+    "data.kubernetes.namespace_name",
+    "data.kubernetes.namespace",
+    "data.k8s.namespace",
+    "data.namespace",
+];
+const POD_FIELDS: [&str; 6] = [
+    "data.kubernetes.pod.name",
+    "data.kubernetes.pod_name",
+    "data.kubernetes.pod",
+    "data.k8s.pod",
+    "data.pod_name",
+    "data.pod",
+];
+const CONTAINER_FIELDS: [&str; 5] = [
+    "data.kubernetes.container_id",
+    "data.kubernetes.docker_id",
+    "data.container.id",
+    "data.container_id",
+    "data.docker.container_id",
+];
+
+fn first_field(event: &Event, fields: &[&str]) -> Option<String> {
+    fields
+        .iter()
+        .find_map(|field| event.field_value(field))
+        .filter(|value| !value.is_empty())
+}
+
+// Container metadata of one event; events without a namespace are not indexed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PodRef {
+    namespace: String,
+    pod: Option<String>,
+    container_id: Option<String>,
+}
+
+impl PodRef {
+    fn of(event: &Event) -> Option<Self> {
+        Some(PodRef {
+            namespace: first_field(event, &NAMESPACE_FIELDS)?,
+            pod: first_field(event, &POD_FIELDS),
+            container_id: first_field(event, &CONTAINER_FIELDS),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct PodSummary {
+    events: usize,
+    containers: Vec<String>,
+    nodes: Vec<String>,
+    last_seen: String,
+}
+
+#[derive(Default)]
+struct NamespaceIndex {
+    // Sequence numbers of this namespace's events, oldest first
+    sequences: VecDeque<u64>,
+    pods: BTreeMap<String, PodSummary>,
+}
+
+// Secondary index from namespace to the sequence numbers of its events
+#[derive(Default)]
+pub struct K8sIndex {
+    namespaces: RwLock<HashMap<String, NamespaceIndex>>,
+}
+
+impl K8sIndex {
+    // Runs before the store lock is taken
+    pub fn extract<'a>(&self, batch: impl IntoIterator<Item = &'a Event>) -> Vec<Option<PodRef>> {
+        batch.into_iter().map(PodRef::of).collect()
+    }
+
+    // Called under the store's write lock with the batch's events and their
+    // sequence numbers
+    pub fn record<'a>(
+        &self,
+        batch: impl IntoIterator<Item = (u64, &'a Event)>,
+        refs: Vec<Option<PodRef>>,
+    ) {
+        if refs.iter().all(Option::is_none) {
+            return;
+        }
+        let mut namespaces = self.namespaces.write();
+        for ((sequence, event), pod_ref) in batch.into_iter().zip(refs) {
+            let Some(pod_ref) = pod_ref else {
+                continue;
+            };
+            let namespace = namespaces.entry(pod_ref.namespace).or_default();
+            namespace.sequences.push_back(sequence);
+
+            let Some(pod) = pod_ref.pod else {
+                continue;
+            };
+            let summary = namespace.pods.entry(pod).or_default();
+            summary.events += 1;
+            summary.last_seen = event.time.clone();
+            if let Some(container) = pod_ref.container_id {
+                if !summary.containers.contains(&container) {
+                    summary.containers.push(container);
+                }
+            }
+            if !summary.nodes.contains(&event.host) {
+                summary.nodes.push(event.host.clone());
+            }
+        }
+    }
+
+    // Forget events a bounded store evicted, which are always its oldest
+    pub fn trim(&self, store: &StoreView) {
+        let Some(first) = store.first_sequence() else {
+            return;
+        };
+        let stale = |index: &NamespaceIndex| {
+            index
+                .sequences
+                .front()
+                .is_some_and(|sequence| *sequence < first)
+        };
+        if !self.namespaces.read().values().any(stale) {
+            return;
+        }
+        for index in self.namespaces.write().values_mut() {
+            let evicted = index
+                .sequences
+                .partition_point(|sequence| *sequence < first);
+            index.sequences.drain(..evicted);
+        }
+    }
+
+    // Forget events that are no longer stored, after retention removed some.
+    // Pod summaries keep counting everything seen.
+    pub fn retain(&self, store: &StoreView) {
+        let mut namespaces = self.namespaces.write();
+        for index in namespaces.values_mut() {
+            index
+                .sequences
+                .retain(|sequence| store.get(*sequence).is_some());
+        }
+    }
+}
+
+// GET /api/k8s/namespaces - Namespaces seen so far with event and pod counts
+pub async fn get_namespaces(data: web::Data<AppState>) -> Result<HttpResponse> {
+    let namespaces = data.k8s.namespaces.read();
+    let mut list: Vec<serde_json::Value> = namespaces
+        .iter()
+        .map(|(name, index)| {
+            serde_json::json!({
+                "namespace": name,
+                "events": index.sequences.len(),
+                "pods": index.pods.len(),
+            })
+        })
+        .collect();
+    list.sort_by(|a, b| a["namespace"].as_str().cmp(&b["namespace"].as_str()));
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "namespaces": list })))
+}
+
+fn unknown_namespace(namespace: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "status": "error",
+        "message": format!("no events seen for namespace '{}'", namespace)
+    }))
+}
+
+// GET /api/k8s/namespaces/{ns}/pods - Pods of a namespace with the
+// containers and nodes they were seen on
+pub async fn get_pods(
+    namespace: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let namespaces = data.k8s.namespaces.read();
+    let Some(index) = namespaces.get(namespace.as_str()) else {
+        return Ok(unknown_namespace(&namespace));
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "namespace": namespace.as_str(),
+        "pods": index.pods,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NamespaceEventsQuery {
+    pod: Option<String>,
+    container_id: Option<String>,
+    min_severity: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    500
+}
+
+// GET /api/k8s/namespaces/{ns}/events - Newest events of a namespace,
+// optionally narrowed to one pod or container
+pub async fn get_namespace_events(
+    namespace: web::Path<String>,
+    query: web::Query<NamespaceEventsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": err
+            })))
+        }
+    };
+
+    // Same lock order as ingest: store first, then the index
+    let store = data.store.read();
+    let namespaces = data.k8s.namespaces.read();
+    let Some(index) = namespaces.get(namespace.as_str()) else {
+        return Ok(unknown_namespace(&namespace));
+    };
+
+    let mut total = 0;
+    let mut events = Vec::new();
+    for &sequence in index.sequences.iter().rev() {
+        let Some(event) = store.get(sequence) else {
+            continue;
+        };
+        if !severity::passes(event, floor) {
+            continue;
+        }
+        if query.pod.is_some() || query.container_id.is_some() {
+            let pod_ref = PodRef::of(event);
+            let (pod, container_id) = pod_ref.as_ref().map_or((None, None), |pod_ref| {
+                (pod_ref.pod.as_ref(), pod_ref.container_id.as_ref())
+            });
+            if query.pod.as_ref().is_some_and(|wanted| pod != Some(wanted))
+                || query
+                    .container_id
+                    .as_ref()
+                    .is_some_and(|wanted| container_id != Some(wanted))
+            {
+                continue;
+            }
+        }
+        total += 1;
+        if events.len() < query.limit {
+            events.push(event.clone());
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "namespace": namespace.as_str(),
+        "total": total,
+        "events": events,
+    })))
+}
@@ -0,0 +1,2026 @@
+use actix_cors::Cors;
+use actix_web::http::Method;
+use actix_web::middleware::from_fn;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Result};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[macro_use]
+mod logging;
+
+mod access;
+mod agents;
+mod api_keys;
+mod audit;
+mod availability;
+#[cfg(feature = "aws")]
+mod aws;
+mod backpressure;
+mod capabilities;
+mod cardinality;
+mod cidr;
+mod cli;
+mod clock;
+mod config;
+mod control;
+mod crash;
+mod datadog;
+mod demo;
+mod display;
+#[cfg(all(feature = "docker", unix))]
+mod docker;
+mod elastic;
+mod fim;
+mod flows;
+#[cfg(feature = "gelf")]
+mod gelf;
+mod generator;
+mod hardening;
+mod hooks;
+mod incidents;
+mod integrity;
+mod inventory;
+mod k8s;
+mod live;
+mod loki;
+mod mailer;
+#[cfg(test)]
+mod mutations;
+#[cfg(feature = "netflow")]
+mod netflow;
+mod notifiers;
+mod otlp;
+mod outputs;
+mod panics;
+mod pipeline;
+mod plugins;
+mod raw;
+mod retention;
+mod routes;
+mod rules;
+mod scheduler;
+mod search;
+mod service;
+mod sessions;
+mod severity;
+mod shutdown;
+mod signing;
+#[cfg(feature = "smtp")]
+mod smtp;
+mod soft_limits;
+mod splunk;
+mod stats;
+#[cfg(feature = "statsd")]
+mod statsd;
+mod storage;
+#[cfg(feature = "syslog")]
+mod syslog;
+mod tls;
+mod usage;
+mod version;
+mod vulnerabilities;
+
+// Parser entry points for the cargo-fuzz targets in fuzz/; not an API
+#[doc(hidden)]
+pub mod fuzzing {
+    #[cfg(feature = "gelf")]
+    pub use crate::gelf::fuzz as gelf;
+    pub use crate::loki::{fuzz_push as loki_push, fuzz_selector as logql};
+    #[cfg(feature = "netflow")]
+    pub use crate::netflow::fuzz as netflow;
+    pub use crate::raw::fuzz as cef;
+    #[cfg(feature = "smtp")]
+    pub use crate::smtp::fuzz as smtp;
+    #[cfg(feature = "statsd")]
+    pub use crate::statsd::fuzz as statsd;
+    #[cfg(feature = "syslog")]
+    pub use crate::syslog::fuzz as syslog;
+}
+
+// Event structure matching Python agent JSON schema
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Event {
+    schema_version: u32,
+    category: String,
+    event_type: String,
+    time: String,
+    host: String,
+    host_ipv4: String,
+    os: String,
+    source: String,
+    severity: String,
+    message: String,
+    data: serde_json::Value,
+    // Base64 Ed25519 signature from the sending agent (see signing.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    // Set by the server when agents post the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verification: Option<signing::Verification>,
+    // CN of the client certificate the event arrived with (see tls.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+    // Seconds the sender wants the event kept; [retention] bounds it (see
+    // retention.rs)
+    #[serde(
+        default,
+        deserialize_with = "retention::de_ttl",
+        skip_serializing_if = "Option::is_none"
+    )]
+    ttl: Option<u64>,
+}
+
+impl Event {
+    // Agents send RFC 3339 timestamps; older ones omit the offset
+    fn timestamp(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        if let Ok(parsed) = chrono::DateTime::parse_from_rfc3339(&self.time) {
+            return Some(parsed.with_timezone(&chrono::Utc));
+        }
+        chrono::NaiveDateTime::parse_from_str(&self.time, "%Y-%m-%dT%H:%M:%S%.f")
+            .ok()
+            .map(|naive| naive.and_utc())
+    }
+
+    // Look up a core field by name, or a value inside `data` via "data.a.b"
+    fn field_value(&self, path: &str) -> Option<String> {
+        let value = match path {
+            "category" => &self.category,
+            "event_type" => &self.event_type,
+            "time" => &self.time,
+            "host" => &self.host,
+            "host_ipv4" => &self.host_ipv4,
+            "os" => &self.os,
+            "source" => &self.source,
+            "severity" => &self.severity,
+            "message" => &self.message,
+            "identity" => return self.identity.clone(),
+            _ => {
+                let mut current = &self.data;
+                for part in path.strip_prefix("data.")?.split('.') {
+                    current = current.get(part)?;
+                }
+                return match current {
+                    serde_json::Value::Null => None,
+                    serde_json::Value::String(text) => Some(text.clone()),
+                    other => Some(other.to_string()),
+                };
+            }
+        };
+        Some(value.clone())
+    }
+}
+
+// Optional since/until bounds (RFC 3339) shared by the query endpoints
+#[derive(Debug, Clone, Copy, Default)]
+struct TimeRange {
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl TimeRange {
+    fn parse(since: Option<&str>, until: Option<&str>) -> std::result::Result<Self, String> {
+        fn bound(
+            value: Option<&str>,
+        ) -> std::result::Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+            value
+                .map(|raw| {
+                    chrono::DateTime::parse_from_rfc3339(raw)
+                        .map(|time| time.with_timezone(&chrono::Utc))
+                        .map_err(|err| format!("invalid timestamp '{}': {}", raw, err))
+                })
+                .transpose()
+        }
+        Ok(TimeRange {
+            since: bound(since)?,
+            until: bound(until)?,
+        })
+    }
+
+    // Events without a usable timestamp only pass an unbounded range
+    fn contains(&self, event: &Event) -> bool {
+        match event.timestamp() {
+            Some(time) => {
+                self.since.is_none_or(|since| time >= since)
+                    && self.until.is_none_or(|until| time <= until)
+            }
+            None => self.since.is_none() && self.until.is_none(),
+        }
+    }
+}
+
+// Optional filters for the GET event listings. Each field takes a
+// comma-separated list, matched case-insensitively.
+#[derive(Debug, Default, Deserialize)]
+struct EventFilter {
+    // RFC 3339 bounds on the event time
+    from: Option<String>,
+    to: Option<String>,
+    severity: Option<String>,
+    category: Option<String>,
+    event_type: Option<String>,
+    source: Option<String>,
+}
+
+impl EventFilter {
+    fn is_empty(&self) -> bool {
+        self.from.is_none()
+            && self.to.is_none()
+            && self.severity.is_none()
+            && self.category.is_none()
+            && self.event_type.is_none()
+            && self.source.is_none()
+    }
+
+    fn range(&self) -> std::result::Result<TimeRange, String> {
+        TimeRange::parse(self.from.as_deref(), self.to.as_deref())
+    }
+
+    // The event fields the filters read, for the [access] check
+    fn fields(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.from.is_some() || self.to.is_some(), "time"),
+            (self.severity.is_some(), "severity"),
+            (self.category.is_some(), "category"),
+            (self.event_type.is_some(), "event_type"),
+            (self.source.is_some(), "source"),
+        ]
+        .into_iter()
+        .filter_map(|(set, field)| set.then_some(field))
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        fn listed(values: &Option<String>, actual: &str) -> bool {
+            values.as_deref().is_none_or(|values| {
+                values
+                    .split(',')
+                    .any(|value| value.trim().eq_ignore_ascii_case(actual))
+            })
+        }
+
+        listed(&self.severity, &event.severity)
+            && listed(&self.category, &event.category)
+            && listed(&self.event_type, &event.event_type)
+            && listed(&self.source, &event.source)
+    }
+}
+
+// ?limit=&cursor= on the GET event listings. Without either, the full
+// matching list is returned as a plain array.
+#[derive(Debug, Deserialize)]
+struct Pagination {
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+impl Pagination {
+    fn is_requested(&self) -> bool {
+        self.limit.is_some() || self.cursor.is_some()
+    }
+
+    fn cursor(&self) -> std::result::Result<Option<u64>, String> {
+        self.cursor
+            .as_deref()
+            .map(storage::decode_cursor)
+            .transpose()
+    }
+}
+
+// Serve a query either whole or as one page of `{events, next_cursor}`
+fn event_listing(
+    data: &AppState,
+    mut query: storage::StoreQuery,
+    pagination: &Pagination,
+) -> HttpResponse {
+    if !pagination.is_requested() {
+        return HttpResponse::Ok().json(data.store.query(&query));
+    }
+    query.cursor = match pagination.cursor() {
+        Ok(cursor) => cursor,
+        Err(err) => return bad_request(err),
+    };
+    query.limit = Some(pagination.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1));
+    let page = data.store.page(&query);
+    HttpResponse::Ok().json(serde_json::json!({
+        "events": page.events,
+        "next_cursor": page.next.map(storage::encode_cursor),
+    }))
+}
+
+// Statistics for frontend
+#[derive(Debug, Serialize)]
+struct CategoryStats {
+    category: String,
+    total_count: usize,
+    event_types: HashMap<String, usize>,
+    severity_counts: HashMap<String, usize>,
+    recent_events: Vec<display::DisplayedEvent<Event>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DashboardStats {
+    // Pass to /api/stats/delta?since= to fetch only what changed
+    version: u64,
+    total_events: usize,
+    categories: Vec<CategoryStats>,
+    last_updated: String,
+    last_updated_display: String,
+    nodes: Vec<NodeStats>,
+    // Locale and time zone of the `*_display` timestamps
+    display: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct NodeStats {
+    host: String,
+    host_ipv4: String,
+    total_events: usize,
+    last_event_time: Option<String>,
+    last_event_time_display: Option<String>,
+    // When the host last reported, by events or heartbeat, and whether
+    // that makes it online, stale or offline
+    last_seen: Option<String>,
+    last_seen_display: Option<String>,
+    status: availability::Liveness,
+    // From the agent registry; registered hosts are listed even before
+    // they send an event
+    agent_id: Option<String>,
+    agent_version: Option<String>,
+    tags: Vec<String>,
+    categories: HashMap<String, usize>,
+    severity_counts: HashMap<String, usize>,
+}
+
+// Events per page when a client sends a cursor without a limit
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
+// Application state
+struct AppState {
+    // Every handler reads and writes events through this
+    store: Arc<dyn storage::Storage>,
+    rules: rules::RuleEngine,
+    ingest_log: logging::IngestLogger,
+    plugins: plugins::PluginRegistry,
+    scheduler: scheduler::Scheduler,
+    outputs: outputs::Outputs,
+    stats: stats::Stats,
+    display: display::TimeDisplay,
+    severity: severity::SeverityFloor,
+    control: control::AgentChannels,
+    inventory: inventory::Inventory,
+    fim: fim::Baselines,
+    vulnerabilities: vulnerabilities::Vulnerabilities,
+    flows: flows::Flows,
+    k8s: k8s::K8sIndex,
+    search: search::SearchIndex,
+    availability: availability::Availability,
+    clock: clock::SharedClock,
+    backpressure: backpressure::Backpressure,
+    pipeline: pipeline::Pipeline,
+    signing: signing::Signing,
+    integrity: integrity::Integrity,
+    retention: retention::Retention,
+    live: live::LiveTail,
+    access: access::Access,
+    sessions: sessions::Sessions,
+    api_keys: api_keys::ApiKeys,
+    agents: agents::AgentRegistry,
+    raw: raw::Raw,
+    hooks: hooks::Hooks,
+    loki: loki::Loki,
+    elastic: elastic::Elastic,
+    splunk: splunk::Splunk,
+    datadog: datadog::Datadog,
+    otlp: otlp::Otlp,
+    audit: audit::Audit,
+    demo: demo::Demo,
+    capabilities: capabilities::Capabilities,
+    soft_limits: soft_limits::SoftLimits,
+}
+
+// Queue a batch for the ingest writers (see pipeline.rs), waiting while
+// they are behind
+async fn ingest(data: &AppState, batch: Vec<Event>) {
+    let reservation = data.backpressure.claim(batch.len());
+    data.pipeline.submit(batch, reservation).await;
+    data.soft_limits.check(data, 0, 0);
+}
+
+// Same, for scheduled tasks, which run on threads that may block
+fn ingest_blocking(data: &AppState, batch: Vec<Event>) {
+    let reservation = data.backpressure.claim(batch.len());
+    data.pipeline.blocking_submit(batch, reservation);
+    data.soft_limits.check(data, 0, 0);
+}
+
+// Log, evaluate, forward and store a batch. Logging, rule evaluation and
+// hashing happen before the store takes its write lock so readers are only
+// blocked for the final insert. Network flows are aggregated instead and never reach the
+// store individually. An error means the backend couldn't keep the batch.
+fn store(data: &AppState, batch: Vec<Event>) -> Result<(), String> {
+    let batch = data.flows.absorb(batch);
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let events = batch.len();
+    panics::guard("log", events, || data.ingest_log.log_batch(&batch));
+    data.retention.observe(&batch);
+
+    panics::guard("rules", events, || data.rules.observe(&batch));
+    panics::guard("fim", events, || data.fim.observe(&batch, &data.rules));
+    panics::guard("outputs", events, || data.outputs.publish(&batch));
+
+    let mut pod_refs = data.k8s.extract(&batch);
+    let mut documents = data.search.extract(&batch);
+    let root = data.integrity.hash(&batch);
+    let total = data
+        .store
+        .insert_batch(batch, &mut |start, batch| {
+            data.k8s
+                .record((start..).zip(batch), std::mem::take(&mut pod_refs));
+            data.search.record(start.., std::mem::take(&mut documents));
+            data.integrity
+                .record(start, batch.len() as u64, batch.len(), root);
+            data.live.publish(batch);
+        })
+        .inspect_err(|err| log_line!("ERROR", "Failed to store {} events: {}", events, err))?;
+    {
+        let store = data.store.read();
+        data.k8s.trim(&store);
+        data.search.trim(&store);
+    }
+    data.ingest_log.log_stored(total);
+    Ok(())
+}
+
+// Rebuild the indexes over events a backend restored at startup. They are
+// chained as one batch spanning every restored sequence number.
+fn reindex(data: &AppState) {
+    let store = data.store.read();
+    if store.is_empty() {
+        return;
+    }
+    data.retention.observe(store.iter());
+    data.k8s
+        .record(store.entries(), data.k8s.extract(store.iter()));
+    data.search.record(
+        store.entries().map(|(sequence, _)| sequence),
+        data.search.extract(store.iter()),
+    );
+    let start = store.first_sequence().unwrap_or_default();
+    let span = data.store.stats().version - start;
+    data.integrity
+        .record(start, span, store.len(), data.integrity.hash(store.iter()));
+    log_line!(
+        "INFO",
+        "Restored {} events from {} storage",
+        store.len(),
+        data.store.name()
+    );
+}
+
+// Content-Encoding values request bodies may use
+const CONTENT_ENCODINGS: [&str; 6] = ["identity", "gzip", "x-gzip", "deflate", "br", "zstd"];
+
+// Bodies in an encoding actix can't undo would otherwise be parsed as-is
+// and fail as invalid JSON
+fn check_encoding(request: &HttpRequest) -> std::result::Result<(), HttpResponse> {
+    let Some(encoding) = request
+        .headers()
+        .get(actix_web::http::header::CONTENT_ENCODING)
+    else {
+        return Ok(());
+    };
+    let encoding = encoding
+        .to_str()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match encoding.as_str() {
+        encoding if CONTENT_ENCODINGS.contains(&encoding) => Ok(()),
+        _ => Err(
+            HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "status": "error",
+                "message": format!("unsupported Content-Encoding '{}'; send gzip or zstd", encoding)
+            })),
+        ),
+    }
+}
+
+// POST /api/events - Receive events from agent. Gzip and zstd bodies are
+// decompressed before parsing; [limits] max_body_size applies to the result.
+async fn receive_events(
+    request: HttpRequest,
+    events: Result<web::Json<Vec<Event>>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    if let Err(response) = check_encoding(&request) {
+        return Ok(response);
+    }
+    let mut batch = events?.into_inner();
+    let received = batch.len();
+    if let Err(response) = data.backpressure.check_batch(received) {
+        return Ok(response);
+    }
+    let reservation = match data.backpressure.reserve(received) {
+        Ok(reservation) => reservation,
+        Err(response) => return Ok(response),
+    };
+    // Only the connection says who sent the events, never the agent itself
+    let identity = tls::client_identity(&request);
+    for event in batch.iter_mut() {
+        event.identity = identity.clone();
+    }
+
+    let rejected = data.signing.check(&mut batch);
+    let accepted = batch.len();
+    data.availability.observe(&batch);
+    // A writer with no room left means the agent retries the whole batch
+    let Some(receipt) = data.pipeline.try_submit(batch, reservation) else {
+        return Ok(data.backpressure.full());
+    };
+    // So does one the store couldn't keep
+    if let Err(err) = receipt.stored().await {
+        return Ok(not_stored(err));
+    }
+    data.api_keys.count_events(&request, accepted);
+    // Compressed bodies are only measured after decompression, by the
+    // extractor; their size isn't known here
+    let body_bytes = match request
+        .headers()
+        .get(actix_web::http::header::CONTENT_ENCODING)
+    {
+        Some(encoding) if encoding != "identity" => 0,
+        _ => request
+            .headers()
+            .get(actix_web::http::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse().ok())
+            .unwrap_or(0),
+    };
+    let warnings = data.soft_limits.check(&data, body_bytes, received);
+
+    Ok(
+        soft_limits::annotate(HttpResponse::Ok(), &warnings).json(serde_json::json!({
+            "status": "success",
+            "received": received,
+            "rejected": rejected
+        })),
+    )
+}
+
+// Events ingested together while streaming NDJSON
+const NDJSON_BATCH: usize = 500;
+// Longest NDJSON line accepted
+const NDJSON_MAX_LINE: usize = 1024 * 1024;
+// Bad lines reported back in the response at most
+const NDJSON_MAX_ERRORS: usize = 10;
+
+// Counts for one NDJSON request, ingesting about every NDJSON_BATCH events so
+// the body is never held in memory whole
+struct NdjsonIngest<'a> {
+    data: &'a AppState,
+    request: &'a HttpRequest,
+    identity: Option<String>,
+    pending: Vec<Event>,
+    lines: usize,
+    received: usize,
+    rejected: usize,
+    invalid: usize,
+    errors: Vec<serde_json::Value>,
+    warnings: Vec<soft_limits::Warning>,
+}
+
+impl NdjsonIngest<'_> {
+    fn line(&mut self, line: &[u8]) {
+        self.lines += 1;
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            return;
+        }
+        match serde_json::from_slice::<Event>(line) {
+            Ok(mut event) => {
+                event.identity = self.identity.clone();
+                self.pending.push(event);
+                self.received += 1;
+            }
+            Err(err) => {
+                self.invalid += 1;
+                if self.errors.len() < NDJSON_MAX_ERRORS {
+                    self.errors.push(serde_json::json!({
+                        "line": self.lines,
+                        "message": err.to_string(),
+                    }));
+                }
+            }
+        }
+    }
+
+    // Waits while the ingest queue is full rather than rejecting the rest
+    // of the stream, and until the chunk is stored
+    async fn flush(&mut self) -> std::result::Result<(), String> {
+        let mut batch = std::mem::take(&mut self.pending);
+        let reservation = self.data.backpressure.wait(batch.len()).await;
+        self.rejected += self.data.signing.check(&mut batch);
+        let accepted = batch.len();
+        self.data.availability.observe(&batch);
+        self.data
+            .pipeline
+            .submit(batch, reservation)
+            .await
+            .stored()
+            .await?;
+        self.data.api_keys.count_events(self.request, accepted);
+        let warnings = self.data.soft_limits.check(self.data, 0, 0);
+        soft_limits::merge(&mut self.warnings, warnings);
+        Ok(())
+    }
+
+    // The counts so far, with the lines after the failed chunk unread
+    fn not_stored(&self, err: String) -> HttpResponse {
+        let mut body = self.summary();
+        body["status"] = "error".into();
+        body["message"] = format!("events could not be stored: {}", err).into();
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+
+    fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "received": self.received,
+            "rejected": self.rejected,
+            "invalid": self.invalid,
+            "errors": self.errors,
+        })
+    }
+}
+
+// POST /api/events/ndjson - Receive events as newline-delimited JSON, one
+// event per line. The body is read as it streams in and ingested in chunks,
+// so it isn't bound by [limits] max_body_size; only each line is. Lines that
+// don't parse are skipped and reported by line number.
+async fn receive_ndjson(
+    request: HttpRequest,
+    payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    use futures_util::StreamExt;
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    if let Err(response) = check_encoding(&request) {
+        return Ok(response);
+    }
+    let mut payload =
+        actix_web::dev::Decompress::from_headers(payload.into_inner(), request.headers());
+    let mut stream = NdjsonIngest {
+        data: &data,
+        request: &request,
+        identity: tls::client_identity(&request),
+        pending: Vec::new(),
+        lines: 0,
+        received: 0,
+        rejected: 0,
+        invalid: 0,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let mut buffer = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                // What arrived before the error is kept
+                if let Err(err) = stream.flush().await {
+                    return Ok(stream.not_stored(err));
+                }
+                return Err(err.into());
+            }
+        };
+        data.api_keys.count_bytes(&request, chunk.len());
+        // Only the new chunk can hold the next newline
+        let mut searched = buffer.len();
+        buffer.extend_from_slice(&chunk);
+        while let Some(position) = buffer[searched..].iter().position(|byte| *byte == b'\n') {
+            let line = buffer.split_to(searched + position + 1);
+            stream.line(&line[..line.len() - 1]);
+            searched = 0;
+        }
+        if stream.pending.len() >= NDJSON_BATCH {
+            if let Err(err) = stream.flush().await {
+                return Ok(stream.not_stored(err));
+            }
+        }
+        if buffer.len() > NDJSON_MAX_LINE {
+            if let Err(err) = stream.flush().await {
+                return Ok(stream.not_stored(err));
+            }
+            let mut body = stream.summary();
+            body["status"] = "error".into();
+            body["message"] = format!(
+                "line {} is longer than {} bytes",
+                stream.lines + 1,
+                NDJSON_MAX_LINE
+            )
+            .into();
+            return Ok(HttpResponse::PayloadTooLarge().json(body));
+        }
+    }
+    stream.line(&buffer);
+    if let Err(err) = stream.flush().await {
+        return Ok(stream.not_stored(err));
+    }
+
+    let mut body = stream.summary();
+    body["status"] = "success".into();
+    Ok(soft_limits::annotate(HttpResponse::Ok(), &stream.warnings).json(body))
+}
+
+// GET /api/stats - Get statistics for dashboard
+async fn get_stats(
+    query: web::Query<severity::SeverityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    // Plain polls share the background snapshot when caching is enabled
+    if query.min_severity.is_none() {
+        if let Some(snapshot) = data.stats.cached() {
+            return Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(snapshot));
+        }
+    }
+
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    Ok(HttpResponse::Ok().json(compute_stats(&data, floor)))
+}
+
+// Dashboard aggregation, read from the store's running counts rather than
+// by walking every event
+fn compute_stats(data: &AppState, floor: usize) -> DashboardStats {
+    let store = data.store.read();
+    let now = data.clock.now();
+    let tally = store.tally();
+
+    let mut categories: Vec<CategoryStats> = tally
+        .categories()
+        .map(|(category, counts)| {
+            // Recent events (time window, bounded both ways), honouring the
+            // severity floor; the counts always cover everything
+            let visible = store
+                .category_by_time(category)
+                .rev()
+                .filter(|event| severity::passes(event, floor));
+            CategoryStats {
+                category: category.clone(),
+                total_count: counts.total,
+                // Bounded in case a source puts IDs in event_type
+                event_types: stats::top_groups(&counts.event_types, data.stats.max_groups),
+                severity_counts: counts.severities.clone(),
+                recent_events: data
+                    .stats
+                    .recent
+                    .select(visible, now)
+                    .into_iter()
+                    .map(|event| data.display.event(event))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    // Sort categories by name
+    categories.sort_by(|a, b| a.category.cmp(&b.category));
+
+    let mut registered = data.agents.by_host();
+    let mut nodes: Vec<NodeStats> = tally
+        .nodes()
+        .map(|(host, host_ipv4, counts)| {
+            let (status, last_seen) = data.availability.liveness(host, counts.last_event_time());
+            let agent = registered.remove(host);
+            NodeStats {
+                host: host.clone(),
+                host_ipv4: host_ipv4.clone(),
+                total_events: counts.total,
+                last_event_time: counts.last_event_time().map(str::to_string),
+                last_event_time_display: counts
+                    .last_event_time()
+                    .and_then(|time| data.display.format(time)),
+                last_seen: last_seen.map(|time| time.to_rfc3339()),
+                last_seen_display: last_seen.map(|time| data.display.format_time(time)),
+                status,
+                agent_id: agent.as_ref().map(|agent| agent.id.clone()),
+                agent_version: agent
+                    .as_ref()
+                    .map(|agent| agent.agent_version.clone())
+                    .filter(|version| !version.is_empty()),
+                tags: agent.map(|agent| agent.tags).unwrap_or_default(),
+                categories: counts.categories.clone(),
+                severity_counts: counts.severities.clone(),
+            }
+        })
+        .collect();
+    // Registered agents that haven't sent anything (or whose events have
+    // all expired)
+    nodes.extend(registered.into_values().map(|agent| {
+        let (status, last_seen) = data.availability.liveness(&agent.hostname, None);
+        NodeStats {
+            host: agent.hostname,
+            host_ipv4: agent.host_ipv4,
+            total_events: 0,
+            last_event_time: None,
+            last_event_time_display: None,
+            last_seen: last_seen.map(|time| time.to_rfc3339()),
+            last_seen_display: last_seen.map(|time| data.display.format_time(time)),
+            status,
+            agent_id: Some(agent.id),
+            agent_version: Some(agent.agent_version).filter(|version| !version.is_empty()),
+            tags: agent.tags,
+            categories: HashMap::new(),
+            severity_counts: HashMap::new(),
+        }
+    }));
+    nodes.sort_by(|a, b| {
+        b.total_events
+            .cmp(&a.total_events)
+            .then_with(|| a.host.cmp(&b.host))
+    });
+
+    DashboardStats {
+        version: data.store.stats().version,
+        total_events: store.len(),
+        categories,
+        last_updated: now.to_rfc3339(),
+        last_updated_display: data.display.format_time(now),
+        nodes,
+        display: data.display.settings(),
+    }
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// The store refused a batch, e.g. the disk is full; the client should retry
+// it later
+fn not_stored(err: String) -> HttpResponse {
+    HttpResponse::ServiceUnavailable().json(serde_json::json!({
+        "status": "error",
+        "message": format!("events could not be stored: {}", err)
+    }))
+}
+
+// GET /api/events - All events, oldest first, optionally filtered by
+// ?from=&to=&severity=&category=&event_type=&source= and paged with
+// ?limit=&cursor=
+async fn get_all_events(
+    request: HttpRequest,
+    query: web::Query<severity::SeverityQuery>,
+    filter: web::Query<EventFilter>,
+    pagination: web::Query<Pagination>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let floor_field = query.min_severity.as_ref().map(|_| "severity");
+    if let Err(response) = data
+        .access
+        .check_fields(&request, filter.fields().chain(floor_field))
+    {
+        return Ok(response);
+    }
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let range = match filter.range() {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    if floor == 0 && filter.is_empty() && !pagination.is_requested() {
+        return Ok(HttpResponse::Ok().json(data.store.read().iter().collect::<Vec<_>>()));
+    }
+    let query = storage::StoreQuery {
+        range,
+        min_severity: floor,
+        matches: Some(&|event: &Event| filter.matches(event)),
+        ..Default::default()
+    };
+    Ok(event_listing(&data, query, &pagination))
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SelectorType {
+    #[default]
+    Host,
+    Ip,
+    // Agent-assigned node identifier, sent as data.node_id
+    Id,
+}
+
+#[derive(Debug, Deserialize)]
+struct SelectorQuery {
+    #[serde(default)]
+    selector_type: SelectorType,
+    // Applies to host and id selectors; agents disagree on hostname case
+    #[serde(default)]
+    ignore_case: bool,
+    min_severity: Option<String>,
+}
+
+// GET /api/events/{selector} - Get events for a host (or by IP / node id with
+// ?selector_type=ip|id), taking the same filters as /api/events
+async fn get_events_for_host(
+    request: HttpRequest,
+    host: web::Path<String>,
+    query: web::Query<SelectorQuery>,
+    filter: web::Query<EventFilter>,
+    pagination: web::Query<Pagination>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let field = match query.selector_type {
+        SelectorType::Host => "host",
+        SelectorType::Ip => "host_ipv4",
+        SelectorType::Id => "data.node_id",
+    };
+    let floor_field = query.min_severity.as_ref().map(|_| "severity");
+    let fields = [field]
+        .into_iter()
+        .chain(filter.fields())
+        .chain(floor_field);
+    if let Err(response) = data.access.check_fields(&request, fields) {
+        return Ok(response);
+    }
+    let decoded = percent_decode_str(&host.into_inner())
+        .decode_utf8_lossy()
+        .to_string();
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let range = match filter.range() {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+
+    let matches = |value: &str| {
+        if query.ignore_case {
+            value.eq_ignore_ascii_case(&decoded)
+        } else {
+            value == decoded
+        }
+    };
+    let selected = |event: &Event| {
+        let selected = match query.selector_type {
+            SelectorType::Host => matches(&event.host),
+            SelectorType::Ip => event.host_ipv4 == decoded,
+            SelectorType::Id => event
+                .field_value("data.node_id")
+                .is_some_and(|id| matches(&id)),
+        };
+        selected && filter.matches(event)
+    };
+
+    // Latest events at the top
+    let query = storage::StoreQuery {
+        range,
+        min_severity: floor,
+        matches: Some(&selected),
+        newest_first: true,
+        ..Default::default()
+    };
+    Ok(event_listing(&data, query, &pagination))
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum GroupBy {
+    #[default]
+    Host,
+    Category,
+    Severity,
+}
+
+impl GroupBy {
+    fn field(self) -> &'static str {
+        match self {
+            GroupBy::Host => "host",
+            GroupBy::Category => "category",
+            GroupBy::Severity => "severity",
+        }
+    }
+}
+
+// Body of POST /api/events/query. Empty lists match everything.
+#[derive(Debug, Deserialize)]
+struct EventQuery {
+    #[serde(default)]
+    hosts: Vec<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    severities: Vec<String>,
+    since: Option<String>,
+    until: Option<String>,
+    #[serde(default)]
+    group_by: GroupBy,
+    // Newest events kept per group
+    #[serde(default = "default_group_limit")]
+    limit: usize,
+}
+
+impl EventQuery {
+    // The event fields the query reads, grouping included
+    fn fields(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (!self.hosts.is_empty(), "host"),
+            (!self.categories.is_empty(), "category"),
+            (!self.severities.is_empty(), "severity"),
+            (self.since.is_some() || self.until.is_some(), "time"),
+            (true, self.group_by.field()),
+        ]
+        .into_iter()
+        .filter_map(|(set, field)| set.then_some(field))
+    }
+}
+
+fn default_group_limit() -> usize {
+    500
+}
+
+#[derive(Debug, Serialize)]
+struct EventGroup {
+    key: String,
+    total: usize,
+    events: Vec<Event>,
+}
+
+// POST /api/events/query - Events for several hosts/categories at once,
+// grouped so a comparison view needs a single request
+async fn query_events(
+    request: HttpRequest,
+    query: web::Json<EventQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    if let Err(response) = data.access.check_fields(&request, query.fields()) {
+        return Ok(response);
+    }
+    let range = match TimeRange::parse(query.since.as_deref(), query.until.as_deref()) {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let listed = |values: &[String], actual: &str| {
+        values.is_empty()
+            || values
+                .iter()
+                .any(|value| value.eq_ignore_ascii_case(actual))
+    };
+
+    let mut groups: Vec<EventGroup> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let store = data.store.read();
+    // Newest first by time, so truncating a group keeps its most recent
+    // events even when older ones arrived later
+    for event in store.by_time().rev() {
+        if !listed(&query.hosts, &event.host)
+            || !listed(&query.categories, &event.category)
+            || !listed(&query.severities, &event.severity)
+            || !range.contains(event)
+        {
+            continue;
+        }
+
+        let key = match query.group_by {
+            GroupBy::Host => &event.host,
+            GroupBy::Category => &event.category,
+            GroupBy::Severity => &event.severity,
+        };
+        let position = *index.entry(key.clone()).or_insert_with(|| {
+            groups.push(EventGroup {
+                key: key.clone(),
+                total: 0,
+                events: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        let group = &mut groups[position];
+        group.total += 1;
+        if group.events.len() < query.limit {
+            group.events.push(event.clone());
+        }
+    }
+    drop(store);
+
+    groups.sort_by(|a, b| a.key.cmp(&b.key));
+    let total: usize = groups.iter().map(|group| group.total).sum();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total": total,
+        "groups": groups,
+    })))
+}
+
+// Event fields that may carry an address in the range, besides host_ipv4.
+// Agents report the peer of remote logins as remote_ip; enrichment and
+// third-party sources tend to use the others.
+const IP_DATA_FIELDS: [&str; 3] = ["data.remote_ip", "data.src_ip", "data.source_ip"];
+
+// GET /api/events/by-ip/{cidr} - Events whose host or source address falls
+// in a network, e.g. /api/events/by-ip/10.0.0.0/8
+async fn get_events_by_ip(
+    request: HttpRequest,
+    cidr: web::Path<String>,
+    query: web::Query<severity::SeverityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let decoded = percent_decode_str(&cidr.into_inner())
+        .decode_utf8_lossy()
+        .to_string();
+    let network = match cidr::Cidr::parse(&decoded) {
+        Ok(network) => network,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+
+    // Only the addresses this caller may see are matched, or the range
+    // could be narrowed down to a hidden address
+    let role = live::caller_role(&request);
+    let fields: Vec<&str> = std::iter::once("host_ipv4")
+        .chain(IP_DATA_FIELDS)
+        .filter(|field| !data.access.hides(&role, field))
+        .collect();
+    let in_network = |event: &Event| {
+        fields.iter().any(|field| {
+            event
+                .field_value(field)
+                .is_some_and(|address| network.contains_str(&address))
+        })
+    };
+    // Latest events at the top
+    let events = data.store.query(&storage::StoreQuery {
+        min_severity: floor,
+        matches: Some(&in_network),
+        newest_first: true,
+        ..Default::default()
+    });
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[derive(Debug, Deserialize)]
+struct AlertsQuery {
+    #[serde(default)]
+    active: bool,
+}
+
+// GET /api/alerts - Alerts fired by detection rules, newest first. With
+// `active=true`, only threshold and absence alerts whose condition holds.
+async fn get_alerts(
+    query: web::Query<AlertsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(if query.active {
+        data.rules.active_alerts()
+    } else {
+        data.rules.alerts()
+    }))
+}
+
+// GET / - Serve dashboard HTML
+async fn serve_dashboard() -> Result<HttpResponse> {
+    let html = include_str!("../static/index.html");
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html))
+}
+
+// GET /style.css - Serve CSS
+async fn serve_css() -> Result<HttpResponse> {
+    let css = include_str!("../static/style.css");
+    Ok(HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .body(css))
+}
+
+// GET /dashboard.js - Serve JavaScript
+async fn serve_js() -> Result<HttpResponse> {
+    let js = include_str!("../static/dashboard.js");
+    Ok(HttpResponse::Ok()
+        .content_type("application/javascript; charset=utf-8")
+        .body(js))
+}
+
+// GET /node.html - Serve node detail page
+async fn serve_node_page() -> Result<HttpResponse> {
+    let html = include_str!("../static/node.html");
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html))
+}
+
+// GET /node.js - Serve node detail JavaScript
+async fn serve_node_js() -> Result<HttpResponse> {
+    let js = include_str!("../static/node.js");
+    Ok(HttpResponse::Ok()
+        .content_type("application/javascript; charset=utf-8")
+        .body(js))
+}
+
+// GET /login.html - Serve the login page
+async fn serve_login_page() -> Result<HttpResponse> {
+    let html = include_str!("../static/login.html");
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html))
+}
+
+// GET /login.js - Serve login page JavaScript
+async fn serve_login_js() -> Result<HttpResponse> {
+    let js = include_str!("../static/login.js");
+    Ok(HttpResponse::Ok()
+        .content_type("application/javascript; charset=utf-8")
+        .body(js))
+}
+
+// Every route the server exposes. Paths with several methods share one
+// resource, and the table refuses registrations that would shadow each other.
+// Routes that read stored data are added with add_query so [audit] records
+// them.
+fn core_routes() -> std::result::Result<routes::RouteTable, String> {
+    let mut table = routes::RouteTable::new();
+
+    // API routes
+    table.add("core", Method::POST, "/api/events", || {
+        web::route().to(receive_events)
+    })?;
+    table.add("core", Method::POST, "/api/events/ndjson", || {
+        web::route().to(receive_ndjson)
+    })?;
+    table.add("core", Method::POST, "/api/events/raw", || {
+        web::route().to(raw::receive_raw)
+    })?;
+    table.add("core", Method::POST, "/api/hook/{source}", || {
+        web::route().to(hooks::receive_hook)
+    })?;
+    table.add("core", Method::POST, "/loki/api/v1/push", || {
+        web::route().to(loki::push)
+    })?;
+    table.add_query("core", Method::GET, "/loki/api/v1/query_range", || {
+        web::route().to(loki::query_range)
+    })?;
+    table.add_query("core", Method::GET, "/loki/api/v1/labels", || {
+        web::route().to(loki::labels)
+    })?;
+    table.add_query(
+        "core",
+        Method::GET,
+        "/loki/api/v1/label/{name}/values",
+        || web::route().to(loki::label_values),
+    )?;
+    table.add_query("core", Method::GET, "/loki/api/v1/series", || {
+        web::route().to(loki::series)
+    })?;
+    table.add("core", Method::GET, "/es", || {
+        web::route().to(elastic::info)
+    })?;
+    table.add("core", Method::POST, "/es/_bulk", || {
+        web::route().to(elastic::bulk)
+    })?;
+    table.add("core", Method::POST, "/es/{index}/_bulk", || {
+        web::route().to(elastic::bulk)
+    })?;
+    for path in [
+        "/services/collector",
+        "/services/collector/event",
+        "/services/collector/event/1.0",
+    ] {
+        table.add("core", Method::POST, path, || {
+            web::route().to(splunk::event)
+        })?;
+    }
+    table.add("core", Method::POST, "/services/collector/raw", || {
+        web::route().to(splunk::raw)
+    })?;
+    table.add("core", Method::GET, "/services/collector/health", || {
+        web::route().to(splunk::health)
+    })?;
+    table.add("core", Method::POST, "/api/v2/logs", || {
+        web::route().to(datadog::intake)
+    })?;
+    table.add("core", Method::POST, "/v1/input", || {
+        web::route().to(datadog::intake)
+    })?;
+    table.add("core", Method::POST, "/v1/logs", || {
+        web::route().to(otlp::logs)
+    })?;
+    table.add_query("core", Method::GET, "/api/events", || {
+        web::route().to(get_all_events)
+    })?;
+    table.add_query("core", Method::POST, "/api/events/query", || {
+        web::route().to(query_events)
+    })?;
+    table.add_query("core", Method::GET, "/api/search", || {
+        web::route().to(search::search)
+    })?;
+    table.add_query("core", Method::GET, "/api/stream", || {
+        web::route().to(live::stream)
+    })?;
+    table.add_query("core", Method::GET, "/ws", || {
+        web::route().to(live::subscribe)
+    })?;
+    table.add_query("core", Method::GET, "/api/events/{selector}", || {
+        web::route().to(get_events_for_host)
+    })?;
+    table.add_query("core", Method::GET, "/api/events/by-ip/{cidr:.*}", || {
+        web::route().to(get_events_by_ip)
+    })?;
+    table.add("core", Method::POST, "/api/inventory", || {
+        web::route().to(inventory::receive_inventory)
+    })?;
+    table.add("core", Method::POST, "/api/heartbeat", || {
+        web::route().to(availability::heartbeat)
+    })?;
+    table.add_query(
+        "core",
+        Method::GET,
+        "/api/nodes/{host}/availability",
+        || web::route().to(availability::get_availability),
+    )?;
+    table.add_query("core", Method::GET, "/api/nodes/{host}/inventory", || {
+        web::route().to(inventory::get_inventory)
+    })?;
+    table.add_query("core", Method::GET, "/api/fim/{host}/diff", || {
+        web::route().to(fim::get_diff)
+    })?;
+    table.add_query("core", Method::GET, "/api/fim/{host}/baseline", || {
+        web::route().to(fim::get_baseline)
+    })?;
+    table.add("core", Method::POST, "/api/fim/{host}/baseline", || {
+        web::route().to(fim::accept_changes)
+    })?;
+    table.add_query("core", Method::GET, "/api/k8s/namespaces", || {
+        web::route().to(k8s::get_namespaces)
+    })?;
+    table.add_query(
+        "core",
+        Method::GET,
+        "/api/k8s/namespaces/{namespace}/events",
+        || web::route().to(k8s::get_namespace_events),
+    )?;
+    table.add_query(
+        "core",
+        Method::GET,
+        "/api/k8s/namespaces/{namespace}/pods",
+        || web::route().to(k8s::get_pods),
+    )?;
+    table.add_query("core", Method::GET, "/api/flows/conversations", || {
+        web::route().to(flows::get_conversations)
+    })?;
+    table.add_query("core", Method::GET, "/api/flows/top", || {
+        web::route().to(flows::get_top_talkers)
+    })?;
+    table.add_query("core", Method::GET, "/api/vulnerabilities", || {
+        web::route().to(vulnerabilities::get_vulnerabilities)
+    })?;
+    table.add("core", Method::GET, "/api/capabilities", || {
+        web::route().to(capabilities::get_capabilities)
+    })?;
+    table.add("core", Method::GET, "/api/version", || {
+        web::route().to(version::get_version)
+    })?;
+    table.add_query("core", Method::GET, "/api/stats", || {
+        web::route().to(get_stats)
+    })?;
+    table.add_query("core", Method::GET, "/api/stats/group-by", || {
+        web::route().to(stats::group_by)
+    })?;
+    table.add_query("core", Method::GET, "/api/stats/delta", || {
+        web::route().to(stats::delta)
+    })?;
+    table.add_query("core", Method::GET, "/api/stats/timeseries", || {
+        web::route().to(stats::timeseries)
+    })?;
+    table.add_query("core", Method::GET, "/api/trends", || {
+        web::route().to(stats::trends)
+    })?;
+    table.add_query("core", Method::GET, "/api/alerts", || {
+        web::route().to(get_alerts)
+    })?;
+    table.add_query("core", Method::GET, "/api/incidents", || {
+        web::route().to(incidents::list_incidents)
+    })?;
+    table.add_query("core", Method::GET, "/api/incidents/{id}", || {
+        web::route().to(incidents::get_incident)
+    })?;
+    table.add("core", Method::POST, "/api/incidents/{id}/status", || {
+        web::route().to(incidents::set_status)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/alert-rules", || {
+        web::route().to(rules::list_rules)
+    })?;
+    table.add("core", Method::POST, "/api/admin/alert-rules", || {
+        web::route().to(rules::add_rule)
+    })?;
+    table.add(
+        "core",
+        Method::DELETE,
+        "/api/admin/alert-rules/{name}",
+        || web::route().to(rules::remove_rule),
+    )?;
+    table.add_query("core", Method::GET, "/api/admin/scheduler", || {
+        web::route().to(scheduler::get_scheduler)
+    })?;
+    table.add(
+        "core",
+        Method::POST,
+        "/api/admin/scheduler/{task}/run",
+        || web::route().to(scheduler::trigger_task),
+    )?;
+    table.add("core", Method::POST, "/api/admin/replay", || {
+        web::route().to(outputs::replay)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/cardinality", || {
+        web::route().to(cardinality::get_cardinality)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/agents", || {
+        web::route().to(control::list_agents)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/signatures", || {
+        web::route().to(signing::get_signatures)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/integrity", || {
+        web::route().to(integrity::get_integrity)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/audit", || {
+        web::route().to(audit::get_audit)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/panics", || {
+        web::route().to(panics::get_panics)
+    })?;
+    table.add_query("core", Method::GET, "/api/admin/api-keys", || {
+        web::route().to(api_keys::list_keys)
+    })?;
+    table.add("core", Method::POST, "/api/admin/api-keys", || {
+        web::route().to(api_keys::mint_key)
+    })?;
+    table.add("core", Method::DELETE, "/api/admin/api-keys/{name}", || {
+        web::route().to(api_keys::revoke_key)
+    })?;
+    table.add_query("core", Method::GET, "/api/usage/keys", || {
+        web::route().to(usage::get_key_usage)
+    })?;
+    table.add(
+        "core",
+        Method::POST,
+        "/api/admin/agents/{host}/commands",
+        || web::route().to(control::send_command),
+    )?;
+    table.add("core", Method::GET, "/api/agents/{host}/control", || {
+        web::route().to(control::connect)
+    })?;
+    table.add("core", Method::POST, "/api/agents", || {
+        web::route().to(agents::register)
+    })?;
+    table.add_query("core", Method::GET, "/api/agents", || {
+        web::route().to(agents::list_agents)
+    })?;
+    table.add_query("core", Method::GET, "/api/agents/{id}", || {
+        web::route().to(agents::get_agent)
+    })?;
+    table.add("core", Method::PUT, "/api/agents/{id}", || {
+        web::route().to(agents::update_agent)
+    })?;
+    table.add("core", Method::DELETE, "/api/agents/{id}", || {
+        web::route().to(agents::delete_agent)
+    })?;
+    table.add("core", Method::POST, "/api/auth/login", || {
+        web::route().to(sessions::login)
+    })?;
+    table.add("core", Method::POST, "/api/auth/logout", || {
+        web::route().to(sessions::logout)
+    })?;
+    table.add("core", Method::GET, "/api/auth/session", || {
+        web::route().to(sessions::get_session)
+    })?;
+
+    // Frontend routes
+    table.add("core", Method::GET, "/", || {
+        web::route().to(serve_dashboard)
+    })?;
+    table.add("core", Method::GET, "/node.html", || {
+        web::route().to(serve_node_page)
+    })?;
+    table.add("core", Method::GET, "/style.css", || {
+        web::route().to(serve_css)
+    })?;
+    table.add("core", Method::GET, "/dashboard.js", || {
+        web::route().to(serve_js)
+    })?;
+    table.add("core", Method::GET, "/node.js", || {
+        web::route().to(serve_node_js)
+    })?;
+    table.add("core", Method::GET, "/login.html", || {
+        web::route().to(serve_login_page)
+    })?;
+    table.add("core", Method::GET, "/login.js", || {
+        web::route().to(serve_login_js)
+    })?;
+
+    Ok(table)
+}
+
+// The binary's entry point, in src/main.rs
+pub fn run() -> std::io::Result<()> {
+    // Usage errors exit with 2, --help and --version with 0
+    let cli = cli::parse(std::env::args_os()).unwrap_or_else(|err| err.exit());
+    if let Some(path) = cli.config {
+        std::env::set_var("LOGLUMEN_SERVER_CONFIG", path);
+    }
+    match cli.command {
+        cli::Command::Serve => actix_web::rt::System::new().block_on(serve(None)),
+        cli::Command::Service(service::ServiceCommand::Install) => service::install(),
+        cli::Command::Service(service::ServiceCommand::Uninstall) => service::uninstall(),
+        cli::Command::Service(service::ServiceCommand::Run) => service::run(),
+        command => match cli::run(command) {
+            Ok(0) => Ok(()),
+            Ok(code) => std::process::exit(code),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1)
+            }
+        },
+    }
+}
+
+// Bodies over [limits] max_body_size get the same JSON error as everything
+// else; other extraction errors keep actix's response
+fn json_error(err: actix_web::error::JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    use actix_web::error::JsonPayloadError;
+    match err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            let response = HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "status": "error",
+                "message": err.to_string(),
+            }));
+            actix_web::error::InternalError::from_response(err, response).into()
+        }
+        err => err.into(),
+    }
+}
+
+// The state every handler shares. The sections serve() reads itself are
+// passed in; the rest come from the config file.
+fn app_state(
+    limits: &config::LimitsConfig,
+    tls: &tls::TlsConfig,
+    storage: storage::StorageConfig,
+    retention: retention::RetentionConfig,
+    clock: clock::SharedClock,
+) -> std::result::Result<AppState, String> {
+    let mut scheduler = scheduler::Scheduler::new(config::load_section("scheduler"), clock.clone());
+    let vulnerabilities = vulnerabilities::Vulnerabilities::new(
+        config::load_section("vulnerabilities"),
+        &mut scheduler,
+    );
+    let retention = retention::Retention::new(retention, &mut scheduler);
+    let writers = storage.writers();
+    let soft_limits = soft_limits::SoftLimits::new(limits, storage.capacity());
+    let usage = usage::KeyUsage::new(config::load_section("usage"), clock.clone());
+    let access = config::load_section_strict("access")?;
+    let api_keys = config::load_section_strict("api_keys")?;
+    let signing = config::load_section_strict("signing")?;
+    let auth = config::load_section_strict("auth")?;
+    Ok(AppState {
+        store: storage::open(storage).into(),
+        rules: rules::RuleEngine::new(
+            config::load_section("detection"),
+            incidents::Incidents::new(config::load_section("incidents")),
+            notifiers::Notifiers::new(
+                config::load_section("notifiers"),
+                config::load_section("notifications"),
+            ),
+            clock.clone(),
+        ),
+        ingest_log: logging::IngestLogger::new(config::load_section("logging")),
+        plugins: plugins::PluginRegistry::new(config::load_section("plugins")),
+        scheduler,
+        outputs: outputs::Outputs::new(config::load_section("outputs")),
+        stats: stats::Stats::new(config::load_section("stats")),
+        display: display::TimeDisplay::new(config::load_section("display")),
+        severity: severity::SeverityFloor::new(config::load_section("severity")),
+        control: control::AgentChannels::default(),
+        inventory: inventory::Inventory::new(config::load_section("inventory")),
+        fim: fim::Baselines::new(config::load_section("fim")),
+        vulnerabilities,
+        flows: flows::Flows::new(config::load_section("flows"), clock.clone()),
+        k8s: k8s::K8sIndex::default(),
+        search: search::SearchIndex::new(config::load_section("search"))?,
+        availability: availability::Availability::new(
+            config::load_section("availability"),
+            clock.clone(),
+        ),
+        clock,
+        backpressure: backpressure::Backpressure::new(limits),
+        pipeline: pipeline::Pipeline::new(writers),
+        signing: signing::Signing::new(signing),
+        integrity: integrity::Integrity::new(config::load_section("integrity")),
+        retention,
+        live: live::LiveTail::default(),
+        access: access::Access::new(access),
+        sessions: sessions::Sessions::new(auth, tls.enabled()),
+        api_keys: api_keys::ApiKeys::new(api_keys, usage),
+        agents: agents::AgentRegistry::new(config::load_section("agents")),
+        raw: raw::Raw::new(config::load_section("raw")),
+        hooks: hooks::Hooks::new(config::load_section("hooks")),
+        loki: loki::Loki::new(config::load_section("loki")),
+        elastic: elastic::Elastic::new(config::load_section("elastic")),
+        splunk: splunk::Splunk::new(config::load_section("splunk")),
+        datadog: datadog::Datadog::new(config::load_section("datadog")),
+        otlp: otlp::Otlp::new(config::load_section("otlp")),
+        audit: audit::Audit::new(config::load_section("audit")),
+        demo: demo::Demo::new(config::load_section("demo")),
+        capabilities: capabilities::Capabilities::new(limits, tls),
+        soft_limits,
+    })
+}
+
+// Run the server until it exits on its own (signals) or `shutdown` fires
+async fn serve(shutdown: Option<tokio::sync::oneshot::Receiver<()>>) -> std::io::Result<()> {
+    logging::init();
+    panics::install();
+    crash::init(config::load_section("crash"));
+    let config::ServeConfig {
+        server,
+        limits,
+        tls,
+        storage,
+        retention,
+    } = config::ServeConfig::load();
+    let bind_address = server.bind_address.clone();
+    let scheme = if tls.enabled() { "https" } else { "http" };
+
+    if logging::is_json() {
+        log_line!(
+            "INFO",
+            "Loglumen Server {} starting on {}://{}",
+            version::describe(),
+            scheme,
+            bind_address
+        );
+    } else {
+        let separator = "=".repeat(70);
+        println!("{}", separator);
+        println!("Loglumen Server {} Starting", version::describe());
+        println!("{}", separator);
+        println!("Listening on: {}://{}", scheme, bind_address);
+        println!("Dashboard: {}://{}/", scheme, bind_address);
+        println!("API endpoint: {}://{}/api/events", scheme, bind_address);
+        println!("Stats endpoint: {}://{}/api/stats", scheme, bind_address);
+        println!("{}", separator);
+    }
+
+    // Create shared state
+    let dev: generator::DevConfig = config::load_section("dev");
+    let clock = dev.clock();
+    if clock.manual().is_some() {
+        log_line!(
+            "WARN",
+            "Manual clock enabled at /api/dev/clock (now {})",
+            clock.now().to_rfc3339()
+        );
+    }
+    let app_state = web::Data::new(app_state(&limits, &tls, storage, retention, clock).map_err(
+        |err| {
+            log_line!("ERROR", "{}", err);
+            std::io::Error::other(err)
+        },
+    )?);
+    if app_state.sessions.enabled() && !app_state.api_keys.required() {
+        log_line!(
+            "WARN",
+            "[auth] logins are on but [api_keys] required is off; ingestion and agent endpoints stay open"
+        );
+    }
+    reindex(&app_state);
+    crash::watch(app_state.store.clone());
+    crash::spawn_uploader();
+    pipeline::spawn_writer(&app_state);
+
+    let routes = core_routes()
+        .and_then(|mut table| {
+            app_state.plugins.register_routes(&mut table)?;
+            if dev.generator_enabled() {
+                log_line!(
+                    "WARN",
+                    "Synthetic event generator enabled at /api/dev/generate"
+                );
+                table.add("dev", Method::POST, "/api/dev/generate", || {
+                    web::route().to(generator::generate)
+                })?;
+            }
+            if app_state.clock.manual().is_some() {
+                table.add("dev", Method::GET, "/api/dev/clock", || {
+                    web::route().to(clock::current)
+                })?;
+                table.add("dev", Method::POST, "/api/dev/clock", || {
+                    web::route().to(clock::update)
+                })?;
+            }
+            Ok(table)
+        })
+        .map_err(|err| {
+            log_line!("ERROR", "Route conflict: {}", err);
+            std::io::Error::other(err)
+        })?;
+
+    // Threshold rules can be added at runtime, so this always runs
+    {
+        let state = app_state.clone();
+        actix_web::rt::spawn(async move {
+            let mut ticker = tokio::time::interval(state.rules.check_interval());
+            loop {
+                ticker.tick().await;
+                state.rules.check();
+            }
+        });
+    }
+
+    if !app_state.scheduler.is_empty() {
+        actix_web::rt::spawn(scheduler::run(app_state.clone()));
+    }
+
+    if !app_state.outputs.is_empty() {
+        outputs::spawn_workers(&app_state);
+    }
+
+    if !app_state.rules.notifiers.is_empty() {
+        notifiers::spawn_worker(&app_state);
+    }
+
+    #[cfg(all(feature = "docker", unix))]
+    {
+        let docker: docker::DockerConfig = config::load_section("docker");
+        if docker.enabled() {
+            docker::spawn(app_state.clone(), docker);
+        }
+    }
+
+    #[cfg(feature = "aws")]
+    {
+        let aws: aws::AwsConfig = config::load_section("aws");
+        if aws.enabled() {
+            aws::spawn(app_state.clone(), aws);
+        }
+    }
+
+    #[cfg(feature = "netflow")]
+    {
+        let netflow: netflow::NetflowConfig = config::load_section("netflow");
+        if netflow.enabled() {
+            netflow::spawn(app_state.clone(), netflow);
+        }
+    }
+
+    #[cfg(feature = "syslog")]
+    {
+        let syslog: syslog::SyslogConfig = config::load_section("syslog");
+        if syslog.enabled() {
+            syslog::spawn(app_state.clone(), syslog);
+        }
+    }
+
+    #[cfg(feature = "gelf")]
+    {
+        let gelf: gelf::GelfConfig = config::load_section("gelf");
+        if gelf.enabled() {
+            gelf::spawn(app_state.clone(), gelf);
+        }
+    }
+
+    #[cfg(feature = "statsd")]
+    {
+        let statsd: statsd::StatsdConfig = config::load_section("statsd");
+        if statsd.enabled() {
+            statsd::spawn(app_state.clone(), statsd);
+        }
+    }
+
+    #[cfg(feature = "smtp")]
+    {
+        let smtp: smtp::SmtpConfig = config::load_section("smtp");
+        if smtp.enabled() {
+            smtp::spawn(app_state.clone(), smtp);
+        }
+    }
+
+    if let Some(interval) = app_state.stats.cache_interval() {
+        let state = app_state.clone();
+        actix_web::rt::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let refresh = state.clone();
+                let snapshot = web::block(move || {
+                    let floor = refresh.severity.resolve(None).unwrap_or(0);
+                    serde_json::to_vec(&compute_stats(&refresh, floor))
+                })
+                .await;
+                match snapshot {
+                    Ok(Ok(snapshot)) => state.stats.store_snapshot(snapshot),
+                    Ok(Err(err)) => log_line!("ERROR", "Stats refresh failed: {}", err),
+                    Err(err) => log_line!("ERROR", "Stats refresh failed: {}", err),
+                }
+            }
+        });
+    }
+
+    let hardening: hardening::HardeningConfig = config::load_section("hardening");
+
+    // Start HTTP server
+    let cors_origins = server.cors_origins;
+    let shutdown_timeout = server.shutdown_timeout;
+    let state = app_state.clone();
+    let max_body_size = limits.max_body_size;
+    let app = move || {
+        // Any origin unless [server] cors_origins lists them
+        let cors = if cors_origins.is_empty() || cors_origins.iter().any(|origin| origin == "*") {
+            Cors::permissive()
+        } else {
+            cors_origins
+                .iter()
+                .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                .allow_any_method()
+                .allow_any_header()
+                .max_age(3600)
+        };
+
+        App::new()
+            .wrap(from_fn(panics::isolate))
+            .wrap(from_fn(access::enforce))
+            .wrap(from_fn(demo::anonymize))
+            .wrap(cors)
+            .app_data(web::PayloadConfig::new(max_body_size))
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(max_body_size)
+                    .error_handler(json_error),
+            )
+            .app_data(app_state.clone())
+            .configure(|cfg| routes.configure(cfg))
+    };
+    // Bind now; serving starts once hardening is applied
+    let start: Box<dyn FnOnce() -> std::io::Result<actix_web::dev::Server>> = if tls.enabled() {
+        let acceptor = tls::acceptor(&tls).inspect_err(|err| {
+            log_line!("ERROR", "Failed to load TLS certificate: {}", err);
+        })?;
+        let listener = std::net::TcpListener::bind(&bind_address)?;
+        Box::new(move || tls::serve(acceptor, listener, server.workers, shutdown_timeout, app))
+    } else {
+        let mut http = HttpServer::new(app)
+            .disable_signals()
+            .shutdown_timeout(shutdown_timeout.as_secs());
+        if let Some(workers) = server.workers {
+            http = http.workers(workers);
+        }
+        let http = http.bind(bind_address)?;
+        Box::new(move || Ok(http.run()))
+    };
+
+    // Drop root, chroot, etc. now that the socket is bound
+    hardening::apply(&hardening).inspect_err(|err| {
+        log_line!("ERROR", "Failed to apply hardening options: {}", err);
+    })?;
+
+    let running = start()?;
+    shutdown::watch(running.handle(), shutdown, shutdown_timeout);
+    running.await?;
+
+    shutdown::flush(&state, shutdown_timeout).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test;
+
+    // Only `[access]` differs from the defaults; there is no config file
+    // next to the tests
+    fn state(access: serde_json::Value) -> web::Data<AppState> {
+        let mut state = app_state(
+            &config::LimitsConfig::default(),
+            &tls::TlsConfig::default(),
+            storage::StorageConfig::default(),
+            retention::RetentionConfig::default(),
+            clock::system(),
+        )
+        .unwrap();
+        state.access = access::Access::new(serde_json::from_value(access).unwrap());
+        web::Data::new(state)
+    }
+
+    #[actix_web::test]
+    async fn stats_hide_fields_from_restricted_roles() {
+        let data = state(serde_json::json!({
+            "default_role": "viewer",
+            "tokens": [{"name": "ops", "token": "adm", "role": "admin"}],
+            "hidden_fields": {"viewer": ["host_ipv4", "data.command_line"]},
+        }));
+        let event = Event {
+            category: "process".to_string(),
+            event_type: "exec".to_string(),
+            time: data.clock.now().to_rfc3339(),
+            host: "web01".to_string(),
+            host_ipv4: "10.1.2.3".to_string(),
+            severity: "info".to_string(),
+            data: serde_json::json!({"command_line": "secret", "user": "bob"}),
+            ..Event::default()
+        };
+        store(&data, vec![event]).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .wrap(from_fn(access::enforce))
+                .route("/api/stats", web::get().to(get_stats))
+                .route("/api/stats/delta", web::get().to(stats::delta)),
+        )
+        .await;
+
+        for uri in ["/api/stats", "/api/stats/delta?since=0"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+            let text = body.to_string();
+            assert!(text.contains("web01"), "{}: {}", uri, text);
+            assert!(!text.contains("10.1.2.3"), "{}: {}", uri, text);
+            assert!(!text.contains("secret"), "{}: {}", uri, text);
+        }
+
+        // Roles without hidden fields see everything
+        let request = test::TestRequest::get()
+            .uri("/api/stats")
+            .insert_header((header::AUTHORIZATION, "Bearer adm"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["nodes"][0]["host_ipv4"], "10.1.2.3");
+    }
+
+    #[actix_web::test]
+    async fn hidden_fields_cannot_be_filtered_on() {
+        let data = state(serde_json::json!({
+            "default_role": "viewer",
+            "hidden_fields": {"viewer": ["source", "host"]},
+        }));
+        let app = test::init_service(
+            App::new()
+                .app_data(data.clone())
+                .wrap(from_fn(access::enforce))
+                .route("/api/events", web::get().to(get_all_events))
+                .route("/api/events/query", web::post().to(query_events)),
+        )
+        .await;
+
+        let status = |request: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, request.to_request()).await.status() }
+        };
+        assert_eq!(
+            status(test::TestRequest::get().uri("/api/events?source=sshd")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(test::TestRequest::get().uri("/api/events?category=auth")).await,
+            StatusCode::OK
+        );
+        // Grouping reads the field as much as a filter does
+        let query = |body| {
+            test::TestRequest::post()
+                .uri("/api/events/query")
+                .set_json(body)
+        };
+        assert_eq!(
+            status(query(serde_json::json!({"group_by": "host"}))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(query(serde_json::json!({"group_by": "category"}))).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(query(
+                serde_json::json!({"group_by": "category", "hosts": ["web01"]})
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::access::Principal;
+use crate::{severity, AppState, Event};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Result};
+use actix_ws::{CloseCode, CloseReason, Message};
+use serde::Deserialize;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+
+// Stored batches buffered for slow clients before they skip ahead
+const CHANNEL_CAPACITY: usize = 256;
+// Encoded messages waiting to be written to one client
+const CLIENT_BUFFER: usize = 64;
+// Comment lines keep proxies from closing an idle stream
+const KEEPALIVE: Duration = Duration::from_secs(15);
+// WebSocket clients that answer no ping for this long are dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
+// A WebSocket client that can't take a message this quickly is too slow
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Fan-out of newly stored batches to connected live tails
+pub struct LiveTail {
+    sender: broadcast::Sender<Arc<Vec<Event>>>,
+}
+
+impl Default for LiveTail {
+    fn default() -> Self {
+        LiveTail {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl LiveTail {
+    // Called after a batch is stored; only copies it when someone listens
+    pub fn publish(&self, batch: &[Event]) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(Arc::new(batch.to_vec()));
+        }
+    }
+}
+
+// Filters for GET /api/stream and /ws subscriptions. `host`, `severity`
+// and `category` take comma-separated lists, matched case-insensitively.
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    host: Option<String>,
+    severity: Option<String>,
+    category: Option<String>,
+    min_severity: Option<String>,
+}
+
+impl StreamQuery {
+    fn matches(&self, event: &Event, floor: usize) -> bool {
+        fn listed(values: &Option<String>, actual: &str) -> bool {
+            values.as_deref().is_none_or(|values| {
+                values
+                    .split(',')
+                    .any(|value| value.trim().eq_ignore_ascii_case(actual))
+            })
+        }
+
+        severity::passes(event, floor)
+            && listed(&self.host, &event.host)
+            && listed(&self.severity, &event.severity)
+            && listed(&self.category, &event.category)
+    }
+
+    // The event fields the filters read, for the [access] check
+    fn fields(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.host.is_some(), "host"),
+            (
+                self.severity.is_some() || self.min_severity.is_some(),
+                "severity",
+            ),
+            (self.category.is_some(), "category"),
+        ]
+        .into_iter()
+        .filter_map(|(set, field)| set.then_some(field))
+    }
+}
+
+// The event as this caller may see it
+pub fn visible(data: &AppState, role: &str, event: &Event) -> serde_json::Value {
+    let mut value = serde_json::to_value(event).unwrap_or_default();
+    data.access.redact(role, &mut value);
+    if data.demo.is_enabled() {
+        data.demo.anonymize(&mut value);
+    }
+    value
+}
+
+pub fn caller_role(request: &HttpRequest) -> String {
+    request
+        .extensions()
+        .get::<Principal>()
+        .map(|principal| principal.role.clone())
+        .unwrap_or_default()
+}
+
+// Response body fed by the client's forwarding task
+struct EventStream(mpsc::Receiver<Bytes>);
+
+impl MessageBody for EventStream {
+    type Error = std::convert::Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Bytes, Self::Error>>> {
+        self.0.poll_recv(cx).map(|chunk| chunk.map(Ok))
+    }
+}
+
+// Events go out as unnamed messages so `EventSource.onmessage` sees them
+fn message(event: Option<&str>, data: &str) -> Bytes {
+    match event {
+        Some(event) => Bytes::from(format!("event: {}\ndata: {}\n\n", event, data)),
+        None => Bytes::from(format!("data: {}\n\n", data)),
+    }
+}
+
+// GET /api/stream - Server-Sent Events with every newly stored event that
+// passes the filters, one message each. Responses skip the JSON
+// middleware, so role redaction and demo anonymisation happen here.
+pub async fn stream(
+    request: HttpRequest,
+    query: web::Query<StreamQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.access.check_fields(&request, query.fields()) {
+        return Ok(response);
+    }
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": err
+            })))
+        }
+    };
+    let role = caller_role(&request);
+    let query = query.into_inner();
+    let mut batches = data.live.sender.subscribe();
+    let (sender, receiver) = mpsc::channel(CLIENT_BUFFER);
+
+    actix_web::rt::spawn(async move {
+        if sender.send(Bytes::from(": connected\n\n")).await.is_err() {
+            return;
+        }
+        let mut keepalive = tokio::time::interval(KEEPALIVE);
+        keepalive.tick().await;
+        loop {
+            let chunk = tokio::select! {
+                batch = batches.recv() => match batch {
+                    Ok(batch) => {
+                        let mut chunk = Vec::new();
+                        for event in batch.iter().filter(|event| query.matches(event, floor)) {
+                            let value = visible(&data, &role, event);
+                            chunk.extend_from_slice(&message(None, &value.to_string()));
+                        }
+                        if chunk.is_empty() {
+                            continue;
+                        }
+                        Bytes::from(chunk)
+                    }
+                    // The client fell behind; tell it how much it missed
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => message(
+                        Some("lagged"),
+                        &serde_json::json!({ "batches": skipped }).to_string(),
+                    ),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = keepalive.tick() => Bytes::from(": keepalive\n\n"),
+            };
+            // Fails once the client disconnects and the body is dropped
+            if sender.send(chunk).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Stops nginx from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .body(EventStream(receiver)))
+}
+
+// Messages a /ws client sends
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    // Start receiving events, or replace the current filter
+    Subscribe(StreamQuery),
+    Unsubscribe,
+}
+
+fn policy_close(description: &str) -> Option<CloseReason> {
+    Some(CloseReason {
+        code: CloseCode::Policy,
+        description: Some(description.to_string()),
+    })
+}
+
+// Send one JSON message; a client that doesn't take it in time is closed
+async fn send(
+    session: &mut actix_ws::Session,
+    message: serde_json::Value,
+) -> std::result::Result<(), Option<CloseReason>> {
+    match tokio::time::timeout(SEND_TIMEOUT, session.text(message.to_string())).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(_)) => Err(None),
+        Err(_) => Err(policy_close("slow consumer")),
+    }
+}
+
+// GET /ws - WebSocket live tail. After connecting, send
+// {"type": "subscribe", "host": "web01", "severity": "error,critical"} with
+// the same filters as /api/stream; matching events then arrive as
+// {"type": "event", "event": {...}}. The server pings every 15 seconds and
+// closes connections that stop answering or fall behind.
+pub async fn subscribe(
+    request: HttpRequest,
+    body: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let role = caller_role(&request);
+    let (response, mut session, mut stream) = actix_ws::handle(&request, body)?;
+    let mut batches = data.live.sender.subscribe();
+
+    actix_web::rt::spawn(async move {
+        let mut filter: Option<(StreamQuery, usize)> = None;
+        let mut heartbeat = tokio::time::interval(KEEPALIVE);
+        let mut last_seen = Instant::now();
+        let reason = loop {
+            let sent = tokio::select! {
+                message = stream.recv() => {
+                    let Some(Ok(message)) = message else {
+                        break None;
+                    };
+                    last_seen = Instant::now();
+                    match message {
+                        Message::Text(text) => {
+                            let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                                Ok(ClientMessage::Subscribe(query)) => {
+                                    // Hidden fields can't be filtered on, as
+                                    // the matches would give their values away
+                                    let floor = match data.access.refusal(&role, query.fields()) {
+                                        Some(refusal) => Err(refusal),
+                                        None => data.severity.resolve(query.min_severity.as_deref()),
+                                    };
+                                    match floor {
+                                        Ok(floor) => {
+                                            filter = Some((query, floor));
+                                            serde_json::json!({ "type": "subscribed" })
+                                        }
+                                        Err(err) => {
+                                            serde_json::json!({ "type": "error", "message": err })
+                                        }
+                                    }
+                                }
+                                Ok(ClientMessage::Unsubscribe) => {
+                                    filter = None;
+                                    serde_json::json!({ "type": "unsubscribed" })
+                                }
+                                Err(err) => serde_json::json!({
+                                    "type": "error",
+                                    "message": format!("invalid message: {}", err),
+                                }),
+                            };
+                            send(&mut session, reply).await
+                        }
+                        Message::Ping(bytes) => session.pong(&bytes).await.map_err(|_| None),
+                        Message::Close(_) => break None,
+                        _ => Ok(()),
+                    }
+                }
+                batch = batches.recv() => match batch {
+                    Ok(batch) => {
+                        let Some((query, floor)) = &filter else {
+                            continue;
+                        };
+                        let mut sent = Ok(());
+                        for event in batch.iter().filter(|event| query.matches(event, *floor)) {
+                            let message = serde_json::json!({
+                                "type": "event",
+                                "event": visible(&data, &role, event),
+                            });
+                            sent = send(&mut session, message).await;
+                            if sent.is_err() {
+                                break;
+                            }
+                        }
+                        sent
+                    }
+                    // Subscribers that fall a whole buffer behind are cut off
+                    // rather than silently missing events
+                    Err(broadcast::error::RecvError::Lagged(_)) if filter.is_some() => {
+                        Err(policy_close("slow consumer"))
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => Ok(()),
+                    Err(broadcast::error::RecvError::Closed) => Err(None),
+                },
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > CLIENT_TIMEOUT {
+                        Err(policy_close("ping timeout"))
+                    } else {
+                        session.ping(b"").await.map_err(|_| None)
+                    }
+                }
+            };
+            if let Err(reason) = sent {
+                break reason;
+            }
+        };
+        if let Some(reason) = &reason {
+            log_line!(
+                "INFO",
+                "Closing live tail WebSocket: {}",
+                reason.description.as_deref().unwrap_or_default()
+            );
+        }
+        let _ = session.close(reason).await;
+    });
+
+    Ok(response)
+}
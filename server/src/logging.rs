@@ -0,0 +1,246 @@
+use crate::Event;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+// Operational log line: `[TAG] message` in text mode, one ECS-style JSON
+// object per line in json mode.
+macro_rules! log_line {
+    ($tag:expr, $($arg:tt)*) => {
+        $crate::logging::emit($tag, module_path!(), format_args!($($arg)*))
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    // Everything on stdout (the historical behaviour)
+    #[default]
+    Stdout,
+    // Everything on stderr
+    Stderr,
+    // Warnings and errors on stderr, the rest on stdout
+    Split,
+}
+
+struct Settings {
+    format: LogFormat,
+    output: LogOutput,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+// Pick the log format before anything else is printed. LOGLUMEN_LOG_FORMAT
+// and LOGLUMEN_LOG_OUTPUT override the config for container deployments.
+pub fn init() {
+    let config: LoggingConfig = crate::config::load_section_quiet("logging");
+    let format = match std::env::var("LOGLUMEN_LOG_FORMAT").as_deref() {
+        Ok("json") => LogFormat::Json,
+        Ok("text") => LogFormat::Text,
+        _ => config.format,
+    };
+    let output = match std::env::var("LOGLUMEN_LOG_OUTPUT").as_deref() {
+        Ok("stdout") => LogOutput::Stdout,
+        Ok("stderr") => LogOutput::Stderr,
+        Ok("split") => LogOutput::Split,
+        _ => config.output,
+    };
+    let _ = SETTINGS.set(Settings { format, output });
+}
+
+// For subcommands that write their results to stdout: every log line goes
+// to stderr whatever the config says
+pub fn init_stderr() {
+    let config: LoggingConfig = crate::config::load_section_quiet("logging");
+    let format = match std::env::var("LOGLUMEN_LOG_FORMAT").as_deref() {
+        Ok("json") => LogFormat::Json,
+        Ok("text") => LogFormat::Text,
+        _ => config.format,
+    };
+    let _ = SETTINGS.set(Settings {
+        format,
+        output: LogOutput::Stderr,
+    });
+}
+
+pub fn is_json() -> bool {
+    SETTINGS
+        .get()
+        .is_some_and(|settings| settings.format == LogFormat::Json)
+}
+
+fn ecs_level(tag: &str) -> &'static str {
+    match tag {
+        "ERROR" => "error",
+        "WARN" | "ALERT" => "warn",
+        "DEBUG" => "debug",
+        _ => "info",
+    }
+}
+
+pub fn emit(tag: &str, logger: &str, message: std::fmt::Arguments) {
+    let (format, output) = SETTINGS
+        .get()
+        .map(|settings| (settings.format, settings.output))
+        .unwrap_or_default();
+
+    let line = match format {
+        LogFormat::Text => format!("[{}] {}", tag, message),
+        LogFormat::Json => json_line(tag, logger, &message.to_string()),
+    };
+    write_line(output, ecs_level(tag), &line);
+}
+
+// Per-event ingest detail: indented under the batch line in text mode
+fn emit_detail(message: std::fmt::Arguments) {
+    let (format, output) = SETTINGS
+        .get()
+        .map(|settings| (settings.format, settings.output))
+        .unwrap_or_default();
+
+    let line = match format {
+        LogFormat::Text => format!("  {}", message),
+        LogFormat::Json => json_line("EVENT", module_path!(), &message.to_string()),
+    };
+    write_line(output, "info", &line);
+}
+
+fn json_line(tag: &str, logger: &str, message: &str) -> String {
+    serde_json::json!({
+        "@timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "log.level": ecs_level(tag),
+        "log.logger": logger.replace("::", "."),
+        "message": message,
+        "labels": { "tag": tag },
+        "service.name": "loglumen-server",
+        "ecs.version": "8.11.0",
+    })
+    .to_string()
+}
+
+fn write_line(output: LogOutput, level: &str, line: &str) {
+    let to_stderr = match output {
+        LogOutput::Stdout => false,
+        LogOutput::Stderr => true,
+        LogOutput::Split => matches!(level, "warn" | "error"),
+    };
+    if to_stderr {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestLogMode {
+    // One line per event (sampled by `ingest_sample_rate`) plus batch summaries
+    Events,
+    // Batch summaries only
+    Summary,
+    Off,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    format: LogFormat,
+    output: LogOutput,
+    ingest: IngestLogMode,
+    ingest_sample_rate: f64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::Text,
+            output: LogOutput::Stdout,
+            ingest: IngestLogMode::Events,
+            ingest_sample_rate: 1.0,
+        }
+    }
+}
+
+// Controls how much of each ingested batch is echoed to stdout. Printing
+// every event dominates CPU at high ingest rates, so operators can sample
+// or drop down to per-batch summaries.
+pub struct IngestLogger {
+    mode: IngestLogMode,
+    // Print one event out of every `every` (1 = all)
+    every: u64,
+    seen: AtomicU64,
+}
+
+impl IngestLogger {
+    pub fn new(mut config: LoggingConfig) -> Self {
+        if let Ok(mode) = std::env::var("LOGLUMEN_INGEST_LOG") {
+            match mode.to_ascii_lowercase().as_str() {
+                "events" => config.ingest = IngestLogMode::Events,
+                "summary" => config.ingest = IngestLogMode::Summary,
+                "off" => config.ingest = IngestLogMode::Off,
+                other => log_line!("WARN", "Ignoring unknown LOGLUMEN_INGEST_LOG '{}'", other),
+            }
+        }
+
+        let rate = config.ingest_sample_rate.clamp(0.0, 1.0);
+        let every = if rate <= 0.0 {
+            u64::MAX
+        } else {
+            (1.0 / rate).round().max(1.0) as u64
+        };
+
+        if config.ingest != IngestLogMode::Events || every > 1 {
+            log_line!(
+                "CONFIG",
+                "Ingest logging: {:?}, sample rate {}",
+                config.ingest,
+                rate
+            );
+        }
+
+        IngestLogger {
+            mode: config.ingest,
+            every,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    pub fn log_batch(&self, batch: &[Event]) {
+        if self.mode == IngestLogMode::Off {
+            return;
+        }
+
+        log_line!("INFO", "Received {} events", batch.len());
+
+        if self.mode != IngestLogMode::Events || self.every == u64::MAX {
+            return;
+        }
+
+        let start = self.seen.fetch_add(batch.len() as u64, Ordering::Relaxed);
+        let skip = (self.every - start % self.every) % self.every;
+        for event in batch
+            .iter()
+            .skip(skip as usize)
+            .step_by(self.every as usize)
+        {
+            emit_detail(format_args!(
+                "[{}] {} - {}",
+                event.category, event.event_type, event.message
+            ));
+        }
+    }
+
+    pub fn log_stored(&self, total: usize) {
+        if self.mode != IngestLogMode::Off {
+            log_line!("OK", "Total events stored: {}", total);
+        }
+    }
+}
@@ -0,0 +1,990 @@
+use crate::{config, live, severity, AppState, Event};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// Promtail batches up to 1 MiB before compressing
+const MAX_BODY: usize = 16 * 1024 * 1024;
+// Refuse snappy payloads claiming to inflate beyond this
+const MAX_DECODED: usize = 64 * 1024 * 1024;
+
+// `[loki]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LokiConfig {
+    category: String,
+    // First label present becomes the event's host, severity and type
+    host_labels: Vec<String>,
+    severity_labels: Vec<String>,
+    event_type_label: String,
+}
+
+impl Default for LokiConfig {
+    fn default() -> Self {
+        let labels = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        LokiConfig {
+            category: "logs".to_string(),
+            host_labels: labels(&["host", "hostname", "instance", "node_name"]),
+            severity_labels: labels(&["level", "severity", "detected_level", "lvl"]),
+            event_type_label: "job".to_string(),
+        }
+    }
+}
+
+pub struct Loki {
+    config: LokiConfig,
+}
+
+impl Loki {
+    pub fn new(config: LokiConfig) -> Self {
+        Loki { config }
+    }
+}
+
+// One log line with the labels of its stream
+struct Entry {
+    labels: Map<String, Value>,
+    time: DateTime<Utc>,
+    line: String,
+    // Loki 3 structured metadata, kept alongside the labels
+    metadata: Map<String, Value>,
+}
+
+// Snappy block format as Promtail sends it, without the framing format
+fn snappy_decode(input: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let (length, mut position) = varint(input, 0).ok_or("snappy: bad length")?;
+    let length = usize::try_from(length).map_err(|_| "snappy: bad length")?;
+    if length > MAX_DECODED {
+        return Err(format!("snappy: {} bytes decoded is too large", length));
+    }
+    let mut output = Vec::with_capacity(length);
+    let byte = |at: usize| input.get(at).copied().ok_or("snappy: truncated");
+    let little_endian = |at: usize, count: usize| -> std::result::Result<usize, &str> {
+        let bytes = input.get(at..at + count).ok_or("snappy: truncated")?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | usize::from(*byte)))
+    };
+    while position < input.len() {
+        let tag = input[position];
+        position += 1;
+        let (copy_length, offset) = match tag & 0b11 {
+            0 => {
+                let mut literal = usize::from(tag >> 2);
+                if literal >= 60 {
+                    let count = literal - 59;
+                    literal = little_endian(position, count)?;
+                    position += count;
+                }
+                let bytes = input
+                    .get(position..position + literal + 1)
+                    .ok_or("snappy: truncated literal")?;
+                output.extend_from_slice(bytes);
+                position += literal + 1;
+                continue;
+            }
+            1 => {
+                let offset = usize::from(tag >> 5) << 8 | usize::from(byte(position)?);
+                position += 1;
+                (4 + usize::from(tag >> 2 & 0b111), offset)
+            }
+            2 => {
+                position += 2;
+                (1 + usize::from(tag >> 2), little_endian(position - 2, 2)?)
+            }
+            _ => {
+                position += 4;
+                (1 + usize::from(tag >> 2), little_endian(position - 4, 4)?)
+            }
+        };
+        if offset == 0 || offset > output.len() {
+            return Err("snappy: copy before start".to_string());
+        }
+        // Copies may overlap what they produce, so go byte by byte
+        let start = output.len() - offset;
+        for index in 0..copy_length {
+            output.push(output[start + index]);
+        }
+        if output.len() > length {
+            return Err("snappy: longer than declared".to_string());
+        }
+    }
+    if output.len() != length {
+        return Err("snappy: shorter than declared".to_string());
+    }
+    Ok(output)
+}
+
+fn varint(input: &[u8], mut position: usize) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *input.get(position)?;
+        position += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some((value, position));
+        }
+    }
+    None
+}
+
+// Protobuf field: varints as numbers, length-delimited fields as bytes
+enum Field<'a> {
+    Number(u64),
+    Bytes(&'a [u8]),
+}
+
+// The (field number, value) pairs of one message; fixed-width fields,
+// which the push request doesn't use, are skipped
+fn fields(input: &[u8]) -> std::result::Result<Vec<(u64, Field<'_>)>, String> {
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while position < input.len() {
+        let (key, next) = varint(input, position).ok_or("protobuf: bad field key")?;
+        position = next;
+        match key & 0b111 {
+            0 => {
+                let (value, next) = varint(input, position).ok_or("protobuf: bad varint")?;
+                position = next;
+                fields.push((key >> 3, Field::Number(value)));
+            }
+            1 => position += 8,
+            2 => {
+                let (length, next) = varint(input, position).ok_or("protobuf: bad length")?;
+                let end = usize::try_from(length)
+                    .ok()
+                    .and_then(|length| next.checked_add(length))
+                    .filter(|end| *end <= input.len())
+                    .ok_or("protobuf: truncated field")?;
+                fields.push((key >> 3, Field::Bytes(&input[next..end])));
+                position = end;
+            }
+            5 => position += 4,
+            other => return Err(format!("protobuf: unsupported wire type {}", other)),
+        }
+    }
+    if position > input.len() {
+        return Err("protobuf: truncated field".to_string());
+    }
+    Ok(fields)
+}
+
+fn utf8(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+// `{job="varlogs", filename="/var/log/syslog"}`
+fn parse_labels(text: &str) -> std::result::Result<Map<String, Value>, String> {
+    let mut labels = Map::new();
+    let inner = text.trim();
+    let inner = inner
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .ok_or_else(|| format!("labels must be wrapped in braces: {}", text))?;
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        let name: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect();
+        let name = name.trim();
+        if name.is_empty() {
+            break;
+        }
+        if chars.next() != Some('=') || chars.next() != Some('"') {
+            return Err(format!("label {} needs a quoted value", name));
+        }
+        let mut value = String::new();
+        loop {
+            match chars.next() {
+                Some('\\') => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(escaped) => value.push(escaped),
+                    None => return Err(format!("label {} is not terminated", name)),
+                },
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => return Err(format!("label {} is not terminated", name)),
+            }
+        }
+        labels.insert(name.to_string(), Value::String(value));
+    }
+    Ok(labels)
+}
+
+fn label_pairs(pairs: &[&[u8]]) -> std::result::Result<Map<String, Value>, String> {
+    let mut map = Map::new();
+    for pair in pairs {
+        let (mut name, mut value) = (String::new(), String::new());
+        for (number, field) in fields(pair)? {
+            match (number, field) {
+                (1, Field::Bytes(bytes)) => name = utf8(bytes),
+                (2, Field::Bytes(bytes)) => value = utf8(bytes),
+                _ => {}
+            }
+        }
+        map.insert(name, Value::String(value));
+    }
+    Ok(map)
+}
+
+// logproto.PushRequest: streams (1) of labels (1) and entries (2), each
+// entry a timestamp (1), line (2) and structured metadata (3)
+fn decode_protobuf(body: &[u8]) -> std::result::Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (number, stream) in fields(body)? {
+        let (1, Field::Bytes(stream)) = (number, stream) else {
+            continue;
+        };
+        let mut labels = Map::new();
+        let mut raw_entries = Vec::new();
+        for (number, field) in fields(stream)? {
+            match (number, field) {
+                (1, Field::Bytes(text)) => labels = parse_labels(&utf8(text))?,
+                (2, Field::Bytes(entry)) => raw_entries.push(entry),
+                _ => {}
+            }
+        }
+        for entry in raw_entries {
+            let (mut seconds, mut nanos, mut line) = (0i64, 0u32, String::new());
+            let mut metadata = Vec::new();
+            for (number, field) in fields(entry)? {
+                match (number, field) {
+                    (1, Field::Bytes(timestamp)) => {
+                        for (number, field) in fields(timestamp)? {
+                            match (number, field) {
+                                (1, Field::Number(value)) => seconds = value as i64,
+                                (2, Field::Number(value)) => nanos = value as u32,
+                                _ => {}
+                            }
+                        }
+                    }
+                    (2, Field::Bytes(bytes)) => line = utf8(bytes),
+                    (3, Field::Bytes(pair)) => metadata.push(pair),
+                    _ => {}
+                }
+            }
+            entries.push(Entry {
+                labels: labels.clone(),
+                time: DateTime::from_timestamp(seconds, nanos).unwrap_or_else(Utc::now),
+                line,
+                metadata: label_pairs(&metadata)?,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPush {
+    streams: Vec<JsonStream>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonStream {
+    #[serde(default)]
+    stream: Map<String, Value>,
+    // ["<unix nanoseconds>", "<line>", {structured metadata}?]
+    values: Vec<Vec<Value>>,
+}
+
+fn decode_json(body: &[u8]) -> std::result::Result<Vec<Entry>, String> {
+    let push: JsonPush = serde_json::from_slice(body).map_err(|err| err.to_string())?;
+    let mut entries = Vec::new();
+    for stream in push.streams {
+        for value in stream.values {
+            let nanos = value
+                .first()
+                .and_then(|time| match time {
+                    Value::String(text) => text.parse::<i64>().ok(),
+                    other => other.as_i64(),
+                })
+                .ok_or("each value needs a nanosecond timestamp")?;
+            let line = value
+                .get(1)
+                .and_then(Value::as_str)
+                .ok_or("each value needs a log line")?;
+            entries.push(Entry {
+                labels: stream.stream.clone(),
+                time: DateTime::from_timestamp_nanos(nanos),
+                line: line.to_string(),
+                metadata: value
+                    .get(2)
+                    .and_then(Value::as_object)
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn to_event(config: &LokiConfig, entry: Entry, tenant: Option<&str>, peer: &str) -> Event {
+    let label = |names: &[String]| {
+        names.iter().find_map(|name| {
+            entry
+                .labels
+                .get(name)
+                .or_else(|| entry.metadata.get(name))
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        })
+    };
+    let host = label(&config.host_labels).unwrap_or_else(|| peer.to_string());
+    let severity = label(&config.severity_labels)
+        .map(|level| severity::normalize(&level).to_string())
+        .unwrap_or_else(|| "info".to_string());
+    let event_type =
+        label(std::slice::from_ref(&config.event_type_label)).unwrap_or_else(|| "loki".to_string());
+
+    // Structured metadata never overrides a stream label
+    let mut data = entry.metadata;
+    data.extend(entry.labels);
+    if let Some(tenant) = tenant {
+        data.insert("tenant".to_string(), Value::String(tenant.to_string()));
+    }
+
+    Event {
+        schema_version: 1,
+        category: config.category.clone(),
+        event_type,
+        time: entry.time.to_rfc3339(),
+        host,
+        host_ipv4: peer.to_string(),
+        os: String::new(),
+        source: "loki".to_string(),
+        severity,
+        message: entry.line,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// POST /loki/api/v1/push - Loki's push API, so Promtail and Grafana Agent
+// can ship here unchanged. Takes snappy-compressed protobuf (what both send)
+// or JSON. Every entry becomes an event with its stream labels in `data`.
+pub async fn push(
+    request: HttpRequest,
+    mut payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY {
+            return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "status": "error",
+                "message": format!("push bodies are limited to {} bytes", MAX_BODY)
+            })));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let entries = if content_type.starts_with("application/json") {
+        decode_json(&body)
+    } else {
+        snappy_decode(&body).and_then(|decoded| decode_protobuf(&decoded))
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(err) => return Ok(bad_request(format!("invalid push request: {}", err))),
+    };
+
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let tenant = request
+        .headers()
+        .get("X-Scope-OrgID")
+        .and_then(|value| value.to_str().ok());
+    let identity = crate::tls::client_identity(&request);
+    let batch: Vec<Event> = entries
+        .into_iter()
+        .map(|entry| {
+            let mut event = to_event(&data.loki.config, entry, tenant, &peer);
+            event.identity = identity.clone();
+            event
+        })
+        .collect();
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+
+    // Loki answers successful pushes with an empty 204
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Event fields exposed as Loki labels
+const LABELS: [&str; 7] = [
+    "host",
+    "category",
+    "event_type",
+    "severity",
+    "source",
+    "os",
+    "identity",
+];
+// Entries returned when the query doesn't set `limit`, as in Loki
+const DEFAULT_LIMIT: usize = 100;
+
+enum Matcher {
+    Equal(String, String),
+    NotEqual(String, String),
+    Matches(String, Regex),
+    NotMatches(String, Regex),
+}
+
+enum LineFilter {
+    Contains(String),
+    NotContains(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+// A LogQL log query: `{host="web01", severity=~"error|critical"} |= "sshd"`.
+// Metric queries and parser stages aren't supported.
+struct Selector {
+    matchers: Vec<Matcher>,
+    filters: Vec<LineFilter>,
+}
+
+impl Selector {
+    fn matches(&self, labels: &Map<String, Value>, line: &str) -> bool {
+        let label = |name: &str| labels.get(name).and_then(Value::as_str).unwrap_or_default();
+        self.matchers.iter().all(|matcher| match matcher {
+            Matcher::Equal(name, value) => label(name) == value,
+            Matcher::NotEqual(name, value) => label(name) != value,
+            Matcher::Matches(name, regex) => regex.is_match(label(name)),
+            Matcher::NotMatches(name, regex) => !regex.is_match(label(name)),
+        }) && self.filters.iter().all(|filter| match filter {
+            LineFilter::Contains(text) => line.contains(text.as_str()),
+            LineFilter::NotContains(text) => !line.contains(text.as_str()),
+            LineFilter::Matches(regex) => regex.is_match(line),
+            LineFilter::NotMatches(regex) => !regex.is_match(line),
+        })
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_spaces();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    fn name(&mut self) -> String {
+        self.skip_spaces();
+        std::iter::from_fn(|| {
+            self.chars
+                .next_if(|c| c.is_ascii_alphanumeric() || *c == '_')
+        })
+        .collect()
+    }
+
+    // "double quoted" with Go escapes, or `raw`
+    fn string(&mut self) -> std::result::Result<String, String> {
+        self.skip_spaces();
+        let quote = match self.chars.next() {
+            Some(quote @ ('"' | '`')) => quote,
+            _ => return Err("expected a quoted string".to_string()),
+        };
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('\\') if quote == '"' => match self.chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(escaped) => value.push(escaped),
+                    None => break,
+                },
+                Some(c) if c == quote => return Ok(value),
+                Some(c) => value.push(c),
+                None => break,
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+fn regex(pattern: &str, anchored: bool) -> std::result::Result<Regex, String> {
+    // Label matchers must match the whole value, line filters anywhere
+    let pattern = if anchored {
+        format!("^(?:{})$", pattern)
+    } else {
+        pattern.to_string()
+    };
+    Regex::new(&pattern).map_err(|err| err.to_string())
+}
+
+fn parse_selector(query: &str) -> std::result::Result<Selector, String> {
+    let mut parser = Parser {
+        chars: query.chars().peekable(),
+    };
+    if !parser.eat('{') {
+        return Err("only log queries like {host=\"web01\"} are supported".to_string());
+    }
+    let mut matchers = Vec::new();
+    loop {
+        if parser.eat('}') {
+            break;
+        }
+        let label = parser.name();
+        if label.is_empty() {
+            return Err("expected a label name".to_string());
+        }
+        parser.skip_spaces();
+        let operator: String =
+            std::iter::from_fn(|| parser.chars.next_if(|c| matches!(c, '=' | '!' | '~'))).collect();
+        let value = parser.string()?;
+        matchers.push(match operator.as_str() {
+            "=" => Matcher::Equal(label, value),
+            "!=" => Matcher::NotEqual(label, value),
+            "=~" => Matcher::Matches(label, regex(&value, true)?),
+            "!~" => Matcher::NotMatches(label, regex(&value, true)?),
+            other => return Err(format!("unknown label operator '{}'", other)),
+        });
+        if parser.eat('}') {
+            break;
+        }
+        if !parser.eat(',') {
+            return Err("expected , or } after a label matcher".to_string());
+        }
+    }
+
+    let mut filters = Vec::new();
+    loop {
+        parser.skip_spaces();
+        let Some(first) = parser.chars.next() else {
+            break;
+        };
+        let operator = (first, parser.chars.next());
+        if !matches!(operator, ('|' | '!', Some('=' | '~'))) {
+            return Err("only line filters (|= != |~ !~) may follow the selector".to_string());
+        }
+        let pattern = parser.string()?;
+        filters.push(match operator {
+            ('|', Some('=')) => LineFilter::Contains(pattern),
+            ('!', Some('=')) => LineFilter::NotContains(pattern),
+            ('|', _) => LineFilter::Matches(regex(&pattern, false)?),
+            _ => LineFilter::NotMatches(regex(&pattern, false)?),
+        });
+    }
+    Ok(Selector { matchers, filters })
+}
+
+// A LogQL query from a fuzzer or a property test. Malformed input must not
+// panic.
+pub fn fuzz_selector(input: &[u8]) {
+    let labels: Map<String, Value> = [("host", "web01"), ("severity", "error")]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), Value::String(value.to_string())))
+        .collect();
+    if let Ok(selector) = parse_selector(&String::from_utf8_lossy(input)) {
+        selector.matches(&labels, "sshd: failed password");
+    }
+}
+
+// A push body, tried as every encoding the push endpoint takes
+pub fn fuzz_push(input: &[u8]) {
+    let _ = snappy_decode(input).and_then(|decoded| decode_protobuf(&decoded));
+    let _ = decode_protobuf(input);
+    let _ = decode_json(input);
+    let _ = parse_timestamp(&String::from_utf8_lossy(input));
+}
+
+// Nanosecond or second Unix timestamps (as Grafana sends them) or RFC 3339
+fn parse_timestamp(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    let invalid = || format!("invalid timestamp '{}'", value);
+    if let Ok(number) = value.parse::<i64>() {
+        return if value.trim_start_matches('-').len() > 12 {
+            Ok(DateTime::from_timestamp_nanos(number))
+        } else {
+            DateTime::from_timestamp(number, 0).ok_or_else(invalid)
+        };
+    }
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Ok(DateTime::from_timestamp_nanos((seconds * 1e9) as i64));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| invalid())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RangeQuery {
+    query: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    // How far before `end` to start when `start` is missing; 1h by default
+    since: Option<String>,
+    limit: Option<usize>,
+    // "backward" (newest first, the default) or "forward"
+    direction: Option<String>,
+}
+
+impl RangeQuery {
+    fn range(&self) -> std::result::Result<(DateTime<Utc>, DateTime<Utc>), String> {
+        let end = match &self.end {
+            Some(end) => parse_timestamp(end)?,
+            None => Utc::now(),
+        };
+        let start = match (&self.start, &self.since) {
+            (Some(start), _) => parse_timestamp(start)?,
+            (None, Some(since)) => {
+                let duration = config::parse_duration(since)
+                    .ok_or_else(|| format!("invalid duration '{}'", since))?;
+                chrono::Duration::from_std(duration)
+                    .ok()
+                    .and_then(|duration| end.checked_sub_signed(duration))
+                    .ok_or_else(|| format!("since '{}' reaches before the earliest time", since))?
+            }
+            (None, None) => end - chrono::Duration::hours(1),
+        };
+        Ok((start, end))
+    }
+}
+
+// Events in the range as the caller may see them: Loki labels, time, line
+fn visible_entries(
+    request: &HttpRequest,
+    data: &AppState,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<(Map<String, Value>, DateTime<Utc>, String)> {
+    let role = live::caller_role(request);
+    let store = data.store.read();
+    store
+        .iter()
+        .filter_map(|event| {
+            let time = event.timestamp()?;
+            if time < start || time > end {
+                return None;
+            }
+            let Value::Object(fields) = live::visible(data, &role, event) else {
+                return None;
+            };
+            let labels = LABELS
+                .iter()
+                .filter_map(|name| {
+                    let value = fields
+                        .get(*name)?
+                        .as_str()
+                        .filter(|value| !value.is_empty())?;
+                    Some((name.to_string(), Value::String(value.to_string())))
+                })
+                .collect();
+            let line = fields
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            Some((labels, time, line))
+        })
+        .collect()
+}
+
+fn success(data: Value) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "data": data
+    }))
+}
+
+// GET /loki/api/v1/query_range - Log lines matching a LogQL selector with
+// optional line filters, as Loki streams, so Grafana's Loki data source can
+// browse stored events. Labels are the core event fields in LABELS.
+pub async fn query_range(
+    request: HttpRequest,
+    query: web::Query<RangeQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let selector = match parse_selector(query.query.as_deref().unwrap_or_default()) {
+        Ok(selector) => selector,
+        Err(err) => return Ok(bad_request(format!("invalid query: {}", err))),
+    };
+    let (start, end) = match query.range() {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let forward = match query.direction.as_deref().unwrap_or("backward") {
+        direction if direction.eq_ignore_ascii_case("forward") => true,
+        direction if direction.eq_ignore_ascii_case("backward") => false,
+        other => return Ok(bad_request(format!("unknown direction '{}'", other))),
+    };
+
+    let mut entries: Vec<_> = visible_entries(&request, &data, start, end)
+        .into_iter()
+        .filter(|(labels, _, line)| selector.matches(labels, line))
+        .collect();
+    entries.sort_by_key(|(_, time, _)| *time);
+    if !forward {
+        entries.reverse();
+    }
+    entries.truncate(query.limit.unwrap_or(DEFAULT_LIMIT));
+
+    // One stream per label set, in order of first appearance
+    let mut streams: Vec<(Map<String, Value>, Vec<Value>)> = Vec::new();
+    for (labels, time, line) in entries {
+        let value = serde_json::json!([
+            time.timestamp_nanos_opt().unwrap_or_default().to_string(),
+            line
+        ]);
+        match streams.iter_mut().find(|(stream, _)| *stream == labels) {
+            Some((_, values)) => values.push(value),
+            None => streams.push((labels, vec![value])),
+        }
+    }
+    let result: Vec<Value> = streams
+        .into_iter()
+        .map(|(stream, values)| serde_json::json!({ "stream": stream, "values": values }))
+        .collect();
+
+    Ok(success(serde_json::json!({
+        "resultType": "streams",
+        "result": result,
+        "stats": {}
+    })))
+}
+
+// GET /loki/api/v1/labels - Label names present in the range
+pub async fn labels(
+    request: HttpRequest,
+    query: web::Query<RangeQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let (start, end) = match query.range() {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let mut names: Vec<String> = visible_entries(&request, &data, start, end)
+        .into_iter()
+        .flat_map(|(labels, _, _)| labels.into_iter().map(|(name, _)| name))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    Ok(success(serde_json::json!(names)))
+}
+
+// GET /loki/api/v1/label/{name}/values - Values of one label in the range,
+// narrowed to a selector when `query` is given
+pub async fn label_values(
+    request: HttpRequest,
+    name: web::Path<String>,
+    query: web::Query<RangeQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let selector = match query.query.as_deref().map(parse_selector).transpose() {
+        Ok(selector) => selector,
+        Err(err) => return Ok(bad_request(format!("invalid query: {}", err))),
+    };
+    let (start, end) = match query.range() {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let name = name.into_inner();
+    let mut values: Vec<String> = visible_entries(&request, &data, start, end)
+        .into_iter()
+        .filter(|(labels, _, line)| {
+            selector
+                .as_ref()
+                .is_none_or(|selector| selector.matches(labels, line))
+        })
+        .filter_map(|(labels, _, _)| labels.get(&name)?.as_str().map(str::to_string))
+        .collect();
+    values.sort_unstable();
+    values.dedup();
+    Ok(success(serde_json::json!(values)))
+}
+
+// GET /loki/api/v1/series - Label sets matching any `match[]` selector
+pub async fn series(
+    request: HttpRequest,
+    params: web::Query<Vec<(String, String)>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let param = |key: &str| {
+        params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+    };
+    let selectors = match params
+        .iter()
+        .filter(|(name, _)| name == "match[]" || name == "match")
+        .map(|(_, value)| parse_selector(value))
+        .collect::<std::result::Result<Vec<_>, _>>()
+    {
+        Ok(selectors) => selectors,
+        Err(err) => return Ok(bad_request(format!("invalid match[]: {}", err))),
+    };
+    let range = RangeQuery {
+        query: None,
+        start: param("start"),
+        end: param("end"),
+        since: param("since"),
+        limit: None,
+        direction: None,
+    };
+    let (start, end) = match range.range() {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+
+    let mut sets: Vec<Map<String, Value>> = Vec::new();
+    for (labels, _, line) in visible_entries(&request, &data, start, end) {
+        let matched = selectors.is_empty()
+            || selectors
+                .iter()
+                .any(|selector| selector.matches(&labels, &line));
+        if matched && !sets.contains(&labels) {
+            sets.push(labels);
+        }
+    }
+    Ok(success(serde_json::json!(sets)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+
+    const SELECTORS: &[&[u8]] = &[
+        b"{host=\"web01\", severity=~\"error|critical\"} |= \"sshd\" != `debug`",
+        b"{job!=\"varlogs\",filename!~\"/var/log/.*\\\\.gz\"} |~ \"fail(ed|ure)\" !~ \"\\\\d+\"",
+        b"{}",
+    ];
+    const TOKENS: &[&[u8]] = &[
+        b"{",
+        b"}",
+        b",",
+        b"=",
+        b"!=",
+        b"=~",
+        b"!~",
+        b"|=",
+        b"|~",
+        b"\"",
+        b"`",
+        b"\\",
+        b"\\n",
+        b"(",
+        b")",
+        b"[",
+        b"*",
+        b"+",
+        b"{1000000}",
+        b"\\p{",
+        b"\xc3\xa9",
+    ];
+
+    proptest! {
+        #[test]
+        fn fuzz_selector(input in mutations::input(SELECTORS, TOKENS)) {
+            super::fuzz_selector(&input);
+        }
+
+        #[test]
+        fn equality_selector_matches_its_labels(
+            values in prop::collection::vec(r#"[abc XYZ019"\\`{}=,|~!\n\t]{1,12}"#, 1..5),
+        ) {
+            let quoted = |value: &str| {
+                let escaped = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\t', "\\t");
+                format!("\"{}\"", escaped)
+            };
+            let matchers: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| format!("label_{}={}", index, quoted(value)))
+                .collect();
+            let selector = parse_selector(&format!("{{{}}}", matchers.join(", "))).unwrap();
+            let labels = parse_labels(&format!("{{{}}}", matchers.join(","))).unwrap();
+            prop_assert_eq!(labels.len(), values.len());
+            prop_assert!(selector.matches(&labels, ""));
+        }
+    }
+
+    #[test]
+    fn range_rejects_a_since_that_overflows() {
+        let query = |since: &str| RangeQuery {
+            query: None,
+            start: None,
+            end: Some("1760436000".to_string()),
+            since: Some(since.to_string()),
+            limit: None,
+            direction: None,
+        };
+        let (start, end) = query("90m").range().unwrap();
+        assert_eq!(end - start, chrono::Duration::minutes(90));
+        assert!(query("1000000000d").range().is_err());
+        assert!(query(&format!("{}", u64::MAX)).range().is_err());
+    }
+
+    // PushRequest with one stream and entry, snappy-framed as a literal
+    fn framed_push() -> Vec<u8> {
+        let mut entry = vec![0x0a, 0x02, 0x08, 0x01, 0x12, 0x05];
+        entry.extend(b"hello");
+        let labels = b"{job=\"varlogs\"}";
+        let mut stream = vec![0x0a, labels.len() as u8];
+        stream.extend(labels);
+        stream.push(0x12);
+        stream.push(entry.len() as u8);
+        stream.extend(&entry);
+        let mut push = vec![0x0a, stream.len() as u8];
+        push.extend(&stream);
+        let mut framed = vec![push.len() as u8, ((push.len() - 1) << 2) as u8];
+        framed.extend(&push);
+        framed
+    }
+
+    const JSON_PUSH: &[u8] = br#"{"streams":[{"stream":{"job":"varlogs"},"values":[["1760436000000000000","line",{"trace_id":"abc"}]]}]}"#;
+
+    proptest! {
+        #[test]
+        fn fuzz_push(
+            input in mutations::input(
+                &[&framed_push(), JSON_PUSH],
+                &[&[0x0a], &[0x12], &[0xff, 0xff, 0xff, 0x7f], &[0xfc]],
+            ),
+        ) {
+            super::fuzz_push(&input);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+// SMTP submission for the email notifier through lettre's SmtpTransport:
+// STARTTLS or implicit TLS, AUTH PLAIN or LOGIN, and one plain-text message
+// per connection. Alerts are rare enough that connections aren't kept open
+// between them, and the blocking transport runs on tokio's blocking pool.
+use base64::Engine;
+use lettre::address::Envelope;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Certificate, CertificateStore, Tls, TlsParameters};
+use lettre::transport::smtp::extension::ClientId;
+use lettre::{SmtpTransport, Transport};
+use serde::Deserialize;
+use std::time::Duration;
+
+// Each connect, read and write; lettre has no limit on the whole session
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+// Characters of a header value kept; lines must stay under 998 bytes
+const MAX_HEADER: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    // Plain connection upgraded with STARTTLS, usually port 587
+    #[default]
+    Starttls,
+    // TLS from the first byte, usually port 465
+    Tls,
+    // No encryption, for a relay on this host or the local network
+    None,
+}
+
+impl Security {
+    fn default_port(self) -> u16 {
+        match self {
+            Security::Starttls => 587,
+            Security::Tls => 465,
+            Security::None => 25,
+        }
+    }
+}
+
+pub struct MailError {
+    pub message: String,
+    // A 5xx reply or a server that can't do what was configured; sending
+    // the same message again gets the same answer
+    pub permanent: bool,
+}
+
+impl From<lettre::transport::smtp::Error> for MailError {
+    fn from(err: lettre::transport::smtp::Error) -> Self {
+        MailError {
+            // Client errors are ours: no STARTTLS or no AUTH mechanism in
+            // common with the server
+            permanent: err.is_permanent() || err.is_client(),
+            message: err.to_string(),
+        }
+    }
+}
+
+pub struct Mailer {
+    // host:port
+    address: String,
+    transport: SmtpTransport,
+}
+
+impl Mailer {
+    // `server` is a host with an optional port, which defaults by security
+    pub fn new(
+        server: &str,
+        security: Security,
+        ca_cert: Option<&str>,
+        credentials: Option<(String, String)>,
+        hostname: String,
+    ) -> Result<Self, String> {
+        let (host, port) = match server.rsplit_once(':') {
+            // An IPv6 address without a port has colons but no brackets
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                let port = port
+                    .parse::<u16>()
+                    .map_err(|_| format!("invalid port in '{}'", server))?;
+                (host, port)
+            }
+            _ => (server, security.default_port()),
+        };
+        let host = host.trim_matches(['[', ']']);
+        if host.is_empty() {
+            return Err("an SMTP `server` is required".to_string());
+        }
+        let tls = match security {
+            Security::None => Tls::None,
+            Security::Starttls => Tls::Required(tls_parameters(host, ca_cert)?),
+            Security::Tls => Tls::Wrapper(tls_parameters(host, ca_cert)?),
+        };
+        let mut builder = SmtpTransport::builder_dangerous(host)
+            .port(port)
+            .tls(tls)
+            .hello_name(ClientId::Domain(hostname))
+            .authentication(vec![Mechanism::Plain, Mechanism::Login])
+            .timeout(Some(COMMAND_TIMEOUT));
+        if let Some((user, password)) = credentials {
+            builder = builder.credentials(Credentials::new(user, password));
+        }
+        let address = if host.contains(':') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+        Ok(Mailer {
+            address,
+            transport: builder.build(),
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    // Hands one message to the server for the given envelope
+    pub async fn send(&self, from: &str, to: &[String], message: &str) -> Result<(), MailError> {
+        let envelope = envelope(from, to).map_err(|message| MailError {
+            message,
+            permanent: true,
+        })?;
+        let transport = self.transport.clone();
+        // lettre ends the data with its own line break and dot
+        let message = message.trim_end_matches("\r\n").to_string();
+        let sent = tokio::task::spawn_blocking(move || {
+            transport
+                .send_raw(&envelope, message.as_bytes())
+                .map(|_| ())
+                .map_err(MailError::from)
+        })
+        .await
+        .unwrap_or_else(|err| {
+            Err(MailError {
+                message: err.to_string(),
+                permanent: false,
+            })
+        });
+        sent.map_err(|mut err| {
+            err.message = format!("{}: {}", self.address, err.message);
+            err
+        })
+    }
+}
+
+// Only the CA bundle when one is given, the bundled public roots otherwise
+fn tls_parameters(host: &str, ca_cert: Option<&str>) -> Result<TlsParameters, String> {
+    let mut builder = TlsParameters::builder(host.to_string());
+    match ca_cert {
+        Some(path) => {
+            let pem = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+            let certificate =
+                Certificate::from_pem(&pem).map_err(|err| format!("{}: {}", path, err))?;
+            builder = builder
+                .certificate_store(CertificateStore::None)
+                .add_root_certificate(certificate);
+        }
+        None => builder = builder.certificate_store(CertificateStore::WebpkiRoots),
+    }
+    builder
+        .build_rustls()
+        .map_err(|err| format!("invalid TLS settings for '{}': {}", host, err))
+}
+
+fn envelope(from: &str, to: &[String]) -> Result<Envelope, String> {
+    let parse = |address: &str| {
+        address
+            .parse()
+            .map_err(|err| format!("invalid address '{}': {}", address, err))
+    };
+    let recipients = to
+        .iter()
+        .map(|address| parse(address))
+        .collect::<Result<Vec<_>, _>>()?;
+    Envelope::new(Some(parse(from)?), recipients).map_err(|err| err.to_string())
+}
+
+// The bare address of "Name <user@example.com>" or "user@example.com"
+pub fn address_of(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(open), Some(close)) if open < close => mailbox[open + 1..close].trim(),
+        _ => mailbox.trim(),
+    }
+}
+
+// Whether an address can go in an envelope as-is
+pub fn valid_address(address: &str) -> bool {
+    address.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty() && !domain.is_empty() && !domain.contains('@')
+    }) && !address
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ',' | ';'))
+}
+
+// Header values come from alerts, so line breaks are flattened; anything
+// outside ASCII is sent as an RFC 2047 encoded word
+fn header_value(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_HEADER)
+        .collect();
+    if value.is_ascii() {
+        value
+    } else {
+        format!(
+            "=?utf-8?B?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(value)
+        )
+    }
+}
+
+// "Name <user@example.com>" with the name encoded when it needs to be
+fn mailbox(value: &str) -> String {
+    let address = address_of(value);
+    match value.rfind('<') {
+        Some(open) if !value[..open].trim().is_empty() => format!(
+            "{} <{}>",
+            header_value(value[..open].trim().trim_matches('"')),
+            address
+        ),
+        _ => address.to_string(),
+    }
+}
+
+// A complete plain-text message. The body is base64 so any text survives
+// servers without 8BITMIME.
+pub fn message(from: &str, to: &[String], subject: &str, body: &str, message_id: &str) -> String {
+    let body = body.replace("\r\n", "\n").replace('\n', "\r\n");
+    let encoded = base64::engine::general_purpose::STANDARD.encode(body);
+    let mut wrapped = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        wrapped.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        wrapped.push_str("\r\n");
+    }
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\nAuto-Submitted: auto-generated\r\n\r\n{}",
+        mailbox(from),
+        to.join(", "),
+        header_value(subject),
+        chrono::Utc::now().to_rfc2822(),
+        message_id,
+        wrapped
+    )
+}
@@ -0,0 +1,95 @@
+// proptest strategies for the parsers that read network input: mostly
+// known-good samples with a few edits (flipped and inserted bytes, cuts,
+// repeats, splices of the format's own tokens), the rest tokens and random
+// bytes strung together. Failing cases shrink and are saved under
+// proptest-regressions/; PROPTEST_CASES runs more of them. Coverage-guided
+// fuzzing of the same parsers lives in fuzz/.
+use proptest::prelude::*;
+use proptest::sample::{select, Index};
+
+#[derive(Clone, Debug)]
+enum Edit {
+    Flip(Index, u8),
+    Insert(Index, u8),
+    Truncate(Index),
+    Cut(Index, Index),
+    Token(Index, Index),
+    // A stretch repeated, for length fields and nesting
+    Repeat(Index, u8, u8),
+    Splice(Index, Vec<u8>),
+}
+
+fn edit() -> impl Strategy<Value = Edit> {
+    prop_oneof![
+        (any::<Index>(), 0..8u8).prop_map(|(at, bit)| Edit::Flip(at, bit)),
+        (any::<Index>(), any::<u8>()).prop_map(|(at, byte)| Edit::Insert(at, byte)),
+        any::<Index>().prop_map(Edit::Truncate),
+        (any::<Index>(), any::<Index>()).prop_map(|(at, end)| Edit::Cut(at, end)),
+        (any::<Index>(), any::<Index>()).prop_map(|(at, token)| Edit::Token(at, token)),
+        (any::<Index>(), 1..32u8, 1..8u8)
+            .prop_map(|(at, length, times)| Edit::Repeat(at, length, times)),
+        (
+            any::<Index>(),
+            select(vec![vec![0x00], vec![0xff], b"\n".to_vec(), b" ".to_vec()])
+        )
+            .prop_map(|(at, bytes)| Edit::Splice(at, bytes)),
+    ]
+}
+
+fn apply(mut input: Vec<u8>, edits: Vec<Edit>, tokens: &[Vec<u8>]) -> Vec<u8> {
+    for edit in edits {
+        // Positions 0..=len, so edits can append
+        let at = |index: &Index| index.index(input.len() + 1);
+        match edit {
+            Edit::Flip(index, bit) if !input.is_empty() => {
+                let at = index.index(input.len());
+                input[at] ^= 1 << bit;
+            }
+            Edit::Insert(index, byte) => input.insert(at(&index), byte),
+            Edit::Truncate(index) => input.truncate(at(&index)),
+            Edit::Cut(index, end) => {
+                let at = at(&index);
+                let end = at + end.index(input.len() - at + 1);
+                input.drain(at..end);
+            }
+            Edit::Token(index, token) if !tokens.is_empty() => {
+                let at = at(&index);
+                let token = &tokens[token.index(tokens.len())];
+                input.splice(at..at, token.iter().copied());
+            }
+            Edit::Repeat(index, length, times) => {
+                let at = at(&index);
+                let end = (at + usize::from(length)).min(input.len());
+                let stretch = input[at..end].to_vec();
+                for _ in 0..times {
+                    input.splice(at..at, stretch.iter().copied());
+                }
+            }
+            Edit::Splice(index, bytes) => {
+                let at = at(&index);
+                input.splice(at..at, bytes);
+            }
+            _ => {}
+        }
+    }
+    input
+}
+
+// Input for a parser from good `samples` and the format's `tokens`
+pub fn input(samples: &[&[u8]], tokens: &[&[u8]]) -> impl Strategy<Value = Vec<u8>> {
+    let samples: Vec<Vec<u8>> = samples.iter().map(|sample| sample.to_vec()).collect();
+    let tokens: Vec<Vec<u8>> = tokens.iter().map(|token| token.to_vec()).collect();
+    let piece = if tokens.is_empty() {
+        prop::collection::vec(any::<u8>(), 0..8).boxed()
+    } else {
+        prop_oneof![
+            select(tokens.clone()),
+            prop::collection::vec(any::<u8>(), 0..8)
+        ]
+        .boxed()
+    };
+    let random = prop::collection::vec(piece, 0..64).prop_map(|pieces| pieces.concat());
+    let mutated = (select(samples), prop::collection::vec(edit(), 0..5))
+        .prop_map(move |(sample, edits)| apply(sample, edits, &tokens));
+    prop_oneof![4 => mutated, 1 => random]
+}
@@ -0,0 +1,520 @@
+// Built with `--features netflow`. Receives NetFlow v5, v9 and IPFIX export
+// packets over UDP and turns every flow record into a flow event, which the
+// flow aggregation absorbs like flows reported by agents.
+use crate::{AppState, Event};
+use actix_web::web;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+// IPFIX/v9 marker for a variable-length field
+const VARIABLE_LENGTH: u16 = 65535;
+
+// `[netflow]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NetflowConfig {
+    enabled: bool,
+    // UDP address exporters send to
+    bind: String,
+    // Exporter address -> host name stored on its flows; unlisted exporters
+    // use their address
+    exporters: HashMap<String, String>,
+}
+
+impl Default for NetflowConfig {
+    fn default() -> Self {
+        NetflowConfig {
+            enabled: false,
+            bind: "0.0.0.0:2055".to_string(),
+            exporters: HashMap::new(),
+        }
+    }
+}
+
+impl NetflowConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+// Big-endian cursor over a packet; every read is bounds-checked
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// Unsigned integer of 1 to 8 bytes (IPFIX allows reduced-size encoding)
+fn unsigned(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+}
+
+fn address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ))),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Flow {
+    src_ip: Option<IpAddr>,
+    dst_ip: Option<IpAddr>,
+    src_port: u16,
+    dst_port: u16,
+    protocol: u8,
+    bytes: u64,
+    packets: u64,
+    tcp_flags: u8,
+}
+
+impl Flow {
+    // Information elements shared by NetFlow v9 and IPFIX
+    fn set(&mut self, element: u16, value: &[u8]) {
+        match element {
+            // octetDeltaCount, octetTotalCount
+            1 | 85 => self.bytes = self.bytes.max(unsigned(value)),
+            // packetDeltaCount, packetTotalCount
+            2 | 86 => self.packets = self.packets.max(unsigned(value)),
+            4 => self.protocol = unsigned(value) as u8,
+            6 => self.tcp_flags = unsigned(value) as u8,
+            7 => self.src_port = unsigned(value) as u16,
+            11 => self.dst_port = unsigned(value) as u16,
+            8 | 27 => self.src_ip = address(value),
+            12 | 28 => self.dst_ip = address(value),
+            _ => {}
+        }
+    }
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "icmp".to_string(),
+        6 => "tcp".to_string(),
+        17 => "udp".to_string(),
+        47 => "gre".to_string(),
+        50 => "esp".to_string(),
+        58 => "icmpv6".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Field {
+    element: u16,
+    length: u16,
+}
+
+// Templates are scoped to the exporter and its source ID / observation domain
+type TemplateKey = (IpAddr, u32, u16);
+
+#[derive(Default)]
+struct Decoder {
+    templates: HashMap<TemplateKey, Vec<Field>>,
+    // Data sets seen before their template, reported once each
+    missing: HashSet<TemplateKey>,
+    // Options templates describe exporter metadata, not flows
+    options: HashSet<TemplateKey>,
+}
+
+impl Decoder {
+    // Flows in one export packet, its export time and the format's name
+    fn decode(
+        &mut self,
+        exporter: IpAddr,
+        packet: &[u8],
+    ) -> Option<(Vec<Flow>, u32, &'static str)> {
+        let mut reader = Reader { bytes: packet };
+        match reader.u16()? {
+            5 => Some((
+                decode_v5(&mut reader)?,
+                packet_time(packet, 8)?,
+                "netflow_v5",
+            )),
+            9 => {
+                let time = packet_time(packet, 8)?;
+                reader.take(14)?;
+                let source = reader.u32()?;
+                Some((
+                    self.decode_sets(exporter, source, reader, false),
+                    time,
+                    "netflow_v9",
+                ))
+            }
+            10 => {
+                let length = reader.u16()? as usize;
+                let time = reader.u32()?;
+                reader.u32()?;
+                let domain = reader.u32()?;
+                let body = packet.get(16..length.min(packet.len()))?;
+                Some((
+                    self.decode_sets(exporter, domain, Reader { bytes: body }, true),
+                    time,
+                    "ipfix",
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    // v9 flowsets and IPFIX sets share a layout; only the template set IDs
+    // and enterprise-specific fields differ
+    fn decode_sets(
+        &mut self,
+        exporter: IpAddr,
+        domain: u32,
+        mut reader: Reader,
+        ipfix: bool,
+    ) -> Vec<Flow> {
+        let (template_set, options_set) = if ipfix { (2, 3) } else { (0, 1) };
+        let mut flows = Vec::new();
+        while let (Some(id), Some(length)) = (reader.u16(), reader.u16()) {
+            let Some(body) = (length as usize)
+                .checked_sub(4)
+                .and_then(|length| reader.take(length))
+            else {
+                break;
+            };
+            let mut body = Reader { bytes: body };
+            if id == template_set {
+                self.read_templates(exporter, domain, body, ipfix);
+            } else if id == options_set {
+                if let Some(template) = body.u16() {
+                    self.options.insert((exporter, domain, template));
+                }
+            } else if id >= 256 {
+                let key = (exporter, domain, id);
+                match self.templates.get(&key) {
+                    Some(fields) => read_records(fields, body, &mut flows),
+                    None if self.options.contains(&key) => {}
+                    None => {
+                        if self.missing.insert(key) {
+                            log_line!(
+                                "WARN",
+                                "NetFlow data from {} uses unknown template {}; waiting for it",
+                                exporter,
+                                id
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        flows
+    }
+
+    fn read_templates(&mut self, exporter: IpAddr, domain: u32, mut reader: Reader, ipfix: bool) {
+        while let (Some(id), Some(count)) = (reader.u16(), reader.u16()) {
+            let mut fields = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let (Some(element), Some(length)) = (reader.u16(), reader.u16()) else {
+                    return;
+                };
+                // Enterprise-specific IPFIX elements carry a vendor number;
+                // none of them are used, so they only need skipping
+                let enterprise = ipfix && element & 0x8000 != 0;
+                if enterprise && reader.u32().is_none() {
+                    return;
+                }
+                fields.push(Field {
+                    element: if enterprise { 0 } else { element },
+                    length,
+                });
+            }
+            let key = (exporter, domain, id);
+            self.missing.remove(&key);
+            self.templates.insert(key, fields);
+        }
+    }
+}
+
+// Export time (unix seconds) at `offset` in the header
+fn packet_time(packet: &[u8], offset: usize) -> Option<u32> {
+    Reader {
+        bytes: packet.get(offset..)?,
+    }
+    .u32()
+}
+
+fn decode_v5(reader: &mut Reader) -> Option<Vec<Flow>> {
+    let count = reader.u16()?;
+    reader.take(20)?;
+    let mut flows = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let Some(record) = reader.take(48) else {
+            break;
+        };
+        let mut record = Reader { bytes: record };
+        let src_ip = address(record.take(4)?);
+        let dst_ip = address(record.take(4)?);
+        record.take(8)?;
+        let packets = record.u32()?;
+        let bytes = record.u32()?;
+        record.take(8)?;
+        let src_port = record.u16()?;
+        let dst_port = record.u16()?;
+        record.u8()?;
+        let tcp_flags = record.u8()?;
+        let protocol = record.u8()?;
+        flows.push(Flow {
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            protocol,
+            bytes: bytes.into(),
+            packets: packets.into(),
+            tcp_flags,
+        });
+    }
+    Some(flows)
+}
+
+fn read_records(fields: &[Field], mut reader: Reader, flows: &mut Vec<Flow>) {
+    // Whatever is left after the last whole record is padding
+    'records: while !reader.bytes.is_empty() {
+        let remaining = reader.bytes.len();
+        let mut flow = Flow::default();
+        for field in fields {
+            let length = match field.length {
+                VARIABLE_LENGTH => match reader.u8() {
+                    Some(255) => match reader.u16() {
+                        Some(length) => length as usize,
+                        None => break 'records,
+                    },
+                    Some(length) => length as usize,
+                    None => break 'records,
+                },
+                length => length as usize,
+            };
+            let Some(value) = reader.take(length) else {
+                break 'records;
+            };
+            flow.set(field.element, value);
+        }
+        // A template of only zero-length fields would repeat forever
+        if reader.bytes.len() == remaining {
+            break;
+        }
+        flows.push(flow);
+    }
+}
+
+fn event(
+    flow: Flow,
+    exporter: IpAddr,
+    host: &str,
+    category: &str,
+    time: u32,
+    source: &str,
+) -> Event {
+    let text = |ip: Option<IpAddr>| ip.map(|ip| ip.to_string()).unwrap_or_default();
+    let (src_ip, dst_ip) = (text(flow.src_ip), text(flow.dst_ip));
+    let protocol = protocol_name(flow.protocol);
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type: "flow".to_string(),
+        time: chrono::DateTime::from_timestamp(time.into(), 0)
+            .unwrap_or_else(chrono::Utc::now)
+            .to_rfc3339(),
+        host: host.to_string(),
+        host_ipv4: exporter.to_string(),
+        os: String::new(),
+        source: source.to_string(),
+        severity: "info".to_string(),
+        message: format!(
+            "{}:{} -> {}:{} {} ({} bytes)",
+            src_ip, flow.src_port, dst_ip, flow.dst_port, protocol, flow.bytes
+        ),
+        data: serde_json::json!({
+            "src_ip": src_ip,
+            "dst_ip": dst_ip,
+            "src_port": flow.src_port,
+            "dst_port": flow.dst_port,
+            "protocol": protocol,
+            "bytes": flow.bytes,
+            "packets": flow.packets,
+            "tcp_flags": flow.tcp_flags,
+        }),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// Export packets from a fuzzer or a property test, through one decoder so
+// templates from one packet meet data from later ones. Malformed input must
+// not panic.
+pub fn fuzz(packets: &[Vec<u8>]) {
+    let exporter = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 9));
+    let mut decoder = Decoder::default();
+    for packet in packets {
+        if let Some((flows, time, source)) = decoder.decode(exporter, packet) {
+            for flow in flows {
+                event(flow, exporter, "router", "network", time, source);
+            }
+        }
+    }
+}
+
+pub fn spawn(state: web::Data<AppState>, config: NetflowConfig) {
+    actix_web::rt::spawn(async move {
+        let socket = match UdpSocket::bind(&config.bind).await {
+            Ok(socket) => socket,
+            Err(err) => {
+                log_line!(
+                    "ERROR",
+                    "NetFlow collector could not bind {}: {}",
+                    config.bind,
+                    err
+                );
+                return;
+            }
+        };
+        log_line!(
+            "CONFIG",
+            "NetFlow/IPFIX collector listening on udp://{}",
+            config.bind
+        );
+
+        let mut decoder = Decoder::default();
+        let mut buffer = vec![0u8; 65535];
+        loop {
+            let (length, peer): (usize, SocketAddr) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(err) => {
+                    log_line!("WARN", "NetFlow receive failed: {}", err);
+                    continue;
+                }
+            };
+            let exporter = peer.ip();
+            let Some((flows, time, source)) = decoder.decode(exporter, &buffer[..length]) else {
+                log_line!(
+                    "WARN",
+                    "Ignoring malformed NetFlow packet from {}",
+                    exporter
+                );
+                continue;
+            };
+            if flows.is_empty() {
+                continue;
+            }
+            let host = config
+                .exporters
+                .get(&exporter.to_string())
+                .cloned()
+                .unwrap_or_else(|| exporter.to_string());
+            let category = state.flows.category();
+            let batch = flows
+                .into_iter()
+                .map(|flow| event(flow, exporter, &host, category, time, source))
+                .collect();
+            crate::ingest(&state, batch).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+
+    fn v5() -> Vec<u8> {
+        let mut packet = vec![0, 5, 0, 1];
+        packet.extend([0; 4]);
+        packet.extend(1_760_436_000u32.to_be_bytes());
+        packet.extend([0; 12]);
+        packet.extend([10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend([0; 8]);
+        packet.extend(3u32.to_be_bytes());
+        packet.extend(180u32.to_be_bytes());
+        packet.extend([0; 8]);
+        packet.extend([0x1f, 0x90, 0, 80, 0, 0x12, 6]);
+        packet.resize(24 + 48, 0);
+        packet
+    }
+
+    // One template (source, destination, bytes, a variable-length field)
+    // and a data set using it, in a v9 or IPFIX packet
+    fn templated(version: u16) -> Vec<u8> {
+        let (template_set, header) = if version == 10 { (2u16, 16) } else { (0, 20) };
+        let mut template = vec![1, 0, 0, 4];
+        for (element, length) in [(8u16, 4u16), (12, 4), (1, 4), (82, 0xffff)] {
+            template.extend(element.to_be_bytes());
+            template.extend(length.to_be_bytes());
+        }
+        let record: &[u8] = &[
+            192, 0, 2, 1, 192, 0, 2, 2, 0, 0, 1, 0, 4, b'e', b't', b'h', b'0',
+        ];
+        let mut packet = Vec::new();
+        packet.extend(version.to_be_bytes());
+        packet.extend([0; 2]);
+        packet.extend([0; 4]);
+        packet.extend(1_760_436_000u32.to_be_bytes());
+        packet.resize(header, 0);
+        packet.extend(template_set.to_be_bytes());
+        packet.extend((template.len() as u16 + 4).to_be_bytes());
+        packet.extend(template);
+        packet.extend(256u16.to_be_bytes());
+        packet.extend((record.len() as u16 + 4).to_be_bytes());
+        packet.extend(record);
+        if version == 10 {
+            let length = packet.len() as u16;
+            packet[2..4].copy_from_slice(&length.to_be_bytes());
+        }
+        packet
+    }
+
+    fn packet() -> impl Strategy<Value = Vec<u8>> {
+        // A template whose fields are all empty, then data for it
+        let mut empty = templated(9)[..20].to_vec();
+        empty.extend([0, 0, 0, 12, 1, 0, 0, 1, 0, 8, 0, 0, 1, 0, 0, 6, 1, 2]);
+        let samples = [v5(), templated(9), templated(10), empty];
+        let samples: Vec<&[u8]> = samples.iter().map(Vec::as_slice).collect();
+        mutations::input(&samples, &[&[0, 0], &[0xff, 0xff], &[1, 0]])
+    }
+
+    proptest! {
+        #[test]
+        fn fuzz_decode(packets in prop::collection::vec(packet(), 1..4)) {
+            fuzz(&packets);
+        }
+    }
+}
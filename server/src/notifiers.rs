@@ -0,0 +1,982 @@
+use crate::config;
+use crate::mailer::{self, Mailer};
+use crate::rules::Alert;
+use crate::AppState;
+use actix_web::web;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Alerts queued for issue creation before new ones are dropped
+const QUEUE_CAPACITY: usize = 256;
+// Longest wait between attempts, whatever `backoff` or Retry-After say
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+// Deliveries retrying at once; past this the queue backs up instead
+const MAX_IN_FLIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Tracker {
+    Jira,
+    Github,
+    // Any HTTP endpoint with a templated body, e.g. ServiceNow or a chat
+    Webhook,
+    // Adaptive card posted to a Teams incoming webhook or Workflows URL
+    Teams,
+    // Message with an attachment for Slack or Mattermost incoming webhooks
+    Slack,
+    // Message sent to a Matrix room through the client-server API
+    Matrix,
+    // Plain-text email through an SMTP server
+    Email,
+}
+
+// One `[[notifiers]]` entry
+#[derive(Debug, Deserialize)]
+struct NotifierConfig {
+    name: String,
+    #[serde(rename = "type")]
+    tracker: Tracker,
+    // Alert rule names that open an issue; "*" for every rule
+    rules: Vec<String>,
+    // Only alerts at least this severe
+    min_severity: Option<String>,
+    // Jira site, e.g. https://example.atlassian.net. For GitHub this is the
+    // API root and defaults to https://api.github.com. Webhooks post here.
+    url: Option<String>,
+    // More URLs for webhook, Slack and Teams notifiers; each gets every
+    // alert
+    #[serde(default)]
+    urls: Vec<String>,
+    // Optional for webhooks, which can carry credentials in `headers`
+    token: Option<String>,
+    // Jira account email; sent with the token as basic auth. Without it the
+    // token is sent as a bearer token (Jira Data Center PATs).
+    user: Option<String>,
+    // Jira project key
+    project: Option<String>,
+    #[serde(default = "default_issue_type")]
+    issue_type: String,
+    // GitHub "owner/repo"
+    repository: Option<String>,
+    // Matrix room ID, e.g. "!abcdef:example.org"
+    room: Option<String>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default = "default_title")]
+    title: String,
+    body: Option<String>,
+    // Webhook request; `body` is a JSON template unless `content_type` says
+    // otherwise
+    #[serde(default = "default_method")]
+    method: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default = "default_content_type")]
+    content_type: String,
+    // Tries per alert and URL, and the wait before the second; it doubles
+    // after each failure
+    #[serde(default = "default_attempts")]
+    attempts: u32,
+    #[serde(default = "default_backoff", deserialize_with = "config::de_duration")]
+    backoff: Duration,
+    // Email: the SMTP server as host or host:port, how the connection is
+    // secured and an optional CA bundle for it. `user` and `password` log
+    // in when both are set.
+    server: Option<String>,
+    #[serde(default)]
+    security: mailer::Security,
+    ca_cert: Option<String>,
+    password: Option<String>,
+    // Email sender, e.g. "Loglumen <siem@example.com>"
+    from: Option<String>,
+    // Email recipients for every rule, and for rules listed in
+    // `recipients` those instead
+    #[serde(default)]
+    to: Vec<String>,
+    #[serde(default)]
+    recipients: HashMap<String, Vec<String>>,
+}
+
+fn default_issue_type() -> String {
+    "Task".to_string()
+}
+
+fn default_title() -> String {
+    "[{severity}] {rule} on {host}".to_string()
+}
+
+fn default_body() -> String {
+    "{message}\n\nRule: {rule} ({kind})\nHost: {host}\nTime: {time}\nNode: {node_url}\nEvents: {events_url}\n\n{events}".to_string()
+}
+
+fn default_chat_body() -> String {
+    "{message}".to_string()
+}
+
+fn default_webhook_body() -> String {
+    r#"{"title": "{title}", "severity": "{severity}", "rule": "{rule}", "host": "{host}", "time": "{time}", "message": "{message}", "url": "{events_url}", "events": {events_json}}"#.to_string()
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_backoff() -> Duration {
+    Duration::from_secs(2)
+}
+
+// Sender and recipients of an email notifier
+struct Email {
+    mailer: Mailer,
+    from: String,
+    to: Vec<String>,
+    recipients: HashMap<String, Vec<String>>,
+}
+
+impl Email {
+    fn build(config: &mut NotifierConfig) -> Result<Self, String> {
+        let server = config
+            .server
+            .as_deref()
+            .ok_or_else(|| "email notifier needs an SMTP `server`".to_string())?;
+        let from = config
+            .from
+            .take()
+            .ok_or_else(|| "email notifier needs a `from` address".to_string())?;
+        let sender = mailer::address_of(&from);
+        if !mailer::valid_address(sender) {
+            return Err(format!("invalid `from` address '{}'", from));
+        }
+        for address in config.to.iter().chain(config.recipients.values().flatten()) {
+            if !mailer::valid_address(address) {
+                return Err(format!("invalid recipient '{}'", address));
+            }
+        }
+        if config.to.is_empty() && config.recipients.values().all(Vec::is_empty) {
+            return Err("email notifier needs `to` or `recipients`".to_string());
+        }
+        let credentials = match (config.user.take(), config.password.take()) {
+            (Some(user), Some(password)) => Some((user, password)),
+            (None, None) => None,
+            _ => return Err("email login needs both `user` and `password`".to_string()),
+        };
+        // The sender's domain stands in for this host's name in EHLO
+        let hostname = sender.rsplit('@').next().unwrap_or("localhost").to_string();
+        let mailer = Mailer::new(
+            server,
+            config.security,
+            config.ca_cert.as_deref(),
+            credentials,
+            hostname,
+        )?;
+        Ok(Email {
+            mailer,
+            from,
+            to: std::mem::take(&mut config.to),
+            recipients: std::mem::take(&mut config.recipients),
+        })
+    }
+
+    fn recipients(&self, rule: &str) -> &[String] {
+        self.recipients.get(rule).unwrap_or(&self.to)
+    }
+
+    fn sender(&self) -> &str {
+        mailer::address_of(&self.from)
+    }
+
+    fn domain(&self) -> &str {
+        self.sender().rsplit('@').next().unwrap_or("localhost")
+    }
+}
+
+struct Notifier {
+    name: String,
+    rules: Vec<String>,
+    min_severity: usize,
+    tracker: Tracker,
+    endpoints: Vec<String>,
+    auth: Option<String>,
+    project: String,
+    issue_type: String,
+    labels: Vec<String>,
+    title: String,
+    body: String,
+    method: reqwest::Method,
+    headers: reqwest::header::HeaderMap,
+    content_type: String,
+    attempts: u32,
+    backoff: Duration,
+    email: Option<Email>,
+    // Matrix transaction IDs start with this, so retries of one alert are
+    // deduplicated but a restart doesn't reuse IDs
+    transaction_prefix: String,
+}
+
+impl Notifier {
+    fn build(mut config: NotifierConfig) -> Result<Self, String> {
+        let min_severity = match &config.min_severity {
+            Some(severity) => crate::severity::parse_level(severity)?,
+            None => 0,
+        };
+        let email = match config.tracker {
+            Tracker::Email => Some(Email::build(&mut config)?),
+            _ => None,
+        };
+        if !config.urls.is_empty()
+            && !matches!(
+                config.tracker,
+                Tracker::Webhook | Tracker::Slack | Tracker::Teams
+            )
+        {
+            return Err("only webhook, slack and teams notifiers take `urls`".to_string());
+        }
+        let (endpoints, project) = match config.tracker {
+            Tracker::Jira => {
+                let url = config
+                    .url
+                    .ok_or_else(|| "jira notifier needs a `url`".to_string())?;
+                let project = config
+                    .project
+                    .ok_or_else(|| "jira notifier needs a `project`".to_string())?;
+                (
+                    vec![format!("{}/rest/api/2/issue", url.trim_end_matches('/'))],
+                    project,
+                )
+            }
+            Tracker::Github => {
+                let repository = config
+                    .repository
+                    .ok_or_else(|| "github notifier needs a `repository`".to_string())?;
+                let url = config.url.as_deref().unwrap_or("https://api.github.com");
+                (
+                    vec![format!(
+                        "{}/repos/{}/issues",
+                        url.trim_end_matches('/'),
+                        repository
+                    )],
+                    String::new(),
+                )
+            }
+            Tracker::Webhook | Tracker::Slack | Tracker::Teams => {
+                let urls: Vec<String> = config.url.into_iter().chain(config.urls).collect();
+                if urls.is_empty() {
+                    return Err(match config.tracker {
+                        Tracker::Webhook => "webhook notifier needs a `url`",
+                        Tracker::Slack => "slack notifier needs the webhook `url`",
+                        _ => "teams notifier needs the webhook `url`",
+                    }
+                    .to_string());
+                }
+                (urls, String::new())
+            }
+            Tracker::Matrix => {
+                let url = config
+                    .url
+                    .ok_or_else(|| "matrix notifier needs the homeserver `url`".to_string())?;
+                let room = config
+                    .room
+                    .as_deref()
+                    .filter(|room| room.starts_with('!'))
+                    .ok_or_else(|| "matrix notifier needs a `room` ID (!id:server)".to_string())?;
+                let room =
+                    percent_encoding::utf8_percent_encode(room, percent_encoding::NON_ALPHANUMERIC);
+                (
+                    vec![format!(
+                        "{}/_matrix/client/v3/rooms/{}/send/m.room.message",
+                        url.trim_end_matches('/'),
+                        room
+                    )],
+                    String::new(),
+                )
+            }
+            Tracker::Email => (
+                email
+                    .iter()
+                    .map(|email| email.mailer.address().to_string())
+                    .collect(),
+                String::new(),
+            ),
+        };
+        if config.attempts == 0 {
+            return Err("`attempts` must be at least 1".to_string());
+        }
+        // Slack and Teams webhook URLs carry their own secret, and email
+        // logs in with a password
+        if config.token.is_none()
+            && !matches!(
+                config.tracker,
+                Tracker::Webhook | Tracker::Slack | Tracker::Teams | Tracker::Email
+            )
+        {
+            return Err("a `token` is required".to_string());
+        }
+        let auth = config.token.map(|token| match &config.user {
+            Some(user) => {
+                use base64::Engine;
+                let credentials = format!("{}:{}", user, token);
+                format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD.encode(credentials)
+                )
+            }
+            None => format!("Bearer {}", token),
+        });
+        let method = match config.tracker {
+            Tracker::Matrix => reqwest::Method::PUT,
+            _ => reqwest::Method::from_bytes(config.method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("invalid method '{}'", config.method))?,
+        };
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|err| format!("invalid header name '{}': {}", name, err))?;
+            let value = reqwest::header::HeaderValue::from_str(value)
+                .map_err(|err| format!("invalid header value for '{}': {}", name, err))?;
+            headers.insert(name, value);
+        }
+        let body = config.body.unwrap_or_else(|| match config.tracker {
+            Tracker::Webhook => default_webhook_body(),
+            Tracker::Slack | Tracker::Teams | Tracker::Matrix => default_chat_body(),
+            Tracker::Jira | Tracker::Github | Tracker::Email => default_body(),
+        });
+        let mut nonce = [0u8; 4];
+        getrandom::getrandom(&mut nonce).map_err(|err| err.to_string())?;
+        Ok(Notifier {
+            name: config.name,
+            rules: config.rules,
+            min_severity,
+            tracker: config.tracker,
+            endpoints,
+            auth,
+            project,
+            issue_type: config.issue_type,
+            labels: config.labels,
+            title: config.title,
+            body,
+            method,
+            headers,
+            content_type: config.content_type,
+            attempts: config.attempts,
+            backoff: config.backoff,
+            email,
+            transaction_prefix: nonce.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        })
+    }
+
+    fn wants(&self, alert: &Alert) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule == "*" || *rule == alert.rule)
+            && crate::severity::rank(&alert.severity) >= self.min_severity
+            && self
+                .email
+                .as_ref()
+                .is_none_or(|email| !email.recipients(&alert.rule).is_empty())
+    }
+
+    // Where to send the notification for one alert
+    fn endpoints(&self, alert: &Alert) -> Vec<String> {
+        match self.tracker {
+            Tracker::Matrix => self
+                .endpoints
+                .iter()
+                .map(|endpoint| {
+                    format!(
+                        "{}/loglumen-{}-{}",
+                        endpoint, self.transaction_prefix, alert.id
+                    )
+                })
+                .collect(),
+            _ => self.endpoints.clone(),
+        }
+    }
+
+    // Wait before attempt `attempt + 1`
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(MAX_BACKOFF)
+    }
+
+    // The request body to send
+    fn payload(&self, variables: &HashMap<&str, String>) -> Result<String, String> {
+        // `{events}` as a code block in the tracker's own markup
+        let mut variables = variables.clone();
+        let json = variables.get("events_json").cloned().unwrap_or_default();
+        let events = match self.tracker {
+            Tracker::Jira => format!("{{code:json}}\n{}\n{{code}}", json),
+            Tracker::Github => format!("```json\n{}\n```", json),
+            Tracker::Webhook
+            | Tracker::Slack
+            | Tracker::Teams
+            | Tracker::Matrix
+            | Tracker::Email => json,
+        };
+        variables.insert("events", events);
+        let title = render(&self.title, &variables);
+        let body = || render(&self.body, &variables);
+        let payload = match self.tracker {
+            Tracker::Jira => serde_json::json!({
+                "fields": {
+                    "project": { "key": self.project },
+                    "issuetype": { "name": self.issue_type },
+                    "summary": title,
+                    "description": body(),
+                    "labels": self.labels,
+                }
+            }),
+            Tracker::Github => serde_json::json!({
+                "title": title,
+                "body": body(),
+                "labels": self.labels,
+            }),
+            Tracker::Webhook => {
+                variables.insert("title", title);
+                return self.webhook_body(variables);
+            }
+            Tracker::Email => {
+                let Some(email) = &self.email else {
+                    return Err("email is not configured".to_string());
+                };
+                let value = |key: &str| variables.get(key).cloned().unwrap_or_default();
+                // Retries keep the ID, so receivers can spot duplicates
+                let message_id = format!(
+                    "loglumen-{}-{}@{}",
+                    self.transaction_prefix,
+                    value("id"),
+                    email.domain()
+                );
+                return Ok(mailer::message(
+                    &email.from,
+                    email.recipients(&value("rule")),
+                    &title,
+                    &body(),
+                    &message_id,
+                ));
+            }
+            Tracker::Slack => slack_message(&title, &body(), &variables),
+            Tracker::Teams => teams_card(&title, &body(), &variables),
+            Tracker::Matrix => {
+                let text = body();
+                let link = |key: &str| variables.get(key).filter(|url| url.starts_with("http"));
+                let mut plain = format!("{}\n{}", title, text);
+                let mut html = format!(
+                    "<strong>{}</strong><br>{}",
+                    escape_html(&title),
+                    escape_html(&text).replace('\n', "<br>")
+                );
+                if let Some(url) = link("events_url") {
+                    plain.push_str(&format!("\n{}", url));
+                    html.push_str(&format!("<br><a href=\"{}\">Events</a>", escape_html(url)));
+                }
+                serde_json::json!({
+                    "msgtype": "m.text",
+                    "body": plain,
+                    "format": "org.matrix.custom.html",
+                    "formatted_body": html,
+                })
+            }
+        };
+        Ok(payload.to_string())
+    }
+
+    // In JSON templates values are escaped to sit inside a string literal,
+    // except `*_json` variables and `{events}`, which are JSON themselves
+    fn webhook_body(&self, mut variables: HashMap<&str, String>) -> Result<String, String> {
+        if !self.is_json() {
+            return Ok(render(&self.body, &variables));
+        }
+        for (name, value) in variables.iter_mut() {
+            if *name != "events" && !name.ends_with("_json") {
+                let quoted = serde_json::Value::String(std::mem::take(value)).to_string();
+                *value = quoted[1..quoted.len() - 1].to_string();
+            }
+        }
+        let body = render(&self.body, &variables);
+        serde_json::from_str::<serde_json::Value>(&body)
+            .map_err(|err| format!("body template did not render to valid JSON: {}", err))?;
+        Ok(body)
+    }
+
+    fn is_json(&self) -> bool {
+        self.content_type
+            .split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().ends_with("json"))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// An incoming-webhook message: the title as the notification text and an
+// attachment coloured by severity with the body, the alert's fields and
+// the title linked to its events when the dashboard URL is known. Slack
+// and Mattermost share this format.
+fn slack_message(title: &str, text: &str, variables: &HashMap<&str, String>) -> serde_json::Value {
+    let value = |key: &str| variables.get(key).cloned().unwrap_or_default();
+    let color = match crate::severity::rank(&value("severity")) {
+        3.. => "#d9534f",
+        2 => "#f0ad4e",
+        _ => "#5bc0de",
+    };
+    let fields: Vec<serde_json::Value> = [
+        ("Rule", "rule"),
+        ("Severity", "severity"),
+        ("Host", "host"),
+        ("Time", "time"),
+    ]
+    .iter()
+    .map(|(label, key)| serde_json::json!({"title": label, "value": value(key), "short": true}))
+    .collect();
+    let mut attachment = serde_json::json!({
+        "fallback": format!("{}: {}", title, text),
+        "color": color,
+        "title": title,
+        "text": text,
+        "fields": fields,
+    });
+    let events_url = value("events_url");
+    if events_url.starts_with("http") {
+        attachment["title_link"] = serde_json::Value::String(events_url);
+    }
+    serde_json::json!({
+        "text": title,
+        "attachments": [attachment],
+    })
+}
+
+// A Teams message carrying one adaptive card: the title coloured by
+// severity, the body, the alert's facts and links back when the dashboard
+// URL is known
+fn teams_card(title: &str, text: &str, variables: &HashMap<&str, String>) -> serde_json::Value {
+    let value = |key: &str| variables.get(key).cloned().unwrap_or_default();
+    let color = match crate::severity::rank(&value("severity")) {
+        3.. => "Attention",
+        2 => "Warning",
+        _ => "Default",
+    };
+    let facts: Vec<serde_json::Value> = [
+        ("Rule", "rule"),
+        ("Severity", "severity"),
+        ("Host", "host"),
+        ("Time", "time"),
+    ]
+    .iter()
+    .map(|(label, key)| serde_json::json!({"title": label, "value": value(key)}))
+    .collect();
+    let actions: Vec<serde_json::Value> = [("View events", "events_url"), ("Open node", "node_url")]
+        .iter()
+        .filter(|(_, key)| value(key).starts_with("http"))
+        .map(|(label, key)| serde_json::json!({"type": "Action.OpenUrl", "title": label, "url": value(key)}))
+        .collect();
+    serde_json::json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "contentUrl": null,
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": [
+                    {"type": "TextBlock", "text": title, "weight": "Bolder", "size": "Medium", "color": color, "wrap": true},
+                    {"type": "TextBlock", "text": text, "wrap": true},
+                    {"type": "FactSet", "facts": facts},
+                ],
+                "actions": actions,
+            }
+        }]
+    })
+}
+
+// Replace `{name}` with the variable's value; unknown names are left as-is
+pub fn render(template: &str, variables: &HashMap<&str, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after
+            .find('}')
+            .and_then(|close| Some((variables.get(&after[..close])?, close)))
+        {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// `[notifications]` section
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    // Where the dashboard is reachable, for links back from issues, e.g.
+    // http://siem.example.com:8080
+    dashboard_url: Option<String>,
+}
+
+pub struct Notifiers {
+    notifiers: Vec<Notifier>,
+    dashboard_url: String,
+    queue: mpsc::Sender<Alert>,
+    receiver: parking_lot::Mutex<Option<mpsc::Receiver<Alert>>>,
+}
+
+// Whether a `[[notifiers]]` entry would be accepted, for `check-config`
+pub fn check(raw: &serde_json::Value) -> Result<(), String> {
+    let config: NotifierConfig =
+        serde_json::from_value(raw.clone()).map_err(|err| err.to_string())?;
+    Notifier::build(config).map(|_| ())
+}
+
+impl Notifiers {
+    pub fn new(configs: Vec<serde_json::Value>, settings: NotificationsConfig) -> Self {
+        let mut notifiers = Vec::new();
+        for raw in configs {
+            let config: NotifierConfig = match serde_json::from_value(raw) {
+                Ok(config) => config,
+                Err(err) => {
+                    log_line!("WARN", "Skipping invalid [[notifiers]] entry: {}", err);
+                    continue;
+                }
+            };
+            let name = config.name.clone();
+            match Notifier::build(config) {
+                Ok(notifier) => {
+                    log_line!(
+                        "CONFIG",
+                        "Notifier '{}' opens issues for: {}",
+                        notifier.name,
+                        notifier.rules.join(", ")
+                    );
+                    notifiers.push(notifier);
+                }
+                Err(err) => log_line!("WARN", "Skipping notifier '{}': {}", name, err),
+            }
+        }
+
+        let (queue, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        Notifiers {
+            notifiers,
+            dashboard_url: settings
+                .dashboard_url
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            queue,
+            receiver: parking_lot::Mutex::new(Some(receiver)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    // Called for every fired alert; never blocks the rule engine
+    pub fn notify(&self, alert: &Alert) {
+        if !self.notifiers.iter().any(|notifier| notifier.wants(alert)) {
+            return;
+        }
+        if self.queue.try_send(alert.clone()).is_err() {
+            log_line!(
+                "WARN",
+                "Notifiers are falling behind; no issue for alert {}",
+                alert.id
+            );
+        }
+    }
+
+    fn variables(&self, alert: &Alert) -> HashMap<&'static str, String> {
+        let encode = |value: &str| {
+            percent_encoding::utf8_percent_encode(value, percent_encoding::NON_ALPHANUMERIC)
+                .to_string()
+        };
+        let times: Vec<&str> = alert
+            .events
+            .iter()
+            .map(|event| event.time.as_str())
+            .collect();
+        let mut events_url = format!("{}/api/events/{}", self.dashboard_url, encode(&alert.host));
+        if let (Some(from), Some(to)) = (times.iter().min(), times.iter().max()) {
+            events_url = format!("{}?from={}&to={}", events_url, encode(from), encode(to));
+        }
+        let events = serde_json::to_string_pretty(&alert.events).unwrap_or_default();
+
+        let mut variables = HashMap::from([
+            ("id", alert.id.to_string()),
+            ("rule", alert.rule.clone()),
+            ("kind", alert.kind.to_string()),
+            ("severity", alert.severity.clone()),
+            ("host", alert.host.clone()),
+            ("key", alert.key.clone()),
+            ("time", alert.time.clone()),
+            ("message", alert.message.clone()),
+            ("events_json", events),
+            ("events_url", events_url),
+            (
+                "node_url",
+                format!(
+                    "{}/node.html?host={}",
+                    self.dashboard_url,
+                    encode(&alert.host)
+                ),
+            ),
+            ("dashboard_url", self.dashboard_url.clone()),
+        ]);
+        // Fields of the latest triggering event
+        if let Some(event) = alert.events.last() {
+            variables.extend([
+                ("category", event.category.clone()),
+                ("event_type", event.event_type.clone()),
+                ("source", event.source.clone()),
+                ("host_ipv4", event.host_ipv4.clone()),
+                ("os", event.os.clone()),
+                ("event_message", event.message.clone()),
+                (
+                    "event_json",
+                    serde_json::to_string(event).unwrap_or_default(),
+                ),
+            ]);
+        }
+        variables
+    }
+}
+
+// Why a request failed and whether trying again could help
+struct Failure {
+    message: String,
+    retry: bool,
+    // A 429 or 503's Retry-After
+    retry_after: Option<Duration>,
+}
+
+// Chat webhook URLs are their own secret, so logs only name the host
+fn host_of(endpoint: &str) -> String {
+    reqwest::Url::parse(endpoint)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| endpoint.to_string())
+}
+
+async fn open_issue(
+    client: &reqwest::Client,
+    notifier: &Notifier,
+    endpoint: &str,
+    payload: &str,
+) -> Result<String, Failure> {
+    let mut request = client
+        .request(notifier.method.clone(), endpoint)
+        .header(reqwest::header::ACCEPT, "application/json")
+        // GitHub rejects requests without one
+        .header(reqwest::header::USER_AGENT, "loglumen-server")
+        .header(reqwest::header::CONTENT_TYPE, &notifier.content_type);
+    if let Some(auth) = &notifier.auth {
+        request = request.header(reqwest::header::AUTHORIZATION, auth);
+    }
+    let response = request
+        .headers(notifier.headers.clone())
+        .body(payload.to_string())
+        .send()
+        .await
+        .map_err(|err| Failure {
+            message: err.to_string(),
+            retry: true,
+            retry_after: None,
+        })?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+    // Slack answers a plain "ok"
+    let text = response.text().await.unwrap_or_default();
+    let body: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+    if !status.is_success() {
+        use reqwest::StatusCode;
+        return Err(Failure {
+            message: format!("{} returned {}: {}", host_of(endpoint), status, text.trim()),
+            // Other client errors come back the same however often they
+            // are sent
+            retry: status.is_server_error()
+                || matches!(
+                    status,
+                    StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+                ),
+            retry_after,
+        });
+    }
+    // Jira answers with the issue key, GitHub with its page, ServiceNow
+    // with the record number and Matrix with the event ID
+    Ok(body["key"]
+        .as_str()
+        .or_else(|| body["html_url"].as_str())
+        .or_else(|| body["result"]["number"].as_str())
+        .or_else(|| body["event_id"].as_str())
+        .map_or_else(|| format!("a request ({})", status), str::to_string))
+}
+
+// One attempt: what was opened, or who was emailed
+async fn send(
+    client: &reqwest::Client,
+    notifier: &Notifier,
+    alert: &Alert,
+    endpoint: &str,
+    payload: &str,
+) -> Result<String, Failure> {
+    let Some(email) = &notifier.email else {
+        return open_issue(client, notifier, endpoint, payload)
+            .await
+            .map(|reference| format!("opened {}", reference));
+    };
+    let recipients = email.recipients(&alert.rule);
+    email
+        .mailer
+        .send(email.sender(), recipients, payload)
+        .await
+        .map(|()| format!("emailed {}", recipients.join(", ")))
+        .map_err(|err| Failure {
+            message: err.message,
+            retry: !err.permanent,
+            retry_after: None,
+        })
+}
+
+// Retry with a doubling backoff, waiting at least as long as the
+// receiver's Retry-After asks
+async fn deliver(
+    client: &reqwest::Client,
+    notifier: &Notifier,
+    alert: &Alert,
+    endpoint: &str,
+    payload: &str,
+) {
+    let mut attempt = 1;
+    loop {
+        match send(client, notifier, alert, endpoint, payload).await {
+            Ok(outcome) => {
+                log_line!(
+                    "INFO",
+                    "Notifier '{}' {} for alert {}",
+                    notifier.name,
+                    outcome,
+                    alert.id
+                );
+                return;
+            }
+            Err(failure) if failure.retry && attempt < notifier.attempts => {
+                let wait = failure
+                    .retry_after
+                    .map_or(notifier.backoff(attempt), |asked| {
+                        asked.max(notifier.backoff(attempt))
+                    })
+                    .min(MAX_BACKOFF);
+                log_line!(
+                    "WARN",
+                    "Notifier '{}' failed (attempt {}, retrying in {}s): {}",
+                    notifier.name,
+                    attempt,
+                    wait.as_secs(),
+                    failure.message
+                );
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            Err(failure) => {
+                log_line!(
+                    "ERROR",
+                    "Notifier '{}' gave up on alert {}: {}",
+                    notifier.name,
+                    alert.id,
+                    failure.message
+                );
+                return;
+            }
+        }
+    }
+}
+
+// Start the loop that turns queued alerts into issues
+pub fn spawn_worker(state: &web::Data<AppState>) {
+    let Some(mut receiver) = state.rules.notifiers.receiver.lock().take() else {
+        return;
+    };
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log_line!("ERROR", "Notifiers disabled: {}", err);
+            return;
+        }
+    };
+    let state = state.clone();
+    // Each URL is delivered to on its own, so one that is down and backing
+    // off doesn't hold up the rest
+    let in_flight = std::sync::Arc::new(tokio::sync::Semaphore::new(MAX_IN_FLIGHT));
+    actix_web::rt::spawn(async move {
+        while let Some(alert) = receiver.recv().await {
+            let alert = std::sync::Arc::new(alert);
+            let notifiers = &state.rules.notifiers;
+            let variables = notifiers.variables(&alert);
+            for (index, notifier) in notifiers
+                .notifiers
+                .iter()
+                .enumerate()
+                .filter(|(_, notifier)| notifier.wants(&alert))
+            {
+                let payload = match notifier.payload(&variables) {
+                    Ok(payload) => payload,
+                    Err(err) => {
+                        log_line!(
+                            "ERROR",
+                            "Notifier '{}' skipped alert {}: {}",
+                            notifier.name,
+                            alert.id,
+                            err
+                        );
+                        continue;
+                    }
+                };
+                for endpoint in notifier.endpoints(&alert) {
+                    let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                        return;
+                    };
+                    let (client, state, payload) = (client.clone(), state.clone(), payload.clone());
+                    let alert = alert.clone();
+                    actix_web::rt::spawn(async move {
+                        let notifier = &state.rules.notifiers.notifiers[index];
+                        deliver(&client, notifier, &alert, &endpoint, &payload).await;
+                        drop(permit);
+                    });
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,605 @@
+use crate::{severity, AppState, Event};
+use actix_web::http::{header, StatusCode};
+use actix_web::{dev, web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+// Largest export body accepted, after decompression
+const MAX_BODY: usize = 32 * 1024 * 1024;
+// AnyValue arrays and maps nested deeper than this are rejected
+const MAX_DEPTH: usize = 32;
+const PROTOBUF: &str = "application/x-protobuf";
+
+// `[otlp]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OtlpConfig {
+    category: String,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            category: "logs".to_string(),
+        }
+    }
+}
+
+pub struct Otlp {
+    config: OtlpConfig,
+}
+
+impl Otlp {
+    pub fn new(config: OtlpConfig) -> Self {
+        Otlp { config }
+    }
+}
+
+// What a LogRecord carries, from either encoding
+#[derive(Default)]
+struct Record {
+    time: u64,
+    observed_time: u64,
+    severity_number: u64,
+    severity_text: String,
+    body: Option<Value>,
+    attributes: Map<String, Value>,
+    trace_id: String,
+    span_id: String,
+    event_name: String,
+}
+
+#[derive(Default)]
+struct Scope {
+    name: String,
+    version: String,
+}
+
+// Protobuf wire format, just enough of it for the logs export request
+mod wire {
+    pub enum Field<'a> {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(&'a [u8]),
+        // Only LogRecord flags use it, and they are skipped
+        Fixed32,
+    }
+
+    pub struct Reader<'a> {
+        buffer: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        pub fn new(buffer: &'a [u8]) -> Self {
+            Reader { buffer }
+        }
+
+        fn varint(&mut self) -> Result<u64, String> {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                let (&byte, rest) = self.buffer.split_first().ok_or("truncated varint")?;
+                self.buffer = rest;
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err("varint too long".to_string())
+        }
+
+        fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+            if length > self.buffer.len() {
+                return Err("truncated field".to_string());
+            }
+            let (taken, rest) = self.buffer.split_at(length);
+            self.buffer = rest;
+            Ok(taken)
+        }
+
+        // The next field number and value, or None at the end
+        pub fn next(&mut self) -> Result<Option<(u64, Field<'a>)>, String> {
+            if self.buffer.is_empty() {
+                return Ok(None);
+            }
+            let key = self.varint()?;
+            let field = match key & 7 {
+                0 => Field::Varint(self.varint()?),
+                1 => Field::Fixed64(u64::from_le_bytes(
+                    self.take(8)?.try_into().unwrap_or_default(),
+                )),
+                2 => {
+                    let length = self.varint()? as usize;
+                    Field::Bytes(self.take(length)?)
+                }
+                5 => {
+                    self.take(4)?;
+                    Field::Fixed32
+                }
+                other => return Err(format!("unsupported wire type {}", other)),
+            };
+            Ok(Some((key >> 3, field)))
+        }
+    }
+
+    pub fn string(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    // google.rpc.Status { code = 1; message = 2 }, for error replies
+    pub fn status(code: u8, message: &str) -> Vec<u8> {
+        let mut out = vec![0x08, code, 0x12];
+        let mut length = message.len();
+        while length >= 0x80 {
+            out.push((length as u8 & 0x7f) | 0x80);
+            length >>= 7;
+        }
+        out.push(length as u8);
+        out.extend_from_slice(message.as_bytes());
+        out
+    }
+}
+
+mod protobuf {
+    use super::wire::{self, Field, Reader};
+    use super::{Record, Scope, MAX_DEPTH};
+    use base64::Engine;
+    use serde_json::{Map, Value};
+
+    // AnyValue { string = 1; bool = 2; int = 3; double = 4; array = 5;
+    // kvlist = 6; bytes = 7 }
+    fn any_value(bytes: &[u8], depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("attributes nested too deeply".to_string());
+        }
+        let mut reader = Reader::new(bytes);
+        let mut value = Value::Null;
+        while let Some((number, field)) = reader.next()? {
+            value = match (number, field) {
+                (1, Field::Bytes(text)) => Value::String(wire::string(text)),
+                (2, Field::Varint(flag)) => Value::Bool(flag != 0),
+                (3, Field::Varint(int)) => Value::from(int as i64),
+                (4, Field::Fixed64(bits)) => Value::from(f64::from_bits(bits)),
+                (5, Field::Bytes(array)) => {
+                    let mut values = Vec::new();
+                    let mut reader = Reader::new(array);
+                    while let Some((number, field)) = reader.next()? {
+                        if let (1, Field::Bytes(item)) = (number, field) {
+                            values.push(any_value(item, depth + 1)?);
+                        }
+                    }
+                    Value::Array(values)
+                }
+                (6, Field::Bytes(list)) => Value::Object(key_values(list, 1, depth + 1)?),
+                (7, Field::Bytes(raw)) => {
+                    Value::String(base64::engine::general_purpose::STANDARD.encode(raw))
+                }
+                _ => continue,
+            };
+        }
+        Ok(value)
+    }
+
+    // Repeated KeyValue { key = 1; value = 2 } at field `repeated` of a
+    // message
+    fn key_values(bytes: &[u8], repeated: u64, depth: usize) -> Result<Map<String, Value>, String> {
+        let mut map = Map::new();
+        let mut reader = Reader::new(bytes);
+        while let Some((number, field)) = reader.next()? {
+            let (true, Field::Bytes(pair)) = (number == repeated, field) else {
+                continue;
+            };
+            let (mut key, mut value) = (String::new(), Value::Null);
+            let mut reader = Reader::new(pair);
+            while let Some((number, field)) = reader.next()? {
+                match (number, field) {
+                    (1, Field::Bytes(text)) => key = wire::string(text),
+                    (2, Field::Bytes(any)) => value = any_value(any, depth)?,
+                    _ => {}
+                }
+            }
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn scope(bytes: &[u8]) -> Result<Scope, String> {
+        let mut scope = Scope::default();
+        let mut reader = Reader::new(bytes);
+        while let Some((number, field)) = reader.next()? {
+            match (number, field) {
+                (1, Field::Bytes(text)) => scope.name = wire::string(text),
+                (2, Field::Bytes(text)) => scope.version = wire::string(text),
+                _ => {}
+            }
+        }
+        Ok(scope)
+    }
+
+    fn record(bytes: &[u8]) -> Result<Record, String> {
+        let mut record = Record::default();
+        let mut reader = Reader::new(bytes);
+        while let Some((number, field)) = reader.next()? {
+            match (number, field) {
+                (1, Field::Fixed64(nanos)) => record.time = nanos,
+                (11, Field::Fixed64(nanos)) => record.observed_time = nanos,
+                (2, Field::Varint(level)) => record.severity_number = level,
+                (3, Field::Bytes(text)) => record.severity_text = wire::string(text),
+                (5, Field::Bytes(any)) => record.body = Some(any_value(any, 0)?),
+                (9, Field::Bytes(id)) => record.trace_id = hex(id),
+                (10, Field::Bytes(id)) => record.span_id = hex(id),
+                (12, Field::Bytes(text)) => record.event_name = wire::string(text),
+                _ => {}
+            }
+        }
+        record.attributes = key_values(bytes, 6, 0)?;
+        Ok(record)
+    }
+
+    // ExportLogsServiceRequest { repeated ResourceLogs = 1 }
+    // ResourceLogs { Resource = 1 { attributes = 1 }; repeated ScopeLogs = 2 }
+    // ScopeLogs { InstrumentationScope = 1; repeated LogRecord = 2 }
+    pub fn decode(
+        body: &[u8],
+        mut emit: impl FnMut(&Map<String, Value>, &Scope, Record),
+    ) -> Result<(), String> {
+        let mut request = Reader::new(body);
+        while let Some((number, field)) = request.next()? {
+            let (1, Field::Bytes(resource_logs)) = (number, field) else {
+                continue;
+            };
+            let mut resource = Map::new();
+            let mut scopes = Vec::new();
+            let mut reader = Reader::new(resource_logs);
+            while let Some((number, field)) = reader.next()? {
+                match (number, field) {
+                    (1, Field::Bytes(bytes)) => resource = key_values(bytes, 1, 0)?,
+                    (2, Field::Bytes(bytes)) => scopes.push(bytes),
+                    _ => {}
+                }
+            }
+            for scope_logs in scopes {
+                let mut current = Scope::default();
+                let mut records = Vec::new();
+                let mut reader = Reader::new(scope_logs);
+                while let Some((number, field)) = reader.next()? {
+                    match (number, field) {
+                        (1, Field::Bytes(bytes)) => current = scope(bytes)?,
+                        (2, Field::Bytes(bytes)) => records.push(record(bytes)?),
+                        _ => {}
+                    }
+                }
+                for record in records {
+                    emit(&resource, &current, record);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The JSON encoding: lowerCamelCase names, 64-bit integers as strings,
+// trace and span IDs in hex
+mod otlp_json {
+    use super::{Record, Scope, MAX_DEPTH};
+    use serde_json::{Map, Value};
+
+    fn integer(value: Option<&Value>) -> u64 {
+        match value {
+            Some(Value::String(text)) => text.parse().unwrap_or_default(),
+            Some(Value::Number(number)) => number.as_u64().unwrap_or_default(),
+            _ => 0,
+        }
+    }
+
+    fn text(object: &Value, key: &str) -> String {
+        object
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn items<'a>(object: &'a Value, key: &str) -> &'a [Value] {
+        object
+            .get(key)
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn any_value(value: &Value, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("attributes nested too deeply".to_string());
+        }
+        let Some(object) = value.as_object() else {
+            return Ok(Value::Null);
+        };
+        for (key, inner) in object {
+            return Ok(match key.as_str() {
+                "stringValue" | "boolValue" | "bytesValue" => inner.clone(),
+                "intValue" => match inner {
+                    Value::String(text) => {
+                        text.parse::<i64>().map(Value::from).unwrap_or(Value::Null)
+                    }
+                    other => other.clone(),
+                },
+                "doubleValue" => inner.clone(),
+                "arrayValue" => Value::Array(
+                    items(inner, "values")
+                        .iter()
+                        .map(|item| any_value(item, depth + 1))
+                        .collect::<Result<_, _>>()?,
+                ),
+                "kvlistValue" => Value::Object(key_values(items(inner, "values"), depth + 1)?),
+                _ => continue,
+            });
+        }
+        Ok(Value::Null)
+    }
+
+    fn key_values(pairs: &[Value], depth: usize) -> Result<Map<String, Value>, String> {
+        let mut map = Map::new();
+        for pair in pairs {
+            let value = match pair.get("value") {
+                Some(value) => any_value(value, depth)?,
+                None => Value::Null,
+            };
+            map.insert(text(pair, "key"), value);
+        }
+        Ok(map)
+    }
+
+    fn record(value: &Value) -> Result<Record, String> {
+        Ok(Record {
+            time: integer(value.get("timeUnixNano")),
+            observed_time: integer(value.get("observedTimeUnixNano")),
+            severity_number: integer(value.get("severityNumber")),
+            severity_text: text(value, "severityText"),
+            body: match value.get("body") {
+                Some(body) => Some(any_value(body, 0)?),
+                None => None,
+            },
+            attributes: key_values(items(value, "attributes"), 0)?,
+            trace_id: text(value, "traceId").to_ascii_lowercase(),
+            span_id: text(value, "spanId").to_ascii_lowercase(),
+            event_name: text(value, "eventName"),
+        })
+    }
+
+    pub fn decode(
+        body: &[u8],
+        mut emit: impl FnMut(&Map<String, Value>, &Scope, Record),
+    ) -> Result<(), String> {
+        let request: Value =
+            serde_json::from_slice(body).map_err(|err| format!("invalid JSON: {}", err))?;
+        if !request.is_object() {
+            return Err("expected an ExportLogsServiceRequest object".to_string());
+        }
+        for resource_logs in items(&request, "resourceLogs") {
+            let resource = match resource_logs.get("resource") {
+                Some(resource) => key_values(items(resource, "attributes"), 0)?,
+                None => Map::new(),
+            };
+            for scope_logs in items(resource_logs, "scopeLogs") {
+                let scope = scope_logs
+                    .get("scope")
+                    .map_or_else(Scope::default, |scope| Scope {
+                        name: text(scope, "name"),
+                        version: text(scope, "version"),
+                    });
+                for log_record in items(scope_logs, "logRecords") {
+                    emit(&resource, &scope, record(log_record)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// SeverityNumber ranges: 1-4 TRACE, 5-8 DEBUG, 9-12 INFO, 13-16 WARN,
+// 17-20 ERROR, 21-24 FATAL. Unset falls back to the text.
+fn severity(record: &Record) -> &'static str {
+    match record.severity_number {
+        1..=8 => "debug",
+        9..=12 => "info",
+        13..=16 => "warning",
+        17..=20 => "error",
+        21.. => "critical",
+        0 if record.severity_text.is_empty() => "info",
+        0 => severity::normalize(&record.severity_text),
+    }
+}
+
+fn to_event(
+    category: &str,
+    resource: &Map<String, Value>,
+    scope: &Scope,
+    record: Record,
+    peer: &str,
+) -> Event {
+    let resource_text = |key: &str| {
+        resource
+            .get(key)
+            .and_then(Value::as_str)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let host = resource_text("host.name").unwrap_or_else(|| peer.to_string());
+    // `host.ip` is a list of addresses in the semantic conventions
+    let host_ipv4 = match resource.get("host.ip") {
+        Some(Value::Array(addresses)) => addresses
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|address| address.parse::<std::net::Ipv4Addr>().is_ok())
+            .map(str::to_string),
+        Some(Value::String(address)) => Some(address.clone()),
+        _ => None,
+    }
+    .unwrap_or_else(|| peer.to_string());
+    let event_type = Some(record.event_name.clone())
+        .filter(|name| !name.is_empty())
+        .or_else(|| {
+            record
+                .attributes
+                .get("event.name")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .or_else(|| resource_text("service.name"))
+        .unwrap_or_else(|| "otlp".to_string());
+    let nanos = if record.time != 0 {
+        record.time
+    } else {
+        record.observed_time
+    };
+    let time = if nanos == 0 {
+        Utc::now()
+    } else {
+        DateTime::from_timestamp_nanos(nanos.min(i64::MAX as u64) as i64)
+    };
+    let severity = severity(&record).to_string();
+
+    let mut data = Map::new();
+    let message = match record.body {
+        Some(Value::String(text)) => text,
+        Some(Value::Null) | None => String::new(),
+        Some(body) => {
+            let message = ["message", "msg"]
+                .iter()
+                .find_map(|key| body.get(key).and_then(Value::as_str))
+                .map(str::to_string)
+                .unwrap_or_else(|| body.to_string());
+            data.insert("body".to_string(), body);
+            message
+        }
+    };
+    if !record.attributes.is_empty() {
+        data.insert("attributes".to_string(), Value::Object(record.attributes));
+    }
+    if !resource.is_empty() {
+        data.insert("resource".to_string(), Value::Object(resource.clone()));
+    }
+    for (key, value) in [
+        ("scope", &scope.name),
+        ("scope_version", &scope.version),
+        ("severity_text", &record.severity_text),
+        ("trace_id", &record.trace_id),
+        ("span_id", &record.span_id),
+    ] {
+        if !value.is_empty() {
+            data.insert(key.to_string(), Value::String(value.clone()));
+        }
+    }
+
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type,
+        time: time.to_rfc3339(),
+        host,
+        host_ipv4,
+        os: resource_text("os.type").unwrap_or_default(),
+        source: "otlp".to_string(),
+        severity,
+        message,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// Errors are a google.rpc.Status in the request's encoding, as OTLP
+// exporters expect
+fn error(protobuf: bool, status: StatusCode, code: u8, message: &str) -> HttpResponse {
+    if protobuf {
+        HttpResponse::build(status)
+            .content_type(PROTOBUF)
+            .body(wire::status(code, message))
+    } else {
+        HttpResponse::build(status).json(json!({"code": code, "message": message}))
+    }
+}
+
+// POST /v1/logs - OTLP/HTTP logs export, in protobuf (what exporters send
+// by default) or JSON. Resource attributes fill in the host and OS; the
+// record's attributes, resource and scope are kept in `data`.
+pub async fn logs(
+    request: HttpRequest,
+    payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let protobuf = content_type.starts_with(PROTOBUF);
+    if !protobuf && !content_type.starts_with("application/json") {
+        return Ok(error(
+            false,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            3,
+            "expected application/x-protobuf or application/json",
+        ));
+    }
+
+    let mut payload = dev::Decompress::from_headers(payload.into_inner(), request.headers());
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY {
+            return Ok(error(
+                protobuf,
+                StatusCode::PAYLOAD_TOO_LARGE,
+                8,
+                "request too large",
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let identity = crate::tls::client_identity(&request);
+    let category = &data.otlp.config.category;
+    let mut batch = Vec::new();
+    let emit = |resource: &Map<String, Value>, scope: &Scope, record: Record| {
+        let mut event = to_event(category, resource, scope, record, &peer);
+        event.identity = identity.clone();
+        batch.push(event);
+    };
+    let decoded = if protobuf {
+        protobuf::decode(&body, emit)
+    } else {
+        otlp_json::decode(&body, emit)
+    };
+    if let Err(err) = decoded {
+        return Ok(error(protobuf, StatusCode::BAD_REQUEST, 3, &err));
+    }
+
+    if !batch.is_empty() {
+        data.api_keys.count_events(&request, batch.len());
+        data.availability.observe(&batch);
+        crate::ingest(&data, batch).await;
+    }
+    // An empty ExportLogsServiceResponse
+    Ok(if protobuf {
+        HttpResponse::Ok().content_type(PROTOBUF).finish()
+    } else {
+        HttpResponse::Ok().json(json!({}))
+    })
+}
@@ -0,0 +1,606 @@
+use crate::rules::EventMatcher;
+use crate::storage::StoreQuery;
+use crate::{AppState, Event, TimeRange};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// Batches queued per output before new ones are dropped
+const QUEUE_CAPACITY: usize = 64;
+const SEND_ATTEMPTS: u32 = 3;
+
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+// A downstream destination for stored events
+pub trait Sink: Send + Sync {
+    fn send<'a>(&'a self, events: &'a [Event]) -> SendFuture<'a>;
+}
+
+// One `[[outputs]]` entry
+#[derive(Debug, Clone, Deserialize)]
+struct OutputConfig {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    // Only events matching this are forwarded live (replay uses its own filter)
+    #[serde(default, rename = "match")]
+    filter: EventMatcher,
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    // webhook
+    url: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    // syslog
+    address: Option<String>,
+    #[serde(default)]
+    tls: bool,
+    // PEM CA certificates for the receiver, instead of the public roots
+    ca_cert: Option<String>,
+    #[serde(default = "default_facility")]
+    facility: String,
+    // Per-category facility, overriding `facility`
+    #[serde(default)]
+    facilities: HashMap<String, String>,
+    #[serde(default = "default_app_name")]
+    app_name: String,
+    // "octet-counting" (RFC 6587, required over TLS) or "newline"
+    #[serde(default = "default_framing")]
+    framing: String,
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_facility() -> String {
+    "local0".to_string()
+}
+
+fn default_app_name() -> String {
+    "loglumen".to_string()
+}
+
+fn default_framing() -> String {
+    "octet-counting".to_string()
+}
+
+struct Output {
+    name: String,
+    filter: EventMatcher,
+    batch_size: usize,
+    sink: Box<dyn Sink>,
+    queue: mpsc::Sender<Vec<Event>>,
+    // Batches queued or being delivered, for `flush`
+    queued: AtomicUsize,
+}
+
+pub struct Outputs {
+    outputs: Vec<Arc<Output>>,
+    receivers: parking_lot::Mutex<Vec<mpsc::Receiver<Vec<Event>>>>,
+}
+
+impl Outputs {
+    pub fn new(configs: Vec<serde_json::Value>) -> Self {
+        let mut outputs = Vec::new();
+        let mut receivers = Vec::new();
+
+        for raw in configs {
+            let config: OutputConfig = match serde_json::from_value(raw) {
+                Ok(config) => config,
+                Err(err) => {
+                    log_line!("WARN", "Skipping invalid [[outputs]] entry: {}", err);
+                    continue;
+                }
+            };
+            let sink = match build_sink(&config) {
+                Ok(sink) => sink,
+                Err(err) => {
+                    log_line!("WARN", "Skipping output '{}': {}", config.name, err);
+                    continue;
+                }
+            };
+
+            log_line!("CONFIG", "Output '{}' ({})", config.name, config.kind);
+            let (queue, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            outputs.push(Arc::new(Output {
+                name: config.name,
+                filter: config.filter,
+                batch_size: config.batch_size.max(1),
+                sink,
+                queue,
+                queued: AtomicUsize::new(0),
+            }));
+            receivers.push(receiver);
+        }
+
+        Outputs {
+            outputs,
+            receivers: parking_lot::Mutex::new(receivers),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    // Queue a freshly ingested batch for every output whose filter matches.
+    // Never blocks ingestion: a full queue drops the batch for that output.
+    pub fn publish(&self, batch: &[Event]) {
+        for output in &self.outputs {
+            let selected: Vec<Event> = batch
+                .iter()
+                .filter(|event| output.filter.matches(event))
+                .cloned()
+                .collect();
+            if selected.is_empty() {
+                continue;
+            }
+            match output.queue.try_send(selected) {
+                Ok(()) => {
+                    output.queued.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_) => log_line!(
+                    "WARN",
+                    "Output '{}' is falling behind; dropped a batch",
+                    output.name
+                ),
+            }
+        }
+    }
+
+    // Wait for every queued batch to be delivered (or given up on), up to
+    // `timeout`. Returns how many batches were still queued.
+    pub async fn flush(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let queued: usize = self
+                .outputs
+                .iter()
+                .map(|output| output.queued.load(Ordering::Relaxed))
+                .sum();
+            if queued == 0 || tokio::time::Instant::now() >= deadline {
+                return queued;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Arc<Output>> {
+        self.outputs.iter().find(|output| output.name == name)
+    }
+}
+
+// Whether an `[[outputs]]` entry would be accepted, for `check-config`
+pub fn check(raw: &serde_json::Value) -> Result<(), String> {
+    let config: OutputConfig =
+        serde_json::from_value(raw.clone()).map_err(|err| err.to_string())?;
+    build_sink(&config).map(|_| ())
+}
+
+fn build_sink(config: &OutputConfig) -> Result<Box<dyn Sink>, String> {
+    match config.kind.as_str() {
+        "webhook" => {
+            let url = config
+                .url
+                .clone()
+                .ok_or_else(|| "webhook output needs a `url`".to_string())?;
+            Ok(Box::new(WebhookSink::new(url, &config.headers)?))
+        }
+        "syslog" => Ok(Box::new(SyslogSink::new(config)?)),
+        other => Err(format!("unsupported output type '{}'", other)),
+    }
+}
+
+// Retry a few times with a short backoff; downstream hiccups are common
+async fn deliver(output: &Output, events: &[Event]) {
+    for chunk in events.chunks(output.batch_size) {
+        let mut attempt = 1;
+        loop {
+            match output.sink.send(chunk).await {
+                Ok(()) => break,
+                Err(err) if attempt < SEND_ATTEMPTS => {
+                    log_line!(
+                        "WARN",
+                        "Output '{}' send failed (attempt {}): {}",
+                        output.name,
+                        attempt,
+                        err
+                    );
+                    tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    log_line!(
+                        "ERROR",
+                        "Output '{}' dropped {} events: {}",
+                        output.name,
+                        chunk.len(),
+                        err
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Start one forwarding loop per output. Queued batches are merged up to
+// batch_size before sending.
+pub fn spawn_workers(state: &web::Data<AppState>) {
+    let receivers = std::mem::take(&mut *state.outputs.receivers.lock());
+    for (output, mut receiver) in state.outputs.outputs.iter().cloned().zip(receivers) {
+        actix_web::rt::spawn(async move {
+            while let Some(mut pending) = receiver.recv().await {
+                let mut batches = 1;
+                while pending.len() < output.batch_size {
+                    match receiver.try_recv() {
+                        Ok(more) => {
+                            pending.extend(more);
+                            batches += 1;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                deliver(&output, &pending).await;
+                output.queued.fetch_sub(batches, Ordering::Relaxed);
+            }
+        });
+    }
+}
+
+// POSTs each batch as a JSON array
+struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    headers: reqwest::header::HeaderMap,
+}
+
+impl WebhookSink {
+    fn new(url: String, headers: &HashMap<String, String>) -> Result<Self, String> {
+        let mut header_map = reqwest::header::HeaderMap::new();
+        for (name, value) in headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|err| format!("invalid header name '{}': {}", name, err))?;
+            let value = reqwest::header::HeaderValue::from_str(value)
+                .map_err(|err| format!("invalid header value for '{}': {}", name, err))?;
+            header_map.insert(name, value);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(WebhookSink {
+            client,
+            url,
+            headers: header_map,
+        })
+    }
+}
+
+impl Sink for WebhookSink {
+    fn send<'a>(&'a self, events: &'a [Event]) -> SendFuture<'a> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .headers(self.headers.clone())
+                .json(events)
+                .send()
+                .await
+                .map_err(|err| err.to_string())?;
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(format!("{} returned {}", self.url, response.status()))
+            }
+        })
+    }
+}
+
+fn facility_code(name: &str) -> Result<u8, String> {
+    crate::severity::FACILITIES
+        .iter()
+        .position(|facility| facility.eq_ignore_ascii_case(name))
+        .map(|code| code as u8)
+        .ok_or_else(|| format!("unknown syslog facility '{}'", name))
+}
+
+type SyslogStream = Box<dyn tokio::io::AsyncWrite + Unpin + Send + Sync>;
+
+// Writes RFC 5424 messages over TCP or TLS to a syslog receiver. The
+// connection is kept open between batches and reopened after a failure.
+struct SyslogSink {
+    address: String,
+    tls: Option<(
+        tokio_rustls::TlsConnector,
+        rustls::pki_types::ServerName<'static>,
+    )>,
+    facility: u8,
+    facilities: HashMap<String, u8>,
+    app_name: String,
+    octet_counting: bool,
+    connection: tokio::sync::Mutex<Option<SyslogStream>>,
+}
+
+impl SyslogSink {
+    fn new(config: &OutputConfig) -> Result<Self, String> {
+        let address = config
+            .address
+            .clone()
+            .ok_or_else(|| "syslog output needs an `address` (host:port)".to_string())?;
+        let octet_counting = match config.framing.as_str() {
+            "octet-counting" => true,
+            "newline" => false,
+            other => return Err(format!("unknown syslog framing '{}'", other)),
+        };
+        let mut facilities = HashMap::new();
+        for (category, name) in &config.facilities {
+            facilities.insert(category.to_ascii_lowercase(), facility_code(name)?);
+        }
+        let tls = if config.tls {
+            let host = address
+                .rsplit_once(':')
+                .map_or(address.as_str(), |(host, _)| host)
+                .trim_matches(['[', ']']);
+            let name = rustls::pki_types::ServerName::try_from(host.to_string())
+                .map_err(|err| format!("invalid TLS server name '{}': {}", host, err))?;
+            Some((
+                tokio_rustls::TlsConnector::from(Arc::new(tls_client(config.ca_cert.as_deref())?)),
+                name,
+            ))
+        } else {
+            None
+        };
+        Ok(SyslogSink {
+            address,
+            tls,
+            facility: facility_code(&config.facility)?,
+            facilities,
+            app_name: printable(&config.app_name, 48),
+            octet_counting,
+            connection: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn connect(&self) -> Result<SyslogStream, String> {
+        let tcp = tokio::net::TcpStream::connect(&self.address)
+            .await
+            .map_err(|err| format!("cannot connect to {}: {}", self.address, err))?;
+        Ok(match &self.tls {
+            Some((connector, name)) => {
+                Box::new(connector.connect(name.clone(), tcp).await.map_err(|err| {
+                    format!("TLS handshake with {} failed: {}", self.address, err)
+                })?)
+            }
+            None => Box::new(tcp),
+        })
+    }
+
+    fn format(&self, event: &Event) -> String {
+        let facility = self
+            .facilities
+            .get(&event.category.to_ascii_lowercase())
+            .copied()
+            .unwrap_or(self.facility);
+        let priority =
+            u32::from(facility) * 8 + u32::from(crate::severity::to_syslog(&event.severity));
+        let timestamp = chrono::DateTime::parse_from_rfc3339(&event.time)
+            .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Micros, true))
+            .unwrap_or_else(|_| "-".to_string());
+        // 32473 is the private enterprise number set aside for examples
+        let mut structured = String::from("[loglumen@32473");
+        for (name, value) in [
+            ("category", &event.category),
+            ("event_type", &event.event_type),
+            ("severity", &event.severity),
+            ("source", &event.source),
+            ("host_ipv4", &event.host_ipv4),
+            ("os", &event.os),
+        ] {
+            if !value.is_empty() {
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace(']', "\\]");
+                structured.push_str(&format!(" {}=\"{}\"", name, value));
+            }
+        }
+        structured.push(']');
+        format!(
+            "<{}>1 {} {} {} - {} {} {}",
+            priority,
+            timestamp,
+            printable(&event.host, 255),
+            self.app_name,
+            printable(&event.event_type, 32),
+            structured,
+            event.message.replace(['\r', '\n'], " ")
+        )
+    }
+}
+
+// PEM CAs from `ca_cert`, or the bundled public roots
+pub fn tls_client(ca_cert: Option<&str>) -> Result<rustls::ClientConfig, String> {
+    use rustls::pki_types::pem::PemObject;
+    let mut roots = rustls::RootCertStore::empty();
+    match ca_cert {
+        Some(path) => {
+            let certs = rustls::pki_types::CertificateDer::pem_file_iter(path)
+                .map_err(|err| format!("{}: {}", path, err))?;
+            for cert in certs {
+                roots
+                    .add(cert.map_err(|err| format!("{}: {}", path, err))?)
+                    .map_err(|err| format!("{}: {}", path, err))?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    Ok(rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|err| err.to_string())?
+    .with_root_certificates(roots)
+    .with_no_client_auth())
+}
+
+// RFC 5424 header fields are printable ASCII without spaces; `-` when empty
+fn printable(value: &str, max: usize) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max)
+        .collect();
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+impl Sink for SyslogSink {
+    fn send<'a>(&'a self, events: &'a [Event]) -> SendFuture<'a> {
+        Box::pin(async move {
+            use tokio::io::AsyncWriteExt;
+            let mut frames = String::new();
+            for event in events {
+                let message = self.format(event);
+                if self.octet_counting {
+                    frames.push_str(&format!("{} {}", message.len(), message));
+                } else {
+                    frames.push_str(&message);
+                    frames.push('\n');
+                }
+            }
+
+            let mut connection = self.connection.lock().await;
+            let mut stream = match connection.take() {
+                Some(stream) => stream,
+                None => tokio::time::timeout(Duration::from_secs(10), self.connect())
+                    .await
+                    .map_err(|_| format!("timed out connecting to {}", self.address))??,
+            };
+            let written = tokio::time::timeout(Duration::from_secs(10), async {
+                stream.write_all(frames.as_bytes()).await?;
+                stream.flush().await
+            })
+            .await;
+            match written {
+                Ok(Ok(())) => {
+                    *connection = Some(stream);
+                    Ok(())
+                }
+                Ok(Err(err)) => Err(format!("write to {} failed: {}", self.address, err)),
+                Err(_) => Err(format!("write to {} timed out", self.address)),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    // Same fields as a rule matcher: category, event_type, severity, source, host
+    #[serde(flatten)]
+    filter: EventMatcher,
+    since: Option<String>,
+    until: Option<String>,
+    // Output names; all outputs when omitted
+    outputs: Option<Vec<String>>,
+    // Events per second
+    #[serde(default = "default_rate")]
+    rate: u32,
+    limit: Option<usize>,
+}
+
+fn default_rate() -> u32 {
+    100
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// POST /api/admin/replay - Re-send stored events to outputs at a fixed rate
+pub async fn replay(
+    http_request: HttpRequest,
+    request: web::Json<ReplayRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&http_request) {
+        return Ok(response);
+    }
+    let request = request.into_inner();
+
+    let range = match TimeRange::parse(request.since.as_deref(), request.until.as_deref()) {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    if request.rate == 0 {
+        return Ok(bad_request("rate must be positive".to_string()));
+    }
+
+    let targets: Vec<Arc<Output>> = match &request.outputs {
+        Some(names) => {
+            let mut targets = Vec::new();
+            for name in names {
+                match data.outputs.get(name) {
+                    Some(output) => targets.push(output.clone()),
+                    None => return Ok(bad_request(format!("no output named '{}'", name))),
+                }
+            }
+            targets
+        }
+        None => data.outputs.outputs.clone(),
+    };
+    if targets.is_empty() {
+        return Ok(bad_request("no outputs are configured".to_string()));
+    }
+
+    let events = data.store.query(&StoreQuery {
+        range,
+        matches: Some(&|event: &Event| request.filter.matches(event)),
+        limit: request.limit,
+        ..Default::default()
+    });
+
+    let names: Vec<String> = targets.iter().map(|output| output.name.clone()).collect();
+    let count = events.len();
+    let rate = request.rate as usize;
+    log_line!(
+        "INFO",
+        "Replaying {} events to {:?} at {}/s",
+        count,
+        names,
+        rate
+    );
+
+    // One chunk per second keeps the pacing simple and batch sizes bounded
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        for chunk in events.chunks(rate) {
+            ticker.tick().await;
+            for output in &targets {
+                deliver(output, chunk).await;
+            }
+        }
+        log_line!("OK", "Replay of {} events finished", count);
+    });
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "status": "accepted",
+        "events": count,
+        "outputs": names,
+        "rate": rate,
+        "estimated_seconds": count.div_ceil(rate),
+    })))
+}
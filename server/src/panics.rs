@@ -0,0 +1,156 @@
+// A panic is caught where it happens instead of taking the process down: a
+// request that panics gets a 500, an ingest stage that panics is skipped
+// for that batch and the batch is still stored. Every panic, wherever it
+// happens, is logged as one line with its thread and location and counted
+// for GET /api/admin/panics.
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use futures_util::FutureExt;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Reports kept for /api/admin/panics
+const MAX_REPORTS: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub id: u64,
+    time: String,
+    thread: String,
+    location: String,
+    message: String,
+}
+
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+static REPORTS: Mutex<VecDeque<Report>> = parking_lot::const_mutex(VecDeque::new());
+
+thread_local! {
+    // Id of the last panic on this thread, for the code that caught it
+    static LAST: Cell<u64> = const { Cell::new(0) };
+}
+
+// Replaces the default hook, which prints an unstructured message to
+// stderr. RUST_BACKTRACE still adds a backtrace to the log line; crash
+// reports (see crash.rs) always have one.
+pub fn install() {
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "(no message)".to_string());
+        let report = Report {
+            id: TOTAL.fetch_add(1, Ordering::AcqRel) + 1,
+            time: chrono::Utc::now().to_rfc3339(),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string(),
+            location: info
+                .location()
+                .map(|location| format!("{}:{}", location.file(), location.line()))
+                .unwrap_or_default(),
+            message,
+        };
+        let backtrace = Backtrace::capture();
+        let logged = backtrace.status() == BacktraceStatus::Captured;
+        log_line!(
+            "ERROR",
+            "Panic #{} in thread '{}' at {}: {}{}",
+            report.id,
+            report.thread,
+            report.location,
+            report.message,
+            if logged {
+                format!("\n{}", backtrace)
+            } else {
+                String::new()
+            }
+        );
+        LAST.with(|last| last.set(report.id));
+        let backtrace = if logged {
+            backtrace
+        } else {
+            Backtrace::force_capture()
+        };
+        crate::crash::write(&report, backtrace.to_string());
+        let mut reports = REPORTS.lock();
+        if reports.len() == MAX_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(report);
+    }));
+}
+
+// Id of the last panic on the calling thread, 0 if there was none
+pub fn last() -> u64 {
+    LAST.with(Cell::get)
+}
+
+// Runs one ingest stage; a panic skips it and is logged with the stage's
+// name, so detection failing doesn't cost the batch
+pub fn guard(stage: &str, events: usize, run: impl FnOnce()) {
+    if catch_unwind(AssertUnwindSafe(run)).is_err() {
+        log_line!(
+            "ERROR",
+            "Ingest stage '{}' panicked (#{}); skipped for a batch of {} events",
+            stage,
+            last(),
+            events
+        );
+    }
+}
+
+// Middleware: a handler (plugin routes included) that panics answers 500
+// instead of dropping the connection
+pub async fn isolate(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    // Not the HttpRequest itself: actix needs it unshared while the
+    // request is being handled
+    let (method, path) = (request.method().clone(), request.path().to_string());
+    match AssertUnwindSafe(next.call(request)).catch_unwind().await {
+        Ok(response) => Ok(response?.map_into_boxed_body()),
+        Err(_) => {
+            let id = last();
+            log_line!(
+                "ERROR",
+                "{} {} panicked (#{}); answered 500",
+                method,
+                path,
+                id
+            );
+            let response = HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": format!("internal error (panic #{}); see the server log", id),
+            }));
+            Err(InternalError::from_response("handler panicked", response).into())
+        }
+    }
+}
+
+// GET /api/admin/panics - How many panics were caught, with the latest
+// reports newest first
+pub async fn get_panics(
+    request: HttpRequest,
+    data: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let reports: Vec<Report> = REPORTS.lock().iter().rev().cloned().collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total": TOTAL.load(Ordering::Acquire),
+        "recent": reports,
+    })))
+}
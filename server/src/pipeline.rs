@@ -0,0 +1,181 @@
+// Batches are stored by writer threads rather than by the request that
+// received them. Handlers that answer an agent await the batch's receipt, so
+// a batch the store refused isn't acknowledged, but the worker thread serves
+// other requests meanwhile. Each host
+// always goes to the same writer, so agents on different writers are
+// logged, evaluated and hashed in parallel while a host's own batches stay
+// in order. The store is split by host the same way, so each writer
+// inserts into its own shard and only waits for readers.
+use crate::backpressure::Reservation;
+use crate::{storage, AppState, Event};
+use actix_web::web;
+use tokio::sync::{mpsc, oneshot};
+
+// Batches queued per writer; events are bounded separately by
+// [limits] max_queued_events
+const QUEUE_CAPACITY: usize = 1024;
+
+struct Queued {
+    batch: Vec<Event>,
+    // Released once the batch is stored
+    reservation: Reservation,
+    // Told how storing went, unless nobody waits for it
+    stored: Option<oneshot::Sender<Result<(), String>>>,
+}
+
+// Resolves once every part of a submitted batch has been stored
+pub struct Receipt(Vec<oneshot::Receiver<Result<(), String>>>);
+
+impl Receipt {
+    // The first error of any part. The other parts are stored regardless.
+    pub async fn stored(self) -> Result<(), String> {
+        let mut result = Ok(());
+        for part in self.0 {
+            let stored = part
+                .await
+                .unwrap_or_else(|_| Err("the ingest writer stopped".to_string()));
+            result = result.and(stored);
+        }
+        result
+    }
+}
+
+pub struct Pipeline {
+    queues: Vec<mpsc::Sender<Queued>>,
+    receivers: parking_lot::Mutex<Vec<mpsc::Receiver<Queued>>>,
+}
+
+impl Pipeline {
+    pub fn new(writers: usize) -> Self {
+        let (queues, receivers) = (0..writers.max(1))
+            .map(|_| mpsc::channel(QUEUE_CAPACITY))
+            .unzip();
+        Pipeline {
+            queues,
+            receivers: parking_lot::Mutex::new(receivers),
+        }
+    }
+
+    // Picked like the store shard, so each writer inserts into its own
+    fn writer_for(&self, host: &str) -> usize {
+        storage::shard_of(host, self.queues.len())
+    }
+
+    // The batch split by writer. Agents send batches of their own events,
+    // so usually there is one part.
+    fn parts(&self, batch: Vec<Event>) -> Vec<(usize, Vec<Event>)> {
+        let Some(first) = batch.first().map(|event| self.writer_for(&event.host)) else {
+            return Vec::new();
+        };
+        if self.queues.len() == 1
+            || batch
+                .iter()
+                .all(|event| self.writer_for(&event.host) == first)
+        {
+            return vec![(first, batch)];
+        }
+        let mut parts: Vec<Vec<Event>> = (0..self.queues.len()).map(|_| Vec::new()).collect();
+        for event in batch {
+            parts[self.writer_for(&event.host)].push(event);
+        }
+        parts
+            .into_iter()
+            .enumerate()
+            .filter(|(_, part)| !part.is_empty())
+            .collect()
+    }
+
+    // Hands the batch to the writers, waiting while a queue is full. The
+    // wait is async, so the worker thread keeps serving other requests, and
+    // a host's batches still reach its writer in order.
+    pub async fn submit(&self, batch: Vec<Event>, mut reservation: Reservation) -> Receipt {
+        let mut receipt = Receipt(Vec::new());
+        for (writer, part) in self.parts(batch) {
+            let share = reservation.split(part.len());
+            let (stored, receiver) = oneshot::channel();
+            receipt.0.push(receiver);
+            // Only fails once the writer is gone, which the receipt reports
+            let _ = self.queues[writer]
+                .send(Queued {
+                    batch: part,
+                    reservation: share,
+                    stored: Some(stored),
+                })
+                .await;
+        }
+        receipt
+    }
+
+    // Same, from a thread that may block, such as a scheduled task
+    pub fn blocking_submit(&self, batch: Vec<Event>, mut reservation: Reservation) {
+        for (writer, part) in self.parts(batch) {
+            let share = reservation.split(part.len());
+            let _ = self.queues[writer].blocking_send(Queued {
+                batch: part,
+                reservation: share,
+                stored: None,
+            });
+        }
+    }
+
+    // Queues the batch only if every writer it goes to has room. Otherwise
+    // nothing is queued and None is returned, so a client can be told to
+    // retry the whole batch without duplicating part of it.
+    pub fn try_submit(&self, batch: Vec<Event>, mut reservation: Reservation) -> Option<Receipt> {
+        let parts = self.parts(batch);
+        let mut permits = Vec::with_capacity(parts.len());
+        for (writer, _) in &parts {
+            permits.push(self.queues[*writer].try_reserve().ok()?);
+        }
+        let mut receipt = Receipt(Vec::with_capacity(parts.len()));
+        for (permit, (_, part)) in permits.into_iter().zip(parts) {
+            let share = reservation.split(part.len());
+            let (stored, receiver) = oneshot::channel();
+            receipt.0.push(receiver);
+            permit.send(Queued {
+                batch: part,
+                reservation: share,
+                stored: Some(stored),
+            });
+        }
+        Some(receipt)
+    }
+}
+
+pub fn spawn_writer(state: &web::Data<AppState>) {
+    let receivers = std::mem::take(&mut *state.pipeline.receivers.lock());
+    for (writer, mut receiver) in receivers.into_iter().enumerate() {
+        let state = state.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("ingest-writer-{}", writer))
+            .spawn(move || {
+                while let Some(Queued {
+                    batch,
+                    reservation,
+                    stored,
+                }) = receiver.blocking_recv()
+                {
+                    // One bad batch mustn't stop everything after it
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        crate::store(&state, batch)
+                    }))
+                    .unwrap_or_else(|_| {
+                        log_line!(
+                            "ERROR",
+                            "Ingest writer failed to store a batch (panic #{})",
+                            crate::panics::last()
+                        );
+                        Err("the ingest writer failed".to_string())
+                    });
+                    drop(reservation);
+                    // Nobody may be waiting any more, e.g. the client left
+                    if let Some(stored) = stored {
+                        let _ = stored.send(result);
+                    }
+                }
+            });
+        if let Err(err) = spawned {
+            log_line!("ERROR", "Cannot start ingest writer {}: {}", writer, err);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::routes::RouteTable;
+use crate::storage::Storage;
+use crate::{AppState, Event};
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Read-only view of the event store handed to plugins. Only shared borrows
+// are exposed, so a plugin can never mutate or hold the write lock.
+pub struct StoreReader {
+    store: Arc<dyn Storage>,
+}
+
+impl StoreReader {
+    // Events oldest first, read from every store shard
+    pub fn with_events<R>(&self, f: impl FnOnce(&mut dyn Iterator<Item = &Event>) -> R) -> R {
+        let store = self.store.read();
+        let mut events = store.iter();
+        f(&mut events)
+    }
+}
+
+pub struct PluginRequest<'a> {
+    pub method: &'a Method,
+    // Path below /api/ext/{plugin}, always starting with '/'
+    pub path: &'a str,
+    pub query: HashMap<String, String>,
+    // Not every plugin reads the raw body; the bundled ones only use the query
+    #[allow(dead_code)]
+    pub body: &'a [u8],
+}
+
+// A compiled-in extension serving routes under /api/ext/{name}
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &'static str;
+    // (method, sub-path) pairs, e.g. (GET, "/report")
+    fn routes(&self) -> Vec<(Method, &'static str)>;
+    fn handle(&self, request: &PluginRequest, store: &StoreReader) -> HttpResponse;
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PluginsConfig {
+    #[serde(default)]
+    enabled: Vec<String>,
+}
+
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl PluginRegistry {
+    pub fn new(config: PluginsConfig) -> Self {
+        let mut plugins: Vec<Box<dyn Plugin>> = Vec::new();
+        for name in &config.enabled {
+            match builtin(name) {
+                Some(plugin) => {
+                    log_line!("CONFIG", "Plugin enabled: {}", name);
+                    plugins.push(plugin);
+                }
+                None => log_line!("WARN", "Unknown plugin '{}' ignored", name),
+            }
+        }
+        PluginRegistry { plugins }
+    }
+
+    // Add every plugin route to the table so conflicts surface at startup.
+    // Plugins only read stored data, so their routes are all audited.
+    pub fn register_routes(&self, table: &mut RouteTable) -> Result<(), String> {
+        for plugin in &self.plugins {
+            for (method, sub_path) in plugin.routes() {
+                let path = format!("/api/ext/{}{}", plugin.name(), sub_path);
+                table.add_query(plugin.name(), method, &path, || web::route().to(dispatch))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn Plugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.name() == name)
+            .map(|plugin| plugin.as_ref())
+    }
+}
+
+fn builtin(name: &str) -> Option<Box<dyn Plugin>> {
+    match name {
+        "compliance" => Some(Box::new(CompliancePlugin)),
+        _ => None,
+    }
+}
+
+// Shared handler for every plugin route; the plugin is picked from the URL
+async fn dispatch(req: HttpRequest, body: web::Bytes, data: web::Data<AppState>) -> HttpResponse {
+    let rest = req.path().strip_prefix("/api/ext/").unwrap_or_default();
+    let (name, path) = match rest.find('/') {
+        Some(split) => rest.split_at(split),
+        None => (rest, "/"),
+    };
+
+    let Some(plugin) = data.plugins.get(name) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+    let request = PluginRequest {
+        method: req.method(),
+        path,
+        query,
+        body: &body,
+    };
+    let store = StoreReader {
+        store: data.store.clone(),
+    };
+
+    plugin.handle(&request, &store)
+}
+
+// Example integration: per-host summary of the events auditors usually ask
+// about (failed logins, privilege use, critical events) over a time window.
+struct CompliancePlugin;
+
+// Longest report window, a leap year
+const MAX_REPORT_HOURS: i64 = 366 * 24;
+
+impl Plugin for CompliancePlugin {
+    fn name(&self) -> &'static str {
+        "compliance"
+    }
+
+    fn routes(&self) -> Vec<(Method, &'static str)> {
+        vec![(Method::GET, "/report")]
+    }
+
+    fn handle(&self, request: &PluginRequest, store: &StoreReader) -> HttpResponse {
+        if request.method != Method::GET || request.path != "/report" {
+            return HttpResponse::NotFound().finish();
+        }
+
+        let window = match request.query.get("hours") {
+            None => chrono::Duration::try_hours(24),
+            Some(hours) => hours
+                .parse::<i64>()
+                .ok()
+                .filter(|hours| (1..=MAX_REPORT_HOURS).contains(hours))
+                .and_then(chrono::Duration::try_hours),
+        };
+        let Some(window) = window else {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": format!("hours must be between 1 and {}", MAX_REPORT_HOURS)
+            }));
+        };
+        let hours = window.num_hours();
+        let cutoff = chrono::Utc::now() - window;
+
+        let mut hosts: HashMap<String, HashMap<&'static str, usize>> = HashMap::new();
+        store.with_events(|events| {
+            for event in events {
+                if event.timestamp().is_some_and(|time| time < cutoff) {
+                    continue;
+                }
+                let counters = hosts.entry(event.host.clone()).or_default();
+                *counters.entry("total").or_insert(0) += 1;
+                if event.event_type.contains("failed") {
+                    *counters.entry("failed_logins").or_insert(0) += 1;
+                }
+                if event.category.starts_with("privilege") {
+                    *counters.entry("privilege_events").or_insert(0) += 1;
+                }
+                if event.severity == "critical" {
+                    *counters.entry("critical_events").or_insert(0) += 1;
+                }
+            }
+        });
+
+        HttpResponse::Ok().json(serde_json::json!({
+            "window_hours": hours,
+            "generated_at": chrono::Utc::now().to_rfc3339(),
+            "hosts": hosts,
+        }))
+    }
+}
@@ -0,0 +1,465 @@
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// `[raw]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RawConfig {
+    // Category given to CEF and LEEF events
+    category: String,
+}
+
+impl Default for RawConfig {
+    fn default() -> Self {
+        RawConfig {
+            category: "security".to_string(),
+        }
+    }
+}
+
+pub struct Raw {
+    category: String,
+}
+
+impl Raw {
+    pub fn new(config: RawConfig) -> Self {
+        Raw {
+            category: config.category,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawQuery {
+    // Host for lines that don't name one; defaults to the sender's address
+    host: Option<String>,
+}
+
+// One parsed CEF or LEEF record, before it becomes an event
+struct Record {
+    format: &'static str,
+    vendor: String,
+    product: String,
+    version: String,
+    event_id: String,
+    name: String,
+    severity: String,
+    extensions: Map<String, Value>,
+    // Host from a syslog header in front of the record
+    syslog_host: Option<String>,
+}
+
+// Split a header on unescaped `|`, undoing `\|` and `\\`
+fn split_header(text: &str, fields: usize) -> Option<(Vec<String>, &str)> {
+    let mut parts = Vec::with_capacity(fields);
+    let mut current = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('|' | '\\'))) => current.push(escaped),
+                Some((_, other)) => {
+                    current.push('\\');
+                    current.push(other);
+                }
+                None => current.push('\\'),
+            },
+            '|' => {
+                parts.push(std::mem::take(&mut current));
+                if parts.len() == fields {
+                    return Some((parts, &text[index + 1..]));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    None
+}
+
+fn flush_word(word: &mut String, value: &mut String) {
+    if !value.is_empty() {
+        value.push(' ');
+    }
+    value.push_str(word);
+    word.clear();
+}
+
+// CEF extensions: `key=value` pairs separated by spaces, where values may
+// contain spaces and run until the next `key=`
+fn cef_extensions(text: &str) -> Map<String, Value> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut value = String::new();
+    let mut key: Option<String> = None;
+    let mut word = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => word.push('\n'),
+                Some('r') => word.push('\r'),
+                Some(escaped) => word.push(escaped),
+                None => word.push('\\'),
+            },
+            '=' if !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') =>
+            {
+                if let Some(key) = key.take() {
+                    pairs.push((key, std::mem::take(&mut value)));
+                } else {
+                    value.clear();
+                }
+                key = Some(std::mem::take(&mut word));
+            }
+            ' ' => {
+                if !word.is_empty() {
+                    flush_word(&mut word, &mut value);
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        flush_word(&mut word, &mut value);
+    }
+    if let Some(key) = key {
+        pairs.push((key, value));
+    }
+
+    // `cs1Label=rule cs1=ssh-brute` becomes `rule: ssh-brute`
+    let labels: Map<String, Value> = pairs
+        .iter()
+        .filter_map(|(key, value)| {
+            let field = key.strip_suffix("Label")?;
+            pairs
+                .iter()
+                .any(|(other, _)| other == field)
+                .then(|| (field.to_string(), Value::String(value.clone())))
+        })
+        .collect();
+    pairs
+        .into_iter()
+        .filter(|(key, _)| {
+            !key.strip_suffix("Label")
+                .is_some_and(|field| labels.contains_key(field))
+        })
+        .map(
+            |(key, value)| match labels.get(&key).and_then(Value::as_str) {
+                Some(label) if !label.is_empty() => (label.to_string(), Value::String(value)),
+                _ => (key, Value::String(value)),
+            },
+        )
+        .collect()
+}
+
+// LEEF attributes: `key=value` pairs split on the declared delimiter (a tab
+// unless LEEF 2.0 names another, as a character or hex like `x5E`)
+fn leef_attributes(text: &str, delimiter: &str) -> Map<String, Value> {
+    let delimiter = match delimiter {
+        "" => '\t',
+        hex if hex.len() > 1 && (hex.starts_with('x') || hex.starts_with("0x")) => {
+            u32::from_str_radix(hex.trim_start_matches("0x").trim_start_matches('x'), 16)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or('\t')
+        }
+        other => other.chars().next().unwrap_or('\t'),
+    };
+    text.split(delimiter)
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let key = key.trim();
+            (!key.is_empty()).then(|| (key.to_string(), Value::String(value.to_string())))
+        })
+        .collect()
+}
+
+// Host from an RFC 5424 (`<134>1 2026-10-14T10:00:00Z fw01 app - - -`) or
+// RFC 3164 (`<134>Oct 14 10:00:00 fw01`) header
+fn syslog_host(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim();
+    let rest = prefix
+        .strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'));
+    let body = rest.map(|(_, body)| body).unwrap_or(prefix);
+    let tokens: Vec<&str> = body.split_whitespace().collect();
+    let host = if tokens
+        .first()
+        .is_some_and(|first| first.chars().all(|c| c.is_ascii_digit()))
+    {
+        tokens.get(2)
+    } else {
+        tokens.get(3)
+    };
+    host.filter(|host| **host != "-")
+        .map(|host| host.trim_end_matches(':').to_string())
+}
+
+fn parse(line: &str) -> std::result::Result<Record, String> {
+    let (format, start) = match (line.find("CEF:"), line.find("LEEF:")) {
+        (Some(cef), Some(leef)) if leef < cef => ("leef", leef),
+        (Some(cef), _) => ("cef", cef),
+        (None, Some(leef)) => ("leef", leef),
+        (None, None) => return Err("not a CEF or LEEF record".to_string()),
+    };
+    let syslog_host = syslog_host(&line[..start]);
+    let record = &line[start..];
+
+    if format == "cef" {
+        let (header, extension) = split_header(&record[4..], 7)
+            .ok_or_else(|| "CEF record needs 7 header fields".to_string())?;
+        let mut header = header.into_iter().skip(1);
+        let mut next = || header.next().unwrap_or_default();
+        Ok(Record {
+            format,
+            vendor: next(),
+            product: next(),
+            version: next(),
+            event_id: next(),
+            name: next(),
+            severity: next(),
+            extensions: cef_extensions(extension),
+            syslog_host,
+        })
+    } else {
+        let version = record[5..].split('|').next().unwrap_or_default();
+        let fields = if version.starts_with('2') { 6 } else { 5 };
+        let (header, attributes) = split_header(&record[5..], fields)
+            .ok_or_else(|| format!("LEEF record needs {} header fields", fields))?;
+        let delimiter = header.get(5).map(String::as_str).unwrap_or_default();
+        let extensions = leef_attributes(attributes, delimiter);
+        let name = extensions
+            .get("cat")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let severity = extensions
+            .get("sev")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let mut header = header.into_iter().skip(1);
+        let mut next = || header.next().unwrap_or_default();
+        Ok(Record {
+            format,
+            vendor: next(),
+            product: next(),
+            version: next(),
+            event_id: next(),
+            name,
+            severity,
+            extensions,
+            syslog_host,
+        })
+    }
+}
+
+// CEF severities are 0-10 or Low/Medium/High/Very-High; LEEF `sev` is 1-10
+fn severity(value: &str) -> &'static str {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "low" => "info",
+        "medium" => "warning",
+        "high" => "error",
+        "very-high" | "very high" => "critical",
+        number => match number.parse::<u8>() {
+            Ok(0..=3) => "info",
+            Ok(4..=6) => "warning",
+            Ok(7..=8) => "error",
+            Ok(_) => "critical",
+            Err(_) => "info",
+        },
+    }
+}
+
+// `rt`/`devTime` as epoch milliseconds or `MMM dd yyyy HH:mm:ss`
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(millis) = value.parse::<i64>() {
+        return DateTime::from_timestamp_millis(millis);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    ["%b %d %Y %H:%M:%S%.f", "%b %d %Y %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|naive| naive.and_utc())
+}
+
+// One CEF or LEEF line from a fuzzer or a property test. Malformed input
+// must not panic.
+pub fn fuzz(input: &[u8]) {
+    if let Ok(record) = parse(&String::from_utf8_lossy(input)) {
+        to_event(record, "security", "fallback", "192.0.2.1");
+    }
+}
+
+fn to_event(record: Record, category: &str, fallback_host: &str, peer: &str) -> Event {
+    let text = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            record
+                .extensions
+                .get(*key)
+                .and_then(Value::as_str)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        })
+    };
+    let time = text(&["rt", "devTime", "end", "start"])
+        .and_then(|time| parse_time(&time))
+        .unwrap_or_else(Utc::now);
+    let host = text(&["dvchost", "identHostName"])
+        .or(record.syslog_host.clone())
+        .unwrap_or_else(|| fallback_host.to_string());
+    let host_ipv4 = text(&["dvc"]).unwrap_or_else(|| peer.to_string());
+    // LEEF has no name field, so those fall back to the event ID
+    let message = text(&["msg"])
+        .or_else(|| (!record.name.is_empty()).then(|| record.name.clone()))
+        .unwrap_or_else(|| format!("{} {} {}", record.vendor, record.product, record.event_id));
+
+    let mut data = record.extensions;
+    data.insert("device_vendor".to_string(), Value::String(record.vendor));
+    data.insert("device_product".to_string(), Value::String(record.product));
+    data.insert("device_version".to_string(), Value::String(record.version));
+    if !record.name.is_empty() {
+        data.insert("name".to_string(), Value::String(record.name));
+    }
+
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type: record.event_id,
+        time: time.to_rfc3339(),
+        host,
+        host_ipv4,
+        os: String::new(),
+        source: record.format.to_string(),
+        severity: severity(&record.severity).to_string(),
+        message,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// POST /api/events/raw - CEF or LEEF records, one per line, optionally
+// behind a syslog header. Extension keys land in `data`.
+pub async fn receive_raw(
+    request: HttpRequest,
+    body: String,
+    query: web::Query<RawQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.check(&request) {
+        return Ok(response);
+    }
+    let peer = request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let fallback_host = query.host.clone().unwrap_or_else(|| peer.clone());
+
+    let mut batch = Vec::new();
+    let mut rejected = 0;
+    for line in body.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match parse(line) {
+            Ok(record) => batch.push(to_event(record, &data.raw.category, &fallback_host, &peer)),
+            Err(err) => {
+                if rejected == 0 {
+                    log_line!("WARN", "Skipping raw line from {}: {}", peer, err);
+                }
+                rejected += 1;
+            }
+        }
+    }
+    let received = batch.len();
+    let identity = crate::tls::client_identity(&request);
+    for event in batch.iter_mut() {
+        event.identity = identity.clone();
+    }
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "received": received,
+        "rejected": rejected
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+
+    const SAMPLES: &[&[u8]] = &[
+        b"<134>1 2026-10-14T10:00:00Z fw01 app - - - CEF:0|Security|threatmanager|1.0|100|worm \\| stopped|10|src=10.0.0.1 dst=2.1.2.2 cs1Label=rule cs1=ssh brute msg=line\\nbreak rt=1760436000000",
+        b"<134>Oct 14 10:00:00 fw01 CEF:0|Vendor|Product|2|login|Login|Very-High|duser=alice end=Oct 14 2026 10:00:00",
+        b"LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=10.50.1.1\tdst=2.10.20.20\tsev=5\tcat=anomaly",
+        b"LEEF:2.0|Lancope|StealthWatch|1.0|41|^|src=10.0.1.8^dst=10.0.0.5^sev=5^devTime=2026-10-14T10:00:00Z",
+        b"LEEF:2.0|Vendor|Product|1|id|x5E|cat=a^sev=11",
+    ];
+    const TOKENS: &[&[u8]] = &[
+        b"CEF:",
+        b"LEEF:",
+        b"LEEF:2.0|",
+        b"|",
+        b"\\|",
+        b"\\",
+        b"\\n",
+        b"=",
+        b" ",
+        b"\t",
+        b"^",
+        b"x5E",
+        b"0x",
+        b"Label=",
+        b"cs1",
+        b"rt=",
+        b"devTime=",
+        b"sev=",
+        b"99999999999999999999",
+        b"\xc3\xa9",
+    ];
+
+    proptest! {
+        #[test]
+        fn fuzz_parse(input in mutations::input(SAMPLES, TOKENS)) {
+            fuzz(&input);
+        }
+
+        #[test]
+        fn cef_header_round_trip(fields in prop::array::uniform6(r"[abc XYZ019|\\=._-]{1,16}")) {
+            let escaped: Vec<String> = fields
+                .iter()
+                .map(|field| field.replace('\\', "\\\\").replace('|', "\\|"))
+                .collect();
+            let record = parse(&format!("CEF:0|{}|src=10.0.0.1", escaped.join("|"))).unwrap();
+            prop_assert_eq!(
+                [
+                    record.vendor,
+                    record.product,
+                    record.version,
+                    record.event_id,
+                    record.name,
+                    record.severity
+                ],
+                fields
+            );
+            prop_assert_eq!(
+                record.extensions.get("src").and_then(Value::as_str),
+                Some("10.0.0.1")
+            );
+        }
+    }
+}
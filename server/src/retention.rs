@@ -0,0 +1,355 @@
+use crate::scheduler::Scheduler;
+use crate::{config, AppState, Event};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Limits for one category; unset fields fall back to the global ones
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryRetention {
+    max_age: Option<String>,
+    max_events: Option<usize>,
+    min_ttl: Option<String>,
+    max_ttl: Option<String>,
+}
+
+// `[retention]` section
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    // Events older than this (by their own timestamp) are removed
+    max_age: Option<String>,
+    // Oldest events are removed past this many
+    max_events: Option<usize>,
+    // Shortest `ttl` an event may ask for; shorter ones are raised to it
+    min_ttl: Option<String>,
+    // Longest `ttl` an event may ask for; longer ones are lowered to it
+    max_ttl: Option<String>,
+    // Cron expression for the `retention` scheduled task
+    schedule: String,
+    // Older setting, turned into a schedule in whole minutes
+    interval: Option<String>,
+    // Category name as agents send it, e.g. "auth" -> limits for it
+    categories: HashMap<String, CategoryRetention>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            max_age: None,
+            max_events: None,
+            min_ttl: Some("10m".to_string()),
+            max_ttl: Some("365d".to_string()),
+            schedule: "*/5 * * * *".to_string(),
+            interval: None,
+            categories: HashMap::new(),
+        }
+    }
+}
+
+struct Limits {
+    max_age: Option<chrono::Duration>,
+    max_events: Option<usize>,
+    min_ttl: Option<chrono::Duration>,
+    max_ttl: Option<chrono::Duration>,
+}
+
+// Per category: the max_age cutoff, then min_ttl and max_ttl
+type AgeLimits = (
+    Option<DateTime<Utc>>,
+    chrono::Duration,
+    Option<chrono::Duration>,
+);
+
+pub struct Retention {
+    global: Limits,
+    categories: HashMap<String, Limits>,
+    // Set once any stored event carries a `ttl`, so runs without limits
+    // can skip scanning the store until then
+    ttl_seen: AtomicBool,
+}
+
+fn parse_age(scope: &str, field: &str, value: Option<&str>) -> Option<chrono::Duration> {
+    let value = value?;
+    match config::parse_duration(value).and_then(|age| chrono::Duration::from_std(age).ok()) {
+        Some(age) => Some(age),
+        None => {
+            log_line!(
+                "WARN",
+                "Ignoring invalid retention {} '{}' for {}",
+                field,
+                value,
+                scope
+            );
+            None
+        }
+    }
+}
+
+// An event's `ttl`: seconds, or a duration like "15m". Anything else is
+// ignored rather than rejecting the event.
+pub fn de_ttl<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(seconds) => seconds.as_u64(),
+        serde_json::Value::String(text) => config::parse_duration(&text).map(|ttl| ttl.as_secs()),
+        _ => None,
+    })
+}
+
+// `interval = "15m"` from older configs as a cron schedule. Cron counts
+// minutes within the hour, so it is rounded up to a whole minute and
+// anything over an hour runs hourly.
+fn interval_schedule(interval: &str) -> Option<String> {
+    let minutes = config::parse_duration(interval)?.as_secs().div_ceil(60);
+    log_line!(
+        "WARN",
+        "[retention] interval is replaced by schedule (a cron expression); running every {} minutes",
+        minutes.clamp(1, 60)
+    );
+    Some(match minutes {
+        0 | 1 => "* * * * *".to_string(),
+        60.. => "0 * * * *".to_string(),
+        minutes => format!("*/{} * * * *", minutes),
+    })
+}
+
+fn describe(limits: &Limits) -> String {
+    let mut parts = Vec::new();
+    if let Some(age) = limits.max_age {
+        parts.push(format!("older than {}s", age.num_seconds()));
+    }
+    if let Some(count) = limits.max_events {
+        parts.push(format!("beyond {} events", count));
+    }
+    parts.join(" or ")
+}
+
+impl Retention {
+    // The store-wide event count limit, if there is one
+    pub fn max_events(&self) -> Option<usize> {
+        self.global.max_events
+    }
+
+    // Registers the `retention` task, which runs without configured limits
+    // too in case events carry a `ttl`
+    pub fn new(config: RetentionConfig, scheduler: &mut Scheduler) -> Self {
+        let schedule = match config.interval.as_deref() {
+            None => config.schedule.clone(),
+            Some(interval) => interval_schedule(interval).unwrap_or_else(|| {
+                log_line!(
+                    "WARN",
+                    "Ignoring invalid [retention] interval '{}'",
+                    interval
+                );
+                config.schedule.clone()
+            }),
+        };
+        let run = Arc::new(|state: &AppState| {
+            let removed = run_once(state, state.clock.now());
+            Ok(format!("removed {} events", removed))
+        });
+        if let Err(err) = scheduler.register("retention", &schedule, run) {
+            log_line!("ERROR", "Retention not scheduled: {}", err);
+        }
+
+        let global = Limits {
+            max_age: parse_age("all events", "max_age", config.max_age.as_deref()),
+            max_events: config.max_events,
+            min_ttl: parse_age("all events", "min_ttl", config.min_ttl.as_deref()),
+            max_ttl: parse_age("all events", "max_ttl", config.max_ttl.as_deref()),
+        };
+        let categories: HashMap<String, Limits> = config
+            .categories
+            .into_iter()
+            .map(|(category, limits)| {
+                let scope = format!("category '{}'", category);
+                let limits = Limits {
+                    max_age: parse_age(&scope, "max_age", limits.max_age.as_deref()),
+                    max_events: limits.max_events,
+                    min_ttl: parse_age(&scope, "min_ttl", limits.min_ttl.as_deref()),
+                    max_ttl: parse_age(&scope, "max_ttl", limits.max_ttl.as_deref()),
+                };
+                (category, limits)
+            })
+            .collect();
+
+        let retention = Retention {
+            global,
+            categories,
+            ttl_seen: AtomicBool::new(false),
+        };
+        if retention.is_enabled() {
+            if retention.global.max_age.is_some() || retention.global.max_events.is_some() {
+                log_line!(
+                    "CONFIG",
+                    "Retention removes events {}",
+                    describe(&retention.global)
+                );
+            }
+            for (category, limits) in &retention.categories {
+                if limits.max_age.is_none() && limits.max_events.is_none() {
+                    continue;
+                }
+                log_line!(
+                    "CONFIG",
+                    "Retention removes '{}' events {}",
+                    category,
+                    describe(limits)
+                );
+            }
+        }
+        retention
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let limited = |limits: &Limits| limits.max_age.is_some() || limits.max_events.is_some();
+        limited(&self.global) || self.categories.values().any(limited)
+    }
+
+    fn max_age(&self, category: &str) -> Option<chrono::Duration> {
+        self.categories
+            .get(category)
+            .and_then(|limits| limits.max_age)
+            .or(self.global.max_age)
+    }
+
+    fn min_ttl(&self, category: &str) -> chrono::Duration {
+        self.categories
+            .get(category)
+            .and_then(|limits| limits.min_ttl)
+            .or(self.global.min_ttl)
+            .unwrap_or_default()
+    }
+
+    fn max_ttl(&self, category: &str) -> Option<chrono::Duration> {
+        self.categories
+            .get(category)
+            .and_then(|limits| limits.max_ttl)
+            .or(self.global.max_ttl)
+    }
+
+    // Called with everything that gets stored
+    pub fn observe<'a>(&self, events: impl IntoIterator<Item = &'a Event>) {
+        if !self.ttl_seen.load(Ordering::Relaxed)
+            && events.into_iter().any(|event| event.ttl.is_some())
+        {
+            self.ttl_seen.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+// Remove everything past the configured limits and return how many events
+// went. Ages are applied first, then per-category counts, then the global
+// count, each removing the oldest by event time first. An event's own `ttl`, kept
+// between `min_ttl` and `max_ttl`, can only make its age limit shorter. Events without
+// a parseable timestamp are only ever removed by count.
+pub fn run_once(data: &AppState, now: DateTime<Utc>) -> usize {
+    let retention = &data.retention;
+    if !retention.is_enabled() && !retention.ttl_seen.load(Ordering::Relaxed) {
+        return 0;
+    }
+    let doomed: Vec<u64> = {
+        let store = data.store.read();
+        // Sequence numbers of the events going
+        let mut remove: HashSet<u64> = HashSet::new();
+
+        let mut cutoffs: HashMap<&str, AgeLimits> = HashMap::new();
+        for (sequence, event) in store.entries() {
+            let (cutoff, min_ttl, max_ttl) =
+                *cutoffs.entry(event.category.as_str()).or_insert_with(|| {
+                    (
+                        retention.max_age(&event.category).map(|age| now - age),
+                        retention.min_ttl(&event.category),
+                        retention.max_ttl(&event.category),
+                    )
+                });
+            // A `ttl` too large for chrono counts as the longest allowed
+            let expiry = event
+                .ttl
+                .map(|ttl| {
+                    i64::try_from(ttl)
+                        .ok()
+                        .and_then(chrono::Duration::try_seconds)
+                        .unwrap_or(chrono::Duration::MAX)
+                })
+                .map(|ttl| max_ttl.map_or(ttl, |max_ttl| ttl.min(max_ttl)).max(min_ttl))
+                .and_then(|ttl| now.checked_sub_signed(ttl));
+            let cutoff = match (cutoff, expiry) {
+                (Some(cutoff), Some(expiry)) => Some(cutoff.max(expiry)),
+                (cutoff, expiry) => cutoff.or(expiry),
+            };
+            if let (Some(cutoff), Some(time)) = (cutoff, event.timestamp()) {
+                if time < cutoff {
+                    remove.insert(sequence);
+                }
+            }
+        }
+
+        let mut remaining: HashMap<&str, usize> = HashMap::new();
+        for (sequence, event) in store.entries() {
+            if !remove.contains(&sequence) {
+                *remaining.entry(event.category.as_str()).or_default() += 1;
+            }
+        }
+        let mut excess: HashMap<&str, usize> = remaining
+            .iter()
+            .filter_map(|(category, count)| {
+                let max = retention.categories.get(*category)?.max_events?;
+                (*count > max).then(|| (*category, count - max))
+            })
+            .collect();
+        if !excess.is_empty() {
+            for (sequence, event) in store.entries_by_time() {
+                if remove.contains(&sequence) {
+                    continue;
+                }
+                if let Some(left) = excess
+                    .get_mut(event.category.as_str())
+                    .filter(|left| **left > 0)
+                {
+                    *left -= 1;
+                    remove.insert(sequence);
+                }
+            }
+        }
+
+        if let Some(max) = retention.global.max_events {
+            let mut over = (store.len() - remove.len()).saturating_sub(max);
+            for (sequence, _) in store.entries_by_time() {
+                if over == 0 {
+                    break;
+                }
+                if remove.insert(sequence) {
+                    over -= 1;
+                }
+            }
+        }
+
+        let mut doomed: Vec<u64> = remove.into_iter().collect();
+        doomed.sort_unstable();
+        doomed
+    };
+    if doomed.is_empty() {
+        return 0;
+    }
+
+    let removed = data.store.remove(&doomed);
+    {
+        let store = data.store.read();
+        data.k8s.retain(&store);
+        data.search.retain(&store);
+    }
+    log_line!(
+        "INFO",
+        "Retention removed {} events ({} remain)",
+        removed,
+        data.store.stats().events
+    );
+    removed
+}
@@ -0,0 +1,151 @@
+use crate::audit;
+use actix_web::http::Method;
+use actix_web::middleware::from_fn;
+use actix_web::{web, Route};
+
+// One method on one path, plus who registered it (core or a plugin name)
+#[derive(Clone)]
+struct RouteSpec {
+    method: Method,
+    path: String,
+    owner: String,
+    build: fn() -> Route,
+    // Reads stored data, so [audit] records each request
+    audited: bool,
+}
+
+// Ordered route table. Routes sharing a path are grouped into a single
+// `web::resource`, and because actix answers 405 once a resource matches a
+// path, an earlier resource whose pattern also matches a later path would
+// swallow it. Both that and exact duplicates are rejected at startup.
+#[derive(Clone, Default)]
+pub struct RouteTable {
+    specs: Vec<RouteSpec>,
+}
+
+impl RouteTable {
+    pub fn new() -> Self {
+        RouteTable::default()
+    }
+
+    pub fn add(
+        &mut self,
+        owner: &str,
+        method: Method,
+        path: &str,
+        build: fn() -> Route,
+    ) -> Result<(), String> {
+        self.insert(owner, method, path, build, false)
+    }
+
+    // A route that returns stored data; wrapped in the audit middleware
+    pub fn add_query(
+        &mut self,
+        owner: &str,
+        method: Method,
+        path: &str,
+        build: fn() -> Route,
+    ) -> Result<(), String> {
+        self.insert(owner, method, path, build, true)
+    }
+
+    fn insert(
+        &mut self,
+        owner: &str,
+        method: Method,
+        path: &str,
+        build: fn() -> Route,
+        audited: bool,
+    ) -> Result<(), String> {
+        for existing in &self.specs {
+            if same_pattern(&existing.path, path) {
+                if existing.path != path {
+                    return Err(format!(
+                        "{} {} ({}) matches the same requests as {} ({})",
+                        method, path, owner, existing.path, existing.owner
+                    ));
+                }
+                if existing.method == method {
+                    return Err(format!(
+                        "{} {} is registered by both {} and {}",
+                        method, path, existing.owner, owner
+                    ));
+                }
+            } else if pattern_matches(&existing.path, path) {
+                return Err(format!(
+                    "{} {} ({}) is shadowed by earlier route {} ({})",
+                    method, path, owner, existing.path, existing.owner
+                ));
+            }
+        }
+
+        self.specs.push(RouteSpec {
+            method,
+            path: path.to_string(),
+            owner: owner.to_string(),
+            build,
+            audited,
+        });
+        Ok(())
+    }
+
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        let mut paths: Vec<&str> = Vec::new();
+        for spec in &self.specs {
+            if !paths.contains(&spec.path.as_str()) {
+                paths.push(&spec.path);
+            }
+        }
+
+        for path in paths {
+            let mut resource = web::resource(path);
+            for spec in self.specs.iter().filter(|spec| spec.path == path) {
+                let mut route = (spec.build)();
+                if spec.audited {
+                    route = route.wrap(from_fn(audit::record));
+                }
+                resource = resource.route(route.method(spec.method.clone()));
+            }
+            cfg.service(resource);
+        }
+    }
+}
+
+// `{name}` segments match any single segment; `{name:.*}` matches the rest
+fn is_dynamic(segment: &str) -> bool {
+    segment.starts_with('{') && segment.ends_with('}')
+}
+
+fn is_tail(segment: &str) -> bool {
+    is_dynamic(segment) && segment.contains(':')
+}
+
+// Both patterns accept exactly the same paths (ignoring parameter names)
+fn same_pattern(a: &str, b: &str) -> bool {
+    let a: Vec<&str> = a.split('/').collect();
+    let b: Vec<&str> = b.split('/').collect();
+    a.len() == b.len()
+        && a.iter()
+            .zip(&b)
+            .all(|(x, y)| x == y || (is_dynamic(x) && is_dynamic(y) && is_tail(x) == is_tail(y)))
+}
+
+// Would a request for `path` (read as a concrete URL) be routed to `pattern`?
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+
+    for (index, segment) in pattern.iter().enumerate() {
+        if is_tail(segment) {
+            return path.len() >= index;
+        }
+        let Some(candidate) = path.get(index) else {
+            return false;
+        };
+        if !is_dynamic(segment) && segment != candidate {
+            return false;
+        }
+    }
+
+    pattern.len() == path.len()
+}
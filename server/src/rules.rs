@@ -0,0 +1,1353 @@
+use crate::clock::SharedClock;
+use crate::config;
+use crate::incidents::Incidents;
+use crate::notifiers::Notifiers;
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+// How many fired alerts are kept in memory for /api/alerts
+const MAX_ALERTS: usize = 1000;
+// Cap on pending first-stage matches per rule key
+const MAX_PENDING_PER_KEY: usize = 100;
+
+// Matches events on any combination of the core fields (case-insensitive).
+// Fields left out of the config match everything.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EventMatcher {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+}
+
+impl EventMatcher {
+    pub fn matches(&self, event: &Event) -> bool {
+        fn field(expected: &Option<String>, actual: &str) -> bool {
+            expected
+                .as_deref()
+                .is_none_or(|value| value.eq_ignore_ascii_case(actual))
+        }
+
+        field(&self.category, &event.category)
+            && field(&self.event_type, &event.event_type)
+            && field(&self.severity, &event.severity)
+            && field(&self.source, &event.source)
+            && field(&self.host, &event.host)
+    }
+}
+
+// "event matching `first` followed by an event matching `then` within `within`",
+// both sharing the same values for every field listed in `key`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SequenceRule {
+    name: String,
+    first: EventMatcher,
+    then: EventMatcher,
+    #[serde(deserialize_with = "config::de_duration")]
+    within: Duration,
+    #[serde(default = "default_key")]
+    key: Vec<String>,
+    #[serde(default = "default_severity")]
+    severity: String,
+    message: Option<String>,
+}
+
+fn default_key() -> Vec<String> {
+    vec!["host".to_string()]
+}
+
+fn default_severity() -> String {
+    "critical".to_string()
+}
+
+// Built-in rule: the same user authenticating from two places further apart
+// than they could have travelled in the time between. Coordinates come from
+// GeoIP enrichment (`data.geo.lat` / `data.geo.lon`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImpossibleTravelConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_travel_match")]
+    r#match: EventMatcher,
+    #[serde(default = "default_user_fields")]
+    user_fields: Vec<String>,
+    #[serde(default = "default_max_speed_kmh")]
+    max_speed_kmh: f64,
+    // Ignore hops shorter than this; GeoIP is rarely accurate below city level
+    #[serde(default = "default_min_distance_km")]
+    min_distance_km: f64,
+    #[serde(
+        default = "default_travel_window",
+        deserialize_with = "config::de_duration"
+    )]
+    window: Duration,
+    #[serde(default = "default_severity")]
+    severity: String,
+}
+
+impl Default for ImpossibleTravelConfig {
+    fn default() -> Self {
+        ImpossibleTravelConfig {
+            enabled: false,
+            r#match: default_travel_match(),
+            user_fields: default_user_fields(),
+            max_speed_kmh: default_max_speed_kmh(),
+            min_distance_km: default_min_distance_km(),
+            window: default_travel_window(),
+            severity: default_severity(),
+        }
+    }
+}
+
+fn default_travel_match() -> EventMatcher {
+    EventMatcher {
+        category: Some("auth".to_string()),
+        ..EventMatcher::default()
+    }
+}
+
+fn default_user_fields() -> Vec<String> {
+    vec!["data.user".to_string(), "data.username".to_string()]
+}
+
+fn default_max_speed_kmh() -> f64 {
+    900.0
+}
+
+fn default_min_distance_km() -> f64 {
+    100.0
+}
+
+fn default_travel_window() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+// "expect at least `min_count` matching events per `window`": alerts once when
+// the count drops below the minimum and re-arms after it recovers.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AbsenceRule {
+    name: String,
+    r#match: EventMatcher,
+    #[serde(default = "default_min_count")]
+    min_count: usize,
+    #[serde(deserialize_with = "config::de_duration")]
+    window: Duration,
+    #[serde(default = "default_absence_severity")]
+    severity: String,
+    message: Option<String>,
+}
+
+fn default_min_count() -> usize {
+    1
+}
+
+fn default_absence_severity() -> String {
+    "warning".to_string()
+}
+
+// "more than `count` matching events per key within `window`": alerts once
+// when a key goes over the count and resolves when it falls back. Also
+// managed through /api/admin/alert-rules.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThresholdRule {
+    name: String,
+    #[serde(default)]
+    r#match: EventMatcher,
+    count: usize,
+    #[serde(
+        deserialize_with = "config::de_duration",
+        serialize_with = "config::ser_duration"
+    )]
+    window: Duration,
+    #[serde(default = "default_key")]
+    key: Vec<String>,
+    #[serde(default = "default_severity")]
+    severity: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    // Defined in server.toml rather than through the API
+    #[serde(skip)]
+    configured: bool,
+}
+
+fn default_check_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DetectionConfig {
+    #[serde(default)]
+    sequence: Vec<SequenceRule>,
+    #[serde(default)]
+    impossible_travel: ImpossibleTravelConfig,
+    #[serde(default)]
+    absence: Vec<AbsenceRule>,
+    #[serde(default)]
+    threshold: Vec<ThresholdRule>,
+    // Threshold rules added through the API are kept here; without it they
+    // last until the server restarts
+    rules_file: Option<String>,
+    #[serde(
+        default = "default_check_interval",
+        deserialize_with = "config::de_duration"
+    )]
+    check_interval: Duration,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        DetectionConfig {
+            sequence: Vec::new(),
+            impossible_travel: ImpossibleTravelConfig::default(),
+            absence: Vec::new(),
+            threshold: Vec::new(),
+            rules_file: None,
+            check_interval: default_check_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub id: u64,
+    pub rule: String,
+    pub kind: &'static str,
+    pub severity: String,
+    pub host: String,
+    pub key: String,
+    pub time: String,
+    pub message: String,
+    pub events: Vec<Event>,
+    // When a threshold or absence alert's condition cleared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+}
+
+struct PendingMatch {
+    time: DateTime<Utc>,
+    event: Event,
+}
+
+#[derive(Default)]
+struct AbsenceState {
+    // Arrival times of matching events inside the rule window
+    seen: VecDeque<DateTime<Utc>>,
+    // Id of the alert while too few arrive (0 until it is raised)
+    active: Option<u64>,
+}
+
+#[derive(Default)]
+struct ThresholdState {
+    // Arrival times of the latest matches, at most `count + 1`
+    seen: VecDeque<DateTime<Utc>>,
+    // Id of the alert while the key is over the count (0 until it is raised)
+    active: Option<u64>,
+}
+
+struct LastLocation {
+    time: DateTime<Utc>,
+    lat: f64,
+    lon: f64,
+    event: Event,
+}
+
+#[derive(Default)]
+struct Locations {
+    // user -> most recent located authentication
+    by_user: HashMap<String, LastLocation>,
+    // When entries past the window were last dropped
+    swept_at: Option<DateTime<Utc>>,
+}
+
+pub struct RuleEngine {
+    sequences: Vec<SequenceRule>,
+    // (rule index, key) -> first-stage matches still waiting for their follow-up
+    pending: Mutex<HashMap<(usize, String), VecDeque<PendingMatch>>>,
+    travel: ImpossibleTravelConfig,
+    locations: Mutex<Locations>,
+    absences: Vec<AbsenceRule>,
+    absence_state: Mutex<Vec<AbsenceState>>,
+    thresholds: RwLock<Vec<ThresholdRule>>,
+    // (rule name, key) -> recent matches
+    threshold_state: Mutex<HashMap<(String, String), ThresholdState>>,
+    rules_file: Option<String>,
+    check_interval: Duration,
+    started_at: DateTime<Utc>,
+    clock: SharedClock,
+    alerts: RwLock<VecDeque<Alert>>,
+    next_id: Mutex<u64>,
+    pub incidents: Incidents,
+    pub notifiers: Notifiers,
+}
+
+impl RuleEngine {
+    pub fn new(
+        config: DetectionConfig,
+        incidents: Incidents,
+        notifiers: Notifiers,
+        clock: SharedClock,
+    ) -> Self {
+        for rule in &config.sequence {
+            log_line!(
+                "CONFIG",
+                "Sequence rule '{}' (window {}s, key {:?})",
+                rule.name,
+                rule.within.as_secs(),
+                rule.key
+            );
+        }
+
+        if config.impossible_travel.enabled {
+            log_line!(
+                "CONFIG",
+                "Impossible travel detection enabled (max {} km/h)",
+                config.impossible_travel.max_speed_kmh
+            );
+        }
+
+        for rule in &config.absence {
+            log_line!(
+                "CONFIG",
+                "Absence rule '{}' (at least {} per {}s)",
+                rule.name,
+                rule.min_count,
+                rule.window.as_secs()
+            );
+        }
+
+        let mut thresholds = config.threshold;
+        for rule in &mut thresholds {
+            rule.configured = true;
+        }
+        if let Some(file) = &config.rules_file {
+            match std::fs::read_to_string(file) {
+                Ok(contents) => match serde_json::from_str::<Vec<ThresholdRule>>(&contents) {
+                    Ok(added) => {
+                        for rule in added {
+                            if thresholds.iter().any(|other| other.name == rule.name) {
+                                log_line!(
+                                    "WARN",
+                                    "Ignoring threshold rule '{}' from {}: server.toml has one by that name",
+                                    rule.name,
+                                    file
+                                );
+                            } else {
+                                thresholds.push(rule);
+                            }
+                        }
+                    }
+                    Err(err) => log_line!(
+                        "ERROR",
+                        "Ignoring unreadable alert rules file {}: {}",
+                        file,
+                        err
+                    ),
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => log_line!("ERROR", "Could not read alert rules file {}: {}", file, err),
+            }
+        }
+        for rule in &thresholds {
+            log_line!(
+                "CONFIG",
+                "Threshold rule '{}' (more than {} per {}s, key {:?})",
+                rule.name,
+                rule.count,
+                rule.window.as_secs(),
+                rule.key
+            );
+        }
+
+        let absence_state = config
+            .absence
+            .iter()
+            .map(|_| AbsenceState::default())
+            .collect();
+
+        RuleEngine {
+            sequences: config.sequence,
+            pending: Mutex::new(HashMap::new()),
+            travel: config.impossible_travel,
+            locations: Mutex::new(Locations::default()),
+            absences: config.absence,
+            absence_state: Mutex::new(absence_state),
+            thresholds: RwLock::new(thresholds),
+            threshold_state: Mutex::new(HashMap::new()),
+            rules_file: config.rules_file,
+            // tokio's interval panics on zero
+            check_interval: config.check_interval.max(Duration::from_secs(1)),
+            started_at: clock.now(),
+            clock,
+            alerts: RwLock::new(VecDeque::new()),
+            next_id: Mutex::new(1),
+            incidents,
+            notifiers,
+        }
+    }
+
+    // Feed a batch of freshly stored events through the rules
+    pub fn observe(&self, events: &[Event]) {
+        self.observe_sequences(events);
+        if self.travel.enabled {
+            self.observe_travel(events);
+        }
+        if !self.absences.is_empty() {
+            self.observe_absence(events);
+        }
+        self.observe_thresholds(events);
+    }
+
+    // Called periodically by the background checker
+    pub fn check(&self) {
+        self.check_absence();
+        self.check_thresholds();
+    }
+
+    pub fn check_interval(&self) -> Duration {
+        self.check_interval
+    }
+
+    // Absence is judged by arrival time, so agents replaying old buffers count
+    // as "the job is alive again".
+    // Only the newest `min_count` arrivals are kept: once that many fall
+    // inside the window the rule is satisfied, and below it they are all of
+    // them, so the count in the alert stays exact.
+    fn observe_absence(&self, events: &[Event]) {
+        let now = self.clock.now();
+        let mut state = self.absence_state.lock();
+        for (rule, rule_state) in self.absences.iter().zip(state.iter_mut()) {
+            for event in events {
+                if rule.r#match.matches(event) {
+                    rule_state.seen.push_back(now);
+                }
+            }
+            while rule_state.seen.len() > rule.min_count.max(1) {
+                rule_state.seen.pop_front();
+            }
+        }
+    }
+
+    fn check_absence(&self) {
+        let now = self.clock.now();
+        let mut fired = Vec::new();
+        let mut resolved = Vec::new();
+        let mut state = self.absence_state.lock();
+
+        for (index, (rule, rule_state)) in self.absences.iter().zip(state.iter_mut()).enumerate() {
+            let window = chrono::Duration::from_std(rule.window).unwrap_or_default();
+            while rule_state
+                .seen
+                .front()
+                .is_some_and(|seen| now - *seen > window)
+            {
+                rule_state.seen.pop_front();
+            }
+
+            // Give a freshly started server one full window before judging
+            if now - self.started_at < window {
+                continue;
+            }
+
+            let count = rule_state.seen.len();
+            if count < rule.min_count {
+                if rule_state.active.is_none() {
+                    rule_state.active = Some(0);
+                    fired.push((index, count));
+                }
+            } else if let Some(id) = rule_state.active.take() {
+                resolved.push(id);
+            }
+        }
+        drop(state);
+        for id in resolved {
+            self.resolve(id, now);
+        }
+
+        for (index, count) in fired {
+            let rule = &self.absences[index];
+            let message = rule.message.clone().unwrap_or_else(|| {
+                format!(
+                    "Expected at least {} matching events in the last {}s, saw {}",
+                    rule.min_count,
+                    rule.window.as_secs(),
+                    count
+                )
+            });
+            let id = self.raise(Alert {
+                id: 0,
+                rule: rule.name.clone(),
+                kind: "absence",
+                severity: rule.severity.clone(),
+                host: rule.r#match.host.clone().unwrap_or_default(),
+                key: String::new(),
+                time: now.to_rfc3339(),
+                message,
+                events: Vec::new(),
+                resolved: None,
+            });
+            let mut state = self.absence_state.lock();
+            if state[index].active == Some(0) {
+                state[index].active = Some(id);
+            }
+        }
+    }
+
+    // Arrival time, like absence, so a replayed backlog counts as a burst
+    // now rather than as the rate it had back then
+    fn observe_thresholds(&self, events: &[Event]) {
+        let rules = self.thresholds.read();
+        if rules.is_empty() {
+            return;
+        }
+        let now = self.clock.now();
+        let mut fired = Vec::new();
+        let mut state = self.threshold_state.lock();
+
+        for event in events {
+            for rule in rules.iter().filter(|rule| rule.r#match.matches(event)) {
+                let key = rule_key(&rule.key, event);
+                let window = chrono::Duration::from_std(rule.window).unwrap_or_default();
+                let entry = state.entry((rule.name.clone(), key.clone())).or_default();
+                entry.seen.push_back(now);
+                while entry.seen.len() > rule.count + 1
+                    || entry.seen.front().is_some_and(|seen| now - *seen > window)
+                {
+                    entry.seen.pop_front();
+                }
+                if entry.active.is_none() && entry.seen.len() > rule.count {
+                    entry.active = Some(0);
+                    fired.push((rule.clone(), key, event.clone()));
+                }
+            }
+        }
+        drop(state);
+        drop(rules);
+
+        for (rule, key, event) in fired {
+            let message = rule.message.clone().unwrap_or_else(|| {
+                format!(
+                    "More than {} matching events for {} within {}s",
+                    rule.count,
+                    key,
+                    rule.window.as_secs()
+                )
+            });
+            let id = self.raise(Alert {
+                id: 0,
+                rule: rule.name.clone(),
+                kind: "threshold",
+                severity: rule.severity.clone(),
+                host: event.host.clone(),
+                key: key.clone(),
+                time: now.to_rfc3339(),
+                message,
+                events: vec![event],
+                resolved: None,
+            });
+            if let Some(entry) = self.threshold_state.lock().get_mut(&(rule.name, key)) {
+                if entry.active == Some(0) {
+                    entry.active = Some(id);
+                }
+            }
+        }
+    }
+
+    // Resolves keys that fell back to the count and forgets idle ones
+    fn check_thresholds(&self) {
+        let now = self.clock.now();
+        let rules = self.thresholds.read();
+        let mut resolved = Vec::new();
+        let mut state = self.threshold_state.lock();
+        state.retain(|(name, _), entry| {
+            let rule = rules.iter().find(|rule| rule.name == *name);
+            if let Some(rule) = rule {
+                let window = chrono::Duration::from_std(rule.window).unwrap_or_default();
+                while entry.seen.front().is_some_and(|seen| now - *seen > window) {
+                    entry.seen.pop_front();
+                }
+            }
+            // A deleted rule's alerts resolve with it
+            if rule.is_none_or(|rule| entry.seen.len() <= rule.count) {
+                resolved.extend(entry.active.take());
+            }
+            rule.is_some() && (entry.active.is_some() || !entry.seen.is_empty())
+        });
+        drop(state);
+        drop(rules);
+        for id in resolved {
+            self.resolve(id, now);
+        }
+    }
+
+    fn observe_sequences(&self, events: &[Event]) {
+        if self.sequences.is_empty() {
+            return;
+        }
+
+        let mut fired = Vec::new();
+        let mut pending = self.pending.lock();
+        let mut latest = None;
+
+        for event in events {
+            let time = event.timestamp().unwrap_or_else(|| self.clock.now());
+            latest = latest.max(Some(time));
+
+            for (index, rule) in self.sequences.iter().enumerate() {
+                let key = rule_key(&rule.key, event);
+                let window = chrono::Duration::from_std(rule.within).unwrap_or_default();
+
+                // Check the follow-up first so one event never completes its own sequence
+                if rule.then.matches(event) {
+                    if let Some(queue) = pending.get_mut(&(index, key.clone())) {
+                        let position = queue.iter().position(|candidate| {
+                            candidate.time <= time && time - candidate.time <= window
+                        });
+                        if let Some(position) = position {
+                            if let Some(first) = queue.remove(position) {
+                                fired.push((index, key.clone(), first.event, event.clone()));
+                            }
+                        }
+                    }
+                }
+
+                if rule.first.matches(event) {
+                    let queue = pending.entry((index, key)).or_default();
+                    if queue.len() >= MAX_PENDING_PER_KEY {
+                        queue.pop_front();
+                    }
+                    queue.push_back(PendingMatch {
+                        time,
+                        event: event.clone(),
+                    });
+                }
+            }
+        }
+
+        // Drop first-stage matches that can no longer complete
+        if let Some(latest) = latest {
+            pending.retain(|(index, _), queue| {
+                let window =
+                    chrono::Duration::from_std(self.sequences[*index].within).unwrap_or_default();
+                queue.retain(|candidate| latest - candidate.time <= window);
+                !queue.is_empty()
+            });
+        }
+        drop(pending);
+
+        for (index, key, first, then) in fired {
+            let rule = &self.sequences[index];
+            let message = rule.message.clone().unwrap_or_else(|| {
+                format!(
+                    "{} followed by {} within {}s",
+                    first.event_type,
+                    then.event_type,
+                    rule.within.as_secs()
+                )
+            });
+            self.raise(Alert {
+                id: 0,
+                rule: rule.name.clone(),
+                kind: "sequence",
+                severity: rule.severity.clone(),
+                host: then.host.clone(),
+                key,
+                time: then.time.clone(),
+                message,
+                events: vec![first, then],
+                resolved: None,
+            });
+        }
+    }
+
+    fn observe_travel(&self, events: &[Event]) {
+        let rule = &self.travel;
+        let window = chrono::Duration::from_std(rule.window).unwrap_or_default();
+        let mut fired = Vec::new();
+        let now = self.clock.now();
+        let mut locations = self.locations.lock();
+        // A location older than the window can't raise an alert any more.
+        // Dropping those at most once a minute keeps one user per recent
+        // login without scanning the map on every batch.
+        if locations
+            .swept_at
+            .is_none_or(|swept_at| now - swept_at >= chrono::Duration::minutes(1))
+        {
+            locations
+                .by_user
+                .retain(|_, location| now - location.time <= window);
+            locations.swept_at = Some(now);
+        }
+
+        for event in events {
+            if !rule.r#match.matches(event) {
+                continue;
+            }
+            let Some(user) = rule
+                .user_fields
+                .iter()
+                .find_map(|field| event.field_value(field))
+            else {
+                continue;
+            };
+            let Some((lat, lon)) = event_coordinates(event) else {
+                continue;
+            };
+            let time = event.timestamp().unwrap_or(now);
+
+            if let Some(previous) = locations.by_user.get(&user) {
+                let elapsed = time - previous.time;
+                if elapsed >= chrono::Duration::zero() && elapsed <= window {
+                    let distance = haversine_km(previous.lat, previous.lon, lat, lon);
+                    let hours = (elapsed.num_seconds().max(1) as f64) / 3600.0;
+                    let speed = distance / hours;
+                    if distance >= rule.min_distance_km && speed > rule.max_speed_kmh {
+                        fired.push((
+                            user.clone(),
+                            distance,
+                            elapsed.num_seconds(),
+                            speed,
+                            previous.event.clone(),
+                            event.clone(),
+                        ));
+                    }
+                } else if elapsed < chrono::Duration::zero() {
+                    // Older than what we already know about; keep the newer location
+                    continue;
+                }
+            }
+
+            locations.by_user.insert(
+                user,
+                LastLocation {
+                    time,
+                    lat,
+                    lon,
+                    event: event.clone(),
+                },
+            );
+        }
+        drop(locations);
+
+        for (user, distance, seconds, speed, previous, current) in fired {
+            self.raise(Alert {
+                id: 0,
+                rule: "impossible_travel".to_string(),
+                kind: "impossible_travel",
+                severity: rule.severity.clone(),
+                host: current.host.clone(),
+                key: user.clone(),
+                time: current.time.clone(),
+                message: format!(
+                    "User {} authenticated {:.0} km apart in {}s (~{:.0} km/h)",
+                    user, distance, seconds, speed
+                ),
+                events: vec![previous, current],
+                resolved: None,
+            });
+        }
+    }
+
+    fn raise(&self, mut alert: Alert) -> u64 {
+        {
+            let mut next_id = self.next_id.lock();
+            alert.id = *next_id;
+            *next_id += 1;
+        }
+        let id = alert.id;
+
+        log_line!(
+            "ALERT",
+            "{} on {}: {}",
+            alert.rule,
+            alert.host,
+            alert.message
+        );
+
+        self.incidents.observe(&alert);
+        self.notifiers.notify(&alert);
+        let mut alerts = self.alerts.write();
+        if alerts.len() >= MAX_ALERTS {
+            alerts.pop_front();
+        }
+        alerts.push_back(alert);
+        id
+    }
+
+    // An alert that was raised is 0 until raise() returns; nothing to mark
+    fn resolve(&self, id: u64, now: DateTime<Utc>) {
+        if id == 0 {
+            return;
+        }
+        let mut alerts = self.alerts.write();
+        if let Some(alert) = alerts.iter_mut().find(|alert| alert.id == id) {
+            log_line!("ALERT", "{} on {} resolved", alert.rule, alert.host);
+            alert.resolved = Some(now.to_rfc3339());
+        }
+    }
+
+    // Alerts detected outside the rule engine (baselines, feeds) share the
+    // same list and numbering as rule alerts
+    pub fn raise_for_event(
+        &self,
+        rule: &str,
+        kind: &'static str,
+        severity: &str,
+        key: &str,
+        message: String,
+        event: &Event,
+    ) {
+        self.raise(Alert {
+            id: 0,
+            rule: rule.to_string(),
+            kind,
+            severity: severity.to_string(),
+            host: event.host.clone(),
+            key: key.to_string(),
+            time: event.time.clone(),
+            message,
+            events: vec![event.clone()],
+            resolved: None,
+        });
+    }
+
+    // Fired alerts, newest first
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.alerts.read().iter().rev().cloned().collect()
+    }
+
+    // Threshold and absence alerts whose condition still holds, newest first
+    pub fn active_alerts(&self) -> Vec<Alert> {
+        let mut ids: Vec<u64> = self
+            .threshold_state
+            .lock()
+            .values()
+            .filter_map(|entry| entry.active)
+            .collect();
+        ids.extend(
+            self.absence_state
+                .lock()
+                .iter()
+                .filter_map(|state| state.active),
+        );
+        let mut alerts = self.alerts_by_id(&ids);
+        alerts.reverse();
+        alerts
+    }
+
+    // The listed alerts that are still held, oldest first
+    pub fn alerts_by_id(&self, ids: &[u64]) -> Vec<Alert> {
+        self.alerts
+            .read()
+            .iter()
+            .filter(|alert| ids.contains(&alert.id))
+            .cloned()
+            .collect()
+    }
+}
+
+fn error(status: actix_web::http::StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+impl RuleEngine {
+    // Write the threshold rules added through the API to `rules_file`,
+    // through a temp file like backups
+    fn save(&self, rules: &[ThresholdRule]) -> std::result::Result<(), String> {
+        let Some(file) = &self.rules_file else {
+            return Ok(());
+        };
+        let added: Vec<&ThresholdRule> = rules.iter().filter(|rule| !rule.configured).collect();
+        let temp_path = format!("{}.tmp", file);
+        let json = serde_json::to_vec_pretty(&added).map_err(|err| err.to_string())?;
+        std::fs::write(&temp_path, json).map_err(|err| err.to_string())?;
+        std::fs::rename(&temp_path, file).map_err(|err| err.to_string())
+    }
+}
+
+// GET /api/admin/alert-rules - Threshold rules from server.toml and the API
+pub async fn list_rules(request: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let rules: Vec<serde_json::Value> = data
+        .rules
+        .thresholds
+        .read()
+        .iter()
+        .map(|rule| {
+            let mut value = serde_json::to_value(rule).unwrap_or_default();
+            if let Some(object) = value.as_object_mut() {
+                let source = if rule.configured { "config" } else { "api" };
+                object.insert("source".to_string(), source.into());
+            }
+            value
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "persistent": data.rules.rules_file.is_some(),
+        "threshold": rules,
+    })))
+}
+
+// POST /api/admin/alert-rules - Add a threshold rule; it applies to events
+// arriving from now on. Needs an admin role, like removing one.
+pub async fn add_rule(
+    request: HttpRequest,
+    body: web::Json<ThresholdRule>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let admin = match data.api_keys.authorize_admin(&request) {
+        Ok(admin) => admin,
+        Err(response) => return Ok(response),
+    };
+    let mut rule = body.into_inner();
+    rule.name = rule.name.trim().to_string();
+    rule.configured = false;
+    if rule.name.is_empty() {
+        return Ok(error(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            "a rule needs a `name`".to_string(),
+        ));
+    }
+    if rule.window.is_zero() {
+        return Ok(error(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            "`window` must be longer than 0s".to_string(),
+        ));
+    }
+
+    let mut rules = data.rules.thresholds.write();
+    if rules.iter().any(|other| other.name == rule.name) {
+        return Ok(error(
+            actix_web::http::StatusCode::CONFLICT,
+            format!("a threshold rule named '{}' already exists", rule.name),
+        ));
+    }
+    rules.push(rule.clone());
+    if let Err(err) = data.rules.save(&rules) {
+        rules.pop();
+        return Ok(error(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the rule: {}", err),
+        ));
+    }
+    drop(rules);
+    log_line!(
+        "CONFIG",
+        "Threshold rule '{}' added by {} (more than {} per {}s, key {:?})",
+        rule.name,
+        admin,
+        rule.count,
+        rule.window.as_secs(),
+        rule.key
+    );
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "status": "success",
+        "rule": rule,
+    })))
+}
+
+// DELETE /api/admin/alert-rules/{name} - Remove a threshold rule added
+// through the API. Its active alerts resolve on the next check.
+pub async fn remove_rule(
+    request: HttpRequest,
+    name: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let admin = match data.api_keys.authorize_admin(&request) {
+        Ok(admin) => admin,
+        Err(response) => return Ok(response),
+    };
+    let name = name.into_inner();
+    let mut rules = data.rules.thresholds.write();
+    let Some(position) = rules.iter().position(|rule| rule.name == name) else {
+        return Ok(error(
+            actix_web::http::StatusCode::NOT_FOUND,
+            format!("no threshold rule named '{}'", name),
+        ));
+    };
+    if rules[position].configured {
+        return Ok(error(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            format!("rule '{}' is defined in server.toml; remove it there", name),
+        ));
+    }
+    let removed = rules.remove(position);
+    if let Err(err) = data.rules.save(&rules) {
+        rules.insert(position, removed);
+        return Ok(error(
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("could not save the rules file: {}", err),
+        ));
+    }
+    drop(rules);
+    log_line!("CONFIG", "Threshold rule '{}' removed by {}", name, admin);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "name": name,
+    })))
+}
+
+fn rule_key(fields: &[String], event: &Event) -> String {
+    fields
+        .iter()
+        .map(|field| event.field_value(field).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn event_coordinates(event: &Event) -> Option<(f64, f64)> {
+    let lat = event.field_value("data.geo.lat")?.parse::<f64>().ok()?;
+    let lon = event.field_value("data.geo.lon")?.parse::<f64>().ok()?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return None;
+    }
+    Some((lat, lon))
+}
+
+// Great-circle distance in kilometres
+fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const START: &str = "2026-10-15T10:00:00Z";
+
+    fn at(offset_secs: i64) -> DateTime<Utc> {
+        START.parse::<DateTime<Utc>>().unwrap() + chrono::Duration::seconds(offset_secs)
+    }
+
+    // Rules on a manual clock standing at START
+    fn engine(detection: serde_json::Value) -> (RuleEngine, Arc<crate::clock::ManualClock>) {
+        let clock = Arc::new(crate::clock::ManualClock::new(at(0)));
+        let engine = RuleEngine::new(
+            serde_json::from_value(detection).unwrap(),
+            Incidents::new(serde_json::from_value(serde_json::json!({})).unwrap()),
+            Notifiers::new(Vec::new(), Default::default()),
+            clock.clone(),
+        );
+        (engine, clock)
+    }
+
+    fn event(event_type: &str, host: &str, offset_secs: i64) -> Event {
+        Event {
+            category: "auth".to_string(),
+            event_type: event_type.to_string(),
+            host: host.to_string(),
+            time: at(offset_secs).to_rfc3339(),
+            severity: "info".to_string(),
+            ..Event::default()
+        }
+    }
+
+    fn rules(engine: &RuleEngine) -> Vec<String> {
+        engine
+            .alerts()
+            .into_iter()
+            .map(|alert| alert.rule)
+            .collect()
+    }
+
+    fn sequence() -> serde_json::Value {
+        serde_json::json!({
+            "sequence": [{
+                "name": "user then sudo",
+                "first": {"event_type": "user_created"},
+                "then": {"event_type": "sudoers_changed"},
+                "within": "10m",
+            }],
+        })
+    }
+
+    #[test]
+    fn sequences_fire_per_key_within_the_window() {
+        let (engine, _) = engine(sequence());
+        engine.observe(&[
+            event("user_created", "web01", 0),
+            event("sudoers_changed", "db01", 60),
+        ]);
+        assert!(engine.alerts().is_empty());
+
+        engine.observe(&[event("sudoers_changed", "web01", 120)]);
+        let alerts = engine.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, "sequence");
+        assert_eq!(alerts[0].key, "web01");
+        let types: Vec<&str> = alerts[0]
+            .events
+            .iter()
+            .map(|event| event.event_type.as_str())
+            .collect();
+        assert_eq!(types, ["user_created", "sudoers_changed"]);
+
+        // The first stage was used up
+        engine.observe(&[event("sudoers_changed", "web01", 180)]);
+        assert_eq!(engine.alerts().len(), 1);
+    }
+
+    #[test]
+    fn sequences_expire_and_keep_their_order() {
+        let (engine, _) = engine(sequence());
+        engine.observe(&[event("user_created", "web01", 0)]);
+        engine.observe(&[event("sudoers_changed", "web01", 601)]);
+        assert!(engine.alerts().is_empty());
+
+        // A follow-up from before the first stage doesn't complete it
+        engine.observe(&[
+            event("user_created", "web01", 1000),
+            event("sudoers_changed", "web01", 900),
+        ]);
+        assert!(engine.alerts().is_empty());
+    }
+
+    #[test]
+    fn an_event_never_completes_its_own_sequence() {
+        let (engine, _) = engine(serde_json::json!({
+            "sequence": [{
+                "name": "repeat",
+                "first": {"event_type": "login_failed"},
+                "then": {"event_type": "login_failed"},
+                "within": "1m",
+            }],
+        }));
+        engine.observe(&[event("login_failed", "web01", 0)]);
+        assert!(engine.alerts().is_empty());
+        engine.observe(&[event("login_failed", "web01", 30)]);
+        assert_eq!(rules(&engine), ["repeat"]);
+    }
+
+    fn login(user: &str, lat: f64, lon: f64, offset_secs: i64) -> Event {
+        Event {
+            data: serde_json::json!({"user": user, "geo": {"lat": lat, "lon": lon}}),
+            ..event("login", "vpn01", offset_secs)
+        }
+    }
+
+    const NEW_YORK: (f64, f64) = (40.71, -74.01);
+    const LONDON: (f64, f64) = (51.51, -0.13);
+
+    #[test]
+    fn great_circle_distances() {
+        let distance = haversine_km(NEW_YORK.0, NEW_YORK.1, LONDON.0, LONDON.1);
+        assert!((distance - 5570.0).abs() < 10.0, "{}", distance);
+        assert_eq!(haversine_km(10.0, 20.0, 10.0, 20.0), 0.0);
+    }
+
+    #[test]
+    fn travel_faster_than_a_plane_fires() {
+        let (fast, _) = engine(serde_json::json!({"impossible_travel": {"enabled": true}}));
+        fast.observe(&[
+            login("bob", NEW_YORK.0, NEW_YORK.1, 0),
+            login("alice", LONDON.0, LONDON.1, 60),
+            login("bob", LONDON.0, LONDON.1, 3600),
+        ]);
+        let alerts = fast.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, "impossible_travel");
+        assert_eq!(alerts[0].key, "bob");
+
+        // Ten hours is slow enough, and so is a short hop at any speed
+        let (slow, _) = engine(serde_json::json!({"impossible_travel": {"enabled": true}}));
+        slow.observe(&[
+            login("bob", NEW_YORK.0, NEW_YORK.1, 0),
+            login("bob", LONDON.0, LONDON.1, 36_000),
+            login("bob", LONDON.0 + 0.5, LONDON.1, 36_060),
+        ]);
+        assert!(slow.alerts().is_empty());
+    }
+
+    #[test]
+    fn travel_ignores_old_and_unlocated_logins() {
+        let (engine, clock) = engine(serde_json::json!({
+            "impossible_travel": {"enabled": true, "window": "1h"},
+        }));
+        engine.observe(&[
+            login("bob", NEW_YORK.0, NEW_YORK.1, 0),
+            // Out-of-range coordinates and logins without a user are skipped
+            login("bob", 95.0, LONDON.1, 60),
+            Event {
+                data: serde_json::json!({"geo": {"lat": LONDON.0, "lon": LONDON.1}}),
+                ..event("login", "vpn01", 120)
+            },
+        ]);
+        assert!(engine.alerts().is_empty());
+
+        // Past the window the earlier location is forgotten
+        clock.advance(chrono::Duration::hours(2));
+        engine.observe(&[login("bob", LONDON.0, LONDON.1, 7200)]);
+        assert!(engine.alerts().is_empty());
+        assert_eq!(engine.locations.lock().by_user.len(), 1);
+    }
+
+    #[test]
+    fn absence_fires_once_after_a_quiet_window_and_resolves() {
+        let (engine, clock) = engine(serde_json::json!({
+            "absence": [{
+                "name": "nightly backup",
+                "match": {"event_type": "backup_done", "host": "db01"},
+                "min_count": 2,
+                "window": "1h",
+            }],
+        }));
+        // A freshly started server waits a whole window before judging
+        engine.check();
+        assert!(engine.alerts().is_empty());
+
+        clock.advance(chrono::Duration::minutes(30));
+        engine.observe(&[event("backup_done", "db01", 1800)]);
+        clock.advance(chrono::Duration::minutes(31));
+        engine.check();
+        engine.check();
+        let alerts = engine.active_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, "absence");
+        assert_eq!(alerts[0].host, "db01");
+        assert!(
+            alerts[0].message.ends_with("saw 1"),
+            "{}",
+            alerts[0].message
+        );
+
+        // Other hosts' backups don't count
+        engine.observe(&[event("backup_done", "db02", 3700)]);
+        engine.check();
+        assert_eq!(engine.active_alerts().len(), 1);
+
+        engine.observe(&[event("backup_done", "db01", 3700)]);
+        engine.check();
+        assert!(engine.active_alerts().is_empty());
+        assert_eq!(engine.alerts().len(), 1);
+        assert!(engine.alerts()[0].resolved.is_some());
+    }
+
+    #[test]
+    fn absence_forgets_arrivals_past_the_window() {
+        let (engine, clock) = engine(serde_json::json!({
+            "absence": [{"name": "heartbeat", "match": {"event_type": "tick"}, "window": "10m"}],
+        }));
+        // Only the newest arrivals that can satisfy the rule are kept
+        engine.observe(&[event("tick", "web01", 0), event("tick", "web01", 1)]);
+        assert_eq!(engine.absence_state.lock()[0].seen.len(), 1);
+
+        clock.advance(chrono::Duration::minutes(11));
+        engine.check();
+        assert_eq!(rules(&engine), ["heartbeat"]);
+        assert!(engine.absence_state.lock()[0].seen.is_empty());
+    }
+
+    fn critical(host: &str, offset_secs: i64) -> Event {
+        Event {
+            severity: "critical".to_string(),
+            ..event("error", host, offset_secs)
+        }
+    }
+
+    fn threshold() -> serde_json::Value {
+        serde_json::json!({
+            "threshold": [{
+                "name": "critical burst",
+                "match": {"severity": "critical"},
+                "count": 2,
+                "window": "5m",
+            }],
+        })
+    }
+
+    #[test]
+    fn thresholds_fire_once_per_key_and_resolve() {
+        let (engine, clock) = engine(threshold());
+        engine.observe(&[critical("web01", 0), critical("web01", 1)]);
+        engine.observe(&[critical("db01", 2), event("error", "web01", 3)]);
+        assert!(engine.alerts().is_empty());
+
+        engine.observe(&[critical("web01", 4), critical("web01", 5)]);
+        let active = engine.active_alerts();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].kind, "threshold");
+        assert_eq!(active[0].key, "web01");
+        assert_eq!(engine.alerts().len(), 1);
+
+        // Still over the count within the window
+        engine.check();
+        assert_eq!(engine.active_alerts().len(), 1);
+        clock.advance(chrono::Duration::minutes(6));
+        engine.check();
+        assert!(engine.active_alerts().is_empty());
+        assert!(engine.alerts()[0].resolved.is_some());
+        // Idle keys are forgotten
+        assert!(engine.threshold_state.lock().is_empty());
+    }
+
+    #[test]
+    fn removed_thresholds_resolve_their_alerts() {
+        let (engine, _) = engine(threshold());
+        engine.observe(&[
+            critical("web01", 0),
+            critical("web01", 1),
+            critical("web01", 2),
+        ]);
+        assert_eq!(engine.active_alerts().len(), 1);
+        engine.thresholds.write().clear();
+        engine.check();
+        assert!(engine.active_alerts().is_empty());
+        assert!(engine.alerts()[0].resolved.is_some());
+    }
+
+    #[test]
+    fn only_api_thresholds_are_saved() {
+        let file = std::env::temp_dir().join(format!("loglumen-rules-{}.json", std::process::id()));
+        let mut detection = threshold();
+        detection["rules_file"] = file.to_string_lossy().into();
+        let (saving, _) = engine(detection.clone());
+        let mut rules = saving.thresholds.read().clone();
+        let added = |name: &str| ThresholdRule {
+            name: name.to_string(),
+            r#match: EventMatcher::default(),
+            count: 10,
+            window: Duration::from_secs(60),
+            key: default_key(),
+            severity: default_severity(),
+            message: None,
+            configured: false,
+        };
+        rules.push(added("api rule"));
+        // Names from server.toml win over the file on the next start
+        rules.push(added("critical burst"));
+        saving.save(&rules).unwrap();
+
+        let (reloaded, _) = engine(detection);
+        std::fs::remove_file(&file).unwrap();
+        let reloaded = reloaded.thresholds.read();
+        let names: Vec<(&str, bool)> = reloaded
+            .iter()
+            .map(|rule| (rule.name.as_str(), rule.configured))
+            .collect();
+        assert_eq!(names, [("critical burst", true), ("api rule", false)]);
+        assert_eq!(reloaded[1].window, Duration::from_secs(60));
+    }
+}
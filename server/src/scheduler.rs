@@ -0,0 +1,555 @@
+use crate::clock::SharedClock;
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Arc;
+
+// Runs kept for the admin API
+const MAX_HISTORY: usize = 200;
+
+// Parsed 5-field cron expression (minute hour day-of-month month day-of-week),
+// evaluated in UTC. Supports `*`, lists, ranges and `/step`.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    // Standard cron: when both day fields are restricted, either may match
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "expected 5 fields in cron expression '{}', found {}",
+                expression,
+                fields.len()
+            ));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 is an alias for Sunday
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            days_restricted: fields[2] != "*",
+            weekdays_restricted: fields[4] != "*",
+        })
+    }
+
+    fn matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days[time.day() as usize];
+        let weekday = self.weekdays[time.weekday().num_days_from_sunday() as usize];
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        };
+
+        self.minutes[time.minute() as usize]
+            && self.hours[time.hour() as usize]
+            && self.months[time.month() as usize]
+            && day_matches
+    }
+
+    // First matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut candidate = after
+            .with_second(0)
+            .and_then(|time| time.with_nanosecond(0))?
+            + Duration::minutes(1);
+
+        // Four years covers every valid combination, including Feb 29
+        let limit = after + Duration::days(4 * 366);
+        while candidate <= limit {
+            if !self.months[candidate.month() as usize] {
+                candidate = first_of_next_month(candidate)?;
+                continue;
+            }
+            if self.matches(&candidate) {
+                return Some(candidate);
+            }
+            if !self.hours[candidate.hour() as usize] {
+                candidate = candidate.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            candidate += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+fn first_of_next_month(time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let (year, month) = if time.month() == 12 {
+        (time.year() + 1, 1)
+    } else {
+        (time.year(), time.month() + 1)
+    };
+    chrono::NaiveDate::from_ymd_opt(year, month, 1)?
+        .and_hms_opt(0, 0, 0)
+        .map(|naive| naive.and_utc())
+}
+
+// Returns a lookup table indexed by value (0..=max)
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max as usize + 1];
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("step must be positive in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, part)?, parse_value(end, part)?)
+        } else {
+            let value = parse_value(range, part)?;
+            // "5/15" means "every 15 starting at 5"
+            (value, if step > 1 { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!(
+                "'{}' is outside the allowed range {}-{}",
+                part, min, max
+            ));
+        }
+
+        let mut value = start;
+        while value <= end {
+            allowed[value as usize] = true;
+            value += step;
+        }
+    }
+
+    Ok(allowed)
+}
+
+fn parse_value(value: &str, part: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value in '{}'", part))
+}
+
+pub type TaskFn = Arc<dyn Fn(&AppState) -> Result<String, String> + Send + Sync>;
+
+struct Task {
+    name: String,
+    expression: String,
+    schedule: CronSchedule,
+    run: TaskFn,
+    next_run: Mutex<Option<DateTime<Utc>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TaskRun {
+    task: String,
+    started_at: String,
+    duration_ms: u128,
+    status: &'static str,
+    detail: String,
+}
+
+// User-defined tasks from `[[scheduler.tasks]]`
+#[derive(Debug, Clone, Deserialize)]
+struct TaskConfig {
+    name: String,
+    cron: String,
+    action: String,
+    // Output file for the `backup` action
+    path: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SchedulerConfig {
+    #[serde(default)]
+    tasks: Vec<TaskConfig>,
+}
+
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    history: Mutex<VecDeque<TaskRun>>,
+    // Same clock as AppState's, so a manual clock also moves the schedule
+    clock: SharedClock,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig, clock: SharedClock) -> Self {
+        let mut scheduler = Scheduler {
+            tasks: Vec::new(),
+            history: Mutex::new(VecDeque::new()),
+            clock,
+        };
+
+        for task in config.tasks {
+            let run = match action(&task) {
+                Ok(run) => run,
+                Err(err) => {
+                    log_line!("WARN", "Skipping scheduled task '{}': {}", task.name, err);
+                    continue;
+                }
+            };
+            if let Err(err) = scheduler.register(&task.name, &task.cron, run) {
+                log_line!("WARN", "Skipping scheduled task '{}': {}", task.name, err);
+            }
+        }
+
+        scheduler
+    }
+
+    // Subsystems call this at startup to run periodic work on a cron schedule
+    pub fn register(&mut self, name: &str, expression: &str, run: TaskFn) -> Result<(), String> {
+        let schedule = CronSchedule::parse(expression)?;
+        let next_run = schedule.next_after(self.clock.now());
+        log_line!("CONFIG", "Scheduled task '{}' ({})", name, expression);
+        self.tasks.push(Task {
+            name: name.to_string(),
+            expression: expression.to_string(),
+            schedule,
+            run,
+            next_run: Mutex::new(next_run),
+        });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    fn record(&self, run: TaskRun) {
+        let mut history = self.history.lock();
+        if history.len() >= MAX_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(run);
+    }
+
+    // Tasks that are due at `now`, each moved on to its next run, and when
+    // the loop should look again (at least once a minute)
+    fn take_due(&self, now: DateTime<Utc>) -> (Vec<usize>, DateTime<Utc>) {
+        let mut due = Vec::new();
+        let mut next_wake = now + Duration::seconds(60);
+
+        for (index, task) in self.tasks.iter().enumerate() {
+            let mut next_run = task.next_run.lock();
+            if let Some(time) = *next_run {
+                if time <= now {
+                    due.push(index);
+                    *next_run = task.schedule.next_after(now);
+                }
+            }
+            if let Some(time) = *next_run {
+                next_wake = next_wake.min(time);
+            }
+        }
+        (due, next_wake)
+    }
+}
+
+fn action(task: &TaskConfig) -> Result<TaskFn, String> {
+    match task.action.as_str() {
+        "stats_report" => Ok(Arc::new(|state: &AppState| {
+            let store = state.store.read();
+            let mut hosts: Vec<&str> = store.iter().map(|event| event.host.as_str()).collect();
+            hosts.sort_unstable();
+            hosts.dedup();
+            let summary = format!("{} events from {} hosts", store.len(), hosts.len());
+            log_line!("REPORT", "{}", summary);
+            Ok(summary)
+        })),
+        "backup" => {
+            let path = task
+                .path
+                .clone()
+                .ok_or_else(|| "backup action needs a `path`".to_string())?;
+            Ok(Arc::new(move |state: &AppState| backup_to(state, &path)))
+        }
+        other => Err(format!("unknown action '{}'", other)),
+    }
+}
+
+// Events copied per hold of the store's read lock while backing up
+const BACKUP_CHUNK: usize = 10_000;
+
+// Snapshot the store as NDJSON, writing to a temp file first so a crash
+// mid-write never leaves a truncated backup behind. Events are copied out in
+// chunks and written with the lock released, so ingest isn't stalled on the
+// disk. The backup covers what was stored when it started, less anything
+// retention removes meanwhile.
+fn backup_to(state: &AppState, path: &str) -> Result<String, String> {
+    let temp_path = format!("{}.tmp", path);
+    let file = std::fs::File::create(&temp_path).map_err(|err| err.to_string())?;
+    let mut writer = std::io::BufWriter::new(file);
+    let end = state.store.stats().version;
+    let mut next = 0;
+    let mut count = 0;
+    loop {
+        let chunk: Vec<Event> = {
+            let store = state.store.read();
+            let mut chunk = Vec::new();
+            for (sequence, event) in store.range(next..end).take(BACKUP_CHUNK) {
+                chunk.push(event.clone());
+                next = sequence + 1;
+            }
+            chunk
+        };
+        if chunk.is_empty() {
+            break;
+        }
+        for event in &chunk {
+            serde_json::to_writer(&mut writer, event).map_err(|err| err.to_string())?;
+            writer.write_all(b"\n").map_err(|err| err.to_string())?;
+        }
+        count += chunk.len();
+    }
+    writer.flush().map_err(|err| err.to_string())?;
+    drop(writer);
+    std::fs::rename(&temp_path, path).map_err(|err| err.to_string())?;
+    Ok(format!("wrote {} events to {}", count, path))
+}
+
+// Run a task off the async workers and record it; returns the record
+async fn run_task(state: web::Data<AppState>, index: usize) -> TaskRun {
+    let task_state = state.clone();
+    let started_at = state.clock.now();
+    let elapsed = std::time::Instant::now();
+    let result = web::block(move || {
+        let task = &task_state.scheduler.tasks[index];
+        (task.run)(&task_state)
+    })
+    .await
+    .unwrap_or_else(|err| Err(err.to_string()));
+
+    let task = &state.scheduler.tasks[index];
+    let (status, detail) = match result {
+        Ok(detail) => ("ok", detail),
+        Err(err) => {
+            log_line!("ERROR", "Scheduled task '{}' failed: {}", task.name, err);
+            ("error", err)
+        }
+    };
+    let run = TaskRun {
+        task: task.name.clone(),
+        started_at: started_at.to_rfc3339(),
+        duration_ms: elapsed.elapsed().as_millis(),
+        status,
+        detail,
+    };
+    state.scheduler.record(run.clone());
+    run
+}
+
+// Background loop: wakes at the next due task (or at least once a minute)
+pub async fn run(state: web::Data<AppState>) {
+    loop {
+        let (due, next_wake) = state.scheduler.take_due(state.clock.now());
+        for index in due {
+            actix_web::rt::spawn(run_task(state.clone(), index));
+        }
+
+        let sleep_for = (next_wake - state.clock.now())
+            .to_std()
+            .unwrap_or(std::time::Duration::from_millis(100));
+        tokio::time::sleep(sleep_for).await;
+    }
+}
+
+// GET /api/admin/scheduler - Scheduled tasks and recent run history
+pub async fn get_scheduler(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let tasks: Vec<serde_json::Value> = data
+        .scheduler
+        .tasks
+        .iter()
+        .map(|task| {
+            serde_json::json!({
+                "name": task.name,
+                "cron": task.expression,
+                "next_run": task.next_run.lock().map(|time| time.to_rfc3339()),
+            })
+        })
+        .collect();
+    let history: Vec<TaskRun> = data
+        .scheduler
+        .history
+        .lock()
+        .iter()
+        .rev()
+        .cloned()
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "tasks": tasks,
+        "history": history,
+    })))
+}
+
+// POST /api/admin/scheduler/{task}/run - Trigger a task immediately
+pub async fn trigger_task(
+    request: HttpRequest,
+    name: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let name = name.into_inner();
+    let Some(index) = data
+        .scheduler
+        .tasks
+        .iter()
+        .position(|task| task.name == name)
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("no scheduled task named '{}'", name)
+        })));
+    };
+
+    let run = run_task(data.clone(), index).await;
+    Ok(HttpResponse::Ok().json(run))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn allowed(field: &str, min: u32, max: u32) -> Vec<u32> {
+        let table = parse_field(field, min, max).unwrap();
+        (min..=max).filter(|value| table[*value as usize]).collect()
+    }
+
+    #[test]
+    fn fields_take_steps_ranges_and_lists() {
+        assert_eq!(allowed("*/15", 0, 59), [0, 15, 30, 45]);
+        assert_eq!(allowed("5/20", 0, 59), [5, 25, 45]);
+        assert_eq!(allowed("9-12", 0, 23), [9, 10, 11, 12]);
+        assert_eq!(allowed("10-20/5", 0, 59), [10, 15, 20]);
+        assert_eq!(allowed("1,15,31", 1, 31), [1, 15, 31]);
+        assert_eq!(allowed("1-3,10,20-30/5", 1, 31), [1, 2, 3, 10, 20, 25, 30]);
+        for invalid in ["*/0", "60", "5-1", "a", "1,", "0"] {
+            assert!(parse_field(invalid, 1, 59).is_err(), "{}", invalid);
+        }
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("* * * * * *").is_err());
+    }
+
+    #[test]
+    fn next_after_follows_the_expression() {
+        let next = |expression: &str, after: &str| {
+            CronSchedule::parse(expression)
+                .unwrap()
+                .next_after(at(after))
+                .map(|time| time.to_rfc3339())
+        };
+        let expect = |time: &str| Some(at(time).to_rfc3339());
+        // Strictly after, on the minute
+        assert_eq!(
+            next("*/5 * * * *", "2026-03-01T10:05:00Z"),
+            expect("2026-03-01T10:10:00Z")
+        );
+        assert_eq!(
+            next("*/5 * * * *", "2026-03-01T10:04:59Z"),
+            expect("2026-03-01T10:05:00Z")
+        );
+        assert_eq!(
+            next("30 2 * * *", "2026-03-01T03:00:00Z"),
+            expect("2026-03-02T02:30:00Z")
+        );
+        // Monday to Friday at 9:00 and 17:00; 2026-03-06 is a Friday
+        assert_eq!(
+            next("0 9,17 * * 1-5", "2026-03-06T17:00:00Z"),
+            expect("2026-03-09T09:00:00Z")
+        );
+        // Sunday as 7
+        assert_eq!(
+            next("0 0 * * 7", "2026-03-02T00:00:00Z"),
+            expect("2026-03-08T00:00:00Z")
+        );
+        // With both day fields set, either one matches
+        assert_eq!(
+            next("0 0 13 * 5", "2026-03-01T00:00:00Z"),
+            expect("2026-03-06T00:00:00Z")
+        );
+        // Skips to the next month that allows it, and to a leap day
+        assert_eq!(
+            next("0 0 1 1,7 *", "2026-03-01T00:00:00Z"),
+            expect("2026-07-01T00:00:00Z")
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2026-03-01T00:00:00Z"),
+            expect("2028-02-29T00:00:00Z")
+        );
+        assert_eq!(next("0 0 31 2 *", "2026-03-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn schedule_follows_the_injected_clock() {
+        let clock = crate::clock::manual(at("2026-03-01T10:02:00Z"));
+        let mut scheduler = Scheduler::new(SchedulerConfig::default(), clock.clone());
+        scheduler
+            .register(
+                "every_five",
+                "*/5 * * * *",
+                Arc::new(|_: &AppState| Ok(String::new())),
+            )
+            .unwrap();
+
+        let next_run = || *scheduler.tasks[0].next_run.lock();
+        assert_eq!(next_run(), Some(at("2026-03-01T10:05:00Z")));
+
+        // Wakes at least once a minute, else at the next run
+        let (due, next_wake) = scheduler.take_due(clock.now());
+        assert!(due.is_empty());
+        assert_eq!(next_wake, at("2026-03-01T10:03:00Z"));
+        clock.manual().unwrap().set(at("2026-03-01T10:04:30Z"));
+        assert_eq!(
+            scheduler.take_due(clock.now()).1,
+            at("2026-03-01T10:05:00Z")
+        );
+
+        clock.manual().unwrap().set(at("2026-03-01T10:05:00Z"));
+        let (due, _) = scheduler.take_due(clock.now());
+        assert_eq!(due, [0]);
+        assert_eq!(next_run(), Some(at("2026-03-01T10:10:00Z")));
+        assert!(scheduler.take_due(clock.now()).0.is_empty());
+    }
+}
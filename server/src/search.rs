@@ -0,0 +1,713 @@
+// Full-text search over event messages and data values. A tantivy index in
+// memory holds each event's words with their positions under its sequence
+// number and is kept up to date as batches are stored. Queries combine words,
+// "quoted phrases", AND (implied between words), OR, NOT or a leading `-`,
+// and parentheses. The index narrows the candidates; each one is then
+// matched against the event's own text, which keeps a phrase within one
+// field, leaves out fields hidden from the caller and finds what to
+// highlight.
+use crate::access::Access;
+use crate::storage::StoreView;
+use crate::{live, severity, AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use tantivy::collector::DocSetCollector;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, PhraseQuery, RangeQuery, TermQuery};
+use tantivy::schema::{IndexRecordOption, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED};
+use tantivy::tokenizer::{PreTokenizedString, Token as TantivyToken};
+use tantivy::{IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+// Longer words (hashes, base64) are not indexed
+const MAX_TERM_LENGTH: usize = 64;
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+// Highlighted fields longer than this are cut to the part around the first
+// match
+const SNIPPET_LENGTH: usize = 200;
+// tantivy's smallest indexing buffer; documents are small and committed
+// every batch
+const WRITER_MEMORY: usize = 15_000_000;
+
+// `[search]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    enabled: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig { enabled: true }
+    }
+}
+
+struct Writer {
+    writer: IndexWriter,
+    // Evicted sequence numbers below this are already deleted
+    swept_to: u64,
+}
+
+struct Index {
+    sequence: tantivy::schema::Field,
+    text: tantivy::schema::Field,
+    writer: Mutex<Writer>,
+    reader: IndexReader,
+}
+
+pub struct SearchIndex {
+    // None with [search] enabled = false
+    index: Option<Index>,
+}
+
+impl Index {
+    fn new() -> tantivy::Result<Self> {
+        let mut schema = Schema::builder();
+        let sequence = schema.add_u64_field("sequence", INDEXED | FAST);
+        let text = schema.add_text_field(
+            "text",
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            ),
+        );
+        let index = tantivy::Index::create_in_ram(schema.build());
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(Index {
+            sequence,
+            text,
+            writer: Mutex::new(Writer {
+                writer,
+                swept_to: 0,
+            }),
+            reader,
+        })
+    }
+
+    // Makes what was added or deleted visible to searches
+    fn commit(&self, writer: &mut Writer) {
+        if let Err(err) = writer.writer.commit().and_then(|_| self.reader.reload()) {
+            log_line!("ERROR", "Failed to update the search index: {}", err);
+        }
+    }
+
+    // Every indexed sequence number, or those a query can match, ascending
+    fn sequences(&self, query: &dyn tantivy::query::Query) -> tantivy::Result<Vec<u64>> {
+        let searcher = self.reader.searcher();
+        let mut columns = Vec::new();
+        for segment in searcher.segment_readers() {
+            columns.push(segment.fast_fields().u64("sequence")?);
+        }
+        let mut sequences: Vec<u64> = searcher
+            .search(query, &DocSetCollector)?
+            .into_iter()
+            .filter_map(|address| columns[address.segment_ord as usize].first(address.doc_id))
+            .collect();
+        sequences.sort_unstable();
+        Ok(sequences)
+    }
+}
+
+impl SearchIndex {
+    pub fn new(config: SearchConfig) -> std::result::Result<Self, String> {
+        if !config.enabled {
+            log_line!("CONFIG", "Full-text search disabled");
+            return Ok(SearchIndex { index: None });
+        }
+        let index = Index::new().map_err(|err| format!("search index: {}", err))?;
+        Ok(SearchIndex { index: Some(index) })
+    }
+
+    // Each event's words by field. Runs before the store lock is taken.
+    pub fn extract<'a>(&self, batch: impl IntoIterator<Item = &'a Event>) -> Vec<TantivyDocument> {
+        let Some(index) = &self.index else {
+            return Vec::new();
+        };
+        batch
+            .into_iter()
+            .map(|event| {
+                let mut document = TantivyDocument::new();
+                for (_, text) in fields(event) {
+                    let tokens = words_of(&text)
+                        .enumerate()
+                        .map(|(position, word)| TantivyToken {
+                            offset_from: word.start,
+                            offset_to: word.end,
+                            position,
+                            text: word.text,
+                            position_length: 1,
+                        })
+                        .collect();
+                    document
+                        .add_pre_tokenized_text(index.text, PreTokenizedString { text, tokens });
+                }
+                document
+            })
+            .collect()
+    }
+
+    // Called from the store's index callback, one batch at a time
+    pub fn record(
+        &self,
+        sequences: impl IntoIterator<Item = u64>,
+        documents: Vec<TantivyDocument>,
+    ) {
+        let Some(index) = &self.index else {
+            return;
+        };
+        if documents.is_empty() {
+            return;
+        }
+        let mut writer = index.writer.lock();
+        for (sequence, mut document) in sequences.into_iter().zip(documents) {
+            document.add_u64(index.sequence, sequence);
+            if let Err(err) = writer.writer.add_document(document) {
+                log_line!("ERROR", "Failed to index event {}: {}", sequence, err);
+            }
+        }
+        index.commit(&mut writer);
+    }
+
+    // Forget events that are no longer stored, after retention removed some
+    pub fn retain(&self, store: &StoreView) {
+        let Some(index) = &self.index else {
+            return;
+        };
+        let mut writer = index.writer.lock();
+        let indexed = match index.sequences(&AllQuery) {
+            Ok(indexed) => indexed,
+            Err(err) => {
+                log_line!("ERROR", "Failed to read the search index: {}", err);
+                return;
+            }
+        };
+        for sequence in indexed {
+            if store.get(sequence).is_none() {
+                writer
+                    .writer
+                    .delete_term(Term::from_field_u64(index.sequence, sequence));
+            }
+        }
+        writer.swept_to = store.first_sequence().unwrap_or(0);
+        index.commit(&mut writer);
+    }
+
+    // Drop what a bounded store evicted. Deleting is only worth a commit once
+    // as many events were evicted as are stored; until then queries skip the
+    // evicted ones.
+    pub fn trim(&self, store: &StoreView) {
+        let Some(index) = &self.index else {
+            return;
+        };
+        let Some(first) = store.first_sequence() else {
+            return;
+        };
+        let mut writer = index.writer.lock();
+        if first.saturating_sub(writer.swept_to) < store.len() as u64 {
+            return;
+        }
+        let evicted = RangeQuery::new(
+            Bound::Unbounded,
+            Bound::Excluded(Term::from_field_u64(index.sequence, first)),
+        );
+        if let Err(err) = writer.writer.delete_query(Box::new(evicted)) {
+            log_line!("ERROR", "Failed to trim the search index: {}", err);
+            return;
+        }
+        writer.swept_to = first;
+        index.commit(&mut writer);
+    }
+}
+
+// What is searched: the message and every scalar under data, by path
+fn fields(event: &Event) -> Vec<(String, String)> {
+    fn walk(path: String, value: &serde_json::Value, out: &mut Vec<(String, String)>) {
+        match value {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(text) => out.push((path, text.clone())),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    walk(path.clone(), item, out);
+                }
+            }
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    walk(format!("{}.{}", path, key), value, out);
+                }
+            }
+            other => out.push((path, other.to_string())),
+        }
+    }
+    let mut out = vec![("message".to_string(), event.message.clone())];
+    walk("data".to_string(), &event.data, &mut out);
+    out
+}
+
+struct Word {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+// Runs of letters, digits and `_`, lowercased
+fn words_of(text: &str) -> impl Iterator<Item = Word> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || loop {
+        let (start, _) = chars.find(|(_, c)| c.is_alphanumeric() || *c == '_')?;
+        let mut end = text.len();
+        while let Some((index, c)) = chars.peek().copied() {
+            if !(c.is_alphanumeric() || c == '_') {
+                end = index;
+                break;
+            }
+            chars.next();
+        }
+        if end - start <= MAX_TERM_LENGTH {
+            return Some(Word {
+                start,
+                end,
+                text: text[start..end].to_lowercase(),
+            });
+        }
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Query {
+    Term(String),
+    Phrase(Vec<String>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Words(Vec<String>),
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, String> {
+    let words = |text: &str| Token::Words(words_of(text).map(|word| word.text).collect());
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = match c {
+            '(' => {
+                tokens.push(Token::Open);
+                1
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                1
+            }
+            '"' => {
+                let end = rest[1..].find('"').ok_or("unclosed quote")?;
+                tokens.push(words(&rest[1..1 + end]));
+                end + 2
+            }
+            '-' if rest[1..].starts_with(|c: char| !c.is_whitespace()) => {
+                tokens.push(Token::Not);
+                1
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '"')
+                    .unwrap_or(rest.len());
+                tokens.push(match &rest[..end] {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    // `192.168.1.10` or `disk-full` are searched as phrases
+                    word => words(word),
+                });
+                end
+            }
+        };
+        rest = rest[length..].trim_start();
+    }
+    // Punctuation on its own has no words to search for
+    tokens.retain(|token| !matches!(token, Token::Words(words) if words.is_empty()));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn parse(input: &str) -> Result<Query, String> {
+        let mut parser = Parser {
+            tokens: lex(input)?.into_iter().peekable(),
+        };
+        if parser.tokens.peek().is_none() {
+            return Err("empty query".to_string());
+        }
+        let query = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(query),
+            Some(Token::Close) => Err("unbalanced ')'".to_string()),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Query, String> {
+        let mut any = vec![self.and()?];
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            any.push(self.and()?);
+        }
+        Ok(if any.len() == 1 {
+            any.remove(0)
+        } else {
+            Query::Or(any)
+        })
+    }
+
+    fn and(&mut self) -> Result<Query, String> {
+        let mut all = vec![self.unary()?];
+        loop {
+            match self.tokens.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) => {
+                    self.tokens.next();
+                }
+                _ => {}
+            }
+            all.push(self.unary()?);
+        }
+        Ok(if all.len() == 1 {
+            all.remove(0)
+        } else {
+            Query::And(all)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Query, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Query::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(Token::Words(mut words)) if words.len() == 1 => Ok(Query::Term(words.remove(0))),
+            Some(Token::Words(words)) => Ok(Query::Phrase(words)),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("query ends with an operator".to_string()),
+        }
+    }
+}
+
+impl Query {
+    // The index query for the events that can match, or None when the index
+    // can't narrow it down (only negations)
+    fn candidates(&self, index: &Index) -> Option<Box<dyn tantivy::query::Query>> {
+        let term = |word: &str| Term::from_field_text(index.text, word);
+        let all = |queries: Vec<Box<dyn tantivy::query::Query>>, occur: Occur| {
+            let clauses = queries.into_iter().map(|query| (occur, query)).collect();
+            Box::new(BooleanQuery::new(clauses)) as Box<dyn tantivy::query::Query>
+        };
+        match self {
+            Query::Term(word) => Some(Box::new(TermQuery::new(
+                term(word),
+                IndexRecordOption::Basic,
+            ))),
+            Query::Phrase(words) => Some(Box::new(PhraseQuery::new(
+                words.iter().map(|word| term(word)).collect(),
+            ))),
+            Query::And(queries) => {
+                let narrowing: Vec<_> = queries
+                    .iter()
+                    .filter_map(|query| query.candidates(index))
+                    .collect();
+                (!narrowing.is_empty()).then(|| all(narrowing, Occur::Must))
+            }
+            Query::Or(queries) => queries
+                .iter()
+                .map(|query| query.candidates(index))
+                .collect::<Option<Vec<_>>>()
+                .map(|any| all(any, Occur::Should)),
+            Query::Not(_) => None,
+        }
+    }
+
+    fn matches(&self, document: &[Field]) -> bool {
+        match self {
+            Query::Term(_) | Query::Phrase(_) => {
+                document.iter().any(|field| !field.spans(self).is_empty())
+            }
+            Query::And(all) => all.iter().all(|query| query.matches(document)),
+            Query::Or(any) => any.iter().any(|query| query.matches(document)),
+            Query::Not(query) => !query.matches(document),
+        }
+    }
+
+    // Words and phrases that count toward a match, for highlighting
+    fn positives<'a>(&'a self, out: &mut Vec<&'a Query>) {
+        match self {
+            Query::Term(_) | Query::Phrase(_) => out.push(self),
+            Query::And(queries) | Query::Or(queries) => {
+                for query in queries {
+                    query.positives(out);
+                }
+            }
+            Query::Not(_) => {}
+        }
+    }
+}
+
+struct Field {
+    path: String,
+    text: String,
+    words: Vec<Word>,
+}
+
+impl Field {
+    // Byte ranges of the text matched by a word or phrase
+    fn spans(&self, query: &Query) -> Vec<(usize, usize)> {
+        let phrase: &[String] = match query {
+            Query::Term(word) => std::slice::from_ref(word),
+            Query::Phrase(words) => words,
+            _ => return Vec::new(),
+        };
+        self.words
+            .windows(phrase.len())
+            .filter(|window| {
+                window
+                    .iter()
+                    .zip(phrase)
+                    .all(|(word, text)| word.text == *text)
+            })
+            .map(|window| (window[0].start, window[window.len() - 1].end))
+            .collect()
+    }
+
+    // The text, HTML-escaped, with matches in <mark>
+    fn highlight(&self, mut spans: Vec<(usize, usize)>) -> String {
+        spans.sort_unstable();
+        let (mut from, mut to) = (0, self.text.len());
+        if to > SNIPPET_LENGTH {
+            from = floor_boundary(&self.text, spans[0].0.saturating_sub(SNIPPET_LENGTH / 4));
+            to = floor_boundary(&self.text, (from + SNIPPET_LENGTH).min(self.text.len()));
+        }
+        let mut out = String::new();
+        if from > 0 {
+            out.push('…');
+        }
+        let mut at = from;
+        for (start, end) in spans {
+            if start < at || end > to {
+                continue;
+            }
+            out.push_str(&escape(&self.text[at..start]));
+            out.push_str("<mark>");
+            out.push_str(&escape(&self.text[start..end]));
+            out.push_str("</mark>");
+            at = end;
+        }
+        out.push_str(&escape(&self.text[at..to]));
+        if to < self.text.len() {
+            out.push('…');
+        }
+        out
+    }
+}
+
+fn floor_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The fields the role may see; hidden ones are neither matched nor
+// highlighted, so a query can't probe their values
+fn document(event: &Event, access: &Access, role: &str) -> Vec<Field> {
+    fields(event)
+        .into_iter()
+        .filter(|(path, _)| !access.hides(role, path))
+        .map(|(path, text)| Field {
+            words: words_of(&text).collect(),
+            path,
+            text,
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+    min_severity: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Hit<'a> {
+    sequence: u64,
+    event: &'a Event,
+    // Field path to its text with the matches marked
+    highlights: BTreeMap<String, String>,
+}
+
+// GET /api/search?q=... - Events whose message or data match, newest first
+pub async fn search(
+    request: HttpRequest,
+    query: web::Query<SearchQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let bad_request = |message: String| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": message
+        }))
+    };
+    let Some(index) = &data.search.index else {
+        return Ok(bad_request(
+            "full-text search is disabled ([search] enabled = false)".to_string(),
+        ));
+    };
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let parsed = match Parser::parse(&query.q) {
+        Ok(parsed) => parsed,
+        Err(err) => return Ok(bad_request(format!("invalid query: {}", err))),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut positives = Vec::new();
+    parsed.positives(&mut positives);
+    let role = live::caller_role(&request);
+
+    // Same lock order as ingest: store first, then the index
+    let store = data.store.read();
+    let candidates = match parsed
+        .candidates(index)
+        .map(|candidates| index.sequences(&*candidates))
+        .transpose()
+    {
+        Ok(candidates) => candidates,
+        Err(err) => {
+            log_line!("ERROR", "Search failed: {}", err);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": "search failed"
+            })));
+        }
+    };
+    let entries: Box<dyn Iterator<Item = (u64, &Event)>> = match &candidates {
+        Some(sequences) => Box::new(
+            sequences
+                .iter()
+                .rev()
+                .filter_map(|sequence| Some((*sequence, store.get(*sequence)?))),
+        ),
+        None => Box::new(store.entries().rev()),
+    };
+    let mut total = 0;
+    let mut hits = Vec::new();
+    for (sequence, event) in entries {
+        if !severity::passes(event, floor) {
+            continue;
+        }
+        let document = document(event, &data.access, &role);
+        if !parsed.matches(&document) {
+            continue;
+        }
+        total += 1;
+        if hits.len() == limit {
+            continue;
+        }
+        let highlights = document
+            .iter()
+            .filter_map(|field| {
+                let spans: Vec<(usize, usize)> = positives
+                    .iter()
+                    .flat_map(|query| field.spans(query))
+                    .collect();
+                (!spans.is_empty()).then(|| (field.path.clone(), field.highlight(spans)))
+            })
+            .collect();
+        hits.push(Hit {
+            sequence,
+            event,
+            highlights,
+        });
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "query": query.q,
+        "total": total,
+        "events": hits,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(message: &str, data: serde_json::Value) -> Event {
+        Event {
+            message: message.to_string(),
+            data,
+            ..Event::default()
+        }
+    }
+
+    fn indexed(events: &[Event]) -> SearchIndex {
+        let search = SearchIndex::new(SearchConfig::default()).unwrap();
+        search.record(1.., search.extract(events));
+        search
+    }
+
+    fn candidates(search: &SearchIndex, query: &str) -> Option<Vec<u64>> {
+        let index = search.index.as_ref().unwrap();
+        let query = Parser::parse(query).unwrap();
+        query
+            .candidates(index)
+            .map(|query| index.sequences(&*query).unwrap())
+    }
+
+    #[test]
+    fn index_narrows_words_phrases_and_or() {
+        let search = indexed(&[
+            event("Disk full on web1", serde_json::json!({})),
+            event("disk ok", serde_json::json!({"host": "web2"})),
+            event("full disk", serde_json::json!({})),
+        ]);
+        assert_eq!(candidates(&search, "disk"), Some(vec![1, 2, 3]));
+        assert_eq!(candidates(&search, "\"disk full\""), Some(vec![1]));
+        assert_eq!(candidates(&search, "web1 OR web2"), Some(vec![1, 2]));
+        assert_eq!(candidates(&search, "full -web1"), Some(vec![1, 3]));
+        assert_eq!(candidates(&search, "-web1"), None);
+    }
+
+    #[test]
+    fn phrases_are_checked_within_one_field() {
+        let document = document(
+            &event("disk", serde_json::json!({"state": "full"})),
+            &Access::new(Default::default()),
+            "admin",
+        );
+        assert!(Parser::parse("disk full").unwrap().matches(&document));
+        assert!(!Parser::parse("\"disk full\"").unwrap().matches(&document));
+    }
+}
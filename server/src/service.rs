@@ -0,0 +1,283 @@
+use std::io;
+
+// `loglumen-server --service <install|uninstall|run>` for running under the
+// native service manager (Windows SCM, launchd on macOS).
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ServiceCommand {
+    Install,
+    Uninstall,
+    Run,
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+const SERVICE_NAME: &str = "LoglumenServer";
+
+// Services start in the system directory; resolve the relative config/ folder
+// against the directory we were installed from instead.
+#[cfg(any(windows, target_os = "macos"))]
+fn install_dir() -> io::Result<std::path::PathBuf> {
+    std::env::current_dir()
+}
+
+#[cfg(windows)]
+pub fn install() -> io::Result<()> {
+    use std::ffi::OsString;
+    use windows_service::service::{
+        ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType,
+    };
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(io::Error::other)?;
+
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("Loglumen Server"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![
+            OsString::from("--service"),
+            OsString::from("run"),
+            OsString::from("--workdir"),
+            install_dir()?.into_os_string(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(io::Error::other)?;
+    service
+        .set_description("Loglumen SIEM event collector and dashboard")
+        .map_err(io::Error::other)?;
+
+    println!("[OK] Installed Windows service {}", SERVICE_NAME);
+    println!("     Start it with: sc.exe start {}", SERVICE_NAME);
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn uninstall() -> io::Result<()> {
+    use windows_service::service::{ServiceAccess, ServiceState};
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(io::Error::other)?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .map_err(io::Error::other)?;
+
+    let status = service.query_status().map_err(io::Error::other)?;
+    if status.current_state != ServiceState::Stopped {
+        service.stop().map_err(io::Error::other)?;
+    }
+    service.delete().map_err(io::Error::other)?;
+
+    println!("[OK] Removed Windows service {}", SERVICE_NAME);
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn run() -> io::Result<()> {
+    windows::run()
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::io;
+    use std::time::Duration;
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn run() -> io::Result<()> {
+        if let Some(dir) = workdir_from_args() {
+            std::env::set_current_dir(dir)?;
+        }
+        service_dispatcher::start(super::SERVICE_NAME, ffi_service_main).map_err(io::Error::other)
+    }
+
+    fn workdir_from_args() -> Option<String> {
+        let args: Vec<String> = std::env::args().collect();
+        let position = args.iter().position(|arg| arg == "--workdir")?;
+        args.get(position + 1).cloned()
+    }
+
+    // There is no console under the SCM, so stdout/stderr go to a log file
+    // next to the config folder.
+    fn redirect_output() -> io::Result<()> {
+        use std::os::windows::io::IntoRawHandle;
+        use windows_sys::Win32::System::Console::{
+            SetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE,
+        };
+
+        std::fs::create_dir_all("logs")?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open("logs/loglumen-server.log")?;
+        let handle = file.into_raw_handle();
+        // SAFETY: the handle stays open for the life of the process
+        unsafe {
+            SetStdHandle(STD_OUTPUT_HANDLE, handle as _);
+            SetStdHandle(STD_ERROR_HANDLE, handle as _);
+        }
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let _ = redirect_output();
+        if let Err(err) = run_service() {
+            log_line!("ERROR", "Service stopped with error: {}", err);
+        }
+    }
+
+    fn status(state: ServiceState, accept: ServiceControlAccept) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: accept,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::from_secs(10),
+            process_id: None,
+        }
+    }
+
+    fn run_service() -> io::Result<()> {
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let mut shutdown_tx = Some(shutdown_tx);
+
+        let handler = move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(sender) = shutdown_tx.take() {
+                    let _ = sender.send(());
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let status_handle = service_control_handler::register(super::SERVICE_NAME, handler)
+            .map_err(io::Error::other)?;
+
+        status_handle
+            .set_service_status(status(
+                ServiceState::Running,
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            ))
+            .map_err(io::Error::other)?;
+
+        let result = actix_web::rt::System::new().block_on(crate::serve(Some(shutdown_rx)));
+
+        status_handle
+            .set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty()))
+            .map_err(io::Error::other)?;
+        result
+    }
+}
+
+#[cfg(target_os = "macos")]
+const LAUNCHD_PLIST: &str = "/Library/LaunchDaemons/com.loglumen.server.plist";
+
+#[cfg(target_os = "macos")]
+pub fn install() -> io::Result<()> {
+    let exe = std::env::current_exe()?;
+    let workdir = install_dir()?;
+    let plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.loglumen.server</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>--service</string>
+        <string>run</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>/var/log/loglumen-server.log</string>
+    <key>StandardErrorPath</key>
+    <string>/var/log/loglumen-server.log</string>
+</dict>
+</plist>
+"#,
+        exe.display(),
+        workdir.display()
+    );
+
+    std::fs::write(LAUNCHD_PLIST, plist)?;
+    launchctl(&["load", "-w", LAUNCHD_PLIST])?;
+    println!(
+        "[OK] Installed launchd job com.loglumen.server ({})",
+        LAUNCHD_PLIST
+    );
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn uninstall() -> io::Result<()> {
+    launchctl(&["unload", "-w", LAUNCHD_PLIST])?;
+    std::fs::remove_file(LAUNCHD_PLIST)?;
+    println!("[OK] Removed launchd job com.loglumen.server");
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn launchctl(args: &[&str]) -> io::Result<()> {
+    let status = std::process::Command::new("launchctl")
+        .args(args)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "launchctl {} failed",
+            args.join(" ")
+        )))
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn install() -> io::Result<()> {
+    Err(io::Error::other(
+        "--service install is for Windows and macOS; on Linux use scripts/install_loglumen_server.sh (systemd)",
+    ))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn uninstall() -> io::Result<()> {
+    Err(io::Error::other(
+        "--service uninstall is for Windows and macOS; on Linux use scripts/uninstall_loglumen_server.sh",
+    ))
+}
+
+// launchd and systemd both supervise a foreground process that exits on
+// SIGTERM, which is exactly what the normal server does.
+#[cfg(not(windows))]
+pub fn run() -> io::Result<()> {
+    actix_web::rt::System::new().block_on(crate::serve(None))
+}
@@ -0,0 +1,612 @@
+// Dashboard logins. Once users are configured, the dashboard and every read
+// API need a session cookie from POST /api/auth/login (or an `[access]`
+// bearer token). Ingestion and agent endpoints keep their own API keys and
+// stay open to agents.
+use crate::access::Principal;
+use crate::config;
+use crate::AppState;
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use ring::{digest, pbkdf2};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+pub const COOKIE: &str = "loglumen_session";
+// OWASP's current figure for PBKDF2-HMAC-SHA256
+const ITERATIONS: u32 = 600_000;
+// Failed logins from one address before it has to wait
+const MAX_FAILURES: usize = 5;
+const FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+// Open with logins on: ingestion and agent traffic, whose handlers check API
+// keys and agent tokens themselves, and what the login page needs. A route
+// only belongs here if its handler does that.
+const OPEN: &[(Method, &str)] = &[
+    (Method::POST, "/api/events"),
+    (Method::POST, "/api/events/ndjson"),
+    (Method::POST, "/api/events/raw"),
+    (Method::POST, "/api/hook/{source}"),
+    (Method::POST, "/loki/api/v1/push"),
+    (Method::GET, "/es"),
+    (Method::POST, "/es/_bulk"),
+    (Method::POST, "/es/{index}/_bulk"),
+    (Method::POST, "/services/collector"),
+    (Method::POST, "/services/collector/event"),
+    (Method::POST, "/services/collector/event/1.0"),
+    (Method::POST, "/services/collector/raw"),
+    (Method::GET, "/services/collector/health"),
+    (Method::POST, "/api/v2/logs"),
+    (Method::POST, "/v1/input"),
+    (Method::POST, "/v1/logs"),
+    (Method::POST, "/api/inventory"),
+    (Method::POST, "/api/heartbeat"),
+    (Method::POST, "/api/agents"),
+    (Method::PUT, "/api/agents/{id}"),
+    (Method::DELETE, "/api/agents/{id}"),
+    (Method::GET, "/api/agents/{host}/control"),
+    (Method::GET, "/api/capabilities"),
+    (Method::GET, "/api/version"),
+    (Method::GET, "/login.html"),
+    (Method::GET, "/login.js"),
+    (Method::GET, "/style.css"),
+    (Method::POST, "/api/auth/login"),
+    (Method::POST, "/api/auth/logout"),
+    (Method::GET, "/api/auth/session"),
+];
+
+// `[[auth.users]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserConfig {
+    name: String,
+    // From `loglumen-server hash-password`, or `{SHA}` from `htpasswd -s`
+    password_hash: String,
+    // `[access]` role, for hidden fields and admin endpoints
+    role: Option<String>,
+}
+
+// `[auth]` section. Unknown fields are refused, as a misspelt `users` would
+// otherwise leave logins off.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    users: Vec<UserConfig>,
+    // `name:hash` lines, read again on each login so users can be added
+    // without a restart
+    htpasswd: Option<String>,
+    // Role of users without one
+    default_role: String,
+    // Sessions end after this long without a request
+    #[serde(deserialize_with = "config::de_duration")]
+    session_ttl: Duration,
+    // Send the cookie only over HTTPS; defaults to on when [tls] is
+    secure_cookie: Option<bool>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            users: Vec::new(),
+            htpasswd: None,
+            default_role: "viewer".to_string(),
+            session_ttl: Duration::from_secs(12 * 60 * 60),
+            secure_cookie: None,
+        }
+    }
+}
+
+struct Session {
+    principal: Principal,
+    expires: DateTime<Utc>,
+}
+
+pub struct Sessions {
+    enabled: bool,
+    users: Vec<UserConfig>,
+    htpasswd: Option<String>,
+    default_role: String,
+    ttl: chrono::Duration,
+    secure: bool,
+    // Hex SHA-256 of the cookie value -> session
+    sessions: Mutex<HashMap<String, Session>>,
+    // Peer -> failed logins in the current window and when it started
+    failures: Mutex<HashMap<IpAddr, (usize, std::time::Instant)>>,
+}
+
+fn hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// `$pbkdf2-sha256$<iterations>$<salt>$<hash>`, base64 without padding
+pub fn hash_password(password: &str) -> std::result::Result<String, String> {
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).map_err(|err| err.to_string())?;
+    let mut derived = [0u8; digest::SHA256_OUTPUT_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(ITERATIONS).expect("nonzero"),
+        &salt,
+        password.as_bytes(),
+        &mut derived,
+    );
+    let b64 = base64::engine::general_purpose::STANDARD_NO_PAD;
+    Ok(format!(
+        "$pbkdf2-sha256${}${}${}",
+        ITERATIONS,
+        b64.encode(salt),
+        b64.encode(derived)
+    ))
+}
+
+// Whether `hash` is a format `verify_password` understands
+fn supported(hash: &str) -> bool {
+    hash.starts_with("$pbkdf2-sha256$") || hash.starts_with("{SHA}")
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    let b64 = base64::engine::general_purpose::STANDARD_NO_PAD;
+    if let Some(rest) = hash.strip_prefix("$pbkdf2-sha256$") {
+        let parts: Vec<&str> = rest.split('$').collect();
+        let [iterations, salt, derived] = parts[..] else {
+            return false;
+        };
+        let (Some(iterations), Ok(salt), Ok(derived)) = (
+            iterations.parse().ok().and_then(NonZeroU32::new),
+            b64.decode(salt.trim_end_matches('=')),
+            b64.decode(derived.trim_end_matches('=')),
+        ) else {
+            return false;
+        };
+        return pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &derived,
+        )
+        .is_ok();
+    }
+    // `htpasswd -s`: unsalted SHA-1, accepted for existing files
+    if let Some(expected) = hash.strip_prefix("{SHA}") {
+        let Ok(expected) = b64.decode(expected.trim_end_matches('=')) else {
+            return false;
+        };
+        let actual = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes());
+        return actual.as_ref().len() == expected.len()
+            && actual
+                .as_ref()
+                .iter()
+                .zip(&expected)
+                .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+    }
+    false
+}
+
+impl Sessions {
+    pub fn new(config: AuthConfig, tls_enabled: bool) -> Self {
+        let enabled = !config.users.is_empty() || config.htpasswd.is_some();
+        let users: Vec<UserConfig> = config
+            .users
+            .into_iter()
+            .filter(|user| {
+                let ok = supported(&user.password_hash);
+                if !ok {
+                    log_line!(
+                        "WARN",
+                        "[auth] user '{}' has an unsupported password hash; create one with `loglumen-server hash-password`",
+                        user.name
+                    );
+                }
+                ok
+            })
+            .collect();
+        let sessions = Sessions {
+            enabled,
+            users,
+            htpasswd: config.htpasswd,
+            default_role: config.default_role,
+            ttl: chrono::Duration::from_std(config.session_ttl)
+                .unwrap_or(chrono::Duration::hours(12)),
+            secure: config.secure_cookie.unwrap_or(tls_enabled),
+            sessions: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+        };
+        if enabled {
+            log_line!(
+                "CONFIG",
+                "Dashboard and read APIs require a login ({} users{})",
+                sessions.users.len(),
+                sessions
+                    .htpasswd
+                    .as_ref()
+                    .map(|file| format!(" plus {}", file))
+                    .unwrap_or_default()
+            );
+        }
+        sessions
+    }
+
+    // Users from the config, then the htpasswd file; the config wins
+    fn find_user(&self, name: &str) -> Option<UserConfig> {
+        if let Some(user) = self.users.iter().find(|user| user.name == name) {
+            return Some(user.clone());
+        }
+        let file = self.htpasswd.as_ref()?;
+        let contents = match std::fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                log_line!("ERROR", "Could not read htpasswd file {}: {}", file, err);
+                return None;
+            }
+        };
+        contents.lines().find_map(|line| {
+            let (user, hash) = line.trim().split_once(':')?;
+            (user == name).then(|| UserConfig {
+                name: user.to_string(),
+                password_hash: hash.to_string(),
+                role: None,
+            })
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Who the session cookie belongs to, extending the session
+    pub fn principal(&self, request: &HttpRequest) -> Option<Principal> {
+        if !self.enabled {
+            return None;
+        }
+        let cookie = request.cookie(COOKIE)?;
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock();
+        let session = sessions.get_mut(&hash(cookie.value()))?;
+        if session.expires <= now {
+            sessions.remove(&hash(cookie.value()));
+            return None;
+        }
+        session.expires = now + self.ttl;
+        Some(session.principal.clone())
+    }
+
+    // The response for a request that needs a login and has none. Pages
+    // redirect to the login form; APIs get a 401.
+    pub fn refuse(&self, request: &ServiceRequest) -> Option<HttpResponse> {
+        if !self.enabled {
+            return None;
+        }
+        let path = request.path();
+        let open = OPEN.iter().any(|(method, pattern)| {
+            method == request.method() && crate::routes::pattern_matches(pattern, path)
+        });
+        if open {
+            return None;
+        }
+        let page = request.method() == Method::GET && (path == "/" || path.ends_with(".html"));
+        Some(if page {
+            let next = percent_encoding::utf8_percent_encode(
+                request
+                    .uri()
+                    .path_and_query()
+                    .map_or("/", |value| value.as_str()),
+                percent_encoding::NON_ALPHANUMERIC,
+            );
+            HttpResponse::Found()
+                .insert_header((header::LOCATION, format!("/login.html?next={}", next)))
+                .finish()
+        } else {
+            error(StatusCode::UNAUTHORIZED, "login required")
+        })
+    }
+
+    fn cookie(&self, value: String, max_age: time::Duration) -> Cookie<'static> {
+        Cookie::build(COOKIE, value)
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .secure(self.secure)
+            .max_age(max_age)
+            .finish()
+    }
+
+    // Too many recent failures from this address
+    fn throttled(&self, peer: Option<IpAddr>) -> Option<u64> {
+        let peer = peer?;
+        let mut failures = self.failures.lock();
+        failures.retain(|_, (_, since)| since.elapsed() < FAILURE_WINDOW);
+        let (count, since) = failures.get(&peer)?;
+        (*count >= MAX_FAILURES).then(|| (FAILURE_WINDOW - since.elapsed()).as_secs().max(1))
+    }
+
+    fn failed(&self, peer: Option<IpAddr>) {
+        if let Some(peer) = peer {
+            let mut failures = self.failures.lock();
+            let entry = failures
+                .entry(peer)
+                .or_insert((0, std::time::Instant::now()));
+            entry.0 += 1;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Login {
+    username: String,
+    password: String,
+}
+
+// POST /api/auth/login - Check a user's password and set the session cookie
+pub async fn login(
+    request: HttpRequest,
+    body: web::Json<Login>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let sessions = &data.sessions;
+    if !sessions.enabled {
+        return Ok(error(
+            StatusCode::NOT_FOUND,
+            "logins are off; configure [auth] users to turn them on",
+        ));
+    }
+    let peer = request.peer_addr().map(|addr| addr.ip());
+    if let Some(wait) = sessions.throttled(peer) {
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, wait.to_string()))
+            .json(serde_json::json!({
+                "status": "error",
+                "message": "too many failed logins; try again later"
+            })));
+    }
+
+    let Login { username, password } = body.into_inner();
+    let user = sessions.find_user(&username);
+    // Unknown users are checked against a throwaway hash so they take as
+    // long as wrong passwords
+    let password_hash = user
+        .as_ref()
+        .map(|user| user.password_hash.clone())
+        .unwrap_or_else(|| DUMMY_HASH.to_string());
+    let verified = web::block(move || verify_password(&password, &password_hash))
+        .await
+        .unwrap_or(false);
+    let Some(user) = user.filter(|_| verified) else {
+        sessions.failed(peer);
+        log_line!(
+            "WARN",
+            "Failed dashboard login for '{}' from {}",
+            username,
+            peer.map(|peer| peer.to_string()).unwrap_or_default()
+        );
+        return Ok(error(
+            StatusCode::UNAUTHORIZED,
+            "wrong username or password",
+        ));
+    };
+
+    let mut secret = [0u8; 32];
+    if let Err(err) = getrandom::getrandom(&mut secret) {
+        return Ok(error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("could not start a session: {}", err),
+        ));
+    }
+    let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret);
+    let principal = Principal {
+        name: user.name.clone(),
+        role: user.role.unwrap_or_else(|| sessions.default_role.clone()),
+    };
+    let now = Utc::now();
+    let expires = now + sessions.ttl;
+    {
+        let mut active = sessions.sessions.lock();
+        active.retain(|_, session| session.expires > now);
+        active.insert(
+            hash(&token),
+            Session {
+                principal: principal.clone(),
+                expires,
+            },
+        );
+    }
+    if let Some(peer) = peer {
+        sessions.failures.lock().remove(&peer);
+    }
+    log_line!("INFO", "Dashboard login for '{}'", principal.name);
+
+    let max_age = time::Duration::seconds(sessions.ttl.num_seconds());
+    Ok(HttpResponse::Ok()
+        .cookie(sessions.cookie(token, max_age))
+        .json(serde_json::json!({
+            "status": "success",
+            "user": principal.name,
+            "role": principal.role,
+            "expires": expires.to_rfc3339(),
+        })))
+}
+
+// Well formed, but no password is known to match it
+const DUMMY_HASH: &str =
+    "$pbkdf2-sha256$600000$8pPLhiKfKzTn0Mq9ZQH2aw$Vq8Mx7ho5pVZ8lC3kGv0K1cGmI4XccAVKsPpUkyi7xg";
+
+// POST /api/auth/logout - End the session and clear the cookie
+pub async fn logout(request: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Some(cookie) = request.cookie(COOKIE) {
+        data.sessions.sessions.lock().remove(&hash(cookie.value()));
+    }
+    Ok(HttpResponse::Ok()
+        .cookie(data.sessions.cookie(String::new(), time::Duration::ZERO))
+        .json(serde_json::json!({"status": "success"})))
+}
+
+// GET /api/auth/session - Who is logged in, for the dashboard header
+pub async fn get_session(request: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    if !data.sessions.enabled {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({"enabled": false})));
+    }
+    Ok(match data.sessions.principal(&request) {
+        Some(principal) => HttpResponse::Ok().json(serde_json::json!({
+            "enabled": true,
+            "user": principal.name,
+            "role": principal.role,
+        })),
+        None => error(StatusCode::UNAUTHORIZED, "not logged in"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn sessions() -> Sessions {
+        Sessions::new(
+            serde_json::from_value(serde_json::json!({
+                "users": [{"name": "alice", "password_hash": "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="}],
+            }))
+            .unwrap(),
+            false,
+        )
+    }
+
+    // Same format as hash_password, with few enough iterations to be quick
+    fn pbkdf2_hash(password: &str, iterations: u32) -> String {
+        let salt = b"0123456789abcdef";
+        let mut derived = [0u8; digest::SHA256_OUTPUT_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(iterations).unwrap(),
+            salt,
+            password.as_bytes(),
+            &mut derived,
+        );
+        let b64 = base64::engine::general_purpose::STANDARD_NO_PAD;
+        format!(
+            "$pbkdf2-sha256${}${}${}",
+            iterations,
+            b64.encode(salt),
+            b64.encode(derived)
+        )
+    }
+
+    #[test]
+    fn hashed_passwords_verify() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with(&format!("$pbkdf2-sha256${}$", ITERATIONS)));
+        // Wrong passwords are checked below, without the full iteration count
+        assert!(verify_password("correct horse", &hash));
+    }
+
+    #[test]
+    fn pbkdf2_hashes_are_checked_as_written() {
+        let hash = pbkdf2_hash("secret", 1000);
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("Secret", &hash));
+        // Padded base64, as some tools write it
+        let padded = hash.replace("MDEyMzQ1Njc4OWFiY2RlZg$", "MDEyMzQ1Njc4OWFiY2RlZg==$");
+        assert!(verify_password("secret", &padded));
+        // The iteration count is part of the hash
+        assert!(!verify_password(
+            "secret",
+            &hash.replace("$1000$", "$1001$")
+        ));
+
+        for malformed in [
+            "$pbkdf2-sha256$",
+            "$pbkdf2-sha256$0$MDEy$MDEy",
+            "$pbkdf2-sha256$1000$not base64!$MDEy",
+            "$pbkdf2-sha256$1000$MDEy",
+        ] {
+            assert!(!verify_password("secret", malformed), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn htpasswd_sha_entries_verify() {
+        assert!(verify_password(
+            "password",
+            "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="
+        ));
+        assert!(!verify_password(
+            "passwort",
+            "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="
+        ));
+        assert!(!verify_password("password", "{SHA}W6ph5Mm5"));
+        assert!(!verify_password("password", "{SHA}not base64!"));
+        // bcrypt and MD5 can't log in, so users with them are dropped
+        assert!(!supported("$2y$05$abcdefghijklmnopqrstuu"));
+        assert!(!supported("$apr1$salt$hash"));
+        assert!(!verify_password("password", "$apr1$salt$hash"));
+    }
+
+    #[test]
+    fn only_open_routes_skip_the_login() {
+        let sessions = sessions();
+        let refused = |request: TestRequest| sessions.refuse(&request.to_srv_request());
+        for (method, path) in [
+            (Method::POST, "/api/events"),
+            (Method::POST, "/api/hook/github"),
+            (Method::PUT, "/api/agents/a1"),
+            (Method::GET, "/login.html"),
+            (Method::POST, "/api/auth/login"),
+        ] {
+            let request = TestRequest::default().method(method.clone()).uri(path);
+            assert!(refused(request).is_none(), "{} {}", method, path);
+        }
+
+        // Reading events needs a login even though sending them doesn't
+        let api = refused(TestRequest::get().uri("/api/events")).unwrap();
+        assert_eq!(api.status(), StatusCode::UNAUTHORIZED);
+        assert!(refused(TestRequest::get().uri("/api/hook/github")).is_some());
+        let page = refused(TestRequest::get().uri("/?view=hosts")).unwrap();
+        assert_eq!(page.status(), StatusCode::FOUND);
+        assert_eq!(
+            page.headers().get(header::LOCATION).unwrap(),
+            "/login.html?next=%2F%3Fview%3Dhosts"
+        );
+
+        // Nothing is refused until logins are configured
+        let open = Sessions::new(AuthConfig::default(), false);
+        assert!(open
+            .refuse(&TestRequest::get().uri("/api/events").to_srv_request())
+            .is_none());
+    }
+
+    #[test]
+    fn repeated_failures_throttle_one_address() {
+        let sessions = sessions();
+        let peer: Option<IpAddr> = Some("192.0.2.1".parse().unwrap());
+        let other: Option<IpAddr> = Some("192.0.2.2".parse().unwrap());
+        for _ in 0..MAX_FAILURES - 1 {
+            sessions.failed(peer);
+        }
+        assert_eq!(sessions.throttled(peer), None);
+        sessions.failed(peer);
+        let wait = sessions.throttled(peer).unwrap();
+        assert!(wait > 0 && wait <= FAILURE_WINDOW.as_secs());
+        assert_eq!(sessions.throttled(other), None);
+        // Without a peer address there is nothing to count against
+        sessions.failed(None);
+        assert_eq!(sessions.throttled(None), None);
+    }
+}
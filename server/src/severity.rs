@@ -0,0 +1,136 @@
+use crate::Event;
+use serde::Deserialize;
+
+// Ordered from least to most severe
+const LEVELS: [&str; 5] = ["debug", "info", "warning", "error", "critical"];
+
+// Position in LEVELS. Unknown severities rank as info so they are never
+// hidden by a debug floor but still drop out under warning and above.
+pub fn rank(severity: &str) -> usize {
+    let severity = severity.to_ascii_lowercase();
+    let severity = match severity.as_str() {
+        "warn" => "warning",
+        "err" => "error",
+        "crit" | "fatal" => "critical",
+        other => other,
+    };
+    LEVELS
+        .iter()
+        .position(|level| *level == severity)
+        .unwrap_or(1)
+}
+
+// The LEVELS name for a severity as sources spell it ("WARN", "Err")
+pub fn normalize(severity: &str) -> &'static str {
+    LEVELS[rank(severity)]
+}
+
+// Syslog facility names by code
+pub const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+// Syslog severity codes (0 emerg to 7 debug), also used by GELF `level`
+#[cfg(any(feature = "syslog", feature = "gelf"))]
+pub fn from_syslog(code: u8) -> &'static str {
+    match code {
+        0..=2 => "critical",
+        3 => "error",
+        4 => "warning",
+        5 | 6 => "info",
+        _ => "debug",
+    }
+}
+
+// The syslog severity code a level is sent as
+pub fn to_syslog(severity: &str) -> u8 {
+    match rank(severity) {
+        0 => 7,
+        1 => 6,
+        2 => 4,
+        3 => 3,
+        _ => 2,
+    }
+}
+
+pub fn parse_level(value: &str) -> Result<usize, String> {
+    LEVELS
+        .iter()
+        .position(|level| level.eq_ignore_ascii_case(value))
+        .ok_or_else(|| {
+            format!(
+                "unknown severity '{}' (expected one of {})",
+                value,
+                LEVELS.join(", ")
+            )
+        })
+}
+
+// `[severity]` section
+#[derive(Debug, Default, Deserialize)]
+pub struct SeverityConfig {
+    // Events below this are left out of default queries and stats recent
+    // events. Clients can pass `min_severity` to pick their own.
+    floor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeverityQuery {
+    pub min_severity: Option<String>,
+}
+
+pub struct SeverityFloor {
+    floor: usize,
+}
+
+impl SeverityFloor {
+    pub fn new(config: SeverityConfig) -> Self {
+        let floor = match config.floor.as_deref().map(parse_level) {
+            Some(Ok(floor)) => {
+                log_line!("CONFIG", "Severity floor: {}", LEVELS[floor]);
+                floor
+            }
+            Some(Err(err)) => {
+                log_line!("WARN", "Ignoring [severity] floor: {}", err);
+                0
+            }
+            None => 0,
+        };
+        SeverityFloor { floor }
+    }
+
+    // The floor for one request: the client's choice, else the global default
+    pub fn resolve(&self, requested: Option<&str>) -> Result<usize, String> {
+        match requested {
+            Some(value) => parse_level(value),
+            None => Ok(self.floor),
+        }
+    }
+}
+
+pub fn passes(event: &Event, floor: usize) -> bool {
+    floor == 0 || rank(&event.severity) >= floor
+}
@@ -0,0 +1,91 @@
+// Orderly exit on SIGTERM/SIGINT (or a service stop): stop accepting
+// connections, let in-flight requests finish, then drain the ingest and
+// output queues and flush the store. A second signal stops without waiting.
+use crate::AppState;
+use actix_web::dev::ServerHandle;
+use actix_web::web;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// Resolves with what asked the server to stop
+async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = terminate.recv() => "SIGTERM",
+            },
+            Err(err) => {
+                log_line!("WARN", "Cannot listen for SIGTERM: {}", err);
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+// Stop `server` gracefully on the first signal or when `service` fires
+pub fn watch(server: ServerHandle, service: Option<oneshot::Receiver<()>>, timeout: Duration) {
+    actix_web::rt::spawn(async move {
+        let service = async {
+            if let Some(receiver) = service {
+                if receiver.await.is_ok() {
+                    return "service stop";
+                }
+            }
+            std::future::pending().await
+        };
+        let reason = tokio::select! {
+            reason = signal() => reason,
+            reason = service => reason,
+        };
+        log_line!(
+            "INFO",
+            "{} received; finishing in-flight requests (up to {}s)",
+            reason,
+            timeout.as_secs()
+        );
+        tokio::select! {
+            _ = server.stop(true) => {}
+            reason = signal() => {
+                log_line!("WARN", "{} received again; stopping now", reason);
+                server.stop(false).await;
+            }
+        }
+    });
+}
+
+// Run once the server has stopped, so no new batches arrive meanwhile
+pub async fn flush(state: &web::Data<AppState>, timeout: Duration) {
+    let left = state.backpressure.drain(timeout).await;
+    if left > 0 {
+        log_line!(
+            "WARN",
+            "Gave up on {} events waiting to be stored after {}s",
+            left,
+            timeout.as_secs()
+        );
+    }
+    if !state.outputs.is_empty() {
+        let left = state.outputs.flush(timeout).await;
+        if left > 0 {
+            log_line!(
+                "WARN",
+                "Gave up on {} queued output batches after {}s",
+                left,
+                timeout.as_secs()
+            );
+        }
+    }
+    if let Err(err) = state.store.flush() {
+        log_line!("ERROR", "Failed to flush the event store: {}", err);
+    }
+    log_line!("INFO", "Shutdown complete");
+}
@@ -0,0 +1,222 @@
+use crate::{AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// `[signing]` section
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    // Drop posted events that are not validly signed instead of storing them
+    // with a failed status
+    require: bool,
+    // Host -> base64 Ed25519 public key, as printed by the agent's
+    // `--generate-signing-key`
+    keys: HashMap<String, String>,
+}
+
+// Stored on every event agents post while signing is configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    Valid,
+    Invalid,
+    Unsigned,
+    // Signed, but no key is registered for the event's host
+    UnknownKey,
+}
+
+pub struct Signing {
+    require: bool,
+    keys: HashMap<String, VerifyingKey>,
+}
+
+impl Signing {
+    pub fn new(config: SigningConfig) -> Self {
+        let mut keys = HashMap::new();
+        for (host, encoded) in config.keys {
+            match parse_key(&encoded) {
+                Ok(key) => {
+                    keys.insert(host, key);
+                }
+                Err(err) => log_line!("WARN", "Ignoring signing key for '{}': {}", host, err),
+            }
+        }
+        Signing {
+            require: config.require,
+            keys,
+        }
+    }
+
+    // Unsigned events are dropped rather than stored as unverified
+    pub fn required(&self) -> bool {
+        self.require
+    }
+
+    fn enabled(&self) -> bool {
+        self.require || !self.keys.is_empty()
+    }
+
+    pub fn verify(&self, event: &Event) -> Verification {
+        let Some(signature) = event.signature.as_deref() else {
+            return Verification::Unsigned;
+        };
+        let Some(key) = self.keys.get(&event.host) else {
+            return Verification::UnknownKey;
+        };
+        let Some(signature) = STANDARD
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        else {
+            return Verification::Invalid;
+        };
+        match key.verify_strict(signed_bytes(event).as_bytes(), &signature) {
+            Ok(()) => Verification::Valid,
+            Err(_) => Verification::Invalid,
+        }
+    }
+
+    // Stamp a posted batch with its verification status. Whatever status the
+    // sender put on the events is discarded. Returns how many events were
+    // dropped because signing is required.
+    pub fn check(&self, batch: &mut Vec<Event>) -> usize {
+        if !self.enabled() {
+            for event in batch.iter_mut() {
+                event.verification = None;
+            }
+            return 0;
+        }
+
+        let before = batch.len();
+        batch.retain_mut(|event| {
+            let verification = self.verify(event);
+            event.verification = Some(verification);
+            !self.require || verification == Verification::Valid
+        });
+        let dropped = before - batch.len();
+        if dropped > 0 {
+            log_line!(
+                "WARN",
+                "Dropped {} event(s) without a valid signature",
+                dropped
+            );
+        }
+        dropped
+    }
+}
+
+fn parse_key(encoded: &str) -> std::result::Result<VerifyingKey, String> {
+    let bytes = STANDARD
+        .decode(encoded.trim())
+        .map_err(|err| format!("not base64: {}", err))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "an Ed25519 public key is 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|err| err.to_string())
+}
+
+// The message agents sign: the event's fields as a compact JSON array with
+// object keys sorted, so both sides produce the same bytes regardless of
+// field order on the wire. The agent's counterpart is
+// `json.dumps(fields, sort_keys=True, separators=(",", ":"), ensure_ascii=False)`.
+fn signed_bytes(event: &Event) -> String {
+    let fields = serde_json::json!([
+        event.schema_version,
+        event.category,
+        event.event_type,
+        event.time,
+        event.host,
+        event.host_ipv4,
+        event.os,
+        event.source,
+        event.severity,
+        event.message,
+        event.data,
+    ]);
+    let mut out = String::new();
+    canonical(&fields, &mut out);
+    out
+}
+
+// Compact JSON with object keys sorted at every level
+pub fn canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                canonical(item, out);
+            }
+            out.push(']');
+        }
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (index, (key, item)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                canonical(item, out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+// GET /api/admin/signatures - Re-verify every stored event and report events
+// whose signature no longer matches the status recorded at ingest
+pub async fn get_signatures(
+    request: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let store = data.store.read();
+    let mut checked = 0;
+    let mut mismatches = 0;
+    let mut counts: HashMap<Verification, usize> = HashMap::new();
+    let mut mismatched = Vec::new();
+    for (sequence, event) in store.entries() {
+        // Events the server made up itself were never signed
+        let Some(recorded) = event.verification else {
+            continue;
+        };
+        checked += 1;
+        let current = data.signing.verify(event);
+        *counts.entry(current).or_default() += 1;
+        if current == recorded {
+            continue;
+        }
+        mismatches += 1;
+        if mismatched.len() < 100 {
+            mismatched.push(serde_json::json!({
+                "position": sequence,
+                "host": event.host,
+                "time": event.time,
+                "recorded": recorded,
+                "current": current,
+            }));
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "enabled": data.signing.enabled(),
+        "require": data.signing.require,
+        "keys": data.signing.keys.len(),
+        "checked": checked,
+        "counts": counts,
+        "mismatches": mismatches,
+        "mismatched": mismatched,
+    })))
+}
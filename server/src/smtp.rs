@@ -0,0 +1,724 @@
+// Built with `--features smtp`. A minimal SMTP receiver for appliances that
+// can only email an admin (UPS units, printers, RAID controllers): each mail
+// becomes one event from its subject and text, and attachments are
+// discarded. There is no relaying, AUTH or STARTTLS; restrict who may
+// connect with `allowed_networks`.
+use crate::cidr::Cidr;
+use crate::{config, AppState, Event};
+use actix_web::web;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+// Longest command line; RFC 5321 allows 512 bytes, but some senders exceed it
+const MAX_LINE: u64 = 4096;
+// Recipients per message at most
+const MAX_RECIPIENTS: usize = 100;
+// Characters of body text kept in `data.body`
+const MAX_TEXT: usize = 64 * 1024;
+// Idle time before a connection is dropped
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// Nested multipart levels looked into
+const MAX_DEPTH: usize = 5;
+
+// `[smtp]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    enabled: bool,
+    // TCP address appliances send mail to
+    bind: String,
+    // Name in the greeting and EHLO reply
+    hostname: String,
+    category: String,
+    // Largest message accepted, e.g. "10MiB"
+    #[serde(deserialize_with = "config::de_size")]
+    max_size: usize,
+    // Networks allowed to connect; anyone when empty
+    allowed_networks: Vec<String>,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            enabled: false,
+            bind: "0.0.0.0:25".to_string(),
+            hostname: "loglumen".to_string(),
+            category: "logs".to_string(),
+            max_size: 10 * 1024 * 1024,
+            allowed_networks: Vec::new(),
+        }
+    }
+}
+
+impl SmtpConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+struct Listener {
+    hostname: String,
+    category: String,
+    max_size: usize,
+    allowed: Vec<Cidr>,
+}
+
+// A parsed MIME entity: headers (unfolded) and raw body
+struct Entity<'a> {
+    headers: Vec<(String, String)>,
+    body: &'a [u8],
+}
+
+impl<'a> Entity<'a> {
+    fn parse(raw: &'a [u8]) -> Self {
+        let (head, body) = match find(raw, b"\r\n\r\n") {
+            Some(at) => (&raw[..at], &raw[at + 4..]),
+            None => match find(raw, b"\n\n") {
+                Some(at) => (&raw[..at], &raw[at + 2..]),
+                None => (raw, &raw[raw.len()..]),
+            },
+        };
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in String::from_utf8_lossy(head).lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+        Entity { headers, body }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // `text/plain; charset="utf-8"` as ("text/plain", parameter lookup)
+    fn content_type(&self) -> (String, Vec<(String, String)>) {
+        parameters(self.header("content-type").unwrap_or("text/plain"))
+    }
+
+    fn decoded_body(&self) -> Vec<u8> {
+        match self
+            .header("content-transfer-encoding")
+            .map(|value| value.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("base64") => {
+                let compact: Vec<u8> = self
+                    .body
+                    .iter()
+                    .copied()
+                    .filter(|byte| !byte.is_ascii_whitespace())
+                    .collect();
+                base64::engine::general_purpose::STANDARD
+                    .decode(compact)
+                    .unwrap_or_default()
+            }
+            Some("quoted-printable") => quoted_printable(self.body, false),
+            _ => self.body.to_vec(),
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn parameters(value: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = value.split(';');
+    let kind = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let params = parts
+        .filter_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            Some((
+                key.trim().to_ascii_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            ))
+        })
+        .collect();
+    (kind, params)
+}
+
+fn parameter<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+// `=41` escapes and `=` soft line breaks; in headers `_` is a space
+fn quoted_printable(input: &[u8], header: bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        match input[index] {
+            b'=' if input[index + 1..].starts_with(b"\r\n") => index += 3,
+            b'=' if input[index + 1..].starts_with(b"\n") => index += 2,
+            b'=' => match (
+                input.get(index + 1).copied().and_then(hex_digit),
+                input.get(index + 2).copied().and_then(hex_digit),
+            ) {
+                (Some(high), Some(low)) => {
+                    output.push(high << 4 | low);
+                    index += 3;
+                }
+                _ => {
+                    output.push(b'=');
+                    index += 1;
+                }
+            },
+            b'_' if header => {
+                output.push(b' ');
+                index += 1;
+            }
+            byte => {
+                output.push(byte);
+                index += 1;
+            }
+        }
+    }
+    output
+}
+
+// Latin-1 maps byte for byte onto Unicode; everything else is read as UTF-8
+fn to_text(bytes: &[u8], charset: Option<&str>) -> String {
+    match charset.map(str::to_ascii_lowercase).as_deref() {
+        Some("iso-8859-1" | "latin1" | "windows-1252") => {
+            bytes.iter().map(|byte| *byte as char).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+// RFC 2047 encoded words (`=?utf-8?B?...?=`) in Subject and From; the
+// whitespace between two adjacent encoded words is dropped
+fn decode_header(value: &str) -> String {
+    let mut output = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..].splitn(3, '?').collect::<Vec<_>>();
+        let word = match decoded.as_slice() {
+            [charset, encoding, tail] => tail.find("?=").map(|end| {
+                let text = &tail[..end];
+                let bytes = match encoding.to_ascii_uppercase().as_str() {
+                    "B" => base64::engine::general_purpose::STANDARD
+                        .decode(text)
+                        .unwrap_or_default(),
+                    _ => quoted_printable(text.as_bytes(), true),
+                };
+                let length = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+                (to_text(&bytes, Some(charset)), length)
+            }),
+            _ => None,
+        };
+        let Some((text, length)) = word else {
+            break;
+        };
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            output.push_str(between);
+        }
+        output.push_str(&text);
+        rest = &rest[start + length..];
+        after_word = true;
+    }
+    output.push_str(rest);
+    output
+}
+
+// Crude, but appliance HTML mails are tables of status lines
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Default)]
+struct Content {
+    plain: Option<String>,
+    html: Option<String>,
+    // Names of discarded attachments
+    attachments: Vec<String>,
+}
+
+fn collect(entity: &Entity, content: &mut Content, depth: usize) {
+    let (kind, params) = entity.content_type();
+    if kind.starts_with("multipart/") {
+        let Some(boundary) = parameter(&params, "boundary").filter(|_| depth < MAX_DEPTH) else {
+            return;
+        };
+        let delimiter = format!("--{}", boundary);
+        let body = String::from_utf8_lossy(entity.body);
+        // Parts sit between delimiter lines; the first piece is the preamble
+        for part in body.split(delimiter.as_str()).skip(1) {
+            if part.starts_with("--") {
+                break;
+            }
+            let part = part
+                .strip_prefix("\r\n")
+                .or_else(|| part.strip_prefix('\n'))
+                .unwrap_or(part);
+            collect(&Entity::parse(part.as_bytes()), content, depth + 1);
+        }
+        return;
+    }
+
+    let (disposition, disposition_params) =
+        parameters(entity.header("content-disposition").unwrap_or(""));
+    let filename =
+        parameter(&disposition_params, "filename").or_else(|| parameter(&params, "name"));
+    let is_text = kind == "text/plain" || kind == "text/html";
+    if disposition == "attachment" || filename.is_some() || !is_text {
+        content
+            .attachments
+            .push(decode_header(filename.unwrap_or(&kind)));
+        return;
+    }
+    let text = to_text(&entity.decoded_body(), parameter(&params, "charset"));
+    let slot = if kind == "text/html" {
+        &mut content.html
+    } else {
+        &mut content.plain
+    };
+    if slot.is_none() {
+        *slot = Some(text);
+    }
+}
+
+// Appliance subjects say how bad it is ("Critical: UPS on battery")
+fn severity_of(subject: &str) -> &'static str {
+    let subject = subject.to_ascii_lowercase();
+    let has = |words: &[&str]| words.iter().any(|word| subject.contains(word));
+    if has(&["critical", "emergency", "fatal"]) {
+        "critical"
+    } else if has(&["error", "fail", "fault", "alarm"]) {
+        "error"
+    } else if has(&["warning", "warn", "degraded"]) {
+        "warning"
+    } else {
+        "info"
+    }
+}
+
+fn to_event(
+    listener: &Listener,
+    raw: &[u8],
+    helo: &str,
+    sender: &str,
+    recipients: &[String],
+    peer: &SocketAddr,
+) -> Event {
+    let entity = Entity::parse(raw);
+    let mut content = Content::default();
+    collect(&entity, &mut content, 0);
+    let mut body = content
+        .plain
+        .or_else(|| content.html.as_deref().map(strip_html))
+        .unwrap_or_default()
+        .replace("\r\n", "\n")
+        .trim()
+        .to_string();
+    if let Some((cut, _)) = body.char_indices().nth(MAX_TEXT) {
+        body.truncate(cut);
+    }
+
+    let subject = entity
+        .header("subject")
+        .map(decode_header)
+        .unwrap_or_default();
+    let message = if subject.is_empty() {
+        body.lines().next().unwrap_or_default().to_string()
+    } else {
+        subject.clone()
+    };
+    let time = entity
+        .header("date")
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+    let peer = peer.ip().to_string();
+    // The EHLO name is usually the device's own; IP literals say nothing new
+    let host =
+        if helo.is_empty() || helo.starts_with('[') || helo.parse::<std::net::IpAddr>().is_ok() {
+            peer.clone()
+        } else {
+            helo.to_string()
+        };
+
+    let mut data = json!({
+        "from": entity.header("from").map(decode_header).unwrap_or_else(|| sender.to_string()),
+        "envelope_from": sender,
+        "to": recipients,
+        "subject": subject,
+        "body": body,
+        "helo": helo,
+    });
+    if let Some(message_id) = entity.header("message-id") {
+        data["message_id"] = Value::String(message_id.to_string());
+    }
+    if !content.attachments.is_empty() {
+        data["discarded_attachments"] = json!(content.attachments);
+    }
+
+    Event {
+        schema_version: 1,
+        category: listener.category.clone(),
+        event_type: "email".to_string(),
+        time: time.to_rfc3339(),
+        host,
+        host_ipv4: peer,
+        os: String::new(),
+        source: "smtp".to_string(),
+        severity: severity_of(&subject).to_string(),
+        message,
+        data,
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+// `MAIL FROM:<a@b> SIZE=10` as ("a@b", "SIZE=10")
+fn path_argument<'a>(arguments: &'a str, prefix: &str) -> Option<(&'a str, &'a str)> {
+    let head = arguments.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let rest = arguments[prefix.len()..].trim_start();
+    match rest.strip_prefix('<') {
+        Some(inner) => {
+            let (path, tail) = inner.split_once('>')?;
+            Some((path.trim(), tail.trim()))
+        }
+        None => Some(rest.split_once(' ').unwrap_or((rest, ""))),
+    }
+}
+
+async fn read_line(
+    reader: &mut BufReader<TcpStream>,
+    line: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+    line.clear();
+    match tokio::time::timeout(
+        IDLE_TIMEOUT,
+        (&mut *reader).take(MAX_LINE).read_until(b'\n', line),
+    )
+    .await
+    {
+        Ok(result) => result,
+        Err(_) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "idle")),
+    }
+}
+
+// DATA up to the lone `.` line, with dot-stuffing undone. None when the
+// message outgrew max_size (the rest is still read off the connection).
+async fn read_data(
+    reader: &mut BufReader<TcpStream>,
+    max_size: usize,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut message = Vec::new();
+    let mut line = Vec::new();
+    let mut oversized = false;
+    loop {
+        // Lines in DATA can be long; search for the newline without a cap
+        // beyond what the message may hold
+        line.clear();
+        let limit = (max_size + 2)
+            .saturating_sub(message.len())
+            .max(MAX_LINE as usize) as u64;
+        let read = tokio::time::timeout(
+            IDLE_TIMEOUT,
+            (&mut *reader).take(limit).read_until(b'\n', &mut line),
+        )
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "idle"))??;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if line == b".\r\n" || line == b".\n" {
+            return Ok((!oversized).then_some(message));
+        }
+        let content = line.strip_prefix(b".").unwrap_or(&line);
+        if message.len() + content.len() > max_size {
+            oversized = true;
+            message.clear();
+        }
+        if !oversized {
+            message.extend_from_slice(content);
+        }
+    }
+}
+
+async fn session(
+    state: web::Data<AppState>,
+    listener: Arc<Listener>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    macro_rules! reply {
+        ($($arg:tt)*) => {
+            reader
+                .get_mut()
+                .write_all(format!("{}\r\n", format!($($arg)*)).as_bytes())
+                .await?
+        };
+    }
+    if !listener.allowed.is_empty()
+        && !listener
+            .allowed
+            .iter()
+            .any(|network| network.contains(peer.ip()))
+    {
+        reply!(
+            "554 {} does not accept mail from {}",
+            listener.hostname,
+            peer.ip()
+        );
+        return Ok(());
+    }
+    reply!("220 {} ESMTP loglumen", listener.hostname);
+
+    let mut helo = String::new();
+    let mut sender: Option<String> = None;
+    let mut recipients: Vec<String> = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        if read_line(&mut reader, &mut line).await? == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        let (verb, arguments) = text.split_once(' ').unwrap_or((text, ""));
+        match verb.to_ascii_uppercase().as_str() {
+            "HELO" => {
+                helo = arguments.trim().to_string();
+                (sender, recipients) = (None, Vec::new());
+                reply!("250 {}", listener.hostname);
+            }
+            "EHLO" => {
+                helo = arguments.trim().to_string();
+                (sender, recipients) = (None, Vec::new());
+                reply!(
+                    "250-{}\r\n250-SIZE {}\r\n250 8BITMIME",
+                    listener.hostname,
+                    listener.max_size
+                );
+            }
+            "MAIL" => match path_argument(arguments, "FROM:") {
+                None => reply!("501 Syntax: MAIL FROM:<address>"),
+                Some(_) if sender.is_some() => reply!("503 Sender already given"),
+                Some((path, options)) => {
+                    let size = options
+                        .split_whitespace()
+                        .find_map(|option| {
+                            option
+                                .to_ascii_uppercase()
+                                .strip_prefix("SIZE=")
+                                .map(str::to_string)
+                        })
+                        .and_then(|size| size.parse::<usize>().ok());
+                    if size.is_some_and(|size| size > listener.max_size) {
+                        reply!("552 Message larger than {} bytes", listener.max_size);
+                    } else {
+                        sender = Some(path.to_string());
+                        reply!("250 OK");
+                    }
+                }
+            },
+            "RCPT" => match path_argument(arguments, "TO:") {
+                None => reply!("501 Syntax: RCPT TO:<address>"),
+                Some(_) if sender.is_none() => reply!("503 Need MAIL first"),
+                Some(_) if recipients.len() >= MAX_RECIPIENTS => reply!("452 Too many recipients"),
+                Some((path, _)) => {
+                    recipients.push(path.to_string());
+                    reply!("250 OK");
+                }
+            },
+            "DATA" => {
+                let Some(from) = sender.take().filter(|_| !recipients.is_empty()) else {
+                    reply!("503 Need MAIL and RCPT first");
+                    continue;
+                };
+                reply!("354 End data with <CR><LF>.<CR><LF>");
+                match read_data(&mut reader, listener.max_size).await? {
+                    Some(message) => {
+                        let event = to_event(&listener, &message, &helo, &from, &recipients, &peer);
+                        let batch = vec![event];
+                        state.availability.observe(&batch);
+                        crate::ingest(&state, batch).await;
+                        reply!("250 OK");
+                    }
+                    None => reply!("552 Message larger than {} bytes", listener.max_size),
+                }
+                recipients.clear();
+            }
+            "RSET" => {
+                (sender, recipients) = (None, Vec::new());
+                reply!("250 OK");
+            }
+            "NOOP" => reply!("250 OK"),
+            "VRFY" => reply!("252 Cannot verify"),
+            "QUIT" => {
+                reply!("221 {} closing", listener.hostname);
+                return Ok(());
+            }
+            _ => reply!("502 Command not implemented"),
+        }
+    }
+}
+
+async fn accept(state: web::Data<AppState>, socket: TcpListener, listener: Arc<Listener>) {
+    loop {
+        match socket.accept().await {
+            Ok((stream, peer)) => {
+                let (state, listener) = (state.clone(), listener.clone());
+                actix_web::rt::spawn(async move {
+                    if let Err(err) = session(state, listener, stream, peer).await {
+                        if err.kind() != std::io::ErrorKind::UnexpectedEof {
+                            log_line!("WARN", "SMTP session with {} ended: {}", peer.ip(), err);
+                        }
+                    }
+                });
+            }
+            Err(err) => log_line!("WARN", "SMTP accept failed: {}", err),
+        }
+    }
+}
+
+// A message from a fuzzer or a property test, as if it arrived over DATA.
+// Malformed input must not panic.
+pub fn fuzz(input: &[u8]) {
+    let listener = Listener {
+        hostname: "loglumen".to_string(),
+        category: "email".to_string(),
+        max_size: 1024 * 1024,
+        allowed: Vec::new(),
+    };
+    let peer: SocketAddr = "192.0.2.1:25".parse().unwrap();
+    to_event(&listener, input, "ups01", "ups@example.com", &[], &peer);
+}
+
+// Binds right away, before hardening drops root (port 25 is privileged)
+pub fn spawn(state: web::Data<AppState>, config: SmtpConfig) {
+    let mut allowed = Vec::new();
+    for network in &config.allowed_networks {
+        match Cidr::parse(network) {
+            Ok(network) => allowed.push(network),
+            Err(err) => {
+                log_line!(
+                    "ERROR",
+                    "SMTP listener not started: allowed_networks: {}",
+                    err
+                );
+                return;
+            }
+        }
+    }
+    let socket = std::net::TcpListener::bind(&config.bind)
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .and_then(TcpListener::from_std);
+    match socket {
+        Ok(socket) => {
+            log_line!("CONFIG", "SMTP listening on tcp://{}", config.bind);
+            let listener = Arc::new(Listener {
+                hostname: config.hostname,
+                category: config.category,
+                max_size: config.max_size,
+                allowed,
+            });
+            actix_web::rt::spawn(accept(state, socket, listener));
+        }
+        Err(err) => log_line!(
+            "ERROR",
+            "SMTP listener could not bind tcp://{}: {}",
+            config.bind,
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+
+    const SAMPLES: &[&[u8]] = &[
+        b"From: UPS <ups@example.com>\r\nSubject: =?UTF-8?B?Q3JpdGljYWw6IG9uIGJhdHRlcnk=?=\r\nDate: Tue, 14 Oct 2026 10:00:00 +0000\r\n\r\nInput power lost.\r\n",
+        b"Subject: =?iso-8859-1?Q?Warning=3A_disk_=E9?=\nContent-Type: multipart/alternative; boundary=\"b1\"\n\n--b1\nContent-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: quoted-printable\n\nline=\n one =C3=A9\n--b1\nContent-Type: text/html\n\n<p>html <b>body</b></p>\n--b1--\n",
+        b"Content-Type: multipart/mixed; boundary=x\r\n\r\n--x\r\nContent-Type: text/plain\r\nContent-Transfer-Encoding: base64\r\n\r\naGVsbG8=\r\n--x\r\nContent-Disposition: attachment; filename=\"report.pdf\"\r\n\r\nJVBERi0=\r\n--x--\r\n",
+    ];
+    const TOKENS: &[&[u8]] = &[
+        b"\r\n",
+        b"\r\n\r\n",
+        b"\n\n",
+        b": ",
+        b"Content-Type: multipart/mixed; boundary=x\n\n",
+        b"--x\n",
+        b"--x--",
+        b"=?UTF-8?B?",
+        b"=?UTF-8?Q?",
+        b"?=",
+        b"=",
+        b"=C3",
+        b"=\n",
+        b"base64",
+        b"quoted-printable",
+        b"<html>",
+        b"<",
+        b">",
+        b"&amp;",
+        b"\xc3\xa9",
+        b"\xff",
+    ];
+
+    proptest! {
+        #[test]
+        fn fuzz_message(input in mutations::input(SAMPLES, TOKENS)) {
+            fuzz(&input);
+        }
+    }
+}
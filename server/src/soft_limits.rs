@@ -0,0 +1,232 @@
+// A heads-up before a hard limit refuses or drops events. Once a request,
+// the ingest queue or the store passes `[limits] soft_limit` of a limit (80%
+// by default), responses to senders carry an `X-Loglumen-Warning` header per
+// limit and the server stores a `limit_warning` event of its own, so rules
+// and outputs can page someone before events are lost.
+use crate::config::LimitsConfig;
+use crate::{AppState, Event};
+use actix_web::HttpResponseBuilder;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const HEADER: &str = "X-Loglumen-Warning";
+// A request limit keeps being hit by the same sender; one event per limit
+// this often is enough
+const WARNING_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    BodySize,
+    BatchEvents,
+    QueuedEvents,
+    EventsInMemory,
+    RetainedEvents,
+}
+
+impl Limit {
+    fn setting(self) -> &'static str {
+        match self {
+            Limit::BodySize => "[limits] max_body_size",
+            Limit::BatchEvents => "[limits] max_batch_events",
+            Limit::QueuedEvents => "[limits] max_queued_events",
+            Limit::EventsInMemory => "[storage] max_events_in_memory",
+            Limit::RetainedEvents => "[retention] max_events",
+        }
+    }
+
+    // What happens past the hard limit
+    fn consequence(self) -> &'static str {
+        match self {
+            Limit::BodySize | Limit::BatchEvents => "requests over it get a 413",
+            Limit::QueuedEvents => "batches get a 429 until the queue drains",
+            Limit::EventsInMemory => "the oldest events are evicted",
+            Limit::RetainedEvents => "retention removes the oldest events",
+        }
+    }
+
+    // Store limits are where a full store sits, so they warn once per
+    // crossing rather than every interval
+    fn sticky(self) -> bool {
+        matches!(self, Limit::EventsInMemory | Limit::RetainedEvents)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Warning {
+    limit: Limit,
+    value: usize,
+    hard: usize,
+}
+
+impl Warning {
+    // `[limits] max_queued_events at 41000 of 50000`
+    fn header(&self) -> String {
+        format!(
+            "{} at {} of {}",
+            self.limit.setting(),
+            self.value,
+            self.hard
+        )
+    }
+}
+
+pub struct SoftLimits {
+    fraction: f64,
+    max_body_size: usize,
+    max_batch_events: usize,
+    max_queued_events: usize,
+    // The bounded memory store's capacity, 0 when unbounded
+    memory_capacity: usize,
+    // Limit -> when its last warning event was stored
+    warned: Mutex<HashMap<Limit, Instant>>,
+}
+
+impl SoftLimits {
+    pub fn new(limits: &LimitsConfig, memory_capacity: Option<usize>) -> Self {
+        let fraction = if limits.soft_limit > 0.0 && limits.soft_limit < 1.0 {
+            log_line!(
+                "CONFIG",
+                "Warning senders past {:.0}% of each limit",
+                limits.soft_limit * 100.0
+            );
+            limits.soft_limit
+        } else {
+            if limits.soft_limit != 0.0 {
+                log_line!(
+                    "WARN",
+                    "[limits] soft_limit must be between 0 and 1; soft limits are off"
+                );
+            }
+            0.0
+        };
+        SoftLimits {
+            fraction,
+            max_body_size: limits.max_body_size,
+            max_batch_events: limits.max_batch_events,
+            max_queued_events: limits.max_queued_events,
+            memory_capacity: memory_capacity.unwrap_or(0),
+            warned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn over(&self, limit: Limit, value: usize, hard: usize) -> Option<Warning> {
+        (self.fraction > 0.0 && hard > 0 && value as f64 >= hard as f64 * self.fraction)
+            .then_some(Warning { limit, value, hard })
+    }
+
+    // Limits the request (0 for what it doesn't have) and the server are
+    // close to. Call once the batch is queued so it counts itself.
+    pub fn check(&self, data: &AppState, body_bytes: usize, batch_events: usize) -> Vec<Warning> {
+        if self.fraction == 0.0 {
+            return Vec::new();
+        }
+        let stored = data.store.stats().events;
+        let warnings: Vec<Warning> = [
+            self.over(Limit::BodySize, body_bytes, self.max_body_size),
+            self.over(Limit::BatchEvents, batch_events, self.max_batch_events),
+            self.over(
+                Limit::QueuedEvents,
+                data.backpressure.queued(),
+                self.max_queued_events,
+            ),
+            self.over(Limit::EventsInMemory, stored, self.memory_capacity),
+            self.over(
+                Limit::RetainedEvents,
+                stored,
+                data.retention.max_events().unwrap_or(0),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        self.record(data, &warnings);
+        warnings
+    }
+
+    // Stores a warning event for each limit that is due one
+    fn record(&self, data: &AppState, warnings: &[Warning]) {
+        let now = Instant::now();
+        let mut warned = self.warned.lock();
+        warned.retain(|limit, _| !limit.sticky() || warnings.iter().any(|w| w.limit == *limit));
+        let due: Vec<&Warning> = warnings
+            .iter()
+            .filter(|warning| match warned.get(&warning.limit) {
+                Some(_) if warning.limit.sticky() => false,
+                Some(last) => now.duration_since(*last) >= WARNING_INTERVAL,
+                None => true,
+            })
+            .collect();
+        for warning in &due {
+            warned.insert(warning.limit, now);
+        }
+        drop(warned);
+        if due.is_empty() {
+            return;
+        }
+
+        let events: Vec<Event> = due
+            .into_iter()
+            .map(|warning| {
+                let message = format!(
+                    "{} is at {} of {} ({:.0}%); {}",
+                    warning.limit.setting(),
+                    warning.value,
+                    warning.hard,
+                    warning.value as f64 * 100.0 / warning.hard as f64,
+                    warning.limit.consequence()
+                );
+                log_line!("WARN", "{}", message);
+                Event {
+                    schema_version: 1,
+                    category: "system".to_string(),
+                    event_type: "limit_warning".to_string(),
+                    time: chrono::Utc::now().to_rfc3339(),
+                    host: "loglumen-server".to_string(),
+                    host_ipv4: String::new(),
+                    os: std::env::consts::OS.to_string(),
+                    source: "loglumen".to_string(),
+                    severity: "warning".to_string(),
+                    message,
+                    data: serde_json::json!({
+                        "limit": warning.limit.setting(),
+                        "value": warning.value,
+                        "soft_limit": (warning.hard as f64 * self.fraction).ceil() as usize,
+                        "hard_limit": warning.hard,
+                    }),
+                    signature: None,
+                    verification: None,
+                    identity: None,
+                    ttl: None,
+                }
+            })
+            .collect();
+        // Called from request handlers, so this can't wait for the writers;
+        // the warning is already in the log if they have no room
+        let reservation = data.backpressure.claim(events.len());
+        if data.pipeline.try_submit(events, reservation).is_none() {
+            log_line!(
+                "WARN",
+                "Ingest queue is full; limit warning events not stored"
+            );
+        }
+    }
+}
+
+// Adds a warning header per limit to a response
+pub fn annotate(mut response: HttpResponseBuilder, warnings: &[Warning]) -> HttpResponseBuilder {
+    for warning in warnings {
+        response.append_header((HEADER, warning.header()));
+    }
+    response
+}
+
+// Keeps the latest warning per limit over a streamed request
+pub fn merge(into: &mut Vec<Warning>, warnings: Vec<Warning>) {
+    for warning in warnings {
+        match into.iter_mut().find(|kept| kept.limit == warning.limit) {
+            Some(kept) => *kept = warning,
+            None => into.push(warning),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{severity, AppState, Event};
+use actix_web::http::{header, StatusCode};
+use actix_web::{dev, web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+// Largest HEC body accepted, after decompression
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+// `[splunk]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SplunkConfig {
+    category: String,
+    // HEC tokens senders may present; without any, the token is checked
+    // against `[api_keys]` like the bearer key on /api/events
+    tokens: Vec<String>,
+}
+
+impl Default for SplunkConfig {
+    fn default() -> Self {
+        SplunkConfig {
+            category: "logs".to_string(),
+            tokens: Vec::new(),
+        }
+    }
+}
+
+pub struct Splunk {
+    config: SplunkConfig,
+}
+
+impl Splunk {
+    pub fn new(config: SplunkConfig) -> Self {
+        if !config.tokens.is_empty() {
+            log_line!("CONFIG", "Splunk HEC tokens: {}", config.tokens.len());
+        }
+        Splunk { config }
+    }
+}
+
+// Event metadata from the query string, for every event in the request
+// that doesn't set its own
+#[derive(Debug, Default, Deserialize)]
+pub struct HecQuery {
+    host: Option<String>,
+    source: Option<String>,
+    sourcetype: Option<String>,
+    index: Option<String>,
+}
+
+// One object of an /event body
+#[derive(Debug, Deserialize)]
+struct HecEvent {
+    time: Option<Value>,
+    host: Option<String>,
+    source: Option<String>,
+    sourcetype: Option<String>,
+    index: Option<String>,
+    event: Option<Value>,
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+// Replies in HEC's `{"text", "code"}` shape, which its clients check
+fn reply(status: StatusCode, text: &str, code: u32) -> HttpResponse {
+    HttpResponse::build(status).json(json!({"text": text, "code": code}))
+}
+
+fn invalid(text: &str, code: u32, number: usize) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "text": text,
+        "code": code,
+        "invalid-event-number": number
+    }))
+}
+
+fn authorize(request: &HttpRequest, data: &AppState) -> std::result::Result<(), HttpResponse> {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let (scheme, token) = value.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("splunk").then(|| token.trim())
+        });
+    let tokens = &data.splunk.config.tokens;
+    let accepted = if tokens.is_empty() {
+        data.api_keys.accepts(request, presented)
+    } else {
+        presented.is_some_and(|token| tokens.iter().any(|known| known == token))
+    };
+    match (accepted, presented) {
+        (true, _) => Ok(()),
+        (false, None) => Err(reply(StatusCode::UNAUTHORIZED, "Token is required", 2)),
+        (false, Some(_)) => Err(reply(StatusCode::FORBIDDEN, "Invalid token", 4)),
+    }
+}
+
+async fn read_body(request: &HttpRequest, payload: web::Payload) -> Result<Option<web::BytesMut>> {
+    let mut payload = dev::Decompress::from_headers(payload.into_inner(), request.headers());
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
+
+// Epoch seconds, with optional fractions, as a number or a string
+fn parse_time(value: &Value) -> Option<DateTime<Utc>> {
+    let seconds = match value {
+        Value::Number(number) => number.as_f64()?,
+        Value::String(text) => text.trim().parse().ok()?,
+        _ => return None,
+    };
+    DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+}
+
+struct Metadata {
+    host: String,
+    source: Option<String>,
+    sourcetype: Option<String>,
+    index: Option<String>,
+}
+
+fn to_event(
+    category: &str,
+    meta: Metadata,
+    time: DateTime<Utc>,
+    event: Value,
+    fields: Map<String, Value>,
+    peer: &str,
+) -> Event {
+    let lookup = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            fields
+                .get(*key)
+                .or_else(|| event.get(key))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+    };
+    let severity = lookup(&["severity", "level", "log_level"])
+        .map(|level| severity::normalize(&level).to_string())
+        .unwrap_or_else(|| "info".to_string());
+    let message = match &event {
+        Value::String(text) => text.clone(),
+        other => lookup(&["message", "msg"]).unwrap_or_else(|| other.to_string()),
+    };
+
+    let mut data = Map::new();
+    for (key, value) in [
+        ("source", &meta.source),
+        ("sourcetype", &meta.sourcetype),
+        ("index", &meta.index),
+    ] {
+        if let Some(value) = value {
+            data.insert(key.to_string(), Value::String(value.clone()));
+        }
+    }
+    if !fields.is_empty() {
+        data.insert("fields".to_string(), Value::Object(fields));
+    }
+    if event.is_object() {
+        data.insert("event".to_string(), event);
+    }
+
+    Event {
+        schema_version: 1,
+        category: category.to_string(),
+        event_type: meta.sourcetype.unwrap_or_else(|| "splunk".to_string()),
+        time: time.to_rfc3339(),
+        host: meta.host,
+        host_ipv4: peer.to_string(),
+        os: String::new(),
+        source: "splunk".to_string(),
+        severity,
+        message,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+fn peer(request: &HttpRequest) -> String {
+    request
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default()
+}
+
+fn too_large() -> HttpResponse {
+    reply(StatusCode::PAYLOAD_TOO_LARGE, "Content too large", 27)
+}
+
+// POST /services/collector/event - Splunk HTTP Event Collector, so
+// anything with HEC output can send here. The body is JSON objects back to
+// back (newlines optional); `event` becomes the message when it is a string
+// and is kept in `data` when it is an object. A bad object rejects the whole
+// request, as its number in the reply says.
+pub async fn event(
+    request: HttpRequest,
+    query: web::Query<HecQuery>,
+    payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = authorize(&request, &data) {
+        return Ok(response);
+    }
+    let Some(body) = read_body(&request, payload).await? else {
+        return Ok(too_large());
+    };
+    let peer = peer(&request);
+    let identity = crate::tls::client_identity(&request);
+
+    let mut batch = Vec::new();
+    let stream = serde_json::Deserializer::from_slice(&body).into_iter::<HecEvent>();
+    for (number, item) in stream.enumerate() {
+        let Ok(item) = item else {
+            return Ok(invalid("Invalid data format", 6, number));
+        };
+        let event = match item.event {
+            None | Some(Value::Null) => return Ok(invalid("Event field is required", 12, number)),
+            Some(Value::String(text)) if text.trim().is_empty() => {
+                return Ok(invalid("Event field cannot be blank", 13, number))
+            }
+            Some(event) => event,
+        };
+        let meta = Metadata {
+            host: item
+                .host
+                .or_else(|| query.host.clone())
+                .unwrap_or_else(|| peer.clone()),
+            source: item.source.or_else(|| query.source.clone()),
+            sourcetype: item.sourcetype.or_else(|| query.sourcetype.clone()),
+            index: item.index.or_else(|| query.index.clone()),
+        };
+        let time = item
+            .time
+            .as_ref()
+            .and_then(parse_time)
+            .unwrap_or_else(Utc::now);
+        let mut event = to_event(
+            &data.splunk.config.category,
+            meta,
+            time,
+            event,
+            item.fields,
+            &peer,
+        );
+        event.identity = identity.clone();
+        batch.push(event);
+    }
+    if batch.is_empty() {
+        return Ok(reply(StatusCode::BAD_REQUEST, "No data", 5));
+    }
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+    Ok(reply(StatusCode::OK, "Success", 0))
+}
+
+// POST /services/collector/raw - one event per line, with metadata from the
+// query string
+pub async fn raw(
+    request: HttpRequest,
+    query: web::Query<HecQuery>,
+    payload: web::Payload,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = authorize(&request, &data) {
+        return Ok(response);
+    }
+    let Some(body) = read_body(&request, payload).await? else {
+        return Ok(too_large());
+    };
+    let peer = peer(&request);
+    let identity = crate::tls::client_identity(&request);
+    let query = query.into_inner();
+
+    let batch: Vec<Event> = String::from_utf8_lossy(&body)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let meta = Metadata {
+                host: query.host.clone().unwrap_or_else(|| peer.clone()),
+                source: query.source.clone(),
+                sourcetype: query.sourcetype.clone(),
+                index: query.index.clone(),
+            };
+            let mut event = to_event(
+                &data.splunk.config.category,
+                meta,
+                Utc::now(),
+                Value::String(line.to_string()),
+                Map::new(),
+                &peer,
+            );
+            event.identity = identity.clone();
+            event
+        })
+        .collect();
+    if batch.is_empty() {
+        return Ok(reply(StatusCode::BAD_REQUEST, "No data", 5));
+    }
+
+    data.api_keys.count_events(&request, batch.len());
+    data.availability.observe(&batch);
+    crate::ingest(&data, batch).await;
+    Ok(reply(StatusCode::OK, "Success", 0))
+}
+
+// GET /services/collector/health - what load balancers and HEC clients poll
+pub async fn health() -> HttpResponse {
+    reply(StatusCode::OK, "HEC is healthy", 17)
+}
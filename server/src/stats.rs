@@ -0,0 +1,925 @@
+use crate::config;
+use crate::{severity, AppState, Event};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+
+// Larger gaps are cheaper to answer with a full /api/stats
+const MAX_DELTA_EVENTS: u64 = 50_000;
+// New events returned by a delta, newest first
+const MAX_DELTA_RECENT: usize = 100;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+// `[stats]` section: how many recent events each dashboard category shows
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    // Events newer than this are candidates for the recent list
+    #[serde(deserialize_with = "config::de_duration")]
+    recent_window: Duration,
+    // Quiet categories still show this many, however old
+    recent_min: usize,
+    // Busy categories show a random sample of this share of the window's
+    // events, within recent_min and recent_max
+    recent_percent: f64,
+    recent_max: usize,
+    // Most distinct keys any one group-by keeps in memory. Past this, counts
+    // become approximate instead of the map growing without bound.
+    max_groups: usize,
+    // Recompute /api/stats on this interval and serve the snapshot to every
+    // poller; unset computes it per request
+    cache_interval: Option<String>,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            recent_window: Duration::from_secs(15 * 60),
+            recent_min: 10,
+            recent_percent: 5.0,
+            recent_max: 25,
+            max_groups: 10_000,
+            cache_interval: None,
+        }
+    }
+}
+
+pub struct Stats {
+    pub recent: RecentWindow,
+    pub max_groups: usize,
+    cache_interval: Option<Duration>,
+    snapshot: parking_lot::RwLock<Option<web::Bytes>>,
+}
+
+impl Stats {
+    pub fn new(config: StatsConfig) -> Self {
+        let cache_interval = match config.cache_interval.as_deref().map(config::parse_duration) {
+            Some(Some(interval)) if !interval.is_zero() => {
+                log_line!(
+                    "CONFIG",
+                    "Stats cached, refreshed every {}s",
+                    interval.as_secs()
+                );
+                Some(interval)
+            }
+            Some(None) => {
+                log_line!("WARN", "Ignoring invalid [stats] cache_interval");
+                None
+            }
+            // Unset or "0s": compute per request
+            Some(Some(_)) | None => None,
+        };
+        Stats {
+            max_groups: config.max_groups.max(1),
+            recent: RecentWindow::new(&config),
+            cache_interval,
+            snapshot: parking_lot::RwLock::new(None),
+        }
+    }
+
+    pub fn cache_interval(&self) -> Option<Duration> {
+        self.cache_interval
+    }
+
+    // Serialized snapshot from the last background refresh
+    pub fn cached(&self) -> Option<web::Bytes> {
+        self.snapshot.read().clone()
+    }
+
+    pub fn store_snapshot(&self, snapshot: Vec<u8>) {
+        *self.snapshot.write() = Some(web::Bytes::from(snapshot));
+    }
+}
+
+// Dashboard counts kept current as events are stored and removed, so
+// /api/stats reads them instead of walking the store. Each store shard
+// keeps its own, updated under the shard's write lock.
+#[derive(Default)]
+pub struct Tally {
+    categories: HashMap<String, CategoryTally>,
+    // host -> host_ipv4 -> counts
+    nodes: HashMap<String, HashMap<String, NodeTally>>,
+}
+
+#[derive(Default)]
+pub struct CategoryTally {
+    pub total: usize,
+    pub event_types: HashMap<String, usize>,
+    pub severities: HashMap<String, usize>,
+}
+
+#[derive(Default)]
+pub struct NodeTally {
+    pub total: usize,
+    pub categories: HashMap<String, usize>,
+    pub severities: HashMap<String, usize>,
+    // Newest event by (time, sequence), the order of StoreView::by_time
+    latest: Option<((i64, u64), String)>,
+}
+
+impl NodeTally {
+    pub fn last_event_time(&self) -> Option<&str> {
+        self.latest.as_ref().map(|(_, time)| time.as_str())
+    }
+}
+
+// Looks up before inserting so the hot path doesn't allocate a key
+fn entry<'a, V: Default>(map: &'a mut HashMap<String, V>, key: &str) -> &'a mut V {
+    if !map.contains_key(key) {
+        map.insert(key.to_string(), V::default());
+    }
+    map.get_mut(key).expect("inserted above")
+}
+
+fn increment(counts: &mut HashMap<String, usize>, key: &str) {
+    *entry(counts, key) += 1;
+}
+
+fn decrement(counts: &mut HashMap<String, usize>, key: &str) {
+    if let Some(count) = counts.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+impl Tally {
+    // `order` is the event's (time key, sequence) in the time index
+    pub fn add(&mut self, event: &Event, order: (i64, u64)) {
+        let category = entry(&mut self.categories, &event.category);
+        category.total += 1;
+        increment(&mut category.event_types, &event.event_type);
+        increment(&mut category.severities, &event.severity);
+
+        let node = entry(entry(&mut self.nodes, &event.host), &event.host_ipv4);
+        node.total += 1;
+        increment(&mut node.categories, &event.category);
+        increment(&mut node.severities, &event.severity);
+        if node
+            .latest
+            .as_ref()
+            .is_none_or(|(latest, _)| *latest < order)
+        {
+            node.latest = Some((order, event.time.clone()));
+        }
+    }
+
+    // True when this was its node's newest event and the node has others
+    // left, which the caller then has to look up with set_latest
+    pub fn remove(&mut self, event: &Event, order: (i64, u64)) -> bool {
+        if let Some(category) = self.categories.get_mut(&event.category) {
+            category.total -= 1;
+            if category.total == 0 {
+                self.categories.remove(&event.category);
+            } else {
+                decrement(&mut category.event_types, &event.event_type);
+                decrement(&mut category.severities, &event.severity);
+            }
+        }
+
+        let Some(addresses) = self.nodes.get_mut(&event.host) else {
+            return false;
+        };
+        let Some(node) = addresses.get_mut(&event.host_ipv4) else {
+            return false;
+        };
+        node.total -= 1;
+        if node.total == 0 {
+            addresses.remove(&event.host_ipv4);
+            if addresses.is_empty() {
+                self.nodes.remove(&event.host);
+            }
+            return false;
+        }
+        decrement(&mut node.categories, &event.category);
+        decrement(&mut node.severities, &event.severity);
+        if node
+            .latest
+            .as_ref()
+            .is_some_and(|(latest, _)| *latest == order)
+        {
+            node.latest = None;
+            return true;
+        }
+        false
+    }
+
+    pub fn set_latest(&mut self, event: &Event, order: (i64, u64)) {
+        if let Some(node) = self
+            .nodes
+            .get_mut(&event.host)
+            .and_then(|addresses| addresses.get_mut(&event.host_ipv4))
+        {
+            node.latest = Some((order, event.time.clone()));
+        }
+    }
+
+    // (host, host_ipv4, counts)
+    pub fn nodes(&self) -> impl Iterator<Item = (&String, &String, &NodeTally)> {
+        self.nodes.iter().flat_map(|(host, addresses)| {
+            addresses
+                .iter()
+                .map(move |(host_ipv4, node)| (host, host_ipv4, node))
+        })
+    }
+}
+
+// Every shard's counts read as one. A host's events all go to one shard,
+// so only the category counts have to be added up.
+pub struct Tallies<'a> {
+    tallies: Vec<&'a Tally>,
+}
+
+impl<'a> Tallies<'a> {
+    pub fn new(tallies: Vec<&'a Tally>) -> Self {
+        Tallies { tallies }
+    }
+
+    pub fn categories(&self) -> impl Iterator<Item = (&'a String, CategoryTally)> {
+        let mut merged: HashMap<&String, CategoryTally> = HashMap::new();
+        for (category, counts) in self.tallies.iter().flat_map(|tally| &tally.categories) {
+            let total = merged.entry(category).or_default();
+            total.total += counts.total;
+            for (event_type, count) in &counts.event_types {
+                *entry(&mut total.event_types, event_type) += count;
+            }
+            for (severity, count) in &counts.severities {
+                *entry(&mut total.severities, severity) += count;
+            }
+        }
+        merged.into_iter()
+    }
+
+    // (host, host_ipv4, counts)
+    pub fn nodes(&self) -> impl Iterator<Item = (&'a String, &'a String, &'a NodeTally)> + '_ {
+        self.tallies.iter().copied().flat_map(Tally::nodes)
+    }
+}
+
+// The `limit` largest counts, for maps that can grow with the data
+pub fn top_groups(counts: &HashMap<String, usize>, limit: usize) -> HashMap<String, usize> {
+    if counts.len() <= limit {
+        return counts.clone();
+    }
+    let mut largest: Vec<(&String, &usize)> = counts.iter().collect();
+    largest.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    largest
+        .into_iter()
+        .take(limit)
+        .map(|(key, count)| (key.clone(), *count))
+        .collect()
+}
+
+pub struct RecentWindow {
+    window: chrono::Duration,
+    min: usize,
+    percent: f64,
+    max: usize,
+    // Seeds the sampling keys; fixed for the process so polls agree
+    seed: RandomState,
+}
+
+impl RecentWindow {
+    fn new(config: &StatsConfig) -> Self {
+        let max = config.recent_max.max(1);
+        let percent = if config.recent_percent > 0.0 && config.recent_percent <= 100.0 {
+            config.recent_percent
+        } else {
+            log_line!(
+                "WARN",
+                "[stats] recent_percent must be above 0 and at most 100; using 5"
+            );
+            5.0
+        };
+        RecentWindow {
+            window: chrono::Duration::from_std(config.recent_window)
+                .unwrap_or(chrono::Duration::minutes(15)),
+            min: config.recent_min.min(max),
+            percent,
+            max,
+            seed: RandomState::new(),
+        }
+    }
+
+    // Pick recent events from one category's events, given newest first by
+    // time (see StoreView::category_by_time). The scan stops at the first
+    // event older than the window, or once `min` are picked if fewer are in
+    // it. Busy windows are sampled. Returns newest first.
+    pub fn select<'a>(
+        &self,
+        events: impl Iterator<Item = &'a Event>,
+        now: DateTime<Utc>,
+    ) -> Vec<Event> {
+        let cutoff = now - self.window;
+        let mut events = events.peekable();
+        let mut in_window = Vec::new();
+        while let Some(event) =
+            events.next_if(|event| event.timestamp().is_some_and(|time| time >= cutoff))
+        {
+            in_window.push(event);
+        }
+
+        if in_window.len() <= self.min {
+            let missing = self.min - in_window.len();
+            in_window.extend(events.take(missing));
+            return in_window.into_iter().cloned().collect();
+        }
+        let size = ((in_window.len() as f64 * self.percent / 100.0).ceil() as usize)
+            .clamp(self.min.max(1), self.max);
+        if in_window.len() <= size {
+            return in_window.into_iter().cloned().collect();
+        }
+        self.sample(&in_window, size)
+    }
+
+    // The newest event plus a reservoir of the rest: the `size - 1` with
+    // the smallest keys, which is a uniform sample of the whole window.
+    // Keys hash each event rather than drawing fresh randomness, so a
+    // dashboard polling the same window keeps its sample instead of
+    // reshuffling every refresh.
+    fn sample(&self, in_window: &[&Event], size: usize) -> Vec<Event> {
+        let mut reservoir: BinaryHeap<(u64, usize)> = BinaryHeap::with_capacity(size);
+        for (index, event) in in_window.iter().enumerate().skip(1) {
+            let key =
+                self.seed
+                    .hash_one((&event.time, &event.host, &event.event_type, &event.message));
+            if reservoir.len() < size - 1 {
+                reservoir.push((key, index));
+            } else if reservoir.peek().is_some_and(|(largest, _)| key < *largest) {
+                reservoir.pop();
+                reservoir.push((key, index));
+            }
+        }
+        let mut picked: Vec<usize> = reservoir.into_iter().map(|(_, index)| index).collect();
+        picked.push(0);
+        picked.sort_unstable();
+        picked
+            .into_iter()
+            .map(|index| in_window[index].clone())
+            .collect()
+    }
+}
+
+// Bounded counter for group-bys (Misra-Gries). Holds at most `capacity` keys;
+// when a new key arrives at capacity, every count drops by one and zeros are
+// evicted. Surviving counts are low by at most `max_error`, and any key seen
+// more than total / (capacity + 1) times is guaranteed to survive.
+pub struct GroupCounter {
+    counts: HashMap<String, usize>,
+    capacity: usize,
+    max_error: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupCount {
+    pub value: String,
+    pub count: usize,
+}
+
+impl GroupCounter {
+    pub fn new(capacity: usize) -> Self {
+        GroupCounter {
+            counts: HashMap::new(),
+            capacity: capacity.max(1),
+            max_error: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: &str) {
+        if let Some(count) = self.counts.get_mut(value) {
+            *count += 1;
+        } else if self.counts.len() < self.capacity {
+            self.counts.insert(value.to_string(), 1);
+        } else {
+            self.max_error += 1;
+            self.counts.retain(|_, count| {
+                *count -= 1;
+                *count > 0
+            });
+        }
+    }
+
+    pub fn is_approximate(&self) -> bool {
+        self.max_error > 0
+    }
+
+    pub fn max_error(&self) -> usize {
+        self.max_error
+    }
+
+    // All kept keys, largest first
+    pub fn into_sorted(self) -> Vec<GroupCount> {
+        let mut groups: Vec<GroupCount> = self
+            .counts
+            .into_iter()
+            .map(|(value, count)| GroupCount { value, count })
+            .collect();
+        groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        groups
+    }
+
+    pub fn into_map(self) -> HashMap<String, usize> {
+        self.counts
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupByQuery {
+    field: String,
+    #[serde(default = "default_top")]
+    top: usize,
+    min_severity: Option<String>,
+}
+
+fn default_top() -> usize {
+    20
+}
+
+// GET /api/stats/group-by?field=data.username&top=20 - Event counts per value
+// of any core or data field, in one pass with bounded memory
+pub async fn group_by(
+    request: HttpRequest,
+    query: web::Query<GroupByQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.access.check_field(&request, &query.field) {
+        return Ok(response);
+    }
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": err
+            })))
+        }
+    };
+
+    let mut counter = GroupCounter::new(data.stats.max_groups);
+    let mut total = 0;
+    let mut missing = 0;
+    {
+        let store = data.store.read();
+        for event in store.iter().filter(|event| severity::passes(event, floor)) {
+            total += 1;
+            match event.field_value(&query.field) {
+                Some(value) => counter.add(&value),
+                None => missing += 1,
+            }
+        }
+    }
+
+    let approximate = counter.is_approximate();
+    let max_error = counter.max_error();
+    let mut groups = counter.into_sorted();
+    groups.truncate(query.top.clamp(1, 1000));
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "field": query.field,
+        "total_events": total,
+        "missing": missing,
+        "approximate": approximate,
+        "max_error": max_error,
+        "groups": groups,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrendsQuery {
+    #[serde(default = "default_trend_field")]
+    field: String,
+    // Recent period whose rate is compared, e.g. "15m"
+    #[serde(default = "default_trend_window")]
+    window: String,
+    // Period right before the window that sets the normal rate
+    #[serde(default = "default_trend_baseline")]
+    baseline: String,
+    #[serde(default = "default_top")]
+    top: usize,
+    min_severity: Option<String>,
+}
+
+fn default_trend_field() -> String {
+    "event_type".to_string()
+}
+
+fn default_trend_window() -> String {
+    "1h".to_string()
+}
+
+fn default_trend_baseline() -> String {
+    "24h".to_string()
+}
+
+#[derive(Debug, Serialize)]
+struct Trend {
+    value: String,
+    current: usize,
+    baseline: usize,
+    // Events per minute in each period
+    current_rate: f64,
+    baseline_rate: f64,
+    // None when the value did not occur during the baseline at all
+    change_percent: Option<f64>,
+}
+
+// GET /api/trends?field=event_type&window=1h&baseline=24h - Rate of each
+// value in the last window against its rate over the baseline before it,
+// biggest movers first
+pub async fn trends(
+    request: HttpRequest,
+    query: web::Query<TrendsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.access.check_field(&request, &query.field) {
+        return Ok(response);
+    }
+    let bad_request = |message: String| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": message
+        }))
+    };
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let period = |raw: &str| {
+        config::parse_duration(raw)
+            .filter(|period| period.as_secs() >= 60)
+            .and_then(|period| chrono::Duration::from_std(period).ok())
+            .ok_or_else(|| format!("invalid period '{}' (at least 1m, e.g. 15m or 24h)", raw))
+    };
+    let (window, baseline) = match (period(&query.window), period(&query.baseline)) {
+        (Ok(window), Ok(baseline)) => (window, baseline),
+        (Err(err), _) | (_, Err(err)) => return Ok(bad_request(err)),
+    };
+
+    let now = data.clock.now();
+    let window_start = now - window;
+    let baseline_start = window_start - baseline;
+    let mut current = GroupCounter::new(data.stats.max_groups);
+    let mut previous = GroupCounter::new(data.stats.max_groups);
+    let (mut current_events, mut baseline_events) = (0, 0);
+    {
+        // Arrival order is not time order, so every event is checked
+        let store = data.store.read();
+        for event in store.iter().filter(|event| severity::passes(event, floor)) {
+            let Some(time) = event.timestamp().filter(|time| *time >= baseline_start) else {
+                continue;
+            };
+            let Some(value) = event.field_value(&query.field) else {
+                continue;
+            };
+            if time >= window_start {
+                current_events += 1;
+                current.add(&value);
+            } else {
+                baseline_events += 1;
+                previous.add(&value);
+            }
+        }
+    }
+
+    let approximate = current.is_approximate() || previous.is_approximate();
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for (value, count) in current.into_map() {
+        counts.entry(value).or_default().0 = count;
+    }
+    for (value, count) in previous.into_map() {
+        counts.entry(value).or_default().1 = count;
+    }
+
+    let window_minutes = window.num_seconds() as f64 / 60.0;
+    let baseline_minutes = baseline.num_seconds() as f64 / 60.0;
+    let mut trends: Vec<Trend> = counts
+        .into_iter()
+        .map(|(value, (current, baseline))| {
+            let current_rate = current as f64 / window_minutes;
+            let baseline_rate = baseline as f64 / baseline_minutes;
+            Trend {
+                value,
+                current,
+                baseline,
+                current_rate,
+                baseline_rate,
+                change_percent: (baseline > 0)
+                    .then(|| (current_rate - baseline_rate) / baseline_rate * 100.0),
+            }
+        })
+        .collect();
+    trends.sort_by(|a, b| {
+        let moved = |trend: &Trend| (trend.current_rate - trend.baseline_rate).abs();
+        moved(b)
+            .total_cmp(&moved(a))
+            .then_with(|| a.value.cmp(&b.value))
+    });
+    trends.truncate(query.top.clamp(1, 1000));
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "field": query.field,
+        "window": query.window,
+        "baseline": query.baseline,
+        "window_start": window_start.to_rfc3339(),
+        "baseline_start": baseline_start.to_rfc3339(),
+        "current_events": current_events,
+        "baseline_events": baseline_events,
+        "approximate": approximate,
+        "trends": trends,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeltaQuery {
+    since: u64,
+    min_severity: Option<String>,
+}
+
+// Counter increments for one category since the requested version
+#[derive(Debug, Default, Serialize)]
+struct CategoryDelta {
+    count: usize,
+    event_types: HashMap<String, usize>,
+    severity_counts: HashMap<String, usize>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct NodeDelta {
+    host: String,
+    host_ipv4: String,
+    count: usize,
+    last_event_time: Option<String>,
+    last_event_time_display: Option<String>,
+    categories: HashMap<String, usize>,
+    severity_counts: HashMap<String, usize>,
+}
+
+// GET /api/stats/delta?since=<version> - Counter increments and new events
+// since a version returned by /api/stats (or a previous delta). `reset: true`
+// means the gap cannot be served incrementally; fetch /api/stats again.
+pub async fn delta(
+    query: web::Query<DeltaQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "status": "error",
+                "message": err
+            })))
+        }
+    };
+
+    let store = data.store.read();
+    let stats = data.store.stats();
+    let version = stats.version;
+    // Counts the client has may include events removed since
+    let removed = stats
+        .removed_at
+        .is_some_and(|removed_at| query.since < removed_at);
+    // One past the limit is enough to know it is over
+    let mut new_events: Vec<&Event> = store
+        .range(query.since..)
+        .map(|(_, event)| event)
+        .take(MAX_DELTA_EVENTS as usize + 1)
+        .collect();
+    if query.since > version || removed || new_events.len() as u64 > MAX_DELTA_EVENTS {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "version": version,
+            "reset": true,
+        })));
+    }
+
+    // Time order, as in /api/stats, so a late batch doesn't move a node's
+    // last event back or crowd out recent ones
+    new_events.sort_by_cached_key(|event| event.timestamp());
+    let by_time = new_events;
+
+    let mut categories: HashMap<String, CategoryDelta> = HashMap::new();
+    let mut nodes: HashMap<String, NodeDelta> = HashMap::new();
+    for event in by_time.iter().copied() {
+        let category = categories.entry(event.category.clone()).or_default();
+        category.count += 1;
+        *category
+            .event_types
+            .entry(event.event_type.clone())
+            .or_insert(0) += 1;
+        *category
+            .severity_counts
+            .entry(event.severity.clone())
+            .or_insert(0) += 1;
+
+        // Same node key as /api/stats
+        let node = nodes
+            .entry(format!("{}|{}", event.host, event.host_ipv4))
+            .or_insert_with(|| NodeDelta {
+                host: event.host.clone(),
+                host_ipv4: event.host_ipv4.clone(),
+                ..NodeDelta::default()
+            });
+        node.count += 1;
+        node.last_event_time = Some(event.time.clone());
+        node.last_event_time_display = data.display.format(&event.time);
+        *node.categories.entry(event.category.clone()).or_insert(0) += 1;
+        *node
+            .severity_counts
+            .entry(event.severity.clone())
+            .or_insert(0) += 1;
+    }
+
+    let recent_events: Vec<_> = by_time
+        .iter()
+        .rev()
+        .copied()
+        .filter(|event| severity::passes(event, floor))
+        .take(MAX_DELTA_RECENT)
+        .map(|event| data.display.event(event))
+        .collect();
+
+    // Nodes go stale without sending anything, so every node's status is
+    // in each delta, registered agents without events included
+    let mut registered = data.agents.by_host();
+    let mut node_status: Vec<serde_json::Value> = store
+        .tally()
+        .nodes()
+        .map(|(host, host_ipv4, counts)| {
+            registered.remove(host);
+            status_of(&data, host, host_ipv4, counts.last_event_time())
+        })
+        .collect();
+    node_status.extend(
+        registered
+            .values()
+            .map(|agent| status_of(&data, &agent.hostname, &agent.host_ipv4, None)),
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "version": version,
+        "since": query.since,
+        "reset": false,
+        "total_events": store.len(),
+        "new_events": by_time.len(),
+        "categories": categories,
+        "nodes": nodes.into_values().collect::<Vec<_>>(),
+        "node_status": node_status,
+        "recent_events": recent_events,
+    })))
+}
+
+fn status_of(
+    data: &AppState,
+    host: &str,
+    host_ipv4: &str,
+    last_event_time: Option<&str>,
+) -> serde_json::Value {
+    let (status, last_seen) = data.availability.liveness(host, last_event_time);
+    serde_json::json!({
+        "host": host,
+        "host_ipv4": host_ipv4,
+        "status": status,
+        "last_seen": last_seen.map(|time| time.to_rfc3339()),
+        "last_seen_display": last_seen.map(|time| data.display.format_time(time)),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeseriesQuery {
+    // Bucket width, e.g. "1m", "5m" or "1h"
+    #[serde(default = "default_bucket")]
+    bucket: String,
+    // RFC 3339 bounds; `to` defaults to now and `from` to 60 buckets before it
+    from: Option<String>,
+    to: Option<String>,
+    // "severity" or "category" to split each bucket's count
+    group_by: Option<String>,
+    min_severity: Option<String>,
+}
+
+fn default_bucket() -> String {
+    "1m".to_string()
+}
+
+// Longest series one request can ask for
+const MAX_BUCKETS: i64 = 10_000;
+// Widest bucket; wider ones would only ever hold one
+const MAX_BUCKET_WIDTH: Duration = Duration::from_secs(366 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize)]
+struct Bucket {
+    time: String,
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<HashMap<String, usize>>,
+}
+
+// GET /api/stats/timeseries?bucket=1m&from=&to=&group_by=severity - Event
+// counts per time bucket by each event's own timestamp, empty buckets
+// included, for rate charts
+pub async fn timeseries(
+    query: web::Query<TimeseriesQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let bad_request = |message: String| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": message
+        }))
+    };
+    let floor = match data.severity.resolve(query.min_severity.as_deref()) {
+        Ok(floor) => floor,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let Some(width) = config::parse_duration(&query.bucket)
+        .filter(|width| width.as_secs() >= 1 && *width <= MAX_BUCKET_WIDTH)
+        .and_then(|width| i64::try_from(width.as_millis()).ok())
+    else {
+        return Ok(bad_request(format!(
+            "invalid bucket '{}' (1s to 366d, e.g. 1m or 1h)",
+            query.bucket
+        )));
+    };
+    let group: Option<fn(&Event) -> &str> = match query.group_by.as_deref() {
+        None => None,
+        Some("severity") => Some(|event| &event.severity),
+        Some("category") => Some(|event| &event.category),
+        Some(other) => {
+            return Ok(bad_request(format!(
+                "invalid group_by '{}' (severity or category)",
+                other
+            )))
+        }
+    };
+    let range = match crate::TimeRange::parse(query.from.as_deref(), query.to.as_deref()) {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let until = range
+        .until
+        .unwrap_or_else(|| data.clock.now())
+        .timestamp_millis();
+    let since = range
+        .since
+        .map_or(until.saturating_sub(width.saturating_mul(59)), |since| {
+            since.timestamp_millis()
+        });
+    if since > until {
+        return Ok(bad_request("from is after to".to_string()));
+    }
+    // Buckets start on multiples of their width, so series line up across
+    // requests
+    let first = since.div_euclid(width);
+    let count = until.div_euclid(width) - first + 1;
+    if count > MAX_BUCKETS {
+        return Ok(bad_request(format!(
+            "{} buckets requested, at most {}; use a wider bucket or a shorter range",
+            count, MAX_BUCKETS
+        )));
+    }
+
+    let mut buckets: Vec<(usize, HashMap<String, usize>)> =
+        vec![Default::default(); count as usize];
+    let mut total = 0;
+    {
+        let store = data.store.read();
+        for event in store
+            .between(since, until)
+            .filter(|event| severity::passes(event, floor))
+        {
+            let Some(time) = event.timestamp() else {
+                continue;
+            };
+            let (events, groups) =
+                &mut buckets[(time.timestamp_millis().div_euclid(width) - first) as usize];
+            *events += 1;
+            total += 1;
+            if let Some(group) = group {
+                *groups.entry(group(event).to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let buckets: Vec<Bucket> = buckets
+        .into_iter()
+        .zip(first..)
+        .map(|((count, groups), index)| Bucket {
+            time: DateTime::<Utc>::from_timestamp_millis(index * width)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            count,
+            groups: group.map(|_| groups),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "bucket_seconds": width / 1000,
+        "from": DateTime::<Utc>::from_timestamp_millis(since).unwrap_or_default().to_rfc3339(),
+        "to": DateTime::<Utc>::from_timestamp_millis(until).unwrap_or_default().to_rfc3339(),
+        "group_by": query.group_by,
+        "total_events": total,
+        "buckets": buckets,
+    })))
+}
@@ -0,0 +1,396 @@
+// Built with `--features statsd`. Receives statsd counters and gauges over
+// UDP and, rather than storing every sample, emits an event when a metric
+// crosses one of its `[[statsd.thresholds]]` and another when it recovers.
+// Those events go through detection rules and notifiers like any other.
+use crate::{config, AppState, Event};
+use actix_web::web;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+// `[[statsd.thresholds]]` entry
+#[derive(Debug, Deserialize)]
+pub struct Threshold {
+    // Metric name, or a prefix ending in `*` (`checkout.*`)
+    metric: String,
+    above: Option<f64>,
+    below: Option<f64>,
+    #[serde(default = "default_severity")]
+    severity: String,
+}
+
+fn default_severity() -> String {
+    "warning".to_string()
+}
+
+impl Threshold {
+    fn applies_to(&self, metric: &str) -> bool {
+        match self.metric.strip_suffix('*') {
+            Some(prefix) => metric.starts_with(prefix),
+            None => self.metric == metric,
+        }
+    }
+
+    fn breached(&self, value: f64) -> bool {
+        self.above.is_some_and(|above| value > above)
+            || self.below.is_some_and(|below| value < below)
+    }
+}
+
+// `[statsd]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StatsdConfig {
+    enabled: bool,
+    // UDP address clients send to
+    bind: String,
+    // Counters are summed over this window, gauges read at its end
+    #[serde(deserialize_with = "config::de_duration")]
+    interval: Duration,
+    category: String,
+    thresholds: Vec<Threshold>,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        StatsdConfig {
+            enabled: false,
+            bind: "0.0.0.0:8125".to_string(),
+            interval: Duration::from_secs(10),
+            category: "metrics".to_string(),
+            thresholds: Vec::new(),
+        }
+    }
+}
+
+impl StatsdConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        }
+    }
+}
+
+struct Sample<'a> {
+    name: &'a str,
+    value: f64,
+    kind: Kind,
+    // Gauge `+3`/`-3` adjusts the current value instead of setting it
+    relative: bool,
+    tags: Map<String, Value>,
+}
+
+// `name:value|type[|@rate][|#tag:value,...]`; timers, histograms and sets
+// are skipped since thresholds only compare counters and gauges
+fn parse_line(line: &str) -> Option<Sample<'_>> {
+    let (name, rest) = line.split_once(':')?;
+    let mut parts = rest.split('|');
+    let raw = parts.next()?.trim();
+    let kind = match parts.next()?.trim() {
+        "c" => Kind::Counter,
+        "g" => Kind::Gauge,
+        _ => return None,
+    };
+    let mut value: f64 = raw.parse().ok()?;
+    let mut tags = Map::new();
+    for part in parts {
+        if let Some(rate) = part.strip_prefix('@') {
+            // A sampled counter stands for 1/rate increments
+            let rate: f64 = rate.parse().ok().filter(|rate| *rate > 0.0)?;
+            if kind == Kind::Counter {
+                value /= rate;
+            }
+        } else if let Some(list) = part.strip_prefix('#') {
+            for tag in list.split(',').filter(|tag| !tag.is_empty()) {
+                let (key, tag_value) = tag.split_once(':').unwrap_or((tag, ""));
+                tags.insert(key.to_string(), Value::String(tag_value.to_string()));
+            }
+        }
+    }
+    Some(Sample {
+        name: name.trim(),
+        value,
+        kind,
+        relative: kind == Kind::Gauge && (raw.starts_with('+') || raw.starts_with('-')),
+        tags,
+    })
+}
+
+// One metric from one host
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Series {
+    metric: String,
+    host: String,
+}
+
+struct Metric {
+    kind: Kind,
+    // Counter total for the current window, or the gauge's value
+    value: f64,
+    tags: Map<String, Value>,
+    sender: String,
+}
+
+#[derive(Default)]
+struct Metrics {
+    series: HashMap<Series, Metric>,
+    // (series, threshold index) currently over their threshold
+    breached: HashSet<(Series, usize)>,
+}
+
+impl Metrics {
+    fn record(&mut self, sample: Sample, peer: &SocketAddr) {
+        let sender = peer.ip().to_string();
+        let host = sample
+            .tags
+            .get("host")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| sender.clone());
+        let series = Series {
+            metric: sample.name.to_string(),
+            host,
+        };
+        let metric = self.series.entry(series).or_insert(Metric {
+            kind: sample.kind,
+            value: 0.0,
+            tags: Map::new(),
+            sender: sender.clone(),
+        });
+        // A name reused with another type starts over as that type
+        if metric.kind != sample.kind {
+            metric.kind = sample.kind;
+            metric.value = 0.0;
+        }
+        match (sample.kind, sample.relative) {
+            (Kind::Counter, _) | (Kind::Gauge, true) => metric.value += sample.value,
+            (Kind::Gauge, false) => metric.value = sample.value,
+        }
+        metric.tags = sample.tags;
+        metric.sender = sender;
+    }
+
+    // Compare every series with its thresholds at the end of a window
+    fn evaluate(&mut self, config: &StatsdConfig) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (series, metric) in self.series.iter_mut() {
+            for (index, threshold) in config.thresholds.iter().enumerate() {
+                if !threshold.applies_to(&series.metric) {
+                    continue;
+                }
+                let key = (series.clone(), index);
+                let breached = threshold.breached(metric.value);
+                let changed = if breached {
+                    self.breached.insert(key)
+                } else {
+                    self.breached.remove(&key)
+                };
+                if changed {
+                    events.push(crossing(config, series, metric, threshold, breached));
+                }
+            }
+            if metric.kind == Kind::Counter {
+                metric.value = 0.0;
+            }
+        }
+        events
+    }
+}
+
+fn crossing(
+    config: &StatsdConfig,
+    series: &Series,
+    metric: &Metric,
+    threshold: &Threshold,
+    breached: bool,
+) -> Event {
+    let limit = match (threshold.above, threshold.below) {
+        (Some(above), _) if metric.value > above => format!("above {}", above),
+        (_, Some(below)) if metric.value < below => format!("below {}", below),
+        (Some(above), Some(below)) => format!("between {} and {}", below, above),
+        (Some(above), None) => format!("at or below {}", above),
+        (None, Some(below)) => format!("at or above {}", below),
+        (None, None) => String::new(),
+    };
+    let message = format!(
+        "{} {} is {} ({}{})",
+        metric.kind.name(),
+        series.metric,
+        metric.value,
+        limit,
+        if metric.kind == Kind::Counter {
+            format!(" per {}s", config.interval.as_secs())
+        } else {
+            String::new()
+        }
+    );
+    Event {
+        schema_version: 1,
+        category: config.category.clone(),
+        event_type: if breached {
+            "threshold_crossed"
+        } else {
+            "threshold_recovered"
+        }
+        .to_string(),
+        time: Utc::now().to_rfc3339(),
+        host: series.host.clone(),
+        host_ipv4: metric.sender.clone(),
+        os: String::new(),
+        source: "statsd".to_string(),
+        severity: if breached {
+            crate::severity::normalize(&threshold.severity).to_string()
+        } else {
+            "info".to_string()
+        },
+        message,
+        data: json!({
+            "metric": series.metric,
+            "kind": metric.kind.name(),
+            "value": metric.value,
+            "above": threshold.above,
+            "below": threshold.below,
+            "interval_seconds": config.interval.as_secs(),
+            "tags": metric.tags,
+        }),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+async fn receive(state: web::Data<AppState>, socket: UdpSocket, config: StatsdConfig) {
+    let mut metrics = Metrics::default();
+    let mut buffer = vec![0u8; 65535];
+    let mut ticker = tokio::time::interval(config.interval.max(Duration::from_secs(1)));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buffer) => match received {
+                Ok((length, peer)) => {
+                    let packet = String::from_utf8_lossy(&buffer[..length]);
+                    for sample in packet.lines().filter_map(parse_line) {
+                        metrics.record(sample, &peer);
+                    }
+                }
+                Err(err) => log_line!("WARN", "statsd receive failed: {}", err),
+            },
+            _ = ticker.tick() => {
+                let batch = metrics.evaluate(&config);
+                if !batch.is_empty() {
+                    crate::ingest(&state, batch).await;
+                }
+            }
+        }
+    }
+}
+
+// Datagram contents from a fuzzer or a property test, recorded and checked
+// against thresholds that every metric can cross. Malformed input must not
+// panic.
+pub fn fuzz(input: &[u8]) {
+    let config = StatsdConfig {
+        thresholds: vec![Threshold {
+            metric: "*".to_string(),
+            above: Some(10.0),
+            below: Some(-10.0),
+            severity: "warning".to_string(),
+        }],
+        ..StatsdConfig::default()
+    };
+    let peer: SocketAddr = "192.0.2.1:8125".parse().unwrap();
+    let mut metrics = Metrics::default();
+    for sample in String::from_utf8_lossy(input)
+        .lines()
+        .filter_map(parse_line)
+    {
+        metrics.record(sample, &peer);
+    }
+    metrics.evaluate(&config);
+}
+
+// Binds right away, before hardening drops root
+pub fn spawn(state: web::Data<AppState>, config: StatsdConfig) {
+    if config.thresholds.is_empty() {
+        log_line!(
+            "WARN",
+            "statsd listener has no [[statsd.thresholds]]; metrics will be ignored"
+        );
+    }
+    let socket = std::net::UdpSocket::bind(&config.bind)
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .and_then(UdpSocket::from_std);
+    match socket {
+        Ok(socket) => {
+            log_line!(
+                "CONFIG",
+                "statsd listening on udp://{} ({} thresholds)",
+                config.bind,
+                config.thresholds.len()
+            );
+            actix_web::rt::spawn(receive(state, socket, config));
+        }
+        Err(err) => log_line!(
+            "ERROR",
+            "statsd listener could not bind udp://{}: {}",
+            config.bind,
+            err
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+
+    const SAMPLES: &[&[u8]] = &[
+        b"checkout.errors:3|c|@0.5|#host:web01,env:prod",
+        b"queue.depth:+12|g\nqueue.depth:-4|g",
+        b"latency:120|ms",
+        b"disk.free:0.25|g|#host:db01",
+    ];
+    const TOKENS: &[&[u8]] = &[
+        b":", b"|", b"|c", b"|g", b"|@", b"@0", b"@-1", b"|#", b",", b"+", b"-", b"NaN", b"inf",
+        b"1e308", b"\n",
+    ];
+
+    proptest! {
+        #[test]
+        fn fuzz_parse(input in mutations::input(SAMPLES, TOKENS)) {
+            fuzz(&input);
+        }
+
+        #[test]
+        fn counter_round_trip(
+            name in "[a-z._]{1,20}",
+            value in (0..1_000_000u32).prop_map(f64::from),
+            rate in (1..=100u32).prop_map(|divisor| 1.0 / f64::from(divisor)),
+        ) {
+            let line = format!("{}:{}|c|@{}", name, value, rate);
+            let sample = parse_line(&line).unwrap();
+            prop_assert_eq!(sample.name, name.as_str());
+            prop_assert_eq!(sample.kind, Kind::Counter);
+            prop_assert!((sample.value - value / rate).abs() <= 1e-6 * (value / rate).max(1.0));
+        }
+    }
+}
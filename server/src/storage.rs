@@ -0,0 +1,1128 @@
+use crate::stats::{Tallies, Tally};
+use crate::{severity, Event, TimeRange};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// Width of one time index segment, in milliseconds
+const SEGMENT_MILLIS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    // Events only live in memory and are gone after a restart
+    #[default]
+    Memory,
+    Sqlite,
+}
+
+// `[storage]` section
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    backend: Backend,
+    // Database file for the sqlite backend
+    path: String,
+    // Ingest writer threads, and store shards to match; a host's events
+    // always go to the same writer and the same shard, so writers don't
+    // wait on each other's inserts. 0 picks one per core, up to 8.
+    // `shards` is the old name.
+    #[serde(alias = "shards")]
+    writers: usize,
+    // Memory backend only: keep at most this many events, evicting the
+    // oldest as new ones arrive. 0 keeps everything.
+    max_events_in_memory: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: Backend::Memory,
+            path: "loglumen.db".to_string(),
+            writers: 0,
+            max_events_in_memory: 0,
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn writers(&self) -> usize {
+        match self.writers {
+            0 => std::thread::available_parallelism().map_or(1, |cores| cores.get().min(8)),
+            writers => writers,
+        }
+    }
+
+    // Events the memory backend keeps at most, if it is bounded
+    pub fn capacity(&self) -> Option<usize> {
+        (self.backend == Backend::Memory && self.max_events_in_memory > 0)
+            .then_some(self.max_events_in_memory)
+    }
+}
+
+// One shard's events in store order with their sequence numbers. Sequence
+// numbers are shared by every shard, only grow and stay with an event for
+// as long as it is stored, so they can be used as stable ids even after
+// retention removes events.
+#[derive(Default)]
+struct Stored {
+    events: Vec<Event>,
+    sequences: Vec<u64>,
+    // Positions before this were evicted by a bounded store and are left
+    // empty until they make up half the vectors, so evicting doesn't shift
+    // every event on each insert
+    start: usize,
+    by_time: TimeIndex,
+    // The same, one index per category, for the dashboard's recent lists
+    by_category: HashMap<String, TimeIndex>,
+    tally: Tally,
+}
+
+impl Stored {
+    fn new(events: Vec<Event>, sequences: Vec<u64>) -> Self {
+        let mut stored = Stored::default();
+        for (event, sequence) in events.iter().zip(&sequences) {
+            stored.index(event, *sequence);
+        }
+        stored.events = events;
+        stored.sequences = sequences;
+        stored
+    }
+
+    fn index(&mut self, event: &Event, sequence: u64) {
+        self.by_time.insert(event, sequence);
+        match self.by_category.get_mut(&event.category) {
+            Some(index) => index.insert(event, sequence),
+            None => {
+                let mut index = TimeIndex::default();
+                index.insert(event, sequence);
+                self.by_category.insert(event.category.clone(), index);
+            }
+        }
+        self.tally.add(event, (TimeIndex::key(event), sequence));
+    }
+
+    fn append(&mut self, event: Event, sequence: u64) {
+        self.index(&event, sequence);
+        self.events.push(event);
+        self.sequences.push(sequence);
+    }
+
+    // Stored events and their sequence numbers, oldest first
+    fn events(&self) -> &[Event] {
+        &self.events[self.start..]
+    }
+
+    fn sequences(&self) -> &[u64] {
+        &self.sequences[self.start..]
+    }
+
+    fn get(&self, sequence: u64) -> Option<&Event> {
+        let position = self.sequences().binary_search(&sequence).ok()?;
+        Some(&self.events()[position])
+    }
+
+    // Events with sequence numbers in [start, end), oldest first
+    fn range(&self, start: u64, end: u64) -> Entries<'_> {
+        let sequences = self.sequences();
+        let first = sequences.partition_point(|sequence| *sequence < start);
+        let last = sequences
+            .partition_point(|sequence| *sequence < end)
+            .max(first);
+        sequences[first..last]
+            .iter()
+            .copied()
+            .zip(&self.events()[first..last])
+    }
+
+    // Time index entries with the events they point at
+    fn resolve<'a>(
+        &'a self,
+        entries: impl DoubleEndedIterator<Item = &'a (i64, u64)> + 'a,
+    ) -> impl DoubleEndedIterator<Item = ((i64, u64), &'a Event)> + 'a {
+        entries.filter_map(move |entry| Some((*entry, self.get(entry.1)?)))
+    }
+
+    // Returns the event's node when it lost its newest event
+    fn unindex(&mut self, event: &Event, sequence: u64) -> Option<(String, String)> {
+        self.by_time.remove(event, sequence);
+        if let Some(index) = self.by_category.get_mut(&event.category) {
+            index.remove(event, sequence);
+            if index.segments.is_empty() {
+                self.by_category.remove(&event.category);
+            }
+        }
+        self.tally
+            .remove(event, (TimeIndex::key(event), sequence))
+            .then(|| (event.host.clone(), event.host_ipv4.clone()))
+    }
+
+    // Drop the events at the positions not kept
+    fn remove(&mut self, keep: &[bool]) {
+        self.compact();
+        let mut stale = Vec::new();
+        for (position, kept) in keep.iter().enumerate() {
+            if !*kept {
+                let event = std::mem::take(&mut self.events[position]);
+                stale.extend(self.unindex(&event, self.sequences[position]));
+            }
+        }
+        let mut kept = keep.iter();
+        self.events.retain(|_| kept.next().copied().unwrap_or(true));
+        let mut kept = keep.iter();
+        self.sequences
+            .retain(|_| kept.next().copied().unwrap_or(true));
+        self.refresh_latest(stale);
+    }
+
+    // Drop the `over` oldest events
+    fn evict(&mut self, over: usize) {
+        let over = over.min(self.events().len());
+        let mut stale = Vec::new();
+        for position in self.start..self.start + over {
+            let event = std::mem::take(&mut self.events[position]);
+            stale.extend(self.unindex(&event, self.sequences[position]));
+        }
+        self.start += over;
+        if self.start >= self.events().len() {
+            self.compact();
+        }
+        self.refresh_latest(stale);
+    }
+
+    fn compact(&mut self) {
+        self.events.drain(..self.start);
+        self.sequences.drain(..self.start);
+        self.start = 0;
+    }
+
+    // A node lost its newest event, which rarely happens since retention
+    // and eviction remove the oldest first; find the next newest the slow way
+    fn refresh_latest(&mut self, stale: Vec<(String, String)>) {
+        for (host, host_ipv4) in stale {
+            let latest = self
+                .by_time
+                .segments
+                .values()
+                .flatten()
+                .rev()
+                .filter_map(|(key, sequence)| {
+                    let sequences = &self.sequences[self.start..];
+                    let position = sequences.binary_search(sequence).ok()?;
+                    Some(((*key, *sequence), &self.events[self.start + position]))
+                })
+                .find(|(_, event)| event.host == host && event.host_ipv4 == host_ipv4);
+            if let Some((order, event)) = latest {
+                self.tally.set_latest(event, order);
+            }
+        }
+    }
+}
+
+// Sequence numbers ordered by event time, split into hour-long segments.
+// Store order is arrival order, which is not time order once an agent
+// flushes an offline buffer; a late event is inserted into its own hour's
+// segment, so it costs no more than an on-time one in a busy hour.
+// Unparseable timestamps sort first. Segments are deques so removing an
+// hour's oldest event, as retention and eviction mostly do, is O(1).
+#[derive(Default)]
+struct TimeIndex {
+    segments: BTreeMap<i64, VecDeque<(i64, u64)>>,
+}
+
+impl TimeIndex {
+    fn key(event: &Event) -> i64 {
+        event
+            .timestamp()
+            .map_or(i64::MIN, |time| time.timestamp_millis())
+    }
+
+    fn insert(&mut self, event: &Event, sequence: u64) {
+        let entry = (Self::key(event), sequence);
+        let segment = self
+            .segments
+            .entry(entry.0.div_euclid(SEGMENT_MILLIS))
+            .or_default();
+        // Most events arrive in order and just append
+        if segment.back().is_none_or(|last| *last < entry) {
+            segment.push_back(entry);
+        } else {
+            let position = segment.partition_point(|stored| *stored < entry);
+            segment.insert(position, entry);
+        }
+    }
+
+    fn remove(&mut self, event: &Event, sequence: u64) {
+        let entry = (Self::key(event), sequence);
+        let hour = entry.0.div_euclid(SEGMENT_MILLIS);
+        if let Some(segment) = self.segments.get_mut(&hour) {
+            if let Ok(position) = segment.binary_search(&entry) {
+                segment.remove(position);
+            }
+            if segment.is_empty() {
+                self.segments.remove(&hour);
+            }
+        }
+    }
+}
+
+// A shard's events with their sequence numbers, oldest first
+type Entries<'a> =
+    std::iter::Zip<std::iter::Copied<std::slice::Iter<'a, u64>>, std::slice::Iter<'a, Event>>;
+
+// The shard a host's events are stored in. The ingest writers are picked
+// the same way, so each writer inserts into its own shard.
+pub fn shard_of(host: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    host.hash(&mut hasher);
+    (hasher.finish() % shards.max(1) as u64) as usize
+}
+
+// Iterators already in order, one per shard, read as one ordered iterator
+// from either end
+struct Merge<I: Iterator, K> {
+    sources: Vec<Source<I>>,
+    key: fn(&I::Item) -> K,
+}
+
+struct Source<I: Iterator> {
+    iter: I,
+    // Taken off either end but not handed out yet
+    front: Option<I::Item>,
+    back: Option<I::Item>,
+}
+
+impl<I: Iterator, K: Ord> Merge<I, K> {
+    fn new(sources: impl IntoIterator<Item = I>, key: fn(&I::Item) -> K) -> Self {
+        Merge {
+            sources: sources
+                .into_iter()
+                .map(|iter| Source {
+                    iter,
+                    front: None,
+                    back: None,
+                })
+                .collect(),
+            key,
+        }
+    }
+}
+
+impl<I: Iterator, K: Ord> Iterator for Merge<I, K> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        for source in &mut self.sources {
+            if source.front.is_none() {
+                source.front = source.iter.next().or_else(|| source.back.take());
+            }
+        }
+        let key = self.key;
+        self.sources
+            .iter_mut()
+            .filter(|source| source.front.is_some())
+            .min_by_key(|source| source.front.as_ref().map(key))?
+            .front
+            .take()
+    }
+}
+
+impl<I: DoubleEndedIterator, K: Ord> DoubleEndedIterator for Merge<I, K> {
+    fn next_back(&mut self) -> Option<I::Item> {
+        for source in &mut self.sources {
+            if source.back.is_none() {
+                source.back = source.iter.next_back().or_else(|| source.front.take());
+            }
+        }
+        let key = self.key;
+        self.sources
+            .iter_mut()
+            .filter(|source| source.back.is_some())
+            .max_by_key(|source| source.back.as_ref().map(key))?
+            .back
+            .take()
+    }
+}
+
+fn by_sequence(entry: &(u64, &Event)) -> u64 {
+    entry.0
+}
+
+fn by_time(entry: &((i64, u64), &Event)) -> (i64, u64) {
+    entry.0
+}
+
+// Stored events from every shard, read in sequence order as one log.
+// Writers are blocked while one is held, so keep it short and never wait
+// while holding it (lock order is always store first, then any index).
+pub(crate) struct StoreView<'a> {
+    shards: Vec<RwLockReadGuard<'a, Stored>>,
+}
+
+impl StoreView<'_> {
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.events().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.events().is_empty())
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Event> + '_ {
+        self.entries().map(|(_, event)| event)
+    }
+
+    // Every event with its sequence number, oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = (u64, &Event)> + '_ {
+        self.range(..)
+    }
+
+    // Events whose sequence numbers are in `sequences`, oldest first
+    pub fn range(
+        &self,
+        sequences: impl RangeBounds<u64>,
+    ) -> impl DoubleEndedIterator<Item = (u64, &Event)> + '_ {
+        let start = match sequences.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match sequences.end_bound() {
+            Bound::Included(end) => end.saturating_add(1),
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => u64::MAX,
+        };
+        Merge::new(
+            self.shards.iter().map(|shard| shard.range(start, end)),
+            by_sequence,
+        )
+    }
+
+    // Sequence number of the oldest stored event
+    pub fn first_sequence(&self) -> Option<u64> {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.sequences().first().copied())
+            .min()
+    }
+
+    // The event with this sequence number, if it is still stored
+    pub fn get(&self, sequence: u64) -> Option<&Event> {
+        self.shards.iter().find_map(|shard| shard.get(sequence))
+    }
+
+    // Every event with its sequence number, oldest first by its own
+    // timestamp rather than by arrival
+    pub fn entries_by_time(&self) -> impl DoubleEndedIterator<Item = (u64, &Event)> + '_ {
+        Merge::new(
+            self.shards
+                .iter()
+                .map(|shard| shard.resolve(shard.by_time.segments.values().flatten())),
+            by_time,
+        )
+        .map(|((_, sequence), event)| (sequence, event))
+    }
+
+    pub fn by_time(&self) -> impl DoubleEndedIterator<Item = &Event> + '_ {
+        self.entries_by_time().map(|(_, event)| event)
+    }
+
+    // Events whose timestamp is within [since, until] in milliseconds,
+    // oldest first, reading only the hours in range
+    pub fn between(&self, since: i64, until: i64) -> impl Iterator<Item = &Event> + '_ {
+        let hours = since.div_euclid(SEGMENT_MILLIS)..=until.div_euclid(SEGMENT_MILLIS);
+        Merge::new(
+            self.shards.iter().map(|shard| {
+                shard.resolve(
+                    shard
+                        .by_time
+                        .segments
+                        .range(hours.clone())
+                        .flat_map(|(_, segment)| segment)
+                        .filter(move |(key, _)| (since..=until).contains(key)),
+                )
+            }),
+            by_time,
+        )
+        .map(|(_, event)| event)
+    }
+
+    // One category's events, oldest first by timestamp
+    pub fn category_by_time(&self, category: &str) -> impl DoubleEndedIterator<Item = &Event> + '_ {
+        Merge::new(
+            self.shards.iter().map(|shard| {
+                shard.resolve(
+                    shard
+                        .by_category
+                        .get(category)
+                        .into_iter()
+                        .flat_map(|index| index.segments.values().flatten()),
+                )
+            }),
+            by_time,
+        )
+        .map(|(_, event)| event)
+    }
+
+    // Running dashboard counts over every stored event
+    pub fn tally(&self) -> Tallies<'_> {
+        Tallies::new(self.shards.iter().map(|shard| &shard.tally).collect())
+    }
+}
+
+// Filtered listing shared by the event endpoints
+#[derive(Default)]
+pub struct StoreQuery<'a> {
+    pub range: TimeRange,
+    // Severity floor, as resolved by `SeverityFloor::resolve`
+    pub min_severity: usize,
+    pub matches: Option<&'a dyn Fn(&Event) -> bool>,
+    pub newest_first: bool,
+    pub limit: Option<usize>,
+    // Sequence number to resume from, inclusive, as returned in `Page::next`
+    pub cursor: Option<u64>,
+}
+
+impl StoreQuery<'_> {
+    fn accepts(&self, event: &Event) -> bool {
+        severity::passes(event, self.min_severity)
+            && self.range.contains(event)
+            && self.matches.is_none_or(|matches| matches(event))
+    }
+}
+
+pub struct Page {
+    pub events: Vec<Event>,
+    // Sequence number of the first event of the next page, if there is one
+    pub next: Option<u64>,
+}
+
+// Cursors are opaque to clients; the "v1:" prefix leaves room to change what
+// they encode
+pub fn encode_cursor(sequence: u64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("v1:{}", sequence))
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<u64, String> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|text| text.strip_prefix("v1:")?.parse().ok())
+        .ok_or_else(|| format!("invalid cursor '{}'", cursor))
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StoreStats {
+    pub backend: &'static str,
+    pub events: usize,
+    // Sequence number the next event gets. Grows on every insert and removal
+    // and only changes while a shard is locked for writing, so it is
+    // consistent with any view held at the same time.
+    pub version: u64,
+    // Version right after the last removal, if events were ever removed
+    pub removed_at: Option<u64>,
+}
+
+// Everything handlers need from the event store. Backends keep the stored
+// events resident for `read`; `query` and `stats` can be overridden where a
+// backend answers them more cheaply.
+pub trait Storage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn read(&self) -> StoreView<'_>;
+
+    // Append a batch and return the new store size. `index` runs under the
+    // batch's write locks with the sequence number of the batch's first
+    // event (the rest follow consecutively), one batch at a time, so
+    // secondary indexes see batches in store order. On an error nothing
+    // was stored and `index` isn't called.
+    fn insert_batch(
+        &self,
+        batch: Vec<Event>,
+        index: &mut dyn FnMut(u64, &[Event]),
+    ) -> Result<usize, String>;
+
+    // Drop the events with these sequence numbers (sorted ascending) and
+    // return how many were stored
+    fn remove(&self, sequences: &[u64]) -> usize;
+
+    // Never takes the lock, so it is safe while holding a view
+    fn stats(&self) -> StoreStats;
+
+    // Called once on shutdown, after the last batch is in
+    fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn query(&self, query: &StoreQuery) -> Vec<Event> {
+        self.page(query).events
+    }
+
+    // Up to `limit` matching events and where the next page starts
+    fn page(&self, query: &StoreQuery) -> Page {
+        let store = self.read();
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let entries: Box<dyn Iterator<Item = (u64, &Event)>> = if query.newest_first {
+            let end = query
+                .cursor
+                .map_or(u64::MAX, |cursor| cursor.saturating_add(1));
+            Box::new(store.range(..end).rev())
+        } else {
+            Box::new(store.range(query.cursor.unwrap_or(0)..))
+        };
+
+        let mut page = Page {
+            events: Vec::new(),
+            next: None,
+        };
+        for (sequence, event) in entries.filter(|(_, event)| query.accepts(event)) {
+            if page.events.len() == limit {
+                page.next = Some(sequence);
+                break;
+            }
+            page.events.push(event.clone());
+        }
+        page
+    }
+}
+
+pub struct MemoryStorage {
+    // Events split by host (see `shard_of`), each shard behind its own lock.
+    // Sequence numbers are shared, so the shards read in sequence order are
+    // the same log a single store would hold.
+    shards: Vec<RwLock<Stored>>,
+    // Held while a batch takes its sequence numbers and runs the index
+    // callback, so the indexes see batches in sequence order
+    sequencer: Mutex<()>,
+    // Most events kept, 0 for no limit
+    capacity: usize,
+    len: AtomicUsize,
+    version: AtomicU64,
+    // 0 until something is removed
+    removed_at: AtomicU64,
+}
+
+impl MemoryStorage {
+    // Keeps at most `capacity` events, dropping the oldest; 0 for no limit
+    fn new(shards: usize, capacity: usize) -> Self {
+        MemoryStorage {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(Stored::default()))
+                .collect(),
+            sequencer: Mutex::new(()),
+            capacity,
+            len: AtomicUsize::new(0),
+            version: AtomicU64::new(0),
+            removed_at: AtomicU64::new(0),
+        }
+    }
+
+    fn with_events(shards: usize, events: Vec<Event>, sequences: Vec<u64>) -> Self {
+        let storage = MemoryStorage::new(shards, 0);
+        let mut parts: Vec<(Vec<Event>, Vec<u64>)> = (0..storage.shards.len())
+            .map(|_| Default::default())
+            .collect();
+        let version = sequences.last().map_or(0, |last| last + 1);
+        storage.len.store(events.len(), Ordering::SeqCst);
+        storage.version.store(version, Ordering::SeqCst);
+        for (event, sequence) in events.into_iter().zip(sequences) {
+            let (events, sequences) = &mut parts[shard_of(&event.host, storage.shards.len())];
+            events.push(event);
+            sequences.push(sequence);
+        }
+        for (shard, (events, sequences)) in storage.shards.iter().zip(parts) {
+            *shard.write() = Stored::new(events, sequences);
+        }
+        storage
+    }
+
+    // Every shard for writing, always locked in order
+    fn write_all(&self) -> Vec<RwLockWriteGuard<'_, Stored>> {
+        self.shards.iter().map(RwLock::write).collect()
+    }
+
+    // Drop the oldest events past the capacity, oldest by sequence number
+    // across every shard. Needs every shard, so it waits for other
+    // writers' inserts; unbounded stores never get here.
+    fn evict(&self) {
+        let mut shards = self.write_all();
+        let stored: usize = shards.iter().map(|shard| shard.events().len()).sum();
+        let mut over = stored.saturating_sub(self.capacity);
+        if over == 0 {
+            return;
+        }
+        let mut counts = vec![0; shards.len()];
+        while over > 0 {
+            let oldest = shards
+                .iter()
+                .zip(&counts)
+                .enumerate()
+                .filter_map(|(shard, (stored, count))| {
+                    Some((*stored.sequences().get(*count)?, shard))
+                })
+                .min();
+            let Some((_, shard)) = oldest else {
+                break;
+            };
+            counts[shard] += 1;
+            over -= 1;
+        }
+        for (shard, count) in shards.iter_mut().zip(counts) {
+            shard.evict(count);
+        }
+        // Same as a removal for deltas
+        self.removed_at
+            .store(self.version.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.len.store(
+            shards.iter().map(|shard| shard.events().len()).sum(),
+            Ordering::SeqCst,
+        );
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn read(&self) -> StoreView<'_> {
+        StoreView {
+            shards: self.shards.iter().map(RwLock::read).collect(),
+        }
+    }
+
+    // Only the shards the batch goes to are locked, so batches from hosts
+    // on other shards are inserted at the same time. Batches from the
+    // ingest writers only ever go to one.
+    fn insert_batch(
+        &self,
+        batch: Vec<Event>,
+        index: &mut dyn FnMut(u64, &[Event]),
+    ) -> Result<usize, String> {
+        let targets: Vec<usize> = batch
+            .iter()
+            .map(|event| shard_of(&event.host, self.shards.len()))
+            .collect();
+        let mut locked: Vec<usize> = targets.clone();
+        locked.sort_unstable();
+        locked.dedup();
+        let mut shards: Vec<RwLockWriteGuard<'_, Stored>> = locked
+            .iter()
+            .map(|shard| self.shards[*shard].write())
+            .collect();
+
+        let inserted = batch.len();
+        let first = {
+            let _sequencer = self.sequencer.lock();
+            let first = self.version.load(Ordering::SeqCst);
+            index(first, &batch);
+            self.version
+                .store(first + inserted as u64, Ordering::SeqCst);
+            first
+        };
+        for ((event, sequence), target) in batch.into_iter().zip(first..).zip(targets) {
+            let shard = locked.binary_search(&target).unwrap_or_default();
+            shards[shard].append(event, sequence);
+        }
+        let total = self.len.fetch_add(inserted, Ordering::SeqCst) + inserted;
+        drop(shards);
+
+        if self.capacity > 0 && total > self.capacity {
+            self.evict();
+        }
+        Ok(self.len.load(Ordering::SeqCst))
+    }
+
+    fn remove(&self, sequences: &[u64]) -> usize {
+        if sequences.is_empty() {
+            return 0;
+        }
+        let mut shards = self.write_all();
+        let mut removed = 0;
+        for shard in shards.iter_mut() {
+            let keep: Vec<bool> = shard
+                .sequences()
+                .iter()
+                .map(|sequence| sequences.binary_search(sequence).is_err())
+                .collect();
+            let before = shard.events().len();
+            shard.remove(&keep);
+            removed += before - shard.events().len();
+        }
+        if removed > 0 {
+            // A removal is a change too, so deltas taken before it reset
+            let version = self.version.fetch_add(1, Ordering::SeqCst) + 1;
+            self.removed_at.store(version, Ordering::SeqCst);
+            self.len.fetch_sub(removed, Ordering::SeqCst);
+        }
+        removed
+    }
+
+    fn stats(&self) -> StoreStats {
+        let removed_at = self.removed_at.load(Ordering::SeqCst);
+        StoreStats {
+            backend: self.name(),
+            events: self.len.load(Ordering::SeqCst),
+            version: self.version.load(Ordering::SeqCst),
+            removed_at: (removed_at > 0).then_some(removed_at),
+        }
+    }
+}
+
+// Writes every batch to a SQLite file and serves reads from the copy loaded
+// into memory at startup. Row ids are the events' sequence numbers.
+pub struct SqliteStorage {
+    memory: MemoryStorage,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str, shards: usize) -> Result<Self, String> {
+        let connection =
+            Connection::open(path).map_err(|err| format!("cannot open {}: {}", path, err))?;
+        // WAL keeps the writer from blocking readers of the file, e.g. backups
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA synchronous = NORMAL;
+                 CREATE TABLE IF NOT EXISTS events (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     time TEXT NOT NULL,
+                     host TEXT NOT NULL,
+                     category TEXT NOT NULL,
+                     severity TEXT NOT NULL,
+                     event TEXT NOT NULL
+                 );
+                 CREATE INDEX IF NOT EXISTS events_time ON events (time);
+                 CREATE INDEX IF NOT EXISTS events_host ON events (host);",
+            )
+            .map_err(|err| format!("cannot prepare {}: {}", path, err))?;
+        let (events, sequences) =
+            load(&connection).map_err(|err| format!("cannot read {}: {}", path, err))?;
+        Ok(SqliteStorage {
+            memory: MemoryStorage::with_events(shards, events, sequences),
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn persist(connection: &mut Connection, first: u64, batch: &[Event]) -> Result<(), String> {
+        let transaction = connection.transaction().map_err(|err| err.to_string())?;
+        {
+            let mut statement = transaction
+                .prepare_cached(
+                    "INSERT INTO events (id, time, host, category, severity, event)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(|err| err.to_string())?;
+            for (sequence, event) in (first..).zip(batch) {
+                let json = serde_json::to_string(event).map_err(|err| err.to_string())?;
+                statement
+                    .execute((
+                        sequence as i64,
+                        &event.time,
+                        &event.host,
+                        &event.category,
+                        &event.severity,
+                        json,
+                    ))
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|err| err.to_string())
+    }
+
+    fn delete(connection: &mut Connection, sequences: &[u64]) -> Result<(), String> {
+        let transaction = connection.transaction().map_err(|err| err.to_string())?;
+        {
+            let mut statement = transaction
+                .prepare_cached("DELETE FROM events WHERE id = ?1")
+                .map_err(|err| err.to_string())?;
+            for sequence in sequences {
+                statement
+                    .execute([*sequence as i64])
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|err| err.to_string())
+    }
+}
+
+// Every persisted event with its sequence number, oldest first
+fn load(connection: &Connection) -> Result<(Vec<Event>, Vec<u64>), String> {
+    let mut statement = connection
+        .prepare("SELECT id, event FROM events ORDER BY id")
+        .map_err(|err| err.to_string())?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|err| err.to_string())?;
+
+    let mut events = Vec::new();
+    let mut sequences = Vec::new();
+    for row in rows {
+        let (id, json) = row.map_err(|err| err.to_string())?;
+        match serde_json::from_str(&json) {
+            Ok(event) => {
+                events.push(event);
+                sequences.push(id as u64);
+            }
+            Err(err) => log_line!("WARN", "Skipping unreadable stored event {}: {}", id, err),
+        }
+    }
+    Ok((events, sequences))
+}
+
+impl Storage for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn read(&self) -> StoreView<'_> {
+        self.memory.read()
+    }
+
+    // Rows are written before any shard is locked, so readers never wait
+    // on the disk, and a batch that can't be written isn't kept in memory
+    // either. The connection is held throughout: it is what keeps the
+    // sequence numbers written here the ones the memory store hands out.
+    fn insert_batch(
+        &self,
+        batch: Vec<Event>,
+        index: &mut dyn FnMut(u64, &[Event]),
+    ) -> Result<usize, String> {
+        let mut connection = self.connection.lock();
+        let first = self.memory.stats().version;
+        Self::persist(&mut connection, first, &batch)?;
+        self.memory.insert_batch(batch, index)
+    }
+
+    // Holds the connection too, as a removal also moves the version on
+    fn remove(&self, sequences: &[u64]) -> usize {
+        let mut connection = self.connection.lock();
+        if let Err(err) = Self::delete(&mut connection, sequences) {
+            log_line!(
+                "ERROR",
+                "Failed to delete {} events from SQLite: {}",
+                sequences.len(),
+                err
+            );
+        }
+        self.memory.remove(sequences)
+    }
+
+    fn stats(&self) -> StoreStats {
+        StoreStats {
+            backend: self.name(),
+            ..self.memory.stats()
+        }
+    }
+
+    // Fold the WAL back into the database file so it is complete on its own
+    fn flush(&self) -> Result<(), String> {
+        self.connection
+            .lock()
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(|err| err.to_string())
+    }
+}
+
+// Falls back to memory when the database cannot be opened, so a bad path
+// is loud in the log but doesn't keep the server from accepting events
+pub fn open(config: StorageConfig) -> Box<dyn Storage> {
+    let shards = config.writers();
+    if config.max_events_in_memory > 0 && config.backend != Backend::Memory {
+        log_line!(
+            "WARN",
+            "[storage] max_events_in_memory only applies to the memory backend; use [retention] max_events"
+        );
+    }
+    match config.backend {
+        Backend::Memory if config.max_events_in_memory > 0 => {
+            log_line!(
+                "CONFIG",
+                "Keeping at most {} events in memory",
+                config.max_events_in_memory
+            );
+            Box::new(MemoryStorage::new(shards, config.max_events_in_memory))
+        }
+        Backend::Memory => Box::new(MemoryStorage::new(shards, 0)),
+        Backend::Sqlite => match SqliteStorage::open(&config.path, shards) {
+            Ok(storage) => {
+                log_line!("CONFIG", "Persisting events to SQLite at {}", config.path);
+                Box::new(storage)
+            }
+            Err(err) => {
+                log_line!(
+                    "ERROR",
+                    "SQLite storage unavailable, keeping events in memory only: {}",
+                    err
+                );
+                Box::new(MemoryStorage::new(shards, 0))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::CategoryTally;
+    use std::collections::HashSet;
+
+    const HOSTS: [&str; 5] = ["web01", "web03", "mail01", "dns01", "cache01"];
+
+    // The n-th event sent; later ones carry earlier timestamps
+    fn event(n: usize) -> Event {
+        Event {
+            category: "auth".to_string(),
+            host: HOSTS[n % HOSTS.len()].to_string(),
+            time: format!("2026-10-15T10:{:02}:00Z", 59 - n),
+            message: n.to_string(),
+            ..Event::default()
+        }
+    }
+
+    // One batch per event, so they are spread over the shards
+    fn filled(capacity: usize, events: usize) -> MemoryStorage {
+        let storage = MemoryStorage::new(4, capacity);
+        let shards: HashSet<usize> = HOSTS.iter().map(|host| shard_of(host, 4)).collect();
+        assert!(shards.len() > 1);
+        for n in 0..events {
+            storage
+                .insert_batch(vec![event(n)], &mut |_, _| {})
+                .unwrap();
+        }
+        storage
+    }
+
+    fn sequences<'a>(entries: impl Iterator<Item = (u64, &'a Event)>) -> Vec<u64> {
+        entries.map(|(sequence, _)| sequence).collect()
+    }
+
+    fn messages(events: &[Event]) -> Vec<&str> {
+        events.iter().map(|event| event.message.as_str()).collect()
+    }
+
+    #[test]
+    fn shards_read_as_one_log() {
+        let storage = filled(0, 20);
+        let store = storage.read();
+        assert_eq!(store.len(), 20);
+        assert_eq!(sequences(store.entries()), (0..20).collect::<Vec<_>>());
+        assert_eq!(
+            sequences(store.entries().rev()),
+            (0..20).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            sequences(store.entries_by_time()),
+            (0..20).rev().collect::<Vec<_>>()
+        );
+        assert_eq!(sequences(store.range(5..8)), [5, 6, 7]);
+        assert_eq!(store.get(7).map(|event| event.message.as_str()), Some("7"));
+        assert_eq!(store.first_sequence(), Some(0));
+
+        // Both ends of one iterator meet without repeating anything
+        let mut entries = store.entries();
+        assert_eq!(entries.next().map(|(sequence, _)| sequence), Some(0));
+        assert_eq!(entries.next_back().map(|(sequence, _)| sequence), Some(19));
+        assert_eq!(sequences(entries), (1..19).collect::<Vec<_>>());
+
+        let tally = store.tally();
+        let categories: Vec<(&String, CategoryTally)> = tally.categories().collect();
+        assert_eq!(categories.len(), 1);
+        assert_eq!(categories[0].1.total, 20);
+        assert_eq!(tally.nodes().count(), HOSTS.len());
+    }
+
+    #[test]
+    fn batches_across_shards_are_numbered_together() {
+        let storage = MemoryStorage::new(4, 0);
+        storage
+            .insert_batch(vec![event(0)], &mut |_, _| {})
+            .unwrap();
+        let mut indexed = None;
+        let batch = (1..=HOSTS.len()).map(event).collect();
+        storage
+            .insert_batch(batch, &mut |first, batch| {
+                indexed = Some((first, batch.len()))
+            })
+            .unwrap();
+        assert_eq!(indexed, Some((1, HOSTS.len())));
+
+        let store = storage.read();
+        let hosts: Vec<&str> = store.iter().map(|event| event.host.as_str()).collect();
+        assert_eq!(
+            hosts,
+            ["web01", "web03", "mail01", "dns01", "cache01", "web01"]
+        );
+    }
+
+    #[test]
+    fn eviction_drops_the_oldest_of_every_shard() {
+        let storage = filled(6, 10);
+        let store = storage.read();
+        assert_eq!(sequences(store.entries()), [4, 5, 6, 7, 8, 9]);
+        let stats = storage.stats();
+        assert_eq!(stats.events, 6);
+        assert_eq!(stats.removed_at, Some(10));
+    }
+
+    #[test]
+    fn removal_and_pages_follow_sequence_numbers() {
+        let storage = filled(0, 10);
+        assert_eq!(storage.remove(&[1, 4, 5]), 3);
+        assert_eq!(sequences(storage.read().entries()), [0, 2, 3, 6, 7, 8, 9]);
+
+        let newest = |cursor| {
+            storage.page(&StoreQuery {
+                newest_first: true,
+                limit: Some(3),
+                cursor,
+                ..StoreQuery::default()
+            })
+        };
+        let page = newest(None);
+        assert_eq!(messages(&page.events), ["9", "8", "7"]);
+        assert_eq!(page.next, Some(6));
+        let page = newest(page.next);
+        assert_eq!(messages(&page.events), ["6", "3", "2"]);
+        assert_eq!(page.next, Some(0));
+
+        let page = storage.page(&StoreQuery {
+            limit: Some(4),
+            cursor: Some(1),
+            ..StoreQuery::default()
+        });
+        assert_eq!(messages(&page.events), ["2", "3", "6", "7"]);
+        assert_eq!(page.next, Some(8));
+    }
+
+    #[test]
+    fn unpersisted_batches_are_not_kept() {
+        let storage = SqliteStorage::open(":memory:", 4).unwrap();
+        storage
+            .insert_batch(vec![event(0)], &mut |_, _| {})
+            .unwrap();
+        storage
+            .connection
+            .lock()
+            .execute_batch("DROP TABLE events")
+            .unwrap();
+
+        let mut indexed = false;
+        let result = storage.insert_batch(vec![event(1), event(2)], &mut |_, _| indexed = true);
+        assert!(result.is_err());
+        assert!(!indexed);
+        assert_eq!(storage.read().len(), 1);
+        assert_eq!(storage.stats().version, 1);
+    }
+}
@@ -0,0 +1,497 @@
+// Built with `--features syslog`. Listens for syslog over UDP and TCP, so
+// routers, firewalls and appliances that can't run the agent can still send
+// events. Both RFC 5424 and the older BSD format (RFC 3164) are parsed.
+use crate::{severity, AppState, Event};
+use actix_web::web;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+// Messages ingested together at most
+const BATCH: usize = 500;
+// Longest TCP frame or line accepted; longer ones close the connection
+const MAX_MESSAGE: usize = 64 * 1024;
+
+// `[syslog]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SyslogConfig {
+    enabled: bool,
+    // Addresses to listen on; either can be left out
+    udp: Option<String>,
+    tcp: Option<String>,
+    // Category of messages outside the auth and authpriv facilities
+    category: String,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        SyslogConfig {
+            enabled: false,
+            udp: Some("0.0.0.0:514".to_string()),
+            tcp: None,
+            category: "logs".to_string(),
+        }
+    }
+}
+
+impl SyslogConfig {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+struct Message {
+    facility: u8,
+    severity: u8,
+    time: Option<DateTime<Utc>>,
+    host: Option<String>,
+    app: Option<String>,
+    procid: Option<String>,
+    msgid: Option<String>,
+    structured: Map<String, Value>,
+    text: String,
+    format: &'static str,
+}
+
+fn nil(field: &str) -> Option<String> {
+    (field != "-" && !field.is_empty()).then(|| field.to_string())
+}
+
+// `<PRI>`, or user.notice as RFC 3164 says for messages without one
+fn priority(line: &str) -> (u8, &str) {
+    let parsed = line.strip_prefix('<').and_then(|rest| {
+        let (digits, rest) = rest.split_once('>')?;
+        let value: u8 = digits.parse().ok().filter(|value| *value < 192)?;
+        Some((value, rest))
+    });
+    parsed.unwrap_or((13, line))
+}
+
+// `[id key="value" ...]...`, with `\"`, `\\` and `\]` escaped in values
+fn structured_data(text: &str) -> Option<(Map<String, Value>, &str)> {
+    let mut elements = Map::new();
+    if let Some(rest) = text.strip_prefix('-') {
+        return Some((elements, rest));
+    }
+    let mut rest = text;
+    while let Some(element) = rest.strip_prefix('[') {
+        let end = element.find([' ', ']'])?;
+        let id = element[..end].to_string();
+        let mut params = Map::new();
+        let mut chars = element[end..].char_indices();
+        let mut consumed = None;
+        let mut name = String::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                ']' => {
+                    consumed = Some(end + index + 1);
+                    break;
+                }
+                ' ' => {}
+                '=' => {
+                    if chars.next().map(|(_, quote)| quote) != Some('"') {
+                        return None;
+                    }
+                    let mut value = String::new();
+                    loop {
+                        match chars.next()?.1 {
+                            '\\' => {
+                                let (_, escaped) = chars.next()?;
+                                if !matches!(escaped, '"' | '\\' | ']') {
+                                    value.push('\\');
+                                }
+                                value.push(escaped);
+                            }
+                            '"' => break,
+                            other => value.push(other),
+                        }
+                    }
+                    params.insert(std::mem::take(&mut name), Value::String(value));
+                }
+                other => name.push(other),
+            }
+        }
+        elements.insert(id, Value::Object(params));
+        rest = &element[consumed?..];
+    }
+    Some((elements, rest))
+}
+
+fn parse_5424(facility: u8, severity: u8, rest: &str) -> Option<Message> {
+    let mut fields = rest.splitn(6, ' ');
+    let time = fields.next()?;
+    let time = DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|time| time.with_timezone(&Utc));
+    let host = nil(fields.next()?);
+    let app = nil(fields.next()?);
+    let procid = nil(fields.next()?);
+    let msgid = nil(fields.next()?);
+    let (structured, text) = structured_data(fields.next().unwrap_or("-"))?;
+    let text = text.strip_prefix(' ').unwrap_or(text);
+    Some(Message {
+        facility,
+        severity,
+        time,
+        host,
+        app,
+        procid,
+        msgid,
+        structured,
+        text: text.trim_start_matches('\u{feff}').to_string(),
+        format: "rfc5424",
+    })
+}
+
+// `Oct 14 10:00:00` in the server's time zone, in the year that puts it
+// closest to now (devices don't send the year)
+fn bsd_time(text: &str) -> Option<DateTime<Utc>> {
+    let now = Local::now();
+    [now.year(), now.year() - 1]
+        .into_iter()
+        .filter_map(|year| {
+            let naive =
+                NaiveDateTime::parse_from_str(&format!("{} {}", year, text), "%Y %b %e %H:%M:%S")
+                    .ok()?;
+            Local.from_local_datetime(&naive).earliest()
+        })
+        .find(|time| *time <= now + chrono::Duration::days(1))
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn parse_3164(facility: u8, severity: u8, rest: &str) -> Message {
+    // BSD timestamps are "Mmm dd hh:mm:ss" (always 15 characters); rsyslog
+    // can send RFC 3339 instead
+    let (time, rest) = match rest.split_once(' ') {
+        Some((first, rest)) if DateTime::parse_from_rfc3339(first).is_ok() => (
+            DateTime::parse_from_rfc3339(first)
+                .ok()
+                .map(|time| time.with_timezone(&Utc)),
+            rest,
+        ),
+        _ => match rest.get(..15).and_then(bsd_time) {
+            Some(time) => (Some(time), rest[15..].trim_start()),
+            None => (None, rest),
+        },
+    };
+    // The hostname is left out by some senders, so a first word that looks
+    // like a tag (`sshd[42]:`, `kernel:`) isn't taken as one
+    let (host, rest) = match rest.split_once(' ') {
+        Some((word, other)) if time.is_some() && !word.ends_with(':') && !word.contains('[') => {
+            (nil(word), other)
+        }
+        _ => (None, rest),
+    };
+    let (tag, text) = match rest.split_once(": ") {
+        Some((tag, text)) if !tag.contains(' ') => (Some(tag), text),
+        _ => (None, rest),
+    };
+    let (app, procid) = match tag.map(|tag| tag.split_once('[')) {
+        Some(Some((app, pid))) => (nil(app), nil(pid.trim_end_matches(']'))),
+        Some(None) => (tag.and_then(nil), None),
+        None => (None, None),
+    };
+    Message {
+        facility,
+        severity,
+        time,
+        host,
+        app,
+        procid,
+        msgid: None,
+        structured: Map::new(),
+        text: text.to_string(),
+        format: "rfc3164",
+    }
+}
+
+fn parse(line: &str) -> Message {
+    let line = line.trim_end_matches(['\r', '\n', '\0']);
+    let (pri, rest) = priority(line);
+    let (facility, severity) = (pri / 8, pri % 8);
+    match rest.strip_prefix("1 ") {
+        Some(rest) => parse_5424(facility, severity, rest)
+            .unwrap_or_else(|| parse_3164(facility, severity, rest)),
+        None => parse_3164(facility, severity, rest),
+    }
+}
+
+// One datagram or line from a fuzzer or a property test. Malformed input
+// must not panic.
+pub fn fuzz(input: &[u8]) {
+    let peer = SocketAddr::from(([192, 0, 2, 1], 514));
+    let message = parse(&String::from_utf8_lossy(input));
+    assert!(message.facility < 24 && message.severity < 8);
+    to_event(message, "syslog", &peer);
+}
+
+fn to_event(message: Message, category: &str, peer: &SocketAddr) -> Event {
+    let peer = peer.ip().to_string();
+    let facility = severity::FACILITIES
+        .get(usize::from(message.facility))
+        .copied()
+        .unwrap_or("user");
+    let mut data = Map::new();
+    data.insert("facility".to_string(), Value::String(facility.to_string()));
+    data.insert(
+        "syslog_severity".to_string(),
+        Value::Number(message.severity.into()),
+    );
+    data.insert(
+        "format".to_string(),
+        Value::String(message.format.to_string()),
+    );
+    for (key, value) in [
+        ("app", &message.app),
+        ("procid", &message.procid),
+        ("msgid", &message.msgid),
+    ] {
+        if let Some(value) = value {
+            data.insert(key.to_string(), Value::String(value.clone()));
+        }
+    }
+    if !message.structured.is_empty() {
+        data.insert(
+            "structured_data".to_string(),
+            Value::Object(message.structured),
+        );
+    }
+
+    Event {
+        schema_version: 1,
+        category: match facility {
+            "auth" | "authpriv" => "auth".to_string(),
+            _ => category.to_string(),
+        },
+        event_type: message.app.unwrap_or_else(|| "syslog".to_string()),
+        time: message.time.unwrap_or_else(Utc::now).to_rfc3339(),
+        host: message.host.unwrap_or_else(|| peer.clone()),
+        host_ipv4: peer,
+        os: String::new(),
+        source: "syslog".to_string(),
+        severity: severity::from_syslog(message.severity).to_string(),
+        message: message.text,
+        data: Value::Object(data),
+        signature: None,
+        verification: None,
+        identity: None,
+        ttl: None,
+    }
+}
+
+async fn ingest(state: &AppState, batch: Vec<Event>) {
+    if batch.is_empty() {
+        return;
+    }
+    state.availability.observe(&batch);
+    crate::ingest(state, batch).await;
+}
+
+async fn receive_udp(state: web::Data<AppState>, socket: UdpSocket, category: String) {
+    let mut buffer = vec![0u8; 65535];
+    loop {
+        let mut batch = Vec::new();
+        match socket.recv_from(&mut buffer).await {
+            Ok((length, peer)) => {
+                let line = String::from_utf8_lossy(&buffer[..length]);
+                batch.push(to_event(parse(&line), &category, &peer));
+            }
+            Err(err) => {
+                log_line!("WARN", "Syslog receive failed: {}", err);
+                continue;
+            }
+        }
+        // Whatever else already arrived goes in the same batch
+        while batch.len() < BATCH {
+            match socket.try_recv_from(&mut buffer) {
+                Ok((length, peer)) => {
+                    let line = String::from_utf8_lossy(&buffer[..length]);
+                    batch.push(to_event(parse(&line), &category, &peer));
+                }
+                Err(_) => break,
+            }
+        }
+        ingest(&state, batch).await;
+    }
+}
+
+// Next frame: RFC 6587 octet counting (`<length> <message>`) when it starts
+// with a digit, a newline-terminated line otherwise
+async fn read_frame(
+    reader: &mut BufReader<TcpStream>,
+    frame: &mut Vec<u8>,
+) -> std::io::Result<bool> {
+    frame.clear();
+    let first = match reader.fill_buf().await?.first() {
+        Some(byte) => *byte,
+        None => return Ok(false),
+    };
+    if first.is_ascii_digit() {
+        let mut digits = Vec::new();
+        reader.read_until(b' ', &mut digits).await?;
+        let length: usize = std::str::from_utf8(&digits)
+            .ok()
+            .and_then(|digits| digits.trim().parse().ok())
+            .filter(|length| *length <= MAX_MESSAGE)
+            .ok_or_else(|| std::io::Error::other("bad syslog frame length"))?;
+        frame.resize(length, 0);
+        reader.read_exact(frame).await?;
+    } else {
+        (&mut *reader)
+            .take(MAX_MESSAGE as u64 + 1)
+            .read_until(b'\n', frame)
+            .await?;
+        if frame.len() > MAX_MESSAGE {
+            return Err(std::io::Error::other("syslog line too long"));
+        }
+    }
+    Ok(true)
+}
+
+async fn receive_tcp(
+    state: web::Data<AppState>,
+    stream: TcpStream,
+    peer: SocketAddr,
+    category: String,
+) {
+    let mut reader = BufReader::new(stream);
+    let mut frame = Vec::new();
+    let mut batch = Vec::new();
+    loop {
+        match read_frame(&mut reader, &mut frame).await {
+            Ok(true) => {
+                let line = String::from_utf8_lossy(&frame);
+                if !line.trim().is_empty() {
+                    batch.push(to_event(parse(&line), &category, &peer));
+                }
+                // Ingest once the sender pauses or the batch is full
+                if reader.buffer().is_empty() || batch.len() >= BATCH {
+                    ingest(&state, std::mem::take(&mut batch)).await;
+                }
+            }
+            Ok(false) => break,
+            Err(err) => {
+                log_line!("WARN", "Closing syslog connection from {}: {}", peer, err);
+                break;
+            }
+        }
+    }
+    ingest(&state, batch).await;
+}
+
+fn bind_error(protocol: &str, address: &str, err: std::io::Error) {
+    log_line!(
+        "ERROR",
+        "Syslog listener could not bind {}://{}: {}",
+        protocol,
+        address,
+        err
+    );
+}
+
+// Binds right away, before hardening drops root, so port 514 works
+pub fn spawn(state: web::Data<AppState>, config: SyslogConfig) {
+    if let Some(address) = &config.udp {
+        let socket = std::net::UdpSocket::bind(address)
+            .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+            .and_then(UdpSocket::from_std);
+        match socket {
+            Ok(socket) => {
+                log_line!("CONFIG", "Syslog listening on udp://{}", address);
+                actix_web::rt::spawn(receive_udp(state.clone(), socket, config.category.clone()));
+            }
+            Err(err) => bind_error("udp", address, err),
+        }
+    }
+    if let Some(address) = &config.tcp {
+        let listener = std::net::TcpListener::bind(address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .and_then(TcpListener::from_std);
+        match listener {
+            Ok(listener) => {
+                log_line!("CONFIG", "Syslog listening on tcp://{}", address);
+                let category = config.category.clone();
+                actix_web::rt::spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, peer)) => {
+                                actix_web::rt::spawn(receive_tcp(
+                                    state.clone(),
+                                    stream,
+                                    peer,
+                                    category.clone(),
+                                ));
+                            }
+                            Err(err) => log_line!("WARN", "Syslog accept failed: {}", err),
+                        }
+                    }
+                });
+            }
+            Err(err) => bind_error("tcp", address, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutations;
+    use proptest::prelude::*;
+
+    const SAMPLES: &[&[u8]] = &[
+        b"<34>1 2026-10-11T22:14:15.003Z mymachine.example.com su - ID47 [exampleSDID@32473 iut=\"3\" eventSource=\"App\\\"lication\"][other x=\"]\"] \xef\xbb\xbfAn application event",
+        b"<13>Oct 14 10:00:00 web01 sshd[42]: Accepted publickey for alice",
+        b"<165>1 2026-10-11T22:14:15+02:00 host app 1234 - - text",
+        b"<30>2026-10-14T10:00:00Z web01 systemd: Started session",
+        b"kernel: Out of memory\r\n",
+    ];
+    const TOKENS: &[&[u8]] = &[
+        b"<",
+        b">",
+        b"<191>",
+        b"<192>",
+        b"1 ",
+        b"-",
+        b"[",
+        b"]",
+        b"=\"",
+        b"\\",
+        b"\"",
+        b": ",
+        b"[42]",
+        b"Oct 14 10:00:00 ",
+        b"2026-10-14T10:00:00Z ",
+        b"\xef\xbb\xbf",
+        b"\xc3\xa9",
+        b"\xf0\x9f\x92\xa5",
+    ];
+
+    proptest! {
+        #[test]
+        fn fuzz_parse(input in mutations::input(SAMPLES, TOKENS)) {
+            fuzz(&input);
+        }
+
+        #[test]
+        fn rfc5424_round_trip(
+            pri in 0u16..192,
+            host in "[a-z0-9._]{1,12}",
+            app in "[a-z0-9._]{1,12}",
+            procid in "[a-z0-9._]{1,12}",
+            text in r#"[abc XYZ019;:,.!?()\[\]{}="'\\/_-]{1,60}"#,
+        ) {
+            let line = format!("<{}>1 2026-10-11T22:14:15Z {} {} {} - - {}", pri, host, app, procid, text);
+            let message = parse(&line);
+            prop_assert_eq!(message.format, "rfc5424");
+            prop_assert_eq!(u16::from(message.facility) * 8 + u16::from(message.severity), pri);
+            prop_assert_eq!(message.host, Some(host));
+            prop_assert_eq!(message.app, Some(app));
+            prop_assert_eq!(message.procid, Some(procid));
+            prop_assert_eq!(message.text, text);
+        }
+    }
+}
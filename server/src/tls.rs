@@ -0,0 +1,256 @@
+// HTTPS without a reverse proxy. Connections are accepted with tokio-rustls
+// and handed to the same actix App over HTTP/1.1.
+use actix_http::{Extensions, HttpService, Request};
+use actix_service::{
+    fn_service, map_config, IntoServiceFactory, Service, ServiceFactory, ServiceFactoryExt,
+};
+use actix_web::body::MessageBody;
+use actix_web::dev::{AppConfig, Response, Server};
+use actix_web::rt::net::TcpStream;
+use actix_web::HttpRequest;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+// Clients that haven't finished the handshake by then are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// `[tls]` section
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    // PEM certificate chain, leaf first
+    cert_path: Option<String>,
+    // PEM private key (PKCS#8, PKCS#1 or SEC1)
+    key_path: Option<String>,
+    // PEM CA certificates that agent client certificates must chain to
+    client_ca_path: Option<String>,
+    // Refuse clients without a certificate; off lets browsers in without
+    // one while agents that present one are still identified
+    require_client_cert: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_path: None,
+            key_path: None,
+            client_ca_path: None,
+            require_client_cert: true,
+        }
+    }
+}
+
+// Common name of the verified client certificate, kept per connection
+#[derive(Debug, Clone)]
+struct ClientIdentity(String);
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert_path.is_some() || self.key_path.is_some()
+    }
+
+    // "off", "optional" or "required", as agents see it
+    pub fn client_certificates(&self) -> &'static str {
+        match (
+            self.enabled() && self.client_ca_path.is_some(),
+            self.require_client_cert,
+        ) {
+            (false, _) => "off",
+            (true, false) => "optional",
+            (true, true) => "required",
+        }
+    }
+}
+
+// Load the certificate and key; done before hardening drops access to them
+pub fn acceptor(config: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let (Some(cert_path), Some(key_path)) = (&config.cert_path, &config.key_path) else {
+        return Err(io::Error::other("[tls] needs both cert_path and key_path"));
+    };
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| io::Error::other(format!("{}: {}", cert_path, err)))?;
+    if certs.is_empty() {
+        return Err(io::Error::other(format!("{}: no certificates", cert_path)));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|err| io::Error::other(format!("{}: {}", key_path, err)))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(ca_path)
+                .map_err(|err| io::Error::other(format!("{}: {}", ca_path, err)))?
+            {
+                let cert = cert.map_err(|err| io::Error::other(format!("{}: {}", ca_path, err)))?;
+                roots
+                    .add(cert)
+                    .map_err(|err| io::Error::other(format!("{}: {}", ca_path, err)))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.require_client_cert {
+                verifier
+            } else {
+                verifier.allow_unauthenticated()
+            };
+            let verifier = verifier
+                .build()
+                .map_err(|err| io::Error::other(format!("{}: {}", ca_path, err)))?;
+            log_line!(
+                "CONFIG",
+                "Client certificates from {} {}",
+                ca_path,
+                if config.require_client_cert {
+                    "are required"
+                } else {
+                    "are accepted but optional"
+                }
+            );
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| io::Error::other(format!("{}: {}", key_path, err)))?;
+    server.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+// Split one DER element into (tag, contents, rest)
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let length = if first < 0x80 {
+        usize::from(first)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (bytes, tail) = rest.split_at(count);
+        rest = tail;
+        bytes
+            .iter()
+            .fold(0usize, |length, byte| length << 8 | usize::from(*byte))
+    };
+    if rest.len() < length {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(length);
+    Some((tag, contents, rest))
+}
+
+// Subject CN of an X.509 certificate
+fn common_name(cert: &[u8]) -> Option<String> {
+    const COMMON_NAME: [u8; 3] = [0x55, 0x04, 0x03];
+    let (_, certificate, _) = der(cert)?;
+    let (_, mut tbs, _) = der(certificate)?;
+    // version (optional, [0]), serial, signature, issuer, validity, subject
+    let mut fields = Vec::new();
+    while fields.len() < 6 {
+        let Some((tag, contents, rest)) = der(tbs) else {
+            break;
+        };
+        fields.push((tag, contents));
+        tbs = rest;
+    }
+    let skip = usize::from(fields.first()?.0 == 0xa0);
+    let (_, mut names) = *fields.get(skip + 4)?;
+    while let Some((_, mut attributes, rest)) = der(names) {
+        names = rest;
+        while let Some((_, attribute, rest)) = der(attributes) {
+            attributes = rest;
+            let (_, oid, value) = der(attribute)?;
+            if oid == COMMON_NAME {
+                let (_, text, _) = der(value)?;
+                return Some(String::from_utf8_lossy(text).into_owned());
+            }
+        }
+    }
+    None
+}
+
+// CN of the client certificate the request's connection was verified with
+pub fn client_identity(request: &HttpRequest) -> Option<String> {
+    request
+        .conn_data::<ClientIdentity>()
+        .map(|identity| identity.0.clone())
+}
+
+fn remember_identity(stream: &TlsStream<TcpStream>, extensions: &mut Extensions) {
+    let (_, connection) = stream.get_ref();
+    if let Some(name) = connection
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(|cert| common_name(cert))
+    {
+        extensions.insert(ClientIdentity(name));
+    }
+}
+
+// The HTTPS counterpart of `HttpServer::new(app).bind(..)?.run()`
+pub fn serve<F, I, S, B>(
+    acceptor: TlsAcceptor,
+    listener: TcpListener,
+    workers: Option<usize>,
+    shutdown_timeout: Duration,
+    app: F,
+) -> io::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<actix_web::Error> + 'static,
+    S::InitError: std::fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service<Request>>::Future: 'static,
+    S::Service: 'static,
+    B: MessageBody + 'static,
+{
+    // Signals are handled by crate::shutdown
+    let mut builder = Server::build()
+        .disable_signals()
+        .shutdown_timeout(shutdown_timeout.as_secs());
+    if let Some(workers) = workers {
+        builder = builder.workers(workers);
+    }
+    Ok(builder
+        .listen("loglumen-https", listener, move || {
+            let acceptor = acceptor.clone();
+            fn_service(move |stream: TcpStream| {
+                let acceptor = acceptor.clone();
+                async move {
+                    let peer = stream.peer_addr().ok();
+                    let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                        .await
+                        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+                    Ok((stream, peer))
+                }
+            })
+            .map_err(actix_http::error::DispatchError::Io)
+            .and_then(
+                HttpService::build()
+                    .on_connect_ext(remember_identity)
+                    .h1(map_config(
+                        app()
+                            .into_factory()
+                            .map_err(|err| err.into().error_response()),
+                        |_| AppConfig::default(),
+                    )),
+            )
+        })?
+        .run())
+}
@@ -0,0 +1,233 @@
+// Requests, bytes and events per API key over time, so growth can be traced
+// to the team or fleet behind it. Counts are kept in fixed buckets in
+// memory and start again when the server restarts.
+use crate::clock::SharedClock;
+use crate::config;
+use crate::AppState;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+// `[usage]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    // Finest resolution /api/usage/keys can report
+    #[serde(deserialize_with = "config::de_duration")]
+    bucket: Duration,
+    // Buckets older than this are dropped
+    #[serde(deserialize_with = "config::de_duration")]
+    retention: Duration,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            bucket: Duration::from_secs(60 * 60),
+            retention: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Counts {
+    pub requests: u64,
+    pub bytes: u64,
+    pub events: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.requests += other.requests;
+        self.bytes += other.bytes;
+        self.events += other.events;
+    }
+}
+
+struct History {
+    first_used: DateTime<Utc>,
+    last_used: DateTime<Utc>,
+    // (bucket index, counts), oldest first; buckets without traffic are
+    // left out
+    buckets: VecDeque<(i64, Counts)>,
+}
+
+// Which key a request was accepted with; stored in the request extensions
+// by `ApiKeys` so later counts land on the same key. None for requests
+// accepted without one.
+#[derive(Debug, Clone)]
+pub struct UsageKey(pub Option<String>);
+
+pub struct KeyUsage {
+    bucket: i64,
+    retention: i64,
+    keys: Mutex<HashMap<Option<String>, History>>,
+    clock: SharedClock,
+}
+
+impl KeyUsage {
+    pub fn new(config: UsageConfig, clock: SharedClock) -> Self {
+        let bucket = config.bucket.as_secs().max(1) as i64;
+        if config.bucket.as_secs() == 0 {
+            log_line!("WARN", "[usage] bucket must be at least 1s; using 1s");
+        }
+        KeyUsage {
+            bucket,
+            retention: config.retention.as_secs() as i64,
+            keys: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+
+    pub fn add(&self, key: Option<&str>, counts: Counts) {
+        let now = self.clock.now();
+        let index = now.timestamp().div_euclid(self.bucket);
+        let oldest = (now.timestamp() - self.retention).div_euclid(self.bucket);
+        let mut keys = self.keys.lock();
+        let history = keys
+            .entry(key.map(str::to_string))
+            .or_insert_with(|| History {
+                first_used: now,
+                last_used: now,
+                buckets: VecDeque::new(),
+            });
+        history.last_used = now;
+        match history.buckets.back_mut() {
+            Some((last, bucket)) if *last == index => bucket.add(counts),
+            _ => history.buckets.push_back((index, counts)),
+        }
+        while history
+            .buckets
+            .front()
+            .is_some_and(|(start, _)| *start < oldest)
+        {
+            history.buckets.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    // RFC 3339 bounds; the default is everything retained
+    from: Option<String>,
+    to: Option<String>,
+    // A multiple of [usage] bucket, e.g. "1d" to sum hourly buckets
+    bucket: Option<String>,
+    key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Point {
+    time: String,
+    #[serde(flatten)]
+    counts: Counts,
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// GET /api/usage/keys - Requests, bytes and events per API key, in total
+// over the range and per bucket, busiest key first. Needs the same role as
+// managing keys.
+pub async fn get_key_usage(
+    request: HttpRequest,
+    query: web::Query<UsageQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(response) = data.api_keys.authorize_admin(&request) {
+        return Ok(response);
+    }
+    let usage = data.api_keys.usage();
+    let width = match &query.bucket {
+        None => usage.bucket,
+        Some(bucket) => match config::parse_duration(bucket).map(|width| width.as_secs() as i64) {
+            Some(width) if width > 0 && width % usage.bucket == 0 => width,
+            _ => {
+                return Ok(bad_request(format!(
+                    "invalid bucket '{}' (a multiple of the [usage] bucket, {}s)",
+                    bucket, usage.bucket
+                )))
+            }
+        },
+    };
+    let range = match crate::TimeRange::parse(query.from.as_deref(), query.to.as_deref()) {
+        Ok(range) => range,
+        Err(err) => return Ok(bad_request(err)),
+    };
+    let since = range.since.map_or(i64::MIN, |since| since.timestamp());
+    let until = range.until.map_or(i64::MAX, |until| until.timestamp());
+    if since > until {
+        return Ok(bad_request("from is after to".to_string()));
+    }
+
+    let mut keys: Vec<(Counts, serde_json::Value)> = usage
+        .keys
+        .lock()
+        .iter()
+        .filter(|(name, _)| query.key.is_none() || **name == query.key)
+        .filter_map(|(name, history)| {
+            let mut total = Counts::default();
+            let mut series: Vec<(i64, Counts)> = Vec::new();
+            for (index, counts) in &history.buckets {
+                let start = index * usage.bucket;
+                if start + usage.bucket <= since || start > until {
+                    continue;
+                }
+                total.add(*counts);
+                let start = start.div_euclid(width) * width;
+                match series.last_mut() {
+                    Some((last, bucket)) if *last == start => bucket.add(*counts),
+                    _ => series.push((start, *counts)),
+                }
+            }
+            if total.requests == 0 {
+                return None;
+            }
+            let series: Vec<Point> = series
+                .into_iter()
+                .map(|(start, counts)| Point {
+                    time: DateTime::<Utc>::from_timestamp(start, 0)
+                        .unwrap_or_default()
+                        .to_rfc3339(),
+                    counts,
+                })
+                .collect();
+            let entry = serde_json::json!({
+                "key": name,
+                // Revoked keys keep their history
+                "active": name.as_deref().is_some_and(|name| data.api_keys.exists(name)),
+                "requests": total.requests,
+                "bytes": total.bytes,
+                "events": total.events,
+                "first_used": history.first_used.to_rfc3339(),
+                "last_used": history.last_used.to_rfc3339(),
+                "series": series,
+            });
+            Some((total, entry))
+        })
+        .collect();
+    keys.sort_by(|(a, a_entry), (b, b_entry)| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| b.events.cmp(&a.events))
+            .then_with(|| a_entry["key"].as_str().cmp(&b_entry["key"].as_str()))
+    });
+
+    let mut total = Counts::default();
+    for (counts, _) in &keys {
+        total.add(*counts);
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "bucket_seconds": width,
+        "retention_seconds": usage.retention,
+        "total": total,
+        "keys": keys.into_iter().map(|(_, entry)| entry).collect::<Vec<_>>(),
+    })))
+}
@@ -0,0 +1,62 @@
+// What this binary is, for operators checking a fleet and for agents that
+// adapt to the server they talk to. The commit and build time come from
+// build.rs.
+use actix_web::{HttpResponse, Result};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Event `schema_version` values this server understands
+pub const SCHEMA_VERSIONS: &[u32] = &[1];
+
+// Cargo features compiled in; the inputs they add only exist when listed
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "aws")]
+    "aws",
+    #[cfg(feature = "docker")]
+    "docker",
+    #[cfg(feature = "gelf")]
+    "gelf",
+    #[cfg(feature = "netflow")]
+    "netflow",
+    #[cfg(feature = "smtp")]
+    "smtp",
+    #[cfg(feature = "statsd")]
+    "statsd",
+    #[cfg(feature = "syslog")]
+    "syslog",
+];
+
+// Short commit hash, if the build knew it
+pub fn commit() -> Option<&'static str> {
+    Some(env!("LOGLUMEN_GIT_COMMIT")).filter(|commit| !commit.is_empty())
+}
+
+pub fn build_time() -> Option<String> {
+    let seconds = env!("LOGLUMEN_BUILD_TIME").parse().ok()?;
+    chrono::DateTime::from_timestamp(seconds, 0).map(|time| time.to_rfc3339())
+}
+
+// `0.1.0 (3f2a9c1d04be)` for the CLI and the startup banner
+pub fn describe() -> String {
+    match commit() {
+        Some(commit) => format!("{} ({})", VERSION, commit),
+        None => VERSION.to_string(),
+    }
+}
+
+// GET /api/version - Version, commit, build time, features and schema
+// versions
+pub async fn get_version() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": VERSION,
+        "git_commit": commit(),
+        "build_time": build_time(),
+        "target": {
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+        },
+        "features": FEATURES,
+        "schema_versions": SCHEMA_VERSIONS,
+    })))
+}
@@ -0,0 +1,403 @@
+use crate::inventory::Package;
+use crate::scheduler::Scheduler;
+use crate::{AppState, Event};
+use actix_web::{web, HttpResponse, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+// `[vulnerabilities]` section
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct VulnerabilityConfig {
+    // OSV JSON file (one record, an array, or NDJSON) or a directory of them,
+    // e.g. an unpacked export from https://osv-vulnerabilities.storage.googleapis.com.
+    // Keep it current with your own download job; the server only reads it.
+    feed: Option<String>,
+    // When to re-read the feed and match it against inventory
+    cron: String,
+    // Severity of vulnerability events and alerts
+    severity: String,
+    // Only use feed entries for these OSV ecosystems ("Debian" also matches
+    // "Debian:12"). Empty uses all, which can match same-named packages from
+    // unrelated ecosystems.
+    ecosystems: Vec<String>,
+}
+
+impl Default for VulnerabilityConfig {
+    fn default() -> Self {
+        VulnerabilityConfig {
+            feed: None,
+            cron: "0 * * * *".to_string(),
+            severity: "error".to_string(),
+            ecosystems: Vec::new(),
+        }
+    }
+}
+
+// The parts of an OSV record needed for matching
+#[derive(Debug, Deserialize)]
+struct OsvRecord {
+    id: String,
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvAffected {
+    package: Option<OsvPackage>,
+    #[serde(default)]
+    versions: Vec<String>,
+    #[serde(default)]
+    ranges: Vec<OsvRange>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvPackage {
+    name: String,
+    #[serde(default)]
+    ecosystem: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsvRange {
+    #[serde(default)]
+    events: Vec<OsvEvent>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OsvEvent {
+    introduced: Option<String>,
+    fixed: Option<String>,
+    last_affected: Option<String>,
+}
+
+impl OsvAffected {
+    // Some(fixed version, if known) when `version` is affected
+    fn matches(&self, version: &str) -> Option<Option<String>> {
+        if self.versions.iter().any(|listed| listed == version) {
+            return Some(None);
+        }
+
+        for range in &self.ranges {
+            // Events are ordered; each `introduced` opens an interval that the
+            // next `fixed` or `last_affected` closes
+            let mut introduced: Option<&str> = None;
+            for event in &range.events {
+                if let Some(start) = &event.introduced {
+                    introduced = Some(start);
+                }
+                let Some(start) = introduced else {
+                    continue;
+                };
+                let after_start =
+                    start == "0" || compare_versions(version, start) != Ordering::Less;
+                if let Some(fixed) = &event.fixed {
+                    if after_start && compare_versions(version, fixed) == Ordering::Less {
+                        return Some(Some(fixed.clone()));
+                    }
+                    introduced = None;
+                } else if let Some(last) = &event.last_affected {
+                    if after_start && compare_versions(version, last) != Ordering::Greater {
+                        return Some(None);
+                    }
+                    introduced = None;
+                }
+            }
+            // An interval that is never closed affects every later version
+            if introduced.is_some_and(|start| {
+                start == "0" || compare_versions(version, start) != Ordering::Less
+            }) {
+                return Some(None);
+            }
+        }
+        None
+    }
+}
+
+// Compares versions segment by segment, numerically where both segments are
+// digits and lexically otherwise ("1.10" > "1.9", "3.0.2-1" > "3.0.2"). Not a
+// full dpkg/rpm implementation, but close enough for feed matching.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn segments(version: &str) -> Vec<&str> {
+        let mut segments = Vec::new();
+        // (start, is_digits) of the segment being read
+        let mut current: Option<(usize, bool)> = None;
+        for (index, ch) in version.char_indices() {
+            let kind = ch.is_ascii_alphanumeric().then(|| ch.is_ascii_digit());
+            match (current, kind) {
+                (Some((_, digits)), Some(kind)) if digits == kind => {}
+                (Some((begin, _)), _) => {
+                    segments.push(&version[begin..index]);
+                    current = kind.map(|kind| (index, kind));
+                }
+                (None, _) => current = kind.map(|kind| (index, kind)),
+            }
+        }
+        if let Some((begin, _)) = current {
+            segments.push(&version[begin..]);
+        }
+        segments
+    }
+
+    let (a, b) = (segments(a), segments(b));
+    for (left, right) in a.iter().zip(&b) {
+        let ordering = match (left.parse::<u64>(), right.parse::<u64>()) {
+            (Ok(left), Ok(right)) => left.cmp(&right),
+            _ => left.cmp(right),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Finding {
+    host: String,
+    package: String,
+    version: String,
+    id: String,
+    aliases: Vec<String>,
+    summary: String,
+    fixed: Option<String>,
+    first_seen: String,
+}
+
+#[derive(Default, Serialize)]
+struct ScanStatus {
+    last_scan: Option<String>,
+    records: usize,
+}
+
+pub struct Vulnerabilities {
+    feed: Option<String>,
+    severity: String,
+    ecosystems: Vec<String>,
+    findings: RwLock<Vec<Finding>>,
+    status: RwLock<ScanStatus>,
+}
+
+impl Vulnerabilities {
+    // Registers the periodic scan when a feed is configured
+    pub fn new(config: VulnerabilityConfig, scheduler: &mut Scheduler) -> Self {
+        if config.feed.is_some() {
+            let run = Arc::new(|state: &AppState| scan(state));
+            if let Err(err) = scheduler.register("vulnerability_scan", &config.cron, run) {
+                log_line!("WARN", "Vulnerability scan not scheduled: {}", err);
+            }
+        }
+
+        Vulnerabilities {
+            feed: config.feed,
+            severity: config.severity,
+            ecosystems: config.ecosystems,
+            findings: RwLock::new(Vec::new()),
+            status: RwLock::new(ScanStatus::default()),
+        }
+    }
+
+    fn wants(&self, ecosystem: &str) -> bool {
+        self.ecosystems.is_empty()
+            || self.ecosystems.iter().any(|wanted| {
+                ecosystem.eq_ignore_ascii_case(wanted)
+                    || ecosystem
+                        .split_once(':')
+                        .is_some_and(|(base, _)| base.eq_ignore_ascii_case(wanted))
+            })
+    }
+}
+
+fn load_feed(path: &Path, records: &mut Vec<OsvRecord>) -> std::result::Result<(), String> {
+    if path.is_dir() {
+        let entries =
+            std::fs::read_dir(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() || path.extension().is_some_and(|ext| ext == "json") {
+                load_feed(&path, records)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let contents =
+        std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if let Ok(list) = serde_json::from_str::<Vec<OsvRecord>>(&contents) {
+        records.extend(list);
+    } else if let Ok(record) = serde_json::from_str::<OsvRecord>(&contents) {
+        records.push(record);
+    } else {
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(line)
+                .map_err(|err| format!("{}:{}: {}", path.display(), number + 1, err))?;
+            records.push(record);
+        }
+    }
+    Ok(())
+}
+
+// Match every host's packages against the feed. New findings become
+// `vulnerability` events and alerts; findings that no longer match (package
+// upgraded or removed) are dropped.
+fn scan(state: &AppState) -> std::result::Result<String, String> {
+    let vulnerabilities = &state.vulnerabilities;
+    let Some(feed) = vulnerabilities.feed.as_deref() else {
+        return Err("no [vulnerabilities] feed configured".to_string());
+    };
+    let mut records = Vec::new();
+    load_feed(Path::new(feed), &mut records)?;
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let previous = vulnerabilities.findings.read().clone();
+    let mut known: HashSet<(&str, &str, &str)> = HashSet::new();
+    for finding in &previous {
+        known.insert((&finding.host, &finding.package, &finding.id));
+    }
+
+    let mut findings = Vec::new();
+    let mut new_findings = Vec::new();
+    for (host, packages) in state.inventory.packages() {
+        for Package { name, version } in packages {
+            for record in &records {
+                let fixed = record.affected.iter().find_map(|affected| {
+                    affected
+                        .package
+                        .as_ref()
+                        .filter(|package| package.name.eq_ignore_ascii_case(&name))
+                        .filter(|package| vulnerabilities.wants(&package.ecosystem))
+                        .and_then(|_| affected.matches(&version))
+                });
+                let Some(fixed) = fixed else {
+                    continue;
+                };
+
+                let first_seen = previous
+                    .iter()
+                    .find(|finding| {
+                        finding.host == host && finding.package == name && finding.id == record.id
+                    })
+                    .map(|finding| finding.first_seen.clone());
+                let finding = Finding {
+                    host: host.clone(),
+                    package: name.clone(),
+                    version: version.clone(),
+                    id: record.id.clone(),
+                    aliases: record.aliases.clone(),
+                    summary: record.summary.clone(),
+                    fixed,
+                    first_seen: first_seen.clone().unwrap_or_else(|| now.clone()),
+                };
+                if !known.contains(&(host.as_str(), name.as_str(), record.id.as_str())) {
+                    new_findings.push(finding.clone());
+                }
+                findings.push(finding);
+            }
+        }
+    }
+
+    let events: Vec<Event> = new_findings
+        .iter()
+        .map(|finding| Event {
+            schema_version: 1,
+            category: "vulnerability".to_string(),
+            event_type: "vulnerable_package".to_string(),
+            time: now.clone(),
+            host: finding.host.clone(),
+            host_ipv4: String::new(),
+            os: String::new(),
+            source: "vulnerability_feed".to_string(),
+            severity: vulnerabilities.severity.clone(),
+            message: format!(
+                "{} {} on {} is affected by {}",
+                finding.package, finding.version, finding.host, finding.id
+            ),
+            data: serde_json::to_value(finding).unwrap_or_default(),
+            signature: None,
+            verification: None,
+            identity: None,
+            ttl: None,
+        })
+        .collect();
+    for (finding, event) in new_findings.iter().zip(&events) {
+        state.rules.raise_for_event(
+            "vulnerability_feed",
+            "vulnerability",
+            &vulnerabilities.severity,
+            &finding.id,
+            event.message.clone(),
+            event,
+        );
+    }
+    if !events.is_empty() {
+        crate::ingest_blocking(state, events);
+    }
+
+    let summary = format!(
+        "{} finding(s), {} new, from {} feed record(s)",
+        findings.len(),
+        new_findings.len(),
+        records.len()
+    );
+    *vulnerabilities.findings.write() = findings;
+    *vulnerabilities.status.write() = ScanStatus {
+        last_scan: Some(now),
+        records: records.len(),
+    };
+    Ok(summary)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VulnerabilityQuery {
+    host: Option<String>,
+    // Matches the OSV id or any alias, e.g. a CVE number
+    id: Option<String>,
+}
+
+// GET /api/vulnerabilities - Current findings from the last scan
+pub async fn get_vulnerabilities(
+    query: web::Query<VulnerabilityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let findings: Vec<Finding> = data
+        .vulnerabilities
+        .findings
+        .read()
+        .iter()
+        .filter(|finding| {
+            query
+                .host
+                .as_deref()
+                .is_none_or(|host| finding.host == host)
+        })
+        .filter(|finding| {
+            query.id.as_deref().is_none_or(|id| {
+                finding.id.eq_ignore_ascii_case(id)
+                    || finding
+                        .aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(id))
+            })
+        })
+        .cloned()
+        .collect();
+    let status = data.vulnerabilities.status.read();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "last_scan": status.last_scan,
+        "feed_records": status.records,
+        "total": findings.len(),
+        "findings": findings,
+    })))
+}
@@ -27,6 +27,7 @@ async function init() {
     // Setup filter event listeners
     setupFilters();
     setupNodesUI();
+    showSession();
 
     await fetchAndUpdate();
     // Auto-refresh every 5 seconds
@@ -78,10 +79,39 @@ function setupNodesUI() {
     });
 }
 
+// Send the browser to the login page when the server wants a login
+function loginIfNeeded(response) {
+    if (response.status !== 401) return false;
+    const next = window.location.pathname + window.location.search;
+    window.location.href = `/login.html?next=${encodeURIComponent(next)}`;
+    return true;
+}
+
+// Who is logged in, with a sign-out button, when logins are on
+async function showSession() {
+    const box = document.getElementById('session-info');
+    if (!box) return;
+    try {
+        const response = await fetch('/api/auth/session');
+        if (!response.ok) return;
+        const session = await response.json();
+        if (!session.enabled) return;
+        document.getElementById('session-user').textContent = session.user;
+        box.style.display = 'block';
+        document.getElementById('logout-button').addEventListener('click', async () => {
+            await fetch('/api/auth/logout', { method: 'POST' });
+            window.location.href = '/login.html';
+        });
+    } catch (error) {
+        console.error('[ERROR] Failed to fetch session:', error);
+    }
+}
+
 // Fetch data and update dashboard
 async function fetchAndUpdate() {
     try {
         const response = await fetch('/api/stats');
+        if (loginIfNeeded(response)) return;
 
         if (!response.ok) {
             throw new Error(`HTTP ${response.status}: ${response.statusText}`);
@@ -94,7 +124,8 @@ async function fetchAndUpdate() {
         document.getElementById('loading').style.display = 'none';
         document.getElementById('error').style.display = 'none';
 
-        if (data.total_events === 0) {
+        // Registered agents are listed before they send anything
+        if (data.total_events === 0 && !(data.nodes || []).length) {
             document.getElementById('no-data').style.display = 'block';
             document.getElementById('categories').innerHTML = '';
             document.getElementById('recent-events-section').style.display = 'none';
@@ -116,8 +147,10 @@ function updateDashboard(data) {
     // Update header stats
     document.getElementById('total-events').textContent = data.total_events.toLocaleString();
 
+    // The server formats times in its configured locale and time zone
     const lastUpdated = new Date(data.last_updated);
-    document.getElementById('last-updated').textContent = lastUpdated.toLocaleTimeString();
+    document.getElementById('last-updated').textContent =
+        data.last_updated_display || lastUpdated.toLocaleTimeString();
 
     // Update categories
     updateCategories(data.categories);
@@ -364,21 +397,31 @@ function renderNodes() {
 
 function createNodeCard(node) {
     const card = document.createElement('div');
-    card.className = 'node-card';
+    const status = node.status || 'online';
+    card.className = `node-card node-card-${status}`;
 
     const lastEventText = node.last_event_time
-        ? formatEventTimestamp(node.last_event_time)
+        ? (node.last_event_time_display || formatEventTimestamp(node.last_event_time))
         : 'No events yet';
+    const lastSeenText = node.last_seen
+        ? (node.last_seen_display || formatEventTimestamp(node.last_seen))
+        : 'Never';
 
     const severitySummary = formatSeveritySummary(node.severity_counts || {});
     const categorySummary = formatCategorySummary(node.categories || {});
+    const agentText = node.agent_id
+        ? [node.agent_version ? `v${node.agent_version}` : null, ...(node.tags || [])]
+            .filter(Boolean).join(', ') || 'registered'
+        : 'not registered';
 
     card.innerHTML = `
-        <div class="node-name">${node.host}</div>
+        <div class="node-name">${node.host} <span class="node-status node-status-${status}">${status}</span></div>
         <div class="node-ip">${node.host_ipv4}</div>
         <div class="node-count">${node.total_events.toLocaleString()} events</div>
         <div class="node-metadata">
+            <span>Last seen: ${lastSeenText}</span>
             <span>Last event: ${lastEventText}</span>
+            <span>Agent: ${agentText}</span>
             <span>Categories: ${categorySummary}</span>
             <span>Severity: ${severitySummary}</span>
         </div>
@@ -459,7 +502,7 @@ function formatEventTimestamp(timestamp) {
 }
 
 function createEventItem(event) {
-    const timeStr = formatEventTimestamp(event.time);
+    const timeStr = event.time_display || formatEventTimestamp(event.time);
 
     return `
         <div class="event-item severity-${event.severity}">
@@ -0,0 +1,36 @@
+// Only same-site paths, so the login page can't be used to redirect elsewhere
+function nextPage() {
+    const next = new URLSearchParams(window.location.search).get('next') || '/';
+    return next.startsWith('/') && !next.startsWith('//') ? next : '/';
+}
+
+async function login(event) {
+    event.preventDefault();
+    const button = document.getElementById('login-button');
+    const errorBox = document.getElementById('login-error');
+    button.disabled = true;
+    errorBox.style.display = 'none';
+
+    try {
+        const response = await fetch('/api/auth/login', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                username: document.getElementById('username').value,
+                password: document.getElementById('password').value
+            })
+        });
+        if (response.ok) {
+            window.location.href = nextPage();
+            return;
+        }
+        const body = await response.json().catch(() => ({}));
+        throw new Error(body.message || `HTTP ${response.status}`);
+    } catch (error) {
+        errorBox.textContent = error.message;
+        errorBox.style.display = 'block';
+        button.disabled = false;
+    }
+}
+
+document.getElementById('login-form').addEventListener('submit', login);
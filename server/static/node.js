@@ -24,6 +24,11 @@ async function initNodePage() {
             fetch(`/api/events/${encodeURIComponent(decodedHost)}`)
         ]);
 
+        if (statsResp.status === 401 || eventsResp.status === 401) {
+            const next = window.location.pathname + window.location.search;
+            window.location.href = `/login.html?next=${encodeURIComponent(next)}`;
+            return;
+        }
         if (!statsResp.ok) {
             throw new Error(`Failed to load stats (HTTP ${statsResp.status})`);
         }
@@ -96,12 +101,14 @@ function updateNodeMeta(nodes, events) {
     const meta = nodes.find(node => node.host === decodedHost);
     const total = meta ? meta.total_events : events.length;
     const lastEvent = meta && meta.last_event_time
-        ? formatEventTimestamp(meta.last_event_time)
+        ? (meta.last_event_time_display || formatEventTimestamp(meta.last_event_time))
         : (events[0] ? formatEventTimestamp(events[0].time) : 'No events yet');
 
     document.getElementById('node-ip').textContent = meta ? `IP: ${meta.host_ipv4}` : 'IP: Unknown';
     document.getElementById('node-total').textContent = `${total} event${total === 1 ? '' : 's'}`;
-    document.getElementById('node-last').textContent = `Last event: ${lastEvent}`;
+    document.getElementById('node-last').textContent = meta && meta.status
+        ? `Last event: ${lastEvent} • ${meta.status}`
+        : `Last event: ${lastEvent}`;
 }
 
 function renderNodeEvents() {